@@ -0,0 +1,148 @@
+//! AIFF and uncompressed AIFF-C decoding, which rodio lacks
+//! Big-endian PCM, plus the little-endian `sowt` variant macOS writes
+
+use rodio::Source;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+/// Samples of an AIFF file, read as they are played
+pub struct Aiff {
+    reader: BufReader<File>,
+    channels: u16,
+    sample_rate: u32,
+    bytes: usize,        // Bytes per sample
+    little_endian: bool, // `sowt` AIFF-C data
+    remaining: u64,      // Samples left, counting every channel
+    total: Duration,
+}
+
+impl Aiff {
+    pub fn new(file: File) -> io::Result<Self> {
+        let mut reader = BufReader::new(file);
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != b"FORM" || !matches!(&header[8..], b"AIFF" | b"AIFC") {
+            return Err(invalid("Not an AIFF file"));
+        }
+
+        let mut format = None;
+        let mut data = None;
+        let mut chunk = [0u8; 8];
+        while reader.read_exact(&mut chunk).is_ok() {
+            let size = u32::from_be_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+            let start = reader.stream_position()?;
+            match &chunk[..4] {
+                b"COMM" => {
+                    let mut comm = vec![0u8; size.min(64) as usize];
+                    reader.read_exact(&mut comm)?;
+                    format = Some(comm);
+                }
+                b"SSND" => {
+                    let mut offset = [0u8; 8];
+                    reader.read_exact(&mut offset)?;
+                    let skip = u32::from_be_bytes([offset[0], offset[1], offset[2], offset[3]]);
+                    data = Some((
+                        start + 8 + skip as u64,
+                        size.saturating_sub(8 + skip as u64),
+                    ));
+                }
+                _ => {}
+            }
+            // Chunks are padded to an even length
+            reader.seek(SeekFrom::Start(start + size + size % 2))?;
+        }
+
+        let comm = format.ok_or_else(|| invalid("AIFF file has no COMM chunk"))?;
+        let (data_start, data_len) = data.ok_or_else(|| invalid("AIFF file has no sound data"))?;
+        if comm.len() < 18 {
+            return Err(invalid("AIFF COMM chunk is too short"));
+        }
+        let channels = u16::from_be_bytes([comm[0], comm[1]]);
+        let frames = u32::from_be_bytes([comm[2], comm[3], comm[4], comm[5]]) as u64;
+        let bits = u16::from_be_bytes([comm[6], comm[7]]);
+        let sample_rate = extended_to_f64(&comm[8..18]).round() as u32;
+        let little_endian = match comm.get(18..22) {
+            None | Some(b"NONE") | Some(b"twos") => false,
+            Some(b"sowt") => true,
+            Some(other) => {
+                return Err(invalid(&format!(
+                    "Compressed AIFF-C ({}) isn't supported",
+                    String::from_utf8_lossy(other)
+                )))
+            }
+        };
+        if channels == 0 || sample_rate == 0 || !(1..=32).contains(&bits) {
+            return Err(invalid("AIFF file has an unsupported format"));
+        }
+
+        let bytes = (bits as usize).div_ceil(8);
+        let samples = (frames * channels as u64).min(data_len / bytes as u64);
+        reader.seek(SeekFrom::Start(data_start))?;
+        Ok(Self {
+            reader,
+            channels,
+            sample_rate,
+            bytes,
+            little_endian,
+            remaining: samples,
+            total: Duration::from_secs_f64(frames as f64 / sample_rate as f64),
+        })
+    }
+}
+
+impl Iterator for Aiff {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let mut sample = [0u8; 4];
+        let bytes = &mut sample[..self.bytes];
+        self.reader.read_exact(bytes).ok()?;
+        if self.little_endian {
+            bytes.reverse();
+        }
+        self.remaining -= 1;
+        // Samples are left-justified, so the top byte carries the sign
+        let value = i32::from_be_bytes(sample);
+        Some(value as f32 / i32::MAX as f32)
+    }
+}
+
+impl Source for Aiff {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.total)
+    }
+}
+
+/// Decodes the 80-bit IEEE extended float AIFF stores the sample rate in
+fn extended_to_f64(bytes: &[u8]) -> f64 {
+    let exponent = (((bytes[0] & 0x7f) as i32) << 8 | bytes[1] as i32) - 16383;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+    let value = mantissa as f64 * 2f64.powi(exponent - 63);
+    if bytes[0] & 0x80 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,146 @@
+//! Spoken announcements of track changes, for listening eyes-free
+//! With `announce = true` under `[accessibility]`, "Now playing: <artist>,
+//! <title>" is spoken through `espeak-ng` (or `espeak`), `say` on macOS or
+//! SAPI on Windows, with the music ducked to `announce_duck` meanwhile.
+//! `announce_command` runs something else instead, such as a script that
+//! plays pre-rendered clips; `{artist}`, `{title}` and `{text}` in it are
+//! filled in, and without them the text is passed as the last argument
+
+use crate::{config::Config, PlayerEvent};
+use std::{
+    io,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{self, Sender},
+        Mutex,
+    },
+    thread,
+};
+
+/// How announcements are made; None while they are off
+static SETTINGS: Mutex<Option<Settings>> = Mutex::new(None);
+
+#[derive(Clone, Debug)]
+struct Settings {
+    command: Option<Vec<String>>, // Replaces the system's speech synthesizer
+    duck: f32,                    // Volume factor while speaking
+}
+
+/// Reads `announce`, `announce_duck` and `announce_command` under
+/// `[accessibility]`
+pub fn configure(config: &Config) {
+    let enabled = config.get_bool("accessibility.announce").unwrap_or(false);
+    *SETTINGS.lock().unwrap() = enabled.then(|| Settings {
+        command: config
+            .get_str("accessibility.announce_command")
+            .map(|command| command.split_whitespace().map(str::to_string).collect())
+            .filter(|words: &Vec<String>| !words.is_empty()),
+        duck: config
+            .get_f64("accessibility.announce_duck")
+            .unwrap_or(0.3)
+            .clamp(0.0, 1.0) as f32,
+    });
+}
+
+/// Volume factor to duck the music by, None while announcements are off
+pub fn duck() -> Option<f32> {
+    SETTINGS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|settings| settings.duck)
+}
+
+/// Speaks announcements one at a time on a background thread
+pub struct Announcer {
+    jobs: Sender<(u64, String, String)>, // Number, artist and title
+}
+
+impl Announcer {
+    /// Starts the speaking thread, which sends `PlayerEvent::Announced`
+    /// with an announcement's number, and any error, once it is over
+    pub fn spawn(events: Sender<PlayerEvent>) -> Self {
+        let (jobs, queue) = mpsc::channel::<(u64, String, String)>();
+        thread::spawn(move || {
+            while let Ok(mut job) = queue.recv() {
+                // Only the latest track is worth announcing after a quick
+                // run of skips
+                while let Ok(newer) = queue.try_recv() {
+                    job = newer;
+                }
+                let (number, artist, title) = job;
+                let settings = SETTINGS.lock().unwrap().clone();
+                let error = settings
+                    .and_then(|settings| speak(&settings, &artist, &title).err())
+                    .map(|e| e.to_string());
+                if events.send(PlayerEvent::Announced(number, error)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { jobs }
+    }
+
+    /// Queues an announcement of a track
+    pub fn announce(&self, number: u64, artist: &str, title: &str) {
+        let _ = self
+            .jobs
+            .send((number, artist.to_string(), title.to_string()));
+    }
+}
+
+/// Speaks one announcement and waits until it is over
+fn speak(settings: &Settings, artist: &str, title: &str) -> io::Result<()> {
+    let text = if artist.is_empty() {
+        format!("Now playing: {}", title)
+    } else {
+        format!("Now playing: {}, {}", artist, title)
+    };
+    let status = match &settings.command {
+        Some(words) => {
+            let fill = |word: &String| {
+                word.replace("{artist}", artist)
+                    .replace("{title}", title)
+                    .replace("{text}", &text)
+            };
+            let mut command = Command::new(&words[0]);
+            command.args(words[1..].iter().map(fill));
+            if !words.iter().any(|word| word.contains('{')) {
+                command.arg(&text);
+            }
+            quiet(&mut command).status()?
+        }
+        None if cfg!(windows) => {
+            // Single quotes are doubled inside a PowerShell string
+            let script = format!(
+                "Add-Type -AssemblyName System.Speech; \
+                 (New-Object System.Speech.Synthesis.SpeechSynthesizer).Speak('{}')",
+                text.replace('\'', "''")
+            );
+            quiet(Command::new("powershell").args(["-NoProfile", "-Command", &script])).status()?
+        }
+        None if cfg!(target_os = "macos") => quiet(Command::new("say").arg(&text)).status()?,
+        None => match quiet(Command::new("espeak-ng").arg(&text)).status() {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                quiet(Command::new("espeak").arg(&text)).status()?
+            }
+            status => status?,
+        },
+    };
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "speech command failed ({})",
+            status
+        )))
+    }
+}
+
+/// Keeps the speech command off the prompt
+fn quiet(command: &mut Command) -> &mut Command {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+}
@@ -0,0 +1,271 @@
+//! Token authentication for the remote control interfaces
+//! Tokens come from `[auth] tokens` in the config as `"secret"` (full
+//! control) or `"secret:read"` (status only)
+//! `[auth] read_only = true` limits every client to status only, whatever
+//! its token
+//! Without configured tokens a session token is generated and saved to
+//! the state directory, where local clients pick it up
+
+use crate::{config::Config, session};
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+/// What an authenticated client may do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    Read,    // Status and library queries only
+    Control, // Every command
+}
+
+/// Tokens accepted by the remote interfaces, shared by every server so a
+/// config reload applies everywhere
+/// With no tokens, authentication is disabled and every client has control;
+/// that only happens with `[auth] required = false`
+#[derive(Clone, Default)]
+pub struct Tokens {
+    tokens: Arc<RwLock<Vec<(String, Scope)>>>,
+    read_only: Arc<AtomicBool>, // Grant at most `Scope::Read`
+}
+
+impl Tokens {
+    /// Reads `[auth] tokens` from the config
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let tokens = config
+            .get_list("auth.tokens")
+            .into_iter()
+            .map(|entry| match entry.rsplit_once(':') {
+                Some((token, "read")) => Ok((token.to_string(), Scope::Read)),
+                Some((token, "control")) => Ok((token.to_string(), Scope::Control)),
+                Some((_, scope)) => Err(format!("Unknown token scope `{}`", scope)),
+                None => Ok((entry, Scope::Control)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            tokens: Arc::new(RwLock::new(tokens)),
+            read_only: Arc::new(AtomicBool::new(
+                config.get_bool("auth.read_only").unwrap_or(false),
+            )),
+        })
+    }
+
+    /// Creates a session with a single freshly generated control token
+    pub fn generated() -> io::Result<(Self, String)> {
+        let token = generate_token()?;
+        let tokens = Self {
+            tokens: Arc::new(RwLock::new(vec![(token.clone(), Scope::Control)])),
+            read_only: Arc::default(),
+        };
+        Ok((tokens, token))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tokens.read().is_ok_and(|tokens| !tokens.is_empty())
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    /// Swaps in another token set, e.g. after the config was reloaded
+    pub fn replace(&self, other: &Tokens) {
+        let new_tokens = other.tokens.read().map(|t| t.clone()).unwrap_or_default();
+        if let Ok(mut tokens) = self.tokens.write() {
+            *tokens = new_tokens;
+        }
+    }
+
+    /// Scope a client starts with before presenting any token
+    pub fn initial_scope(&self) -> Option<Scope> {
+        (!self.is_enabled()).then_some(self.limit(Scope::Control))
+    }
+
+    /// Scope granted by a token, or None for an unknown token
+    pub fn check(&self, candidate: &str) -> Option<Scope> {
+        self.tokens.read().ok().and_then(|tokens| {
+            tokens
+                .iter()
+                .find(|(token, _)| constant_time_eq(token.as_bytes(), candidate.as_bytes()))
+                .map(|(_, scope)| self.limit(*scope))
+        })
+    }
+
+    fn limit(&self, scope: Scope) -> Scope {
+        if self.is_read_only() {
+            Scope::Read
+        } else {
+            scope
+        }
+    }
+
+    /// First configured token, used by local clients such as `tmux-status`
+    pub fn first(&self) -> Option<String> {
+        self.tokens
+            .read()
+            .ok()
+            .and_then(|tokens| tokens.first().map(|(token, _)| token.clone()))
+    }
+}
+
+/// Whether a command only reads player state and is allowed with
+/// `Scope::Read`
+pub fn is_read_only(command: &str) -> bool {
+    matches!(command, "status" | "list" | "help")
+}
+
+/// Whether a listen address is reachable from other machines
+pub fn is_exposed(addr: &str) -> bool {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    !matches!(host, "127.0.0.1" | "localhost" | "[::1]" | "::1")
+}
+
+/// Session token file, readable only by its owner
+const TOKEN_FILE: &str = "control-token";
+
+/// Saves the generated session token for local clients, returning the path
+pub fn save_token(token: &str) -> io::Result<PathBuf> {
+    let dir = session::state_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(TOKEN_FILE);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // An older file keeps its mode when truncated
+        if path.exists() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(&path)?.write_all(token.as_bytes())?;
+    Ok(path)
+}
+
+/// Token the running player saved, used by local clients without a
+/// configured one
+pub fn saved_token() -> Option<String> {
+    let path = session::state_dir()?.join(TOKEN_FILE);
+    let token = fs::read_to_string(path).ok()?;
+    Some(token.trim().to_string()).filter(|token| !token.is_empty())
+}
+
+/// Compares without returning early, so timing doesn't reveal how much
+/// of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Generates a 128-bit hex token from the operating system's random source
+fn generate_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    random_bytes(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(unix)]
+fn random_bytes(buffer: &mut [u8]) -> io::Result<()> {
+    use std::io::Read;
+    fs::File::open("/dev/urandom")?.read_exact(buffer)
+}
+
+#[cfg(windows)]
+fn random_bytes(buffer: &mut [u8]) -> io::Result<()> {
+    #[link(name = "advapi32")]
+    extern "system" {
+        // RtlGenRandom
+        fn SystemFunction036(buffer: *mut u8, length: u32) -> u8;
+    }
+    let length = u32::try_from(buffer.len()).map_err(|_| io::ErrorKind::InvalidInput)?;
+    match unsafe { SystemFunction036(buffer.as_mut_ptr(), length) } {
+        0 => Err(io::Error::other("No random source")),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_text(config: &str) -> Result<Tokens, String> {
+        Tokens::from_config(&Config::parse(config).unwrap())
+    }
+
+    #[test]
+    fn tokens_grant_their_scope() {
+        let tokens =
+            from_text("[auth]\ntokens = [\"full\", \"view:read\", \"a:b:control\"]").unwrap();
+        assert!(tokens.is_enabled());
+        assert_eq!(tokens.initial_scope(), None);
+        assert_eq!(tokens.check("full"), Some(Scope::Control));
+        assert_eq!(tokens.check("view"), Some(Scope::Read));
+        assert_eq!(tokens.check("a:b"), Some(Scope::Control));
+        assert_eq!(tokens.check("view:read"), None);
+        assert_eq!(tokens.check("ful"), None);
+        assert_eq!(tokens.check(""), None);
+        assert_eq!(tokens.first().as_deref(), Some("full"));
+    }
+
+    #[test]
+    fn read_only_caps_every_scope() {
+        let tokens = from_text("[auth]\ntokens = [\"full\"]\nread_only = true").unwrap();
+        assert_eq!(tokens.check("full"), Some(Scope::Read));
+        let open = from_text("[auth]\nread_only = true").unwrap();
+        assert_eq!(open.initial_scope(), Some(Scope::Read));
+        assert_eq!(Tokens::default().initial_scope(), Some(Scope::Control));
+    }
+
+    #[test]
+    fn rejects_unknown_scopes() {
+        assert!(from_text("[auth]\ntokens = [\"secret:admin\"]").is_err());
+    }
+
+    #[test]
+    fn compares_whole_tokens() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn loopback_is_not_exposed() {
+        for addr in ["127.0.0.1:6600", "localhost:6600", "[::1]:6600"] {
+            assert!(!is_exposed(addr), "{}", addr);
+        }
+        for addr in ["0.0.0.0:6600", "192.168.1.5:6600", "[::]:6600", "box:6600"] {
+            assert!(is_exposed(addr), "{}", addr);
+        }
+    }
+
+    #[test]
+    fn generated_tokens_are_random_hex() {
+        let (tokens, token) = Tokens::generated().unwrap();
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(tokens.check(&token), Some(Scope::Control));
+        assert_ne!(generate_token().unwrap(), generate_token().unwrap());
+    }
+
+    #[test]
+    fn only_queries_are_read_only() {
+        assert!(is_read_only("status"));
+        assert!(is_read_only("list"));
+        assert!(!is_read_only("play"));
+        assert!(!is_read_only("queue"));
+    }
+}
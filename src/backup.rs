@@ -0,0 +1,206 @@
+//! Exports what the player has learned about the library to one JSON file
+//! and reads it back, so ratings and the like survive a move to another
+//! machine whatever becomes of the state files
+//! Tracks under the music directory are stored relative to it and land
+//! under the music directory of the importing machine
+
+use crate::{
+    json, json::Json, labels, labels::Labels, speeds::Speeds, stats::Stats, sync::PositionSync,
+};
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Format of the file written; newer files are refused
+const VERSION: f64 = 1.0;
+
+/// Everything a backup covers
+pub struct Library<'a> {
+    pub music_dir: Option<&'a Path>,
+    pub stats: &'a mut Stats,
+    pub labels: &'a mut Labels,
+    pub speeds: &'a mut Speeds,
+    pub positions: Option<&'a mut PositionSync>, // Only with a sync folder set up
+}
+
+/// How much was written or taken in
+#[derive(Default)]
+pub struct Summary {
+    pub tracks: usize,
+    pub speeds: usize,
+    pub positions: usize,
+}
+
+/// Writes the backup to `file`
+pub fn export(file: &Path, library: &Library) -> io::Result<Summary> {
+    let paths: BTreeSet<&Path> = library
+        .stats
+        .paths()
+        .chain(library.labels.paths())
+        .collect();
+    let tracks: Vec<Json> = paths
+        .iter()
+        .map(|path| {
+            let stats = library.stats.get(path).cloned().unwrap_or_default();
+            let labels = library.labels.get(path);
+            Json::object(vec![
+                ("path", Json::from(stored(path, library.music_dir))),
+                ("plays", Json::from(stats.plays as f64)),
+                (
+                    "rating",
+                    stats.rating.map_or(Json::Null, |r| Json::from(r as f64)),
+                ),
+                (
+                    "intro",
+                    stats.intro.map_or(Json::Null, |i| Json::from(i as f64)),
+                ),
+                (
+                    "labels",
+                    Json::Array(labels.into_iter().map(Json::from).collect()),
+                ),
+            ])
+        })
+        .collect();
+    let speeds: Vec<(String, Json)> = library
+        .speeds
+        .iter()
+        .map(|(key, speed)| (key.to_string(), Json::from(speed as f64)))
+        .collect();
+    let positions: Vec<Json> = library
+        .positions
+        .as_ref()
+        .map(|sync| sync.all())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(key, updated, position)| {
+            Json::object(vec![
+                ("key", Json::from(key)),
+                ("updated", Json::from(updated as f64)),
+                ("position", Json::from(position as f64)),
+            ])
+        })
+        .collect();
+    let summary = Summary {
+        tracks: tracks.len(),
+        speeds: speeds.len(),
+        positions: positions.len(),
+    };
+    let backup = Json::object(vec![
+        ("version", Json::from(VERSION)),
+        ("tracks", Json::Array(tracks)),
+        ("speeds", Json::Object(speeds)),
+        ("positions", Json::Array(positions)),
+    ]);
+    fs::write(file, format!("{}\n", backup))?;
+    Ok(summary)
+}
+
+/// Merges the backup in `file` into the library and saves it
+/// Ratings, intros and speeds from the backup win, labels are added to the
+/// ones already there, a play count only grows, and a position is taken
+/// only if it is newer than the one known
+pub fn import(file: &Path, library: &mut Library) -> Result<Summary, String> {
+    let text = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let backup = json::parse(&text).map_err(|e| format!("Not a backup: {}", e))?;
+    match backup.get("version").and_then(Json::as_f64) {
+        Some(version) if version <= VERSION => {}
+        Some(version) => {
+            return Err(format!(
+                "Backup format {} is newer than this player",
+                version
+            ))
+        }
+        None => return Err("Not a backup: no version".to_string()),
+    }
+    let mut summary = Summary::default();
+
+    for track in array(backup.get("tracks")) {
+        let Some(path) = track.get("path").and_then(Json::as_str) else {
+            continue;
+        };
+        let Some(path) = restored(path, library.music_dir) else {
+            continue;
+        };
+        let number = |key: &str| track.get(key).and_then(Json::as_f64);
+        let stats = library.stats.entry(&path);
+        if let Some(plays) = number("plays") {
+            stats.plays = stats.plays.max(plays as u64);
+        }
+        if let Some(rating) = number("rating").filter(|r| (1.0..=5.0).contains(r)) {
+            stats.rating = Some(rating as u8);
+        }
+        if let Some(intro) = number("intro") {
+            stats.intro = Some(intro as u64);
+        }
+        let labels = array(track.get("labels")).iter().filter_map(Json::as_str);
+        for label in labels.filter_map(labels::normalize) {
+            library.labels.add(&path, &label);
+        }
+        summary.tracks += 1;
+    }
+    library
+        .stats
+        .save()
+        .map_err(|e| format!("Could not save stats: {}", e))?;
+    library
+        .labels
+        .save()
+        .map_err(|e| format!("Could not save labels: {}", e))?;
+
+    if let Some(Json::Object(members)) = backup.get("speeds") {
+        let speeds: Vec<(String, f32)> = members
+            .iter()
+            .filter_map(|(key, speed)| Some((key.clone(), speed.as_f64()? as f32)))
+            .collect();
+        library
+            .speeds
+            .set_all(&speeds)
+            .map_err(|e| format!("Could not save speeds: {}", e))?;
+        summary.speeds = speeds.len();
+    }
+
+    if let Some(sync) = library.positions.as_mut() {
+        let positions: Vec<(String, u64, u64)> = array(backup.get("positions"))
+            .iter()
+            .filter_map(|entry| {
+                let key = entry.get("key")?.as_str()?.to_string();
+                let updated = entry.get("updated")?.as_f64()? as u64;
+                let position = entry.get("position")?.as_f64()? as u64;
+                Some((key, updated, position))
+            })
+            .collect();
+        summary.positions = sync
+            .import(&positions)
+            .map_err(|e| format!("Could not save positions: {}", e))?;
+    }
+    Ok(summary)
+}
+
+/// How a track is written: relative to the music directory when under it
+fn stored(path: &Path, music_dir: Option<&Path>) -> String {
+    let relative = music_dir.and_then(|dir| path.strip_prefix(dir).ok());
+    match relative {
+        Some(relative) => relative.to_string_lossy().replace('\\', "/"),
+        None => path.to_string_lossy().to_string(),
+    }
+}
+
+/// Where a stored track lives here; relative ones need a music directory
+fn restored(stored: &str, music_dir: Option<&Path>) -> Option<PathBuf> {
+    let path = Path::new(stored);
+    if path.is_absolute() {
+        return Some(path.to_path_buf());
+    }
+    let mut restored = music_dir?.to_path_buf();
+    restored.extend(stored.split('/'));
+    Some(restored)
+}
+
+fn array(value: Option<&Json>) -> &[Json] {
+    match value {
+        Some(Json::Array(items)) => items,
+        _ => &[],
+    }
+}
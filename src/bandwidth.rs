@@ -0,0 +1,43 @@
+//! Download rate cap for remote tracks, so streaming and reading ahead
+//! don't take over a metered or shared connection
+//! `max_rate_kb` under `[network]`, in KiB per second, holds for all
+//! downloads together; unset or 0 means no cap
+
+use crate::config::Config;
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Bytes per second all downloads may use together, None for no cap
+static RATE: Mutex<Option<f64>> = Mutex::new(None);
+
+/// When the bytes taken so far have been paid for at the capped rate
+static PAID_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Reads `max_rate_kb` under `[network]`
+pub fn configure(config: &Config) {
+    *RATE.lock().unwrap() = config
+        .get_f64("network.max_rate_kb")
+        .filter(|&kb| kb > 0.0)
+        .map(|kb| kb * 1024.0);
+}
+
+/// Accounts for `bytes` just downloaded, sleeping as long as it takes to
+/// stay under the cap
+pub fn take(bytes: usize) {
+    let Some(rate) = *RATE.lock().unwrap() else {
+        return;
+    };
+    let now = Instant::now();
+    let until = {
+        let mut paid_until = PAID_UNTIL.lock().unwrap();
+        // Time left unused while idle isn't saved up for a burst later
+        let from = paid_until.filter(|&until| until > now).unwrap_or(now);
+        let until = from + Duration::from_secs_f64(bytes as f64 / rate);
+        *paid_until = Some(until);
+        until
+    };
+    thread::sleep(until - now);
+}
@@ -0,0 +1,84 @@
+//! `bench decode`: how fast this build reads tags and decodes each format,
+//! to help choose feature flags and track down underruns
+//! Only local files are measured, and each for at most `MAX_AUDIO` of
+//! audio, so the numbers reflect the decoder rather than the network
+
+use crate::{codecs, tags};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Most audio decoded from one track
+const MAX_AUDIO: Duration = Duration::from_secs(30);
+
+/// Decoding slower than this many times real time leaves little room
+/// on a busy machine
+pub const TIGHT_SPEED: f64 = 20.0;
+
+/// What one track measured
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub format: &'static str,   // Decoder used
+    pub tags: Duration,         // Reading the tags
+    pub first_sample: Duration, // Opening until the first sample came out
+    pub audio: Duration,        // Audio decoded
+    pub decoding: Duration,     // Time it took, first sample included
+}
+
+impl Sample {
+    /// Decoding speed as a multiple of real time
+    pub fn speed(&self) -> f64 {
+        self.audio.as_secs_f64() / self.decoding.as_secs_f64().max(1e-9)
+    }
+}
+
+/// Up to `count` tracks spread evenly over the library, one format after
+/// another so each decoder gets its share
+pub fn pick(paths: &[PathBuf], count: usize) -> Vec<PathBuf> {
+    let mut local: Vec<&PathBuf> = paths
+        .iter()
+        .filter(|path| path.is_file() && codecs::supported(path))
+        .collect();
+    local.sort_by_key(|path| (codecs::format_of(path), path.as_path()));
+    if local.len() <= count {
+        return local.into_iter().cloned().collect();
+    }
+    let step = local.len() as f64 / count as f64;
+    (0..count)
+        .map(|i| local[(i as f64 * step) as usize].clone())
+        .collect()
+}
+
+/// Reads a track's tags and decodes its start, timing both
+pub fn measure(path: &Path) -> Result<Sample, Box<dyn Error>> {
+    let format = codecs::format_of(path).ok_or("Unsupported format")?;
+    let started = Instant::now();
+    tags::read(path)?;
+    let tags = started.elapsed();
+
+    let started = Instant::now();
+    let mut track = codecs::open(path)?;
+    let (channels, rate) = (
+        track.channels().max(1) as u64,
+        track.sample_rate().max(1) as u64,
+    );
+    let limit = MAX_AUDIO.as_secs() * rate * channels;
+    if track.next().is_none() {
+        return Err("No audio in the track".into());
+    }
+    let first_sample = started.elapsed();
+    let mut samples = 1;
+    while samples < limit && track.next().is_some() {
+        samples += 1;
+    }
+    let decoding = started.elapsed();
+    Ok(Sample {
+        format,
+        tags,
+        first_sample,
+        audio: Duration::from_secs_f64(samples as f64 / (rate * channels) as f64),
+        decoding,
+    })
+}
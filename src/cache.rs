@@ -0,0 +1,113 @@
+//! Local copies of remote tracks, so a track heard before plays again
+//! without the network
+//! A track is kept once it has downloaded in full. The least recently
+//! played copies go first when the cache outgrows `max_size_mb` under
+//! `[cache]` (2048 by default, 0 turns caching off)
+
+use crate::config::Config;
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// Cache size when `max_size_mb` isn't set
+const DEFAULT_MAX_SIZE: u64 = 2048 * 1024 * 1024;
+
+/// Largest total size of the cached tracks, in bytes
+static MAX_SIZE: Mutex<u64> = Mutex::new(DEFAULT_MAX_SIZE);
+
+/// Reads `max_size_mb` under `[cache]`
+pub fn configure(config: &Config) {
+    *MAX_SIZE.lock().unwrap() = match config.get_f64("cache.max_size_mb") {
+        Some(mb) if mb >= 0.0 => (mb * 1024.0 * 1024.0) as u64,
+        _ => DEFAULT_MAX_SIZE,
+    };
+}
+
+/// `$XDG_CACHE_HOME/musicplayer/tracks` or `~/.cache/musicplayer/tracks`,
+/// and `%LOCALAPPDATA%\musicplayer\cache` on Windows
+fn cache_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("LOCALAPPDATA")
+            .map(|dir| PathBuf::from(dir).join("musicplayer").join("cache"));
+    }
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("musicplayer").join("tracks"))
+}
+
+/// Where the copy of a remote track goes, named by a hash of its URL
+fn entry(track: &Path) -> Option<PathBuf> {
+    let url = track.to_string_lossy();
+    // FNV-1a, which unlike std's hasher stays the same across releases
+    let hash = url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    let name = match track.extension() {
+        Some(ext) => format!("{:016x}.{}", hash, ext.to_string_lossy()),
+        None => format!("{:016x}", hash),
+    };
+    Some(cache_dir()?.join(name))
+}
+
+/// The cached copy of a remote track, if there is one; marks it as just
+/// played
+pub fn open(track: &Path) -> Option<File> {
+    if *MAX_SIZE.lock().unwrap() == 0 {
+        return None;
+    }
+    let file = File::open(entry(track)?).ok()?;
+    let _ = file.set_modified(SystemTime::now());
+    Some(file)
+}
+
+/// Whether a remote track has a cached copy
+pub fn contains(track: &Path) -> bool {
+    *MAX_SIZE.lock().unwrap() > 0 && entry(track).is_some_and(|path| path.is_file())
+}
+
+/// Keeps a fully downloaded track, making room for it first
+pub fn store(track: &Path, data: &[u8]) -> io::Result<()> {
+    let max_size = *MAX_SIZE.lock().unwrap();
+    if data.len() as u64 > max_size {
+        return Ok(());
+    }
+    let path = entry(track)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory"))?;
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir)?;
+    evict(dir, max_size - data.len() as u64)?;
+    // Written aside first, so a half-written copy is never played
+    let partial = path.with_extension("partial");
+    File::create(&partial)?.write_all(data)?;
+    fs::rename(&partial, &path)
+}
+
+/// Removes the least recently played copies until at most `room` bytes
+/// are left
+fn evict(dir: &Path, room: u64) -> io::Result<()> {
+    let mut copies = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            copies.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+    copies.sort();
+    let mut total: u64 = copies.iter().map(|(_, len, _)| len).sum();
+    for (_, len, path) in copies {
+        if total <= room {
+            break;
+        }
+        fs::remove_file(path)?;
+        total -= len;
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Where command output goes: the terminal, or the reply to a command sent
+//! over the control socket or JSON-RPC
+//! Commands print through `out!` and `outln!`, which write to stdout unless
+//! `to` has given the thread a sink; output from other threads, such as the
+//! prompt, never ends up in a reply
+
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{self, Write},
+};
+
+thread_local! {
+    /// Output caught on this thread while a sink is set
+    static SINK: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+/// `print!` for command output
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::capture::write(format_args!($($arg)*))
+    };
+}
+
+/// `println!` for command output
+macro_rules! outln {
+    () => {
+        $crate::capture::write(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::capture::write(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Runs `f` with what it prints through `out!` going to `out` instead of
+/// stdout
+pub fn to(out: &mut dyn Write, f: impl FnOnce()) -> io::Result<()> {
+    let outer = SINK.with(|sink| sink.replace(Some(Vec::new())));
+    // Put back even if `f` panics, so later output isn't swallowed
+    struct Restore(Option<Option<Vec<u8>>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(outer) = self.0.take() {
+                SINK.with(|sink| sink.replace(outer));
+            }
+        }
+    }
+    let mut restore = Restore(Some(outer));
+    f();
+    let caught = SINK.with(|sink| sink.replace(restore.0.take().flatten()));
+    out.write_all(&caught.unwrap_or_default())
+}
+
+/// Whether output on this thread is being caught for a reply
+pub fn is_caught() -> bool {
+    SINK.with(|sink| sink.borrow().is_some())
+}
+
+/// Writes to this thread's sink, or stdout without one
+pub fn write(args: fmt::Arguments) {
+    SINK.with(|sink| match sink.borrow_mut().as_mut() {
+        Some(buffer) => {
+            let _ = buffer.write_fmt(args);
+        }
+        None => {
+            let _ = io::stdout().write_fmt(args);
+        }
+    });
+}
+
+/// Text without the color codes the messages carry
+pub fn plain(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // CSI sequences end with a letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
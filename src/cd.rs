@@ -0,0 +1,531 @@
+//! Audio CD playback, reading the disc directly through the Linux CD-ROM
+//! ioctls
+//! Tracks join the library as `cdda://<device>/<nn> <title>.cdda`, so
+//! `play`, playlists and the rest treat them like files. Titles come from
+//! MusicBrainz, looked up by the disc's table of contents
+
+use crate::{config::Config, files, json};
+use rodio::Source;
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// Prefix of the paths CD tracks are listed under
+pub const SCHEME: &str = "cdda://";
+
+/// Drive used when `device` under `[cd]` isn't set
+const DEFAULT_DEVICE: &str = "/dev/cdrom";
+
+/// Bytes in one CD sector of audio: 588 stereo frames of 16-bit samples
+const SECTOR: usize = 2352;
+
+/// Sectors per second of audio
+const SECTORS_PER_SECOND: u32 = 75;
+
+/// Sectors before the first track that addresses count from
+const LEAD_IN: u32 = 150;
+
+/// Gap between the audio and data sessions of an enhanced CD
+const SESSION_GAP: u32 = 11400;
+
+/// Drive the `cd` command reads
+static DEVICE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Reads `device` under `[cd]`
+pub fn configure(config: &Config) {
+    *DEVICE.lock().unwrap() = config.get_str("cd.device").map(str::to_string);
+}
+
+fn device() -> String {
+    DEVICE
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| DEFAULT_DEVICE.to_string())
+}
+
+/// Whether a library entry is a track on a CD
+pub fn is_cd(path: &Path) -> bool {
+    path.to_string_lossy().starts_with(SCHEME)
+}
+
+/// A disc's audio tracks, with titles once looked up
+#[derive(Clone, Debug)]
+pub struct Disc {
+    pub device: String,         // Drive the disc is in
+    pub tracks: Vec<DiscTrack>, // Audio tracks, data tracks left out
+    pub artist: Option<String>, // Album artist from MusicBrainz
+    pub album: Option<String>,  // Album title from MusicBrainz
+}
+
+#[derive(Clone, Debug)]
+pub struct DiscTrack {
+    pub number: u8,            // Track number on the disc
+    start: u32,                // First sector
+    end: u32,                  // Sector after the last one
+    pub title: Option<String>, // Title from MusicBrainz
+}
+
+impl DiscTrack {
+    pub fn length(&self) -> Duration {
+        sectors_to_duration(self.end - self.start)
+    }
+}
+
+/// Reads the table of contents of the disc in the configured drive
+pub fn read_disc() -> io::Result<Disc> {
+    Ok(disc_in(&device())?.1)
+}
+
+/// Opens a drive and reads the table of contents of its disc
+fn disc_in(device: &str) -> io::Result<(File, Disc)> {
+    let file = imp::open_device(device)?;
+    imp::check_audio(&file, device)?;
+    let entries = imp::toc(&file)?;
+    let leadout = entries
+        .last()
+        .map(|&(_, start, _)| start)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Empty table of contents"))?;
+    let mut tracks = Vec::new();
+    for (i, &(number, start, audio)) in entries.iter().enumerate() {
+        let Some(&(_, next, next_audio)) = entries.get(i + 1) else {
+            break;
+        };
+        if !audio {
+            continue;
+        }
+        // The data session of an enhanced CD starts after a gap
+        let end = if next_audio || next == leadout {
+            next
+        } else {
+            next.saturating_sub(SESSION_GAP)
+        };
+        tracks.push(DiscTrack {
+            number,
+            start,
+            end,
+            title: None,
+        });
+    }
+    if tracks.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("The disc in {} has no audio tracks", device),
+        ));
+    }
+    let disc = Disc {
+        device: device.to_string(),
+        tracks,
+        artist: None,
+        album: None,
+    };
+    Ok((file, disc))
+}
+
+impl Disc {
+    /// Fills in the album and track titles from MusicBrainz
+    pub fn look_up(&mut self) -> Result<(), String> {
+        // First track, last track, lead-out, then every track's start,
+        // all counted from the start of the lead-in
+        let (first, last) = (&self.tracks[0], &self.tracks[self.tracks.len() - 1]);
+        let mut toc = vec![first.number.to_string(), last.number.to_string()];
+        toc.push((last.end + LEAD_IN).to_string());
+        toc.extend(
+            self.tracks
+                .iter()
+                .map(|track| (track.start + LEAD_IN).to_string()),
+        );
+        let first = first.number;
+        let url = format!(
+            "https://musicbrainz.org/ws/2/discid/-?toc={}&inc=artist-credits+recordings&fmt=json",
+            toc.join("+")
+        );
+        let output = Command::new("curl")
+            .args([
+                "--silent",
+                "--show-error",
+                "--fail",
+                "--location",
+                "--user-agent",
+                concat!("musicplayer/", env!("CARGO_PKG_VERSION")),
+                "--",
+                &url,
+            ])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("Could not run curl: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        let response = json::parse(&String::from_utf8_lossy(&output.stdout))?;
+        let releases = match response.get("releases") {
+            Some(json::Json::Array(releases)) => releases,
+            _ => return Err("MusicBrainz doesn't know this disc".to_string()),
+        };
+        // The first release with a medium of the same length wins
+        for release in releases {
+            let Some(json::Json::Array(media)) = release.get("media") else {
+                continue;
+            };
+            let medium = media.iter().find_map(|medium| match medium.get("tracks") {
+                Some(json::Json::Array(tracks)) if tracks.len() == self.tracks.len() => {
+                    Some(tracks)
+                }
+                _ => None,
+            });
+            let Some(titles) = medium else {
+                continue;
+            };
+            self.album = release
+                .get("title")
+                .and_then(|t| t.as_str())
+                .map(str::to_string);
+            self.artist = match release.get("artist-credit") {
+                Some(json::Json::Array(credits)) => credits
+                    .first()
+                    .and_then(|credit| credit.get("name"))
+                    .and_then(|name| name.as_str())
+                    .map(str::to_string),
+                _ => None,
+            };
+            for track in &mut self.tracks {
+                track.title = titles
+                    .get((track.number - first) as usize)
+                    .and_then(|entry| entry.get("title"))
+                    .and_then(|title| title.as_str())
+                    .map(str::to_string);
+            }
+            return Ok(());
+        }
+        Err("MusicBrainz doesn't know this disc".to_string())
+    }
+
+    /// Library entries for the tracks
+    pub fn track_paths(&self) -> Vec<PathBuf> {
+        self.tracks
+            .iter()
+            .map(|track| {
+                let title = track
+                    .title
+                    .as_deref()
+                    .map_or_else(|| "Track".to_string(), files::sanitize);
+                PathBuf::from(format!(
+                    "{}{}/{:02} {}.cdda",
+                    SCHEME, self.device, track.number, title
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Opens the tray of the configured drive
+pub fn eject() -> io::Result<()> {
+    imp::eject(&imp::open_device(&device())?)
+}
+
+/// Drive and track number of a CD track's library entry
+fn parse(path: &Path) -> Option<(String, u8)> {
+    let text = path.to_string_lossy();
+    let (device, name) = text.strip_prefix(SCHEME)?.rsplit_once('/')?;
+    let number = name.split(' ').next()?.parse().ok()?;
+    Some((device.to_string(), number))
+}
+
+fn sectors_to_duration(sectors: u32) -> Duration {
+    Duration::from_secs_f64(sectors as f64 / SECTORS_PER_SECOND as f64)
+}
+
+/// A CD track being played, read from the disc a second at a time
+pub struct CdTrack {
+    file: File,        // The drive
+    next: u32,         // Next sector to read
+    end: u32,          // Sector after the track's last one
+    length: Duration,  // Length of the whole track
+    samples: Vec<i16>, // Samples read but not played yet
+    at: usize,         // Next sample in `samples`
+}
+
+impl CdTrack {
+    /// Opens a CD track `offset` into it
+    pub fn open(path: &Path, offset: Duration) -> io::Result<Self> {
+        let (device, number) = parse(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a CD track"))?;
+        let (file, disc) = disc_in(&device)?;
+        let track = disc
+            .tracks
+            .iter()
+            .find(|track| track.number == number)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("The disc in {} has no track {}", device, number),
+                )
+            })?;
+        let skip = (offset.as_secs_f64() * SECTORS_PER_SECOND as f64) as u32;
+        Ok(Self {
+            file,
+            next: (track.start + skip).min(track.end),
+            end: track.end,
+            length: track.length(),
+            samples: Vec::new(),
+            at: 0,
+        })
+    }
+
+    /// Reads the next second of audio as 16-bit little-endian stereo,
+    /// None once the track is over
+    pub fn next_block(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.next >= self.end {
+            return Ok(None);
+        }
+        let sectors = SECTORS_PER_SECOND.min(self.end - self.next);
+        let mut bytes = vec![0u8; sectors as usize * SECTOR];
+        // One more try, as a scratch often reads on the second pass
+        imp::read_audio(&self.file, self.next, sectors, &mut bytes)
+            .or_else(|_| imp::read_audio(&self.file, self.next, sectors, &mut bytes))?;
+        self.next += sectors;
+        Ok(Some(bytes))
+    }
+
+    /// Reads the next second of samples; a sector that can't be read is
+    /// played as silence rather than ending the track
+    fn refill(&mut self) -> bool {
+        let start = self.next;
+        let bytes = match self.next_block() {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return false,
+            Err(_) => {
+                let sectors = SECTORS_PER_SECOND.min(self.end - start);
+                self.next = start + sectors;
+                vec![0u8; sectors as usize * SECTOR]
+            }
+        };
+        self.samples = bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        self.at = 0;
+        true
+    }
+}
+
+impl Iterator for CdTrack {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.at == self.samples.len() && !self.refill() {
+            return None;
+        }
+        let sample = self.samples[self.at];
+        self.at += 1;
+        Some(sample as f32 / 32768.0)
+    }
+}
+
+impl Source for CdTrack {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        44100
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.length)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::{
+        fs::{File, OpenOptions},
+        io,
+        os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    };
+
+    // Requests from linux/cdrom.h
+    const CDROMREADTOCHDR: libc::Ioctl = 0x5305;
+    const CDROMREADTOCENTRY: libc::Ioctl = 0x5306;
+    const CDROMEJECT: libc::Ioctl = 0x5309;
+    const CDROMREADAUDIO: libc::Ioctl = 0x530e;
+    const CDROM_DRIVE_STATUS: libc::Ioctl = 0x5326;
+    const CDROM_DISC_STATUS: libc::Ioctl = 0x5327;
+
+    const CDROM_LBA: u8 = 0x01;
+    const CDROM_LEADOUT: u8 = 0xaa;
+    const CDROM_DATA_TRACK: u8 = 0x04;
+    const CDSL_CURRENT: libc::c_int = i32::MAX;
+    const CDS_NO_DISC: libc::c_int = 1;
+    const CDS_TRAY_OPEN: libc::c_int = 2;
+    const CDS_DRIVE_NOT_READY: libc::c_int = 3;
+    const CDS_AUDIO: libc::c_int = 100;
+    const CDS_MIXED: libc::c_int = 105;
+
+    #[repr(C)]
+    struct TocHeader {
+        first: u8, // First track number
+        last: u8,  // Last track number
+    }
+
+    #[repr(C)]
+    struct TocEntry {
+        track: u8,    // Track number, or CDROM_LEADOUT
+        adr_ctrl: u8, // Control bits in the high nibble
+        format: u8,   // Address format wanted, CDROM_LBA here
+        lba: i32,     // First sector
+        datamode: u8, // Unused for audio
+    }
+
+    #[repr(C)]
+    struct ReadAudio {
+        lba: i32,        // First sector to read
+        addr_format: u8, // CDROM_LBA
+        frames: i32,     // Sectors to read
+        buffer: *mut u8, // Room for `frames` sectors
+    }
+
+    /// Opens a drive without waiting for a disc, as status ioctls need
+    pub fn open_device(device: &str) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(device)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", device, e)))
+    }
+
+    /// Fails unless the drive holds a disc with audio on it
+    pub fn check_audio(file: &File, device: &str) -> io::Result<()> {
+        // SAFETY: these requests take the slot number as a plain argument
+        let drive = unsafe { libc::ioctl(file.as_raw_fd(), CDROM_DRIVE_STATUS, CDSL_CURRENT) };
+        if drive < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} isn't a CD drive", device),
+            ));
+        }
+        let problem = match drive {
+            CDS_NO_DISC => Some("No disc"),
+            CDS_TRAY_OPEN => Some("The tray is open"),
+            CDS_DRIVE_NOT_READY => Some("The drive isn't ready"),
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} in {}", problem, device),
+            ));
+        }
+        // SAFETY: as above
+        let disc = unsafe { libc::ioctl(file.as_raw_fd(), CDROM_DISC_STATUS, CDSL_CURRENT) };
+        if disc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if disc != CDS_AUDIO && disc != CDS_MIXED {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The disc in {} isn't an audio CD", device),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Number, first sector and whether it holds audio, for every track
+    /// and then the lead-out
+    pub fn toc(file: &File) -> io::Result<Vec<(u8, u32, bool)>> {
+        let mut header = TocHeader { first: 0, last: 0 };
+        // SAFETY: CDROMREADTOCHDR fills a cdrom_tochdr, laid out as TocHeader
+        if unsafe { libc::ioctl(file.as_raw_fd(), CDROMREADTOCHDR, &mut header) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let numbers = (header.first..=header.last).chain([CDROM_LEADOUT]);
+        let mut entries = Vec::new();
+        for number in numbers {
+            let mut entry = TocEntry {
+                track: number,
+                adr_ctrl: 0,
+                format: CDROM_LBA,
+                lba: 0,
+                datamode: 0,
+            };
+            // SAFETY: CDROMREADTOCENTRY fills a cdrom_tocentry, laid out as
+            // TocEntry with the address union read as its LBA member
+            if unsafe { libc::ioctl(file.as_raw_fd(), CDROMREADTOCENTRY, &mut entry) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let audio = (entry.adr_ctrl >> 4) & CDROM_DATA_TRACK == 0;
+            entries.push((number, entry.lba.max(0) as u32, audio));
+        }
+        Ok(entries)
+    }
+
+    /// Reads `sectors` sectors of audio from `lba` into `buffer`
+    pub fn read_audio(file: &File, lba: u32, sectors: u32, buffer: &mut [u8]) -> io::Result<()> {
+        assert!(buffer.len() >= sectors as usize * super::SECTOR);
+        let mut request = ReadAudio {
+            lba: lba as i32,
+            addr_format: CDROM_LBA,
+            frames: sectors as i32,
+            buffer: buffer.as_mut_ptr(),
+        };
+        // SAFETY: the buffer holds `sectors` whole sectors, checked above
+        if unsafe { libc::ioctl(file.as_raw_fd(), CDROMREADAUDIO, &mut request) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn eject(file: &File) -> io::Result<()> {
+        // SAFETY: CDROMEJECT takes no argument
+        if unsafe { libc::ioctl(file.as_raw_fd(), CDROMEJECT) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::{fs::File, io};
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Audio CDs can only be played on Linux",
+        )
+    }
+
+    pub fn open_device(_device: &str) -> io::Result<File> {
+        Err(unsupported())
+    }
+
+    pub fn check_audio(_file: &File, _device: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn toc(_file: &File) -> io::Result<Vec<(u8, u32, bool)>> {
+        Err(unsupported())
+    }
+
+    pub fn read_audio(
+        _file: &File,
+        _lba: u32,
+        _sectors: u32,
+        _buffer: &mut [u8],
+    ) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn eject(_file: &File) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
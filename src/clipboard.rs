@@ -0,0 +1,38 @@
+//! Reading the system clipboard through the platform's command-line tools
+
+use std::{io, process::Command};
+
+/// Commands that print the clipboard, tried in order
+#[cfg(target_os = "macos")]
+const READERS: &[&[&str]] = &[&["pbpaste"]];
+#[cfg(windows)]
+const READERS: &[&[&str]] = &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]];
+#[cfg(not(any(target_os = "macos", windows)))]
+const READERS: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-o", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--output"],
+];
+
+/// Text on the clipboard
+pub fn read() -> io::Result<String> {
+    for reader in READERS {
+        let Ok(output) = Command::new(reader[0]).args(&reader[1..]).output() else {
+            continue;
+        };
+        if output.status.success() {
+            return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "Could not read the clipboard; install one of: {}",
+            READERS
+                .iter()
+                .map(|reader| reader[0])
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    ))
+}
@@ -0,0 +1,184 @@
+//! Formats this build can play and opening tracks in them
+//! WAV and MP3 are always built in; the others follow cargo features, so
+//! the library only lists files the player can actually decode
+//! `--include-ext` adds extensions to the list, e.g. for formats the mpv or
+//! GStreamer backend decodes, or files of a known format named oddly
+
+use crate::{
+    cache, cd, icy,
+    readahead::{self, ReadAhead},
+    remote::{self, Download},
+};
+use rodio::{Decoder, Source};
+use std::{error::Error, fs::File, io::BufReader, path::Path, sync::Mutex, time::Duration};
+
+/// Format name, whether it is compiled in, and its file extensions
+const FORMATS: &[(&str, bool, &[&str])] = &[
+    ("wav", true, &["wav", "wave"]),
+    ("mp3", true, &["mp3"]),
+    ("flac", cfg!(feature = "flac"), &["flac"]),
+    ("vorbis", cfg!(feature = "vorbis"), &["ogg"]),
+    ("aiff", cfg!(feature = "aiff"), &["aif", "aiff", "aifc"]),
+    ("dsd", cfg!(feature = "dsd"), &["dsf", "dff"]),
+];
+
+/// Extensions listed on top of the compiled-in formats, lowercase
+static EXTRA: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// A decoded track
+pub type Track = Box<dyn Source<Item = f32> + Send>;
+
+/// Names of the formats compiled in
+pub fn compiled_in() -> Vec<&'static str> {
+    FORMATS
+        .iter()
+        .filter(|(_, built, _)| *built)
+        .map(|(name, _, _)| *name)
+        .collect()
+}
+
+/// Also lists files with these extensions, given with or without the dot
+pub fn include_extensions(extensions: &[String]) {
+    let extensions = extensions
+        .iter()
+        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty());
+    EXTRA.lock().unwrap().extend(extensions);
+}
+
+/// Whether a file's extension belongs to a compiled-in format, or was
+/// included with `--include-ext`
+pub fn supported(path: &Path) -> bool {
+    format_of(path).is_some() || {
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        ext.is_some_and(|ext| EXTRA.lock().unwrap().contains(&ext))
+    }
+}
+
+/// Name of the compiled-in format a file is decoded as
+pub fn format_of(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    FORMATS
+        .iter()
+        .find(|(_, built, exts)| *built && exts.contains(&ext.as_str()))
+        .map(|(name, _, _)| *name)
+}
+
+/// Opens a track for decoding
+pub fn open(path: &Path) -> Result<Track, Box<dyn Error>> {
+    if cd::is_cd(path) {
+        return Ok(Box::new(cd::CdTrack::open(path, Duration::ZERO)?));
+    }
+    if icy::is_stream(path) {
+        let stream = icy::Stream::connect(path)?;
+        return Ok(Box::new(
+            Decoder::new(BufReader::new(stream))?.convert_samples::<f32>(),
+        ));
+    }
+    if remote::is_remote(path) {
+        if let Some(copy) = cache::open(path) {
+            return Ok(Box::new(
+                Decoder::new(BufReader::new(copy))?.convert_samples::<f32>(),
+            ));
+        }
+        let download = Download::start(path)?;
+        return Ok(Box::new(
+            Decoder::new(BufReader::new(download))?.convert_samples::<f32>(),
+        ));
+    }
+    let mut file = File::open(path)?;
+    match format_of(path) {
+        #[cfg(feature = "aiff")]
+        Some("aiff") => Ok(Box::new(crate::aiff::Aiff::new(file)?)),
+        #[cfg(feature = "dsd")]
+        Some("dsd") => Ok(Box::new(crate::dsd::Dsd::new(file)?)),
+        // Tracks on a network share are read ahead in the background
+        _ if readahead::needed(path, &mut file) => Ok(Box::new(
+            Decoder::new(BufReader::new(ReadAhead::new(file)?))?.convert_samples::<f32>(),
+        )),
+        _ => Ok(Box::new(
+            Decoder::new(BufReader::new(file))?.convert_samples::<f32>(),
+        )),
+    }
+}
+
+/// Length of a track as its decoder reports it, without decoding it;
+/// stations are never opened for it, as they have none
+pub fn length(path: &Path) -> Option<Duration> {
+    if icy::is_stream(path) {
+        return None;
+    }
+    open(path).ok()?.total_duration()
+}
+
+/// Opens a track and skips to `offset` into it
+pub fn open_at(path: &Path, offset: Duration) -> Result<Track, Box<dyn Error>> {
+    // Reading a disc up to the offset would take a while
+    if cd::is_cd(path) {
+        return Ok(Box::new(cd::CdTrack::open(path, offset)?));
+    }
+    let mut track = open(path)?;
+    // A station is joined wherever it is
+    if !icy::is_stream(path) {
+        skip_to(&mut track, offset);
+    }
+    Ok(track)
+}
+
+/// Skips whole frames up to the one nearest `offset`; rodio's
+/// skip_duration drifts on long skips and can stop mid-frame, swapping
+/// the channels
+fn skip_to(track: &mut Track, offset: Duration) {
+    let mut left = offset.as_secs_f64(); // Seconds still to skip
+    loop {
+        let channels = track.channels().max(1) as usize;
+        let rate = track.sample_rate().max(1);
+        let frames = (left * rate as f64).round() as usize;
+        if frames == 0 {
+            return;
+        }
+        // The rate or layout can only change where a decoded block ends
+        let samples = match track.current_frame_len() {
+            Some(0) => return,
+            Some(len) => (frames * channels).min(len / channels * channels),
+            None => frames * channels,
+        };
+        if samples == 0 {
+            return;
+        }
+        for _ in 0..samples {
+            if track.next().is_none() {
+                return;
+            }
+        }
+        left -= (samples / channels) as f64 / rate as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn included_extensions_ignore_dots_and_case() {
+        // Extensions of their own, since the list is shared by every test
+        let given = [".XyzA", " xyzb ", "", "."].map(String::from);
+        include_extensions(&given);
+        assert!(supported(Path::new("song.xyza")));
+        assert!(supported(Path::new("SONG.XYZA")));
+        assert!(supported(Path::new("dir/song.xYzB")));
+        assert!(!supported(Path::new("song.xyzc")));
+        assert!(!supported(Path::new("song")));
+        assert_eq!(format_of(Path::new("song.xyza")), None);
+    }
+
+    #[test]
+    fn compiled_in_formats_match_any_case() {
+        assert_eq!(format_of(Path::new("a.WAV")), Some("wav"));
+        assert_eq!(format_of(Path::new("a.wave")), Some("wav"));
+        assert!(supported(Path::new("a.Mp3")));
+        assert!(!supported(Path::new("cover.jpg")));
+    }
+}
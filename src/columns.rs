@@ -0,0 +1,154 @@
+//! Columns shown by `list`, chosen with `columns` under `[list]` or with
+//! `list --columns`, e.g. `index,title:30,artist:20,duration`
+//! A width after the colon overrides the column's default one
+
+/// What a column shows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Index,    // Number `play` takes
+    Track,    // "Artist – Title – Album" from the tags, or the file
+    Title,    // Title tag, or the file name without its extension
+    Artist,   // Artist tag
+    Album,    // Album tag
+    Duration, // Track length
+    Plays,    // Times the track was started
+    Rating,   // Stars
+    File,     // File name, with its folders below the library root
+    Codec,    // Format the track is decoded as
+    Rate,     // Sample rate
+    Bitrate,  // Average bitrate, from the file size and length
+    Size,     // File size
+}
+
+/// A column and how wide it is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Column {
+    pub kind: Kind,
+    pub width: usize, // Characters, padding included
+}
+
+/// Columns shown when none are configured
+pub const DEFAULT: &str = "index,duration,track";
+
+/// Columns shown by `list --detail`, for auditing a library's quality
+pub const DETAIL: &str = "index,file:40,codec,rate,bitrate,size,duration";
+
+/// Every column name, for error messages
+const NAMES: &str =
+    "index, track, title, artist, album, duration, plays, rating, file, codec, rate, bitrate, size";
+
+impl Kind {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "index" => Kind::Index,
+            "track" => Kind::Track,
+            "title" => Kind::Title,
+            "artist" => Kind::Artist,
+            "album" => Kind::Album,
+            "duration" | "length" => Kind::Duration,
+            "plays" => Kind::Plays,
+            "rating" => Kind::Rating,
+            "file" | "filename" => Kind::File,
+            "codec" => Kind::Codec,
+            "rate" => Kind::Rate,
+            "bitrate" => Kind::Bitrate,
+            "size" => Kind::Size,
+            _ => return None,
+        })
+    }
+
+    pub fn header(&self) -> &'static str {
+        match self {
+            Kind::Index => "Index",
+            Kind::Track => "Track",
+            Kind::Title => "Title",
+            Kind::Artist => "Artist",
+            Kind::Album => "Album",
+            Kind::Duration => "Length",
+            Kind::Plays => "Plays",
+            Kind::Rating => "Rating",
+            Kind::File => "Filename",
+            Kind::Codec => "Codec",
+            Kind::Rate => "Rate",
+            Kind::Bitrate => "Bitrate",
+            Kind::Size => "Size",
+        }
+    }
+
+    fn default_width(&self) -> usize {
+        match self {
+            Kind::Index | Kind::Duration | Kind::Plays | Kind::Codec => 7,
+            Kind::Rate | Kind::Size => 10,
+            Kind::Bitrate => 11,
+            Kind::Rating => 8,
+            Kind::Track => 72,
+            Kind::Title | Kind::File => 40,
+            Kind::Artist | Kind::Album => 24,
+        }
+    }
+
+    /// Whether showing the column means reading the file's tags
+    pub fn needs_tags(&self) -> bool {
+        matches!(self, Kind::Track | Kind::Title | Kind::Artist | Kind::Album)
+    }
+}
+
+/// Sample rate as shown, e.g. "44.1 kHz"
+pub fn format_rate(rate: u32) -> String {
+    format!("{} kHz", rate as f64 / 1000.0)
+}
+
+/// File size as shown, e.g. "4.2 MB"
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=999_999 => format!("{:.0} kB", bytes as f64 / 1e3),
+        1_000_000..=999_999_999 => format!("{:.1} MB", bytes as f64 / 1e6),
+        _ => format!("{:.2} GB", bytes as f64 / 1e9),
+    }
+}
+
+/// Parses column names with optional widths, given as one comma-separated
+/// string or as separate items
+pub fn parse<S: AsRef<str>>(items: &[S]) -> Result<Vec<Column>, String> {
+    let mut columns = Vec::new();
+    for item in items.iter().flat_map(|item| item.as_ref().split(',')) {
+        let item = item.trim().to_lowercase();
+        if item.is_empty() {
+            continue;
+        }
+        let (name, width) = match item.split_once(':') {
+            Some((name, width)) => (name, Some(width)),
+            None => (item.as_str(), None),
+        };
+        let kind = Kind::from_name(name)
+            .ok_or_else(|| format!("Unknown column `{}`; use {}", name, NAMES))?;
+        let width = match width {
+            Some(width) => width
+                .parse::<usize>()
+                .ok()
+                .filter(|&width| width > 0)
+                .ok_or_else(|| format!("Invalid width for column `{}`", name))?,
+            None => kind.default_width(),
+        };
+        columns.push(Column { kind, width });
+    }
+    if columns.is_empty() {
+        return Err("No columns given".to_string());
+    }
+    Ok(columns)
+}
+
+/// Lays out one row, cutting long values short; the last column isn't
+/// padded so rows don't end in spaces
+pub fn row(columns: &[Column], values: &[String]) -> String {
+    let mut line = String::new();
+    for (i, (column, value)) in columns.iter().zip(values).enumerate() {
+        if i + 1 == columns.len() {
+            line.push_str(value);
+        } else {
+            let value = crate::control::truncate(value, column.width.saturating_sub(1));
+            line.push_str(&format!("{:<width$} ", value, width = column.width - 1));
+        }
+    }
+    line
+}
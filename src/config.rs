@@ -0,0 +1,327 @@
+//! Configuration file support
+//! Reads a small TOML subset: `[section]` headers and `key = value` lines
+//! holding strings, numbers, booleans, or single-line arrays of those
+
+use crate::PlayerEvent;
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Name of the config file inside the config directory
+const CONFIG_FILE: &str = "config.toml";
+
+/// Sections starting with this hold named profiles
+const PROFILE_PREFIX: &str = "profile.";
+
+/// How often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A single configuration value
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// Parsed configuration, keyed by `section.key`
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    values: HashMap<String, Value>,
+}
+
+/// Directory holding the config file and other user data
+/// `$XDG_CONFIG_HOME/musicplayer` or `~/.config/musicplayer`, and
+/// `%APPDATA%\musicplayer` on Windows
+pub fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("musicplayer"));
+    }
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("musicplayer"))
+}
+
+/// File the config is read from: the explicit one, else the default
+/// location, whether or not it exists yet
+pub fn file_path(path: Option<&str>) -> Option<PathBuf> {
+    match path {
+        Some(path) => Some(PathBuf::from(path)),
+        None => config_dir().map(|dir| dir.join(CONFIG_FILE)),
+    }
+}
+
+/// Watches the config file on a background thread and sends a
+/// `PlayerEvent::Reload` whenever it is saved, created or removed
+pub fn spawn_watcher(path: PathBuf, events: Sender<PlayerEvent>) {
+    thread::spawn(move || {
+        let modified =
+            |path: &Path| -> Option<SystemTime> { fs::metadata(path).ok()?.modified().ok() };
+        let mut current = modified(&path);
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let seen = modified(&path);
+            if seen != current {
+                current = seen;
+                if events.send(PlayerEvent::Reload).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+impl Config {
+    /// Loads the config from an explicit path, which must exist, or from
+    /// the default location, which may be missing
+    pub fn load(path: Option<&str>) -> io::Result<Self> {
+        match path {
+            Some(path) => Self::from_file(Path::new(path)),
+            None => match config_dir().map(|dir| dir.join(CONFIG_FILE)) {
+                Some(path) if path.is_file() => Self::from_file(&path),
+                _ => Ok(Self::default()),
+            },
+        }
+    }
+
+    /// Loads the config like `load`, then applies the named profile
+    pub fn load_profile(path: Option<&str>, profile: Option<&str>) -> io::Result<Self> {
+        let config = Self::load(path)?;
+        match profile {
+            Some(name) => config
+                .with_profile(name)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e)),
+            None => Ok(config),
+        }
+    }
+
+    /// Overlays the keys of `[profile.NAME]`, e.g. `[profile.work.output]`
+    /// overrides `[output]`, keeping what the profile doesn't set
+    pub fn with_profile(mut self, name: &str) -> Result<Self, String> {
+        let prefix = format!("{}{}.", PROFILE_PREFIX, name);
+        let overrides: Vec<(String, Value)> = self
+            .values
+            .iter()
+            .filter_map(|(key, value)| {
+                let key = key.strip_prefix(&prefix)?;
+                // Keys right under `[profile.NAME]` belong to `[player]`
+                let key = if key.contains('.') {
+                    key.to_string()
+                } else {
+                    format!("player.{}", key)
+                };
+                Some((key, value.clone()))
+            })
+            .collect();
+        if overrides.is_empty() {
+            return Err(match self.profiles().as_slice() {
+                [] => format!("No profile `{}`: the config defines none", name),
+                names => format!("No profile `{}`; use {}", name, names.join(", ")),
+            });
+        }
+        self.values.extend(overrides);
+        Ok(self)
+    }
+
+    /// Names of the profiles the config defines, sorted
+    fn profiles(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .values
+            .keys()
+            .filter_map(|key| key.strip_prefix(PROFILE_PREFIX)?.split('.').next())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Reads and parses a config file
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| {
+            io::Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+        })
+    }
+
+    /// Parses config text, reporting the first bad line
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut values = HashMap::new();
+        let mut section = String::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", number + 1))?;
+            let value =
+                parse_value(value.trim()).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            let key = if section.is_empty() {
+                key.trim().to_string()
+            } else {
+                format!("{}.{}", section, key.trim())
+            };
+            values.insert(key, value);
+        }
+        Ok(Self { values })
+    }
+
+    /// Raw value for a `section.key`
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key) {
+            Some(Value::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key) {
+            Some(Value::Bool(b)) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        match self.get(key) {
+            Some(Value::Number(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Number items of an array value; a lone number counts as one item
+    pub fn get_numbers(&self, key: &str) -> Vec<f64> {
+        match self.get(key) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .filter_map(|item| match item {
+                    Value::Number(n) => Some(*n),
+                    _ => None,
+                })
+                .collect(),
+            Some(Value::Number(n)) => vec![*n],
+            _ => Vec::new(),
+        }
+    }
+
+    /// String items of an array value; a lone string counts as one item
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .filter_map(|item| match item {
+                    Value::String(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .collect(),
+            Some(Value::String(s)) => vec![s.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Drops a trailing `#` comment, ignoring `#` inside quoted strings
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..i],
+            None => {}
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return split_items(inner)
+            .into_iter()
+            .map(|item| parse_value(item.trim()))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array);
+    }
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return Ok(Value::String(unescape(inner)));
+    }
+    if let Some(inner) = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        return Ok(Value::String(inner.to_string()));
+    }
+    match text {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => text
+            .replace('_', "")
+            .parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid value `{}`", text)),
+    }
+}
+
+/// Splits array contents on commas outside quoted strings
+fn split_items(inner: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ',' => {
+                items.push(&inner[start..i]);
+                start = i + 1;
+            }
+            None => {}
+        }
+    }
+    items.push(&inner[start..]);
+    // Tolerate a trailing comma and empty arrays
+    items.retain(|item| !item.trim().is_empty());
+    items
+}
+
+/// Resolves the escapes allowed in basic strings
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
@@ -0,0 +1,217 @@
+//! Control socket for talking to a running player instance
+//! Clients send one command per line and receive zero or more reply lines
+//! terminated by `OK` or `ERR <message>`
+//! When authentication is enabled, clients first send `auth <token>`
+
+use crate::{
+    auth::{self, Scope, Tokens},
+    PlayerEvent,
+};
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+/// Address the control socket listens on unless overridden
+pub const DEFAULT_CONTROL_ADDR: &str = "127.0.0.1:6601";
+
+/// How long clients wait for the player before giving up
+const CLIENT_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// How long a one-shot command may take, e.g. a rescan
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Binds the control socket and serves clients on background threads
+/// Each received line is forwarded to the main loop as a `PlayerEvent::Remote`
+pub fn spawn_server(addr: &str, events: Sender<PlayerEvent>, tokens: Tokens) -> io::Result<()> {
+    serve(TcpListener::bind(addr)?, events, tokens);
+    Ok(())
+}
+
+/// Serves clients on an already listening socket, e.g. one passed in by
+/// systemd socket activation
+pub fn serve(listener: TcpListener, events: Sender<PlayerEvent>, tokens: Tokens) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let events = events.clone();
+            let tokens = tokens.clone();
+            thread::spawn(move || {
+                // A failed client connection only affects that client
+                let _ = serve_client(stream, events, tokens);
+            });
+        }
+    });
+}
+
+/// Handles a single client connection until it disconnects
+fn serve_client(stream: TcpStream, events: Sender<PlayerEvent>, tokens: Tokens) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let reader = BufReader::new(stream);
+    let mut scope = tokens.initial_scope();
+
+    for line in reader.lines() {
+        let line = line?;
+        let command = line.split_whitespace().next().unwrap_or("").to_lowercase();
+        if command.is_empty() {
+            continue;
+        }
+
+        // Authentication is handled per connection, never by the player
+        if command == "auth" {
+            let token = line.split_whitespace().nth(1).unwrap_or("");
+            let reply = match tokens.check(token) {
+                Some(granted) => {
+                    scope = Some(granted);
+                    "OK\n"
+                }
+                None => "ERR invalid token\n",
+            };
+            writer.write_all(reply.as_bytes())?;
+            continue;
+        }
+        let denied = match scope {
+            None => Some("ERR authentication required\n"),
+            Some(Scope::Read) if !auth::is_read_only(&command) => Some("ERR permission denied\n"),
+            Some(_) => None,
+        };
+        if let Some(reply) = denied {
+            writer.write_all(reply.as_bytes())?;
+            continue;
+        }
+
+        let (reply_tx, reply_rx) = mpsc::channel();
+        if events.send(PlayerEvent::Remote(line, reply_tx)).is_err() {
+            break;
+        }
+        let reply = reply_rx
+            .recv()
+            .unwrap_or_else(|_| "ERR player unavailable\n".to_string());
+        writer.write_all(reply.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Sends one command to a running instance and returns its reply lines
+/// An `ERR` reply is turned into an `io::Error` carrying the message
+pub fn send_command(addr: &str, token: Option<&str>, command: &str) -> io::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    exchange(addr, token, command, CLIENT_TIMEOUT, |line| {
+        lines.push(line.to_string())
+    })?;
+    Ok(lines)
+}
+
+/// Runs a command line on a running instance, handing each reply line to
+/// `each` as it arrives, for `musicplayer <command>`
+pub fn run_command(
+    addr: &str,
+    token: Option<&str>,
+    command: &str,
+    each: impl FnMut(&str),
+) -> io::Result<()> {
+    exchange(addr, token, command, COMMAND_TIMEOUT, each)
+}
+
+fn exchange(
+    addr: &str,
+    token: Option<&str>,
+    command: &str,
+    timeout: Duration,
+    mut each: impl FnMut(&str),
+) -> io::Result<()> {
+    let socket_addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "Invalid control address"))?;
+    let mut stream = TcpStream::connect_timeout(&socket_addr, CLIENT_TIMEOUT)?;
+    stream.set_read_timeout(Some(timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    if let Some(token) = token {
+        writeln!(stream, "auth {}", token)?;
+        read_reply(&mut reader, |_| {})?;
+    }
+    writeln!(stream, "{}", command)?;
+    read_reply(&mut reader, &mut each)
+}
+
+/// Reads reply lines up to the terminating `OK` or `ERR`
+fn read_reply(reader: &mut impl BufRead, mut each: impl FnMut(&str)) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line == "OK" {
+            return Ok(());
+        }
+        if let Some(message) = line.strip_prefix("ERR ") {
+            return Err(io::Error::other(message.to_string()));
+        }
+        each(&line);
+    }
+    Err(io::Error::new(
+        ErrorKind::UnexpectedEof,
+        "Connection closed",
+    ))
+}
+
+/// Prints a compact now-playing segment for tmux's `status-right`
+/// Prints nothing when no instance is running or nothing is playing, so
+/// the status line simply stays empty
+pub fn print_tmux_status(addr: &str, token: Option<&str>, max_width: usize) {
+    let Ok(lines) = send_command(addr, token, "status") else {
+        return;
+    };
+
+    let field = |key: &str| {
+        lines
+            .iter()
+            .find_map(|line| line.strip_prefix(key).and_then(|v| v.strip_prefix(": ")))
+            .unwrap_or("")
+    };
+    let icon = match field("state") {
+        "playing" => "▶",
+        "paused" => "⏸",
+        _ => return,
+    };
+    let title = std::path::Path::new(field("file"))
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let elapsed = field("elapsed").parse::<u64>().unwrap_or(0);
+    // The player only sends the time left when it is set to count down
+    let (sign, time) = match field("remaining").parse::<u64>() {
+        Ok(remaining) => ("-", remaining),
+        Err(_) => ("", elapsed),
+    };
+
+    let segment = format!(
+        "{} {} {}{}:{:02}",
+        icon,
+        truncate(&title, max_width),
+        sign,
+        time / 60,
+        time % 60
+    );
+    println!("{}", tmux_escape(&segment));
+}
+
+/// Shortens text to at most `max_width` characters, ending with an ellipsis
+pub fn truncate(text: &str, max_width: usize) -> String {
+    if text.chars().count() <= max_width {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(max_width.saturating_sub(1)).collect();
+    short.push('…');
+    short
+}
+
+/// Escapes text so tmux prints it literally: `#` starts format sequences
+/// and control characters would break the status line
+fn tmux_escape(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .replace('#', "##")
+}
@@ -0,0 +1,66 @@
+//! Call awareness for PulseAudio and PipeWire
+//! Sound servers cork music streams while a call is running, but our ALSA
+//! stream never sees that request, so we watch for streams with a
+//! communication role through `pactl` instead
+
+use crate::PlayerEvent;
+use std::{process::Command, sync::mpsc::Sender, thread, time::Duration};
+
+/// How often the sound server's streams are checked
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What to do with playback while a call is active
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CallBehavior {
+    Pause,     // Pause and resume afterwards
+    Duck(f32), // Lower the volume by this factor
+    Ignore,    // Keep playing
+}
+
+impl CallBehavior {
+    /// Reads `on_call = "pause" | "duck" | "ignore"` and `duck_volume`
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        match config.get_str("output.on_call") {
+            Some("duck") => {
+                let factor = config.get_f64("output.duck_volume").unwrap_or(0.3);
+                CallBehavior::Duck(factor.clamp(0.0, 1.0) as f32)
+            }
+            Some("ignore") => CallBehavior::Ignore,
+            _ => CallBehavior::Pause,
+        }
+    }
+}
+
+/// Whether any playback stream currently has a phone/communication role
+/// Returns None when `pactl` isn't available
+fn call_active() -> Option<bool> {
+    let output = Command::new("pactl")
+        .args(["list", "sink-inputs"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    Some(text.lines().any(|line| {
+        let line = line.trim();
+        line == "media.role = \"phone\"" || line == "media.role = \"communication\""
+    }))
+}
+
+/// Polls the sound server and sends `PlayerEvent::Call` when a call starts
+/// or ends; gives up quietly on systems without `pactl`
+pub fn spawn_watcher(events: Sender<PlayerEvent>) {
+    thread::spawn(move || {
+        let mut active = false;
+        while let Some(now_active) = call_active() {
+            if now_active != active {
+                active = now_active;
+                if events.send(PlayerEvent::Call(active)).is_err() {
+                    break;
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
@@ -0,0 +1,35 @@
+//! Output device monitoring
+//! Polls the system default output device so the player can pause when
+//! headphones are unplugged or a Bluetooth device disconnects
+
+use crate::PlayerEvent;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use std::{sync::mpsc::Sender, thread, time::Duration};
+
+/// How often the default output device is checked
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Name of the current default output device, if there is one
+pub fn default_output_name() -> Option<String> {
+    rodio::cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.name().ok())
+}
+
+/// Watches the default output device on a background thread and sends a
+/// `PlayerEvent::DeviceChanged` whenever it changes or disappears
+pub fn spawn_watcher(initial: Option<String>, events: Sender<PlayerEvent>) {
+    thread::spawn(move || {
+        let mut current = initial;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let device = default_output_name();
+            if device != current {
+                current = device.clone();
+                if events.send(PlayerEvent::DeviceChanged(device)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,264 @@
+//! Output straight to a cpal stream, bypassing rodio's sink and its
+//! stream, so the buffer size and sample format can be chosen
+//! Tracks are mixed here, which also lets us meter what goes out
+
+use rodio::{
+    cpal::{
+        self,
+        traits::{DeviceTrait, StreamTrait},
+        BufferSize, SampleFormat, StreamConfig, SupportedBufferSize,
+    },
+    source::UniformSourceIterator,
+    Source,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Tracks being played, shared with the audio callback
+#[derive(Default)]
+struct Mixer {
+    sources: Mutex<Vec<Box<dyn Iterator<Item = f32> + Send>>>, // Already at the stream's format
+    paused: AtomicBool,
+    volume: AtomicU32, // f32 bits
+    peak: AtomicU32,   // f32 bits, loudest sample of the last buffer
+}
+
+impl Mixer {
+    /// Fills one buffer with the sum of every track, dropping the ones
+    /// that ran out
+    fn fill(&self, out: &mut [f32]) {
+        out.fill(0.0);
+        if !self.paused.load(Ordering::Relaxed) {
+            let mut sources = self.sources.lock().unwrap();
+            sources.retain_mut(|source| {
+                for sample in out.iter_mut() {
+                    match source.next() {
+                        Some(value) => *sample += value,
+                        None => return false,
+                    }
+                }
+                true
+            });
+        }
+        let volume = f32::from_bits(self.volume.load(Ordering::Relaxed));
+        let mut peak = 0.0f32;
+        for sample in out.iter_mut() {
+            *sample *= volume;
+            peak = peak.max(sample.abs());
+        }
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// How the direct stream is opened; unset values are the device's default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Settings {
+    pub frames: Option<u32>,    // Frames per buffer
+    pub bit_depth: Option<u16>, // 16 for integer or 32 for float samples
+    pub dither: bool,           // Dither when sending 16-bit samples
+}
+
+impl Settings {
+    /// Whether anything asks for the direct stream
+    pub fn wanted(&self) -> bool {
+        self.frames.is_some() || self.bit_depth.is_some()
+    }
+}
+
+/// An open stream and the tracks mixed into it
+pub struct Direct {
+    _stream: cpal::Stream, // Stops when dropped
+    mixer: Arc<Mixer>,
+    channels: u16,
+    sample_rate: u32,
+    format: SampleFormat,
+    settings: Settings, // What the stream was opened with
+}
+
+impl Direct {
+    /// Opens `device` with the given buffer size and sample format
+    pub fn new(device: &cpal::Device, settings: Settings) -> Result<Self, String> {
+        let default = device.default_output_config().map_err(|e| e.to_string())?;
+        let supported = match settings.bit_depth {
+            None => default,
+            Some(bits) => {
+                // cpal has no 24-bit sample type yet
+                let format = match bits {
+                    16 => SampleFormat::I16,
+                    32 => SampleFormat::F32,
+                    other => {
+                        return Err(format!(
+                            "{}-bit output isn't supported; use 16 or 32",
+                            other
+                        ))
+                    }
+                };
+                device
+                    .supported_output_configs()
+                    .map_err(|e| e.to_string())?
+                    .find(|range| {
+                        range.sample_format() == format
+                            && range.channels() == default.channels()
+                            && range.min_sample_rate() <= default.sample_rate()
+                            && default.sample_rate() <= range.max_sample_rate()
+                    })
+                    .map(|range| range.with_sample_rate(default.sample_rate()))
+                    .ok_or_else(|| format!("The device doesn't accept {}-bit output", bits))?
+            }
+        };
+        if let (Some(frames), SupportedBufferSize::Range { min, max }) =
+            (settings.frames, supported.buffer_size())
+        {
+            if !(*min..=*max).contains(&frames) {
+                return Err(format!(
+                    "Buffer size {} is outside what the device supports ({} to {} frames)",
+                    frames, min, max
+                ));
+            }
+        }
+        let config = StreamConfig {
+            channels: supported.channels(),
+            sample_rate: supported.sample_rate(),
+            buffer_size: settings
+                .frames
+                .map_or(BufferSize::Default, BufferSize::Fixed),
+        };
+        let mixer = Arc::new(Mixer {
+            volume: AtomicU32::new(1.0f32.to_bits()),
+            ..Mixer::default()
+        });
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build::<f32>(device, &config, &mixer, false),
+            SampleFormat::I16 => build::<i16>(device, &config, &mixer, settings.dither),
+            SampleFormat::U16 => build::<u16>(device, &config, &mixer, settings.dither),
+        }
+        .map_err(|e| format!("Could not open the output stream: {}", e))?;
+        stream.play().map_err(|e| e.to_string())?;
+        Ok(Self {
+            _stream: stream,
+            mixer,
+            channels: config.channels,
+            sample_rate: config.sample_rate.0,
+            format: supported.sample_format(),
+            settings,
+        })
+    }
+
+    /// Adds a track to the mix, converted to the stream's format
+    pub fn append<S>(&self, source: S)
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        let source: UniformSourceIterator<S, f32> =
+            UniformSourceIterator::new(source, self.channels, self.sample_rate);
+        self.mixer.sources.lock().unwrap().push(Box::new(source));
+    }
+
+    /// Drops every track
+    pub fn clear(&self) {
+        self.mixer.sources.lock().unwrap().clear();
+    }
+
+    pub fn empty(&self) -> bool {
+        self.mixer.sources.lock().unwrap().is_empty()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.mixer.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.mixer.volume.load(Ordering::Relaxed))
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.mixer.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Loudest sample of the last buffer sent to the device, 1.0 being
+    /// full scale
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.mixer.peak.load(Ordering::Relaxed))
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// Time one buffer takes to play, when its size was chosen
+    pub fn latency_ms(&self) -> Option<f64> {
+        let frames = self.settings.frames?;
+        Some(frames as f64 * 1000.0 / self.sample_rate as f64)
+    }
+
+    /// Sample format sent to the device, e.g. "16-bit, dithered"
+    pub fn format_name(&self) -> &'static str {
+        match self.format {
+            SampleFormat::I16 | SampleFormat::U16 if self.settings.dither => "16-bit, dithered",
+            SampleFormat::I16 | SampleFormat::U16 => "16-bit",
+            SampleFormat::F32 => "32-bit float",
+        }
+    }
+}
+
+/// Builds the stream for the device's sample type, mixing in f32
+/// Dithering is the last step, after volume, right before rounding
+fn build<T: cpal::Sample>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mixer: &Arc<Mixer>,
+    dither: bool,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let mixer = Arc::clone(mixer);
+    let mut mixed: Vec<f32> = Vec::new();
+    let mut dither = dither.then(Dither::new);
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            mixed.resize(data.len(), 0.0);
+            mixer.fill(&mut mixed);
+            if let Some(dither) = &mut dither {
+                mixed
+                    .iter_mut()
+                    .for_each(|sample| *sample = dither.apply(*sample));
+            }
+            for (out, sample) in data.iter_mut().zip(&mixed) {
+                *out = T::from(sample);
+            }
+        },
+        |e| eprintln!("Audio stream error: {}", e),
+    )
+}
+
+/// TPDF dither: triangular noise one 16-bit step wide each way, which
+/// turns rounding distortion on quiet passages into a steady noise floor
+struct Dither {
+    state: u32, // xorshift state, never zero
+}
+
+impl Dither {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        Self { state: seed | 1 }
+    }
+
+    /// Uniform value in 0.0..1.0
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn apply(&mut self, sample: f32) -> f32 {
+        let noise = (self.uniform() - self.uniform()) / 32768.0;
+        (sample + noise).clamp(-1.0, 1.0)
+    }
+}
@@ -0,0 +1,439 @@
+//! `musicplayer doctor`: checks the setup from the config to the speakers
+//! and says what to do about anything wrong
+//! Every check prints one PASS, WARN or FAIL line; the exit status is 1
+//! when anything failed
+
+use crate::{config::Config, devices, output, playlist, remote, scan, session, stats};
+use clap::ArgMatches;
+use colored::*;
+use rodio::{Decoder, Source};
+use std::{
+    env, fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+/// Length of the test tone
+const TONE: Duration = Duration::from_millis(400);
+
+/// State files, each line a number and at least this many more
+/// tab-separated fields, the path last
+const TABLES: &[(&str, usize)] = &[("stats", 2), ("durations", 2), ("speeds", 1)];
+
+/// Outcome of one check
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Pass, // Works
+    Warn, // Works, but something is off
+    Fail, // Broken
+}
+
+/// Collects the results as they are printed
+#[derive(Default)]
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn line(&mut self, status: Status, check: &str, detail: &str) {
+        let label = match status {
+            Status::Pass => "PASS".green(),
+            Status::Warn => "WARN".yellow(),
+            Status::Fail => {
+                self.failures += 1;
+                "FAIL".red()
+            }
+        };
+        println!("{} {}: {}", label.bold(), check.bold(), detail);
+    }
+}
+
+/// Runs every check; false when any failed
+pub fn run(arguments: &ArgMatches) -> bool {
+    let mut report = Report::default();
+    let config = check_config(&mut report, arguments);
+    let config = config.unwrap_or_default();
+    check_music_dir(&mut report, arguments, &config);
+    check_state(&mut report);
+    let backend = arguments
+        .get_one::<String>("backend")
+        .cloned()
+        .or_else(|| config.get_str("output.backend").map(str::to_string));
+    check_audio(&mut report, backend.as_deref());
+    check_tools(&mut report);
+    println!();
+    if report.failures == 0 {
+        println!("{}: Everything needed to play works", "Success".green());
+    } else {
+        println!(
+            "{}: {} check(s) failed; see the lines marked FAIL",
+            "Error".red(),
+            report.failures
+        );
+    }
+    report.failures == 0
+}
+
+fn check_config(report: &mut Report, arguments: &ArgMatches) -> Option<Config> {
+    let explicit = arguments.get_one::<String>("config").map(String::as_str);
+    let profile = arguments.get_one::<String>("profile").map(String::as_str);
+    let shown = crate::config::file_path(explicit).map_or_else(
+        || "no config directory".to_string(),
+        |p| p.display().to_string(),
+    );
+    match Config::load_profile(explicit, profile) {
+        Ok(config) => {
+            let found = crate::config::file_path(explicit).is_some_and(|path| path.is_file());
+            let detail = if found {
+                format!("{} reads fine", shown)
+            } else {
+                format!("no file at {}, using the defaults", shown)
+            };
+            report.line(Status::Pass, "Config", &detail);
+            Some(config)
+        }
+        Err(e) => {
+            report.line(
+                Status::Fail,
+                "Config",
+                &format!("{}; fix that line or move the file aside", e),
+            );
+            None
+        }
+    }
+}
+
+fn check_music_dir(report: &mut Report, arguments: &ArgMatches, config: &Config) {
+    let Some(dir) = arguments
+        .get_one::<String>("music-dir")
+        .map(String::as_str)
+        .or_else(|| config.get_str("player.dir"))
+    else {
+        report.line(
+            Status::Fail,
+            "Music directory",
+            "none set; pass --dir or set `dir` under [player]",
+        );
+        return;
+    };
+    let dir = match (dir.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(dir),
+    };
+    if remote::is_remote(&dir) {
+        remote::configure(config);
+        match scan::Filter::from_config(config).files(&dir) {
+            Ok(tracks) => report.line(
+                Status::Pass,
+                "Music directory",
+                &format!("{} lists {} tracks", dir.display(), tracks.len()),
+            ),
+            Err(e) => report.line(
+                Status::Fail,
+                "Music directory",
+                &format!("{}: {}", dir.display(), e),
+            ),
+        }
+        return;
+    }
+    if let Err(e) = fs::read_dir(&dir) {
+        let hint = match e.kind() {
+            io::ErrorKind::NotFound => "check the path",
+            io::ErrorKind::PermissionDenied => "give your user read access to it",
+            _ => "check that it is a readable folder",
+        };
+        report.line(
+            Status::Fail,
+            "Music directory",
+            &format!("{}: {}; {}", dir.display(), e, hint),
+        );
+        return;
+    }
+    match scan::Filter::from_config(config).files(&dir) {
+        Ok(tracks) if tracks.is_empty() => report.line(
+            Status::Warn,
+            "Music directory",
+            &format!(
+                "{} holds no files this build plays ({})",
+                dir.display(),
+                crate::codecs::compiled_in().join(", ")
+            ),
+        ),
+        Ok(tracks) => report.line(
+            Status::Pass,
+            "Music directory",
+            &format!("{} holds {} playable tracks", dir.display(), tracks.len()),
+        ),
+        Err(e) => report.line(
+            Status::Fail,
+            "Music directory",
+            &format!("{} can't be scanned: {}", dir.display(), e),
+        ),
+    }
+    if let Err(e) = writable(&dir) {
+        report.line(
+            Status::Warn,
+            "Write access",
+            &format!(
+                "can't write to {} ({}); delete, move, organize and rip won't work",
+                dir.display(),
+                e
+            ),
+        );
+    }
+}
+
+/// Checks the state files line by line, and that the directory takes writes
+fn check_state(report: &mut Report) {
+    let Some(dir) = session::state_dir() else {
+        report.line(
+            Status::Warn,
+            "State",
+            "no state directory (HOME isn't set); play counts won't be kept",
+        );
+        return;
+    };
+    if dir.exists() {
+        if let Err(e) = writable(&dir) {
+            report.line(
+                Status::Fail,
+                "State",
+                &format!(
+                    "can't write to {}: {}; fix its permissions",
+                    dir.display(),
+                    e
+                ),
+            );
+            return;
+        }
+    }
+    let mut problems = Vec::new();
+    let mut lines = 0;
+    for &(name, fields) in TABLES {
+        let text = match fs::read_to_string(dir.join(name)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                problems.push(format!("{} can't be read: {}", name, e));
+                continue;
+            }
+        };
+        let bad = text
+            .lines()
+            .filter(|line| {
+                let mut parts = line.split('\t');
+                let number = parts.next().and_then(|n| n.parse::<f64>().ok());
+                number.is_none() || parts.count() < fields
+            })
+            .count();
+        lines += text.lines().count();
+        if bad > 0 {
+            problems.push(format!(
+                "{} has {} damaged line(s), which are dropped on the next save",
+                name, bad
+            ));
+        }
+    }
+    match stats::Stats::load() {
+        Ok(stats) => {
+            let gone = stats
+                .paths()
+                .filter(|path| !remote::is_remote(path) && !crate::cd::is_cd(path))
+                .filter(|path| !path.exists())
+                .count();
+            if gone > 0 {
+                problems.push(format!(
+                    "{} play count(s) belong to files that are gone; moves outside `organize move` lose them",
+                    gone
+                ));
+            }
+        }
+        Err(e) => problems.push(format!("stats can't be read: {}", e)),
+    }
+    for name in playlist::list() {
+        match playlist::Playlist::load(&name) {
+            Ok(list) => {
+                let missing = list
+                    .tracks
+                    .iter()
+                    .filter(|track| !remote::is_remote(&track.path) && !track.path.exists())
+                    .count();
+                if missing > 0 {
+                    problems.push(format!(
+                        "playlist {} has {} missing track(s)",
+                        name, missing
+                    ));
+                }
+            }
+            Err(e) => problems.push(format!("playlist {} can't be read: {}", name, e)),
+        }
+    }
+    if problems.is_empty() {
+        report.line(
+            Status::Pass,
+            "State",
+            &format!("{} intact ({} lines)", dir.display(), lines),
+        );
+    } else {
+        for problem in problems {
+            report.line(Status::Warn, "State", &problem);
+        }
+    }
+}
+
+/// Looks for the output device, opens the backend and plays a short tone
+fn check_audio(report: &mut Report, backend: Option<&str>) {
+    match devices::default_output_name() {
+        Some(name) => report.line(
+            Status::Pass,
+            "Output device",
+            &format!("default is {}", name),
+        ),
+        None => report.line(
+            Status::Fail,
+            "Output device",
+            "no default output device; check that a sound card or sound server is running",
+        ),
+    }
+    let available = output::available_backends();
+    if let Some(backend) = backend.filter(|b| *b != "default") {
+        if !available
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(backend))
+        {
+            report.line(
+                Status::Fail,
+                "Backend",
+                &format!(
+                    "`{}` isn't available in this build (available: {})",
+                    backend,
+                    available.join(", ")
+                ),
+            );
+            return;
+        }
+    }
+    let output = match output::open(backend) {
+        Ok(output) => {
+            report.line(
+                Status::Pass,
+                "Backend",
+                &format!(
+                    "{} opened with {} channels (available: {})",
+                    backend.unwrap_or("default"),
+                    output.channels,
+                    available.join(", ")
+                ),
+            );
+            output
+        }
+        Err(e) => {
+            report.line(
+                Status::Fail,
+                "Backend",
+                &format!(
+                    "{}: {}; try another with --backend",
+                    backend.unwrap_or("default"),
+                    e
+                ),
+            );
+            return;
+        }
+    };
+    let tone = match Decoder::new(Cursor::new(test_tone())) {
+        Ok(decoder) => decoder.convert_samples::<f32>(),
+        Err(e) => {
+            report.line(
+                Status::Fail,
+                "Test tone",
+                &format!("could not decode: {}", e),
+            );
+            return;
+        }
+    };
+    let samples: Vec<f32> = tone.collect();
+    let expected = (TONE.as_secs_f64() * 44100.0) as usize;
+    if samples.len() != expected || samples.iter().all(|&s| s == 0.0) {
+        report.line(
+            Status::Fail,
+            "Test tone",
+            "decoded to the wrong samples; the WAV decoder is broken",
+        );
+        return;
+    }
+    let sink = match rodio::Sink::try_new(&output.handle) {
+        Ok(sink) => sink,
+        Err(e) => {
+            report.line(Status::Fail, "Test tone", &format!("could not play: {}", e));
+            return;
+        }
+    };
+    sink.set_volume(0.2);
+    sink.append(rodio::buffer::SamplesBuffer::new(1, 44100, samples));
+    sink.sleep_until_end();
+    report.line(
+        Status::Pass,
+        "Test tone",
+        "decoded and played; you should have heard a short beep",
+    );
+}
+
+/// Tools some features run
+fn check_tools(report: &mut Report) {
+    let tools = [
+        ("curl", "podcasts, S3, WebDAV and CD titles"),
+        ("flac", "rip"),
+    ];
+    for (tool, needed_by) in tools {
+        let found = Command::new(tool)
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok();
+        if found {
+            report.line(Status::Pass, tool, "installed");
+        } else {
+            report.line(
+                Status::Warn,
+                tool,
+                &format!("not installed; needed for {}", needed_by),
+            );
+        }
+    }
+}
+
+/// Whether files can be created in a directory
+fn writable(dir: &Path) -> io::Result<()> {
+    let probe = dir.join(format!(".musicplayer-doctor-{}", std::process::id()));
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+/// A 440 Hz sine as a 16-bit mono WAV file
+fn test_tone() -> Vec<u8> {
+    let rate = 44100u32;
+    let frames = (TONE.as_secs_f64() * rate as f64) as u32;
+    let mut wav = Vec::with_capacity(44 + frames as usize * 2);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + frames * 2).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    wav.extend_from_slice(&rate.to_le_bytes());
+    wav.extend_from_slice(&(rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(frames * 2).to_le_bytes());
+    for frame in 0..frames {
+        let t = frame as f64 / rate as f64;
+        let sample = (t * 440.0 * std::f64::consts::TAU).sin() * 0.5 * i16::MAX as f64;
+        wav.extend_from_slice(&(sample as i16).to_le_bytes());
+    }
+    wav
+}
@@ -0,0 +1,288 @@
+//! DSD playback from DSF and DSDIFF (DFF) files, converted to PCM
+//! Each byte of 1-bit DSD is averaged into one sample at 1/8 of the DSD
+//! rate, then a low-pass FIR filter removes the shaped noise above the
+//! audio band and keeps every 8th sample, e.g. DSD64 becomes 44.1 kHz
+
+use rodio::Source;
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+/// Length of the low-pass filter, in samples at 1/8 of the DSD rate
+const TAPS: usize = 256;
+/// Samples averaged from bytes per output sample
+const DECIMATION: usize = 8;
+/// Bytes of each channel read from DFF files at a time
+const DFF_GROUP: usize = 4096;
+
+/// How channels are laid out in the sound data
+enum Layout {
+    Blocks(usize), // DSF: fixed-size blocks, one channel after another
+    Interleaved,   // DFF: one byte of each channel in turn
+}
+
+/// PCM samples decoded from a DSD file
+pub struct Dsd {
+    reader: BufReader<File>,
+    layout: Layout,
+    channels: u16,
+    dsd_rate: u32,
+    remaining: u64,            // Bytes left to read per channel
+    bytes: Vec<Vec<u8>>,       // Bytes read for each channel, not yet used
+    position: usize,           // Next unused byte in `bytes`
+    history: Vec<[f32; TAPS]>, // Recent averaged samples per channel, as a ring
+    head: usize,               // Where the next averaged sample goes in the ring
+    filter: Vec<f32>,
+    frame: Vec<f32>, // Output frame being handed out
+    next: usize,     // Next sample of `frame` to hand out
+    total: Duration,
+}
+
+impl Dsd {
+    /// Opens a DSF file or a DSDIFF file, telling them apart by content
+    pub fn new(file: File) -> io::Result<Self> {
+        let mut reader = BufReader::new(file);
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        reader.seek(SeekFrom::Start(0))?;
+        let (layout, channels, dsd_rate, bytes_per_channel) = match &magic {
+            b"DSD " => dsf_header(&mut reader)?,
+            b"FRM8" => dff_header(&mut reader)?,
+            _ => return Err(invalid("Not a DSF or DFF file")),
+        };
+        if channels == 0 || dsd_rate < 64 * 8000 {
+            return Err(invalid("DSD file has an unsupported format"));
+        }
+        Ok(Self {
+            reader,
+            layout,
+            channels,
+            dsd_rate,
+            remaining: bytes_per_channel,
+            bytes: vec![Vec::new(); channels as usize],
+            position: 0,
+            history: vec![[0.0; TAPS]; channels as usize],
+            head: 0,
+            filter: low_pass(),
+            frame: Vec::new(),
+            next: 0,
+            total: Duration::from_secs_f64(bytes_per_channel as f64 * 8.0 / dsd_rate as f64),
+        })
+    }
+
+    /// Reads the next stretch of bytes for every channel
+    fn fill(&mut self) -> Option<()> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let channels = self.channels as usize;
+        let group = match self.layout {
+            Layout::Blocks(size) => size,
+            Layout::Interleaved => DFF_GROUP,
+        };
+        let mut data = vec![0u8; group * channels];
+        let read = read_up_to(&mut self.reader, &mut data).ok()?;
+        if read == 0 {
+            return None;
+        }
+        let used = (self.remaining.min(group as u64)) as usize;
+        for (channel, bytes) in self.bytes.iter_mut().enumerate() {
+            bytes.clear();
+            match self.layout {
+                Layout::Blocks(size) => {
+                    // A truncated file may end partway through a block
+                    let start = (channel * size).min(read);
+                    let end = (start + used).min(read);
+                    bytes.extend_from_slice(&data[start..end]);
+                }
+                Layout::Interleaved => {
+                    let available = (read / channels).min(used);
+                    bytes.extend((0..available).map(|i| data[i * channels + channel]));
+                }
+            }
+        }
+        self.remaining -= used as u64;
+        self.position = 0;
+        Some(())
+    }
+
+    /// Filters the next output frame from DECIMATION bytes per channel
+    fn decode_frame(&mut self) -> Option<()> {
+        for _ in 0..DECIMATION {
+            if self.position >= self.bytes[0].len() {
+                self.fill()?;
+                if self.bytes[0].is_empty() {
+                    return None;
+                }
+            }
+            for (channel, bytes) in self.bytes.iter().enumerate() {
+                // The share of ones in the byte, as -1.0 to 1.0; the bit
+                // order doesn't matter for an average
+                let ones = bytes[self.position].count_ones() as f32;
+                self.history[channel][self.head] = ones / 4.0 - 1.0;
+            }
+            self.position += 1;
+            self.head = (self.head + 1) % TAPS;
+        }
+
+        self.frame.clear();
+        for history in &self.history {
+            let mut sum = 0.0;
+            for (k, tap) in self.filter.iter().enumerate() {
+                sum += tap * history[(self.head + TAPS - 1 - k) % TAPS];
+            }
+            self.frame.push(sum);
+        }
+        self.next = 0;
+        Some(())
+    }
+}
+
+impl Iterator for Dsd {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.next >= self.frame.len() {
+            self.decode_frame()?;
+        }
+        self.next += 1;
+        Some(self.frame[self.next - 1])
+    }
+}
+
+impl Source for Dsd {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.dsd_rate / (8 * DECIMATION as u32)
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.total)
+    }
+}
+
+/// Reads a DSF header, leaving the reader at the sound data
+fn dsf_header(reader: &mut BufReader<File>) -> io::Result<(Layout, u16, u32, u64)> {
+    let mut header = [0u8; 28];
+    reader.read_exact(&mut header)?;
+    let mut fmt = [0u8; 52];
+    reader.read_exact(&mut fmt)?;
+    if &fmt[..4] != b"fmt " {
+        return Err(invalid("DSF file has no fmt chunk"));
+    }
+    let le32 = |at: usize| u32::from_le_bytes(fmt[at..at + 4].try_into().unwrap());
+    let fmt_size = u64::from_le_bytes(fmt[4..12].try_into().unwrap());
+    let channels = le32(24) as u16;
+    let dsd_rate = le32(28);
+    let samples = u64::from_le_bytes(fmt[36..44].try_into().unwrap());
+    let block = le32(44) as usize;
+    if le32(16) != 0 || block == 0 {
+        return Err(invalid("DSF file has an unsupported format"));
+    }
+    // The data chunk follows the fmt chunk
+    reader.seek(SeekFrom::Start(28 + fmt_size))?;
+    let mut data = [0u8; 12];
+    reader.read_exact(&mut data)?;
+    if &data[..4] != b"data" {
+        return Err(invalid("DSF file has no sound data"));
+    }
+    Ok((Layout::Blocks(block), channels, dsd_rate, samples / 8))
+}
+
+/// Reads a DSDIFF header, leaving the reader at the sound data
+fn dff_header(reader: &mut BufReader<File>) -> io::Result<(Layout, u16, u32, u64)> {
+    let mut form = [0u8; 16];
+    reader.read_exact(&mut form)?;
+    if &form[12..] != b"DSD " {
+        return Err(invalid("Not a DSDIFF file"));
+    }
+    let (mut channels, mut dsd_rate) = (0u16, 0u32);
+    loop {
+        let (id, size, start) = dff_chunk(reader)?;
+        match &id {
+            b"PROP" => {
+                let mut kind = [0u8; 4];
+                reader.read_exact(&mut kind)?;
+                while reader.stream_position()? < start + size {
+                    let (id, size, sub_start) = dff_chunk(reader)?;
+                    let mut body = vec![0u8; size.min(64) as usize];
+                    reader.read_exact(&mut body)?;
+                    match &id {
+                        b"FS  " if body.len() >= 4 => {
+                            dsd_rate = u32::from_be_bytes(body[..4].try_into().unwrap())
+                        }
+                        b"CHNL" if body.len() >= 2 => {
+                            channels = u16::from_be_bytes([body[0], body[1]])
+                        }
+                        b"CMPR" if body.get(..4) != Some(b"DSD ") => {
+                            return Err(invalid("Compressed (DST) DFF files aren't supported"));
+                        }
+                        _ => {}
+                    }
+                    reader.seek(SeekFrom::Start(sub_start + size + size % 2))?;
+                }
+            }
+            b"DSD " => {
+                let per_channel = size / channels.max(1) as u64;
+                return Ok((Layout::Interleaved, channels, dsd_rate, per_channel));
+            }
+            _ => {}
+        }
+        reader.seek(SeekFrom::Start(start + size + size % 2))?;
+    }
+}
+
+/// Reads a DSDIFF chunk header: its id, size and where its data starts
+fn dff_chunk(reader: &mut BufReader<File>) -> io::Result<([u8; 4], u64, u64)> {
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+    let id = header[..4].try_into().unwrap();
+    let size = u64::from_be_bytes(header[4..].try_into().unwrap());
+    Ok((id, size, reader.stream_position()?))
+}
+
+/// A Blackman-windowed sinc filter passing up to 45% of the output rate
+fn low_pass() -> Vec<f32> {
+    let cutoff = 0.45 / DECIMATION as f64;
+    let middle = (TAPS - 1) as f64 / 2.0;
+    let taps: Vec<f64> = (0..TAPS)
+        .map(|n| {
+            let x = n as f64 - middle;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * x).sin() / (PI * x)
+            };
+            let phase = 2.0 * PI * n as f64 / (TAPS - 1) as f64;
+            sinc * (0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos())
+        })
+        .collect();
+    let sum: f64 = taps.iter().sum();
+    taps.iter().map(|tap| (tap / sum) as f32).collect()
+}
+
+/// Reads until `buffer` is full or the file ends
+fn read_up_to(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,530 @@
+//! Audio processing between the decoder and the output
+//! Stages are `Source` adapters over interleaved f32 samples
+
+use crate::eq::{Filter, Preset};
+use rodio::Source;
+use std::{
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// -3 dB, the usual level for folding a channel into both sides
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Below this frequency karaoke keeps the centre, where bass usually sits
+const KARAOKE_BASS_CUTOFF: f32 = 150.0;
+
+/// Crossfeed low-pass cutoff and level, bs2b's default preset
+const CROSSFEED_CUTOFF: f32 = 700.0;
+const CROSSFEED_LEVEL_DB: f32 = 4.5;
+
+/// Night mode compressor: everything above the threshold is squeezed by
+/// the ratio, then made up so quiet passages come up at low volume
+const NIGHT_THRESHOLD_DB: f32 = -28.0;
+const NIGHT_RATIO: f32 = 3.0;
+const NIGHT_MAKEUP_DB: f32 = 9.0;
+const NIGHT_ATTACK: f32 = 0.005; // Seconds
+const NIGHT_RELEASE: f32 = 0.250; // Seconds
+/// Peak ceiling of the limiter after the compressor
+const NIGHT_CEILING: f32 = 0.9;
+
+/// Gains from each input channel (columns) to each output channel (rows)
+pub type Matrix = Vec<Vec<f32>>;
+
+/// Stereo downmix for a multichannel layout, in WAV/FLAC channel order
+/// Follows ITU-R BS.775 (centre and surrounds at -3 dB, LFE dropped),
+/// scaled so a full-scale input can't clip
+/// Returns None for mono and stereo, which need no downmix
+pub fn downmix_matrix(channels: u16) -> Option<Matrix> {
+    const L: (f32, f32) = (1.0, 0.0);
+    const R: (f32, f32) = (0.0, 1.0);
+    const C: (f32, f32) = (MINUS_3DB, MINUS_3DB);
+    const LFE: (f32, f32) = (0.0, 0.0);
+    const SL: (f32, f32) = (MINUS_3DB, 0.0);
+    const SR: (f32, f32) = (0.0, MINUS_3DB);
+
+    let layout: &[(f32, f32)] = match channels {
+        3 => &[L, R, C],
+        4 => &[L, R, SL, SR],
+        5 => &[L, R, C, SL, SR],
+        6 => &[L, R, C, LFE, SL, SR],
+        7 => &[L, R, C, LFE, C, SL, SR],
+        8 => &[L, R, C, LFE, SL, SR, SL, SR],
+        _ => return None,
+    };
+    let left: Vec<f32> = layout.iter().map(|(l, _)| *l).collect();
+    let right: Vec<f32> = layout.iter().map(|(_, r)| *r).collect();
+    let scale = 1.0 / left.iter().sum::<f32>();
+    Some(vec![
+        left.iter().map(|gain| gain * scale).collect(),
+        right.iter().map(|gain| gain * scale).collect(),
+    ])
+}
+
+/// Routing for a channel map: output channel `i` plays input channel
+/// `map[i]`, or silence for None or a channel the input doesn't have
+pub fn channel_map_matrix(map: &[Option<usize>], channels: u16) -> Matrix {
+    map.iter()
+        .map(|source| {
+            (0..channels as usize)
+                .map(|channel| if Some(channel) == *source { 1.0 } else { 0.0 })
+                .collect()
+        })
+        .collect()
+}
+
+/// Mixes every frame through a matrix, changing the channel count
+/// Without a matrix, samples pass through untouched
+pub struct Remix<S> {
+    input: S,
+    matrix: Option<Matrix>,
+    frame: Vec<f32>,  // Current input frame
+    output: Vec<f32>, // Current output frame
+    next: usize,      // Next output sample to hand out
+}
+
+impl<S: Source<Item = f32>> Remix<S> {
+    pub fn new(input: S, matrix: Option<Matrix>) -> Self {
+        let in_channels = input.channels() as usize;
+        let out_channels = matrix.as_ref().map_or(0, Vec::len);
+        Self {
+            input,
+            matrix,
+            frame: vec![0.0; in_channels],
+            output: vec![0.0; out_channels],
+            next: out_channels,
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Remix<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let Some(matrix) = &self.matrix else {
+            return self.input.next();
+        };
+
+        if self.next == self.output.len() {
+            self.frame[0] = self.input.next()?;
+            for sample in &mut self.frame[1..] {
+                *sample = self.input.next().unwrap_or(0.0);
+            }
+            for (out, gains) in self.output.iter_mut().zip(matrix) {
+                *out = gains.iter().zip(&self.frame).map(|(g, s)| g * s).sum();
+            }
+            self.next = 0;
+        }
+        let sample = self.output[self.next];
+        self.next += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Remix<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        match &self.matrix {
+            Some(matrix) => self
+                .input
+                .current_frame_len()
+                .map(|len| len / self.frame.len() * matrix.len()),
+            None => self.input.current_frame_len(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        match &self.matrix {
+            Some(matrix) => matrix.len() as u16,
+            None => self.input.channels(),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// Which effects are enabled
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EffectSettings {
+    pub karaoke: bool,      // Cancel centre-panned vocals
+    pub crossfeed: bool,    // Blend the channels for headphone listening
+    pub eq: Option<Preset>, // Equalizer curve, if any
+    pub night_mode: bool,   // Compress dynamics for quiet listening
+}
+
+/// Effect settings shared between the player and the playing track, so
+/// changes are heard immediately
+#[derive(Clone, Default)]
+pub struct Effects {
+    settings: Arc<Mutex<EffectSettings>>,
+    version: Arc<AtomicUsize>, // Bumped on every change
+}
+
+impl Effects {
+    pub fn settings(&self) -> EffectSettings {
+        self.settings.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// Changes the settings and notifies playing tracks
+    pub fn update(&self, change: impl FnOnce(&mut EffectSettings)) {
+        if let Ok(mut settings) = self.settings.lock() {
+            change(&mut settings);
+        }
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    /// Runs a source through the effect chain
+    pub fn apply<S: Source<Item = f32>>(&self, input: S) -> EffectChain<S> {
+        let channels = input.channels() as usize;
+        let sample_rate = input.sample_rate() as f32;
+        let settings = self.settings();
+        EffectChain {
+            input,
+            effects: self.clone(),
+            version: self.version.load(Ordering::Acquire),
+            eq: settings
+                .eq
+                .as_ref()
+                .map(|preset| Equalizer::new(preset, sample_rate, channels)),
+            settings,
+            frame: vec![0.0; channels],
+            next: channels,
+            karaoke: Karaoke::new(sample_rate),
+            crossfeed: Crossfeed::new(sample_rate),
+            night_mode: NightMode::new(sample_rate),
+        }
+    }
+}
+
+/// Source adapter applying the enabled effects frame by frame
+pub struct EffectChain<S> {
+    input: S,
+    effects: Effects,
+    version: usize,           // Settings version last read
+    settings: EffectSettings, // Copy of the settings, to avoid locking per frame
+    frame: Vec<f32>,          // Current processed frame
+    next: usize,              // Next sample of the frame to hand out
+    karaoke: Karaoke,
+    crossfeed: Crossfeed,
+    eq: Option<Equalizer>,
+    night_mode: NightMode,
+}
+
+impl<S: Source<Item = f32>> EffectChain<S> {
+    /// Reads and processes the next frame, returning false at the end
+    fn fill_frame(&mut self) -> bool {
+        let version = self.effects.version.load(Ordering::Acquire);
+        if version != self.version {
+            self.version = version;
+            let settings = self.effects.settings();
+            if settings.eq != self.settings.eq {
+                let sample_rate = self.input.sample_rate() as f32;
+                self.eq = settings
+                    .eq
+                    .as_ref()
+                    .map(|preset| Equalizer::new(preset, sample_rate, self.frame.len()));
+            }
+            self.settings = settings;
+        }
+
+        let Some(first) = self.input.next() else {
+            return false;
+        };
+        self.frame[0] = first;
+        for sample in &mut self.frame[1..] {
+            *sample = self.input.next().unwrap_or(0.0);
+        }
+
+        if let Some(eq) = &mut self.eq {
+            eq.process(&mut self.frame);
+        }
+        if let [left, right] = &mut self.frame[..] {
+            if self.settings.karaoke {
+                self.karaoke.process(left, right);
+            }
+            if self.settings.crossfeed {
+                self.crossfeed.process(left, right);
+            }
+        }
+        if self.settings.night_mode {
+            self.night_mode.process(&mut self.frame);
+        }
+        true
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for EffectChain<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.frame.is_empty() {
+            return self.input.next();
+        }
+        if self.next == self.frame.len() {
+            if !self.fill_frame() {
+                return None;
+            }
+            self.next = 0;
+        }
+        let sample = self.frame[self.next];
+        self.next += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for EffectChain<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// Vocal removal by centre-channel cancellation
+/// Subtracting one side from the other removes anything panned dead
+/// centre; the low end of the centre is added back to keep the bass
+struct Karaoke {
+    alpha: f32, // One-pole low-pass coefficient
+    bass: f32,  // Low-passed centre signal
+}
+
+impl Karaoke {
+    fn new(sample_rate: f32) -> Self {
+        Self {
+            alpha: 1.0 - (-2.0 * PI * KARAOKE_BASS_CUTOFF / sample_rate).exp(),
+            bass: 0.0,
+        }
+    }
+
+    fn process(&mut self, left: &mut f32, right: &mut f32) {
+        let mid = (*left + *right) * 0.5;
+        let side = (*left - *right) * 0.5;
+        self.bass += self.alpha * (mid - self.bass);
+        *left = side + self.bass;
+        *right = -side + self.bass;
+    }
+}
+
+/// Bauer stereophonic-to-binaural crossfeed, after bs2b
+/// Each ear hears a low-passed (and so slightly delayed) copy of the
+/// other channel, while its own channel gets a matching high-shelf so
+/// the overall tone stays flat
+struct Crossfeed {
+    a0_lo: f32,
+    b1_lo: f32,
+    a0_hi: f32,
+    a1_hi: f32,
+    b1_hi: f32,
+    gain: f32,          // Output gain that keeps the blend from clipping
+    lo: [f32; 2],       // Low-pass state per channel
+    hi: [f32; 2],       // High-shelf state per channel
+    previous: [f32; 2], // Last input sample per channel
+}
+
+impl Crossfeed {
+    fn new(sample_rate: f32) -> Self {
+        let gain_lo_db = CROSSFEED_LEVEL_DB * -5.0 / 6.0 - 3.0;
+        let gain_hi_db = CROSSFEED_LEVEL_DB / 6.0 - 3.0;
+        let gain_lo = 10f32.powf(gain_lo_db / 20.0);
+        let gain_hi = 1.0 - 10f32.powf(gain_hi_db / 20.0);
+        let cutoff_hi = CROSSFEED_CUTOFF * 2f32.powf((gain_lo_db - 20.0 * gain_hi.log10()) / 12.0);
+
+        let x_lo = (-2.0 * PI * CROSSFEED_CUTOFF / sample_rate).exp();
+        let x_hi = (-2.0 * PI * cutoff_hi / sample_rate).exp();
+        Self {
+            a0_lo: gain_lo * (1.0 - x_lo),
+            b1_lo: x_lo,
+            a0_hi: 1.0 - gain_hi * (1.0 - x_hi),
+            a1_hi: -x_hi,
+            b1_hi: x_hi,
+            gain: 1.0 / (1.0 - gain_hi + gain_lo),
+            lo: [0.0; 2],
+            hi: [0.0; 2],
+            previous: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, left: &mut f32, right: &mut f32) {
+        let input = [*left, *right];
+        for (channel, &sample) in input.iter().enumerate() {
+            self.lo[channel] = self.a0_lo * sample + self.b1_lo * self.lo[channel];
+            self.hi[channel] = self.a0_hi * sample
+                + self.a1_hi * self.previous[channel]
+                + self.b1_hi * self.hi[channel];
+        }
+        self.previous = input;
+        *left = (self.hi[0] + self.lo[1]) * self.gain;
+        *right = (self.hi[1] + self.lo[0]) * self.gain;
+    }
+}
+
+/// Preamp plus one biquad per band and channel
+struct Equalizer {
+    preamp: f32,
+    bands: Vec<Vec<Biquad>>, // One filter per channel for every band
+}
+
+impl Equalizer {
+    fn new(preset: &Preset, sample_rate: f32, channels: usize) -> Self {
+        Self {
+            preamp: 10f32.powf(preset.preamp_db / 20.0),
+            bands: preset
+                .bands
+                .iter()
+                .map(|band| {
+                    let filter =
+                        Biquad::new(band.filter, band.freq, band.gain_db, band.q, sample_rate);
+                    vec![filter; channels]
+                })
+                .collect(),
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        for sample in frame.iter_mut() {
+            *sample *= self.preamp;
+        }
+        for band in &mut self.bands {
+            for (filter, sample) in band.iter_mut().zip(frame.iter_mut()) {
+                *sample = filter.process(*sample);
+            }
+        }
+    }
+}
+
+/// Second-order filter with coefficients from the RBJ Audio EQ Cookbook
+#[derive(Clone)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32, // Transposed direct form II state
+    z2: f32,
+}
+
+impl Biquad {
+    fn new(filter: Filter, freq: f32, gain_db: f32, q: f32, sample_rate: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        // Keep the frequency below Nyquist so low sample rates stay stable
+        let w0 = 2.0 * PI * freq.min(sample_rate * 0.49) / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.01));
+
+        let (b0, b1, b2, a0, a1, a2) = match filter {
+            Filter::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            Filter::LowShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + k),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - k),
+                    (a + 1.0) + (a - 1.0) * cos + k,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - k,
+                )
+            }
+            Filter::HighShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + k),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - k),
+                    (a + 1.0) - (a - 1.0) * cos + k,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - k,
+                )
+            }
+        };
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+}
+
+/// Dynamic range compression followed by a peak limiter
+/// Gain is computed from the loudest channel, so the stereo image
+/// doesn't shift when one side gets loud
+struct NightMode {
+    attack: f32,   // Envelope smoothing when the level rises
+    release: f32,  // Envelope smoothing when the level falls
+    envelope: f32, // Smoothed peak level
+    limiter: f32,  // Current limiter gain
+}
+
+impl NightMode {
+    fn new(sample_rate: f32) -> Self {
+        Self {
+            attack: (-1.0 / (NIGHT_ATTACK * sample_rate)).exp(),
+            release: (-1.0 / (NIGHT_RELEASE * sample_rate)).exp(),
+            envelope: 0.0,
+            limiter: 1.0,
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let peak = frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let smoothing = if peak > self.envelope {
+            self.attack
+        } else {
+            self.release
+        };
+        self.envelope = peak + smoothing * (self.envelope - peak);
+
+        let level_db = 20.0 * self.envelope.max(1e-6).log10();
+        let over_db = (level_db - NIGHT_THRESHOLD_DB).max(0.0);
+        let gain_db = NIGHT_MAKEUP_DB - over_db * (1.0 - 1.0 / NIGHT_RATIO);
+        let gain = 10f32.powf(gain_db / 20.0);
+
+        // Clamp peaks instantly and let the limiter recover slowly
+        let limited = (NIGHT_CEILING / (peak * gain).max(f32::EPSILON)).min(1.0);
+        self.limiter = if limited < self.limiter {
+            limited
+        } else {
+            limited + self.release * (self.limiter - limited)
+        };
+        for sample in frame.iter_mut() {
+            *sample *= gain * self.limiter;
+        }
+    }
+}
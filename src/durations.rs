@@ -0,0 +1,142 @@
+//! Track lengths for the list view
+//! Read from the decoder's metadata when it has them, otherwise counted by
+//! decoding the track once in the background. Kept in the state directory
+//! as one `seconds<TAB>modified<TAB>path` line per track, so a changed
+//! file is measured again
+
+use crate::{codecs, remote, session};
+use rodio::Source;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Name of the durations file inside the state directory
+const DURATIONS_FILE: &str = "durations";
+
+/// Known track lengths, keyed by path
+#[derive(Clone, Debug, Default)]
+pub struct Durations {
+    tracks: BTreeMap<PathBuf, (u64, u64)>, // Seconds and modification time
+    changed: bool,                         // Measured tracks not saved yet
+}
+
+impl Durations {
+    /// Reads the durations file, which may not exist yet
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = durations_path() else {
+            return Ok(Self::default());
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let mut tracks = BTreeMap::new();
+        for line in text.lines() {
+            let mut fields = line.splitn(3, '\t');
+            let (Some(Ok(seconds)), Some(Ok(modified)), Some(path)) = (
+                fields.next().map(str::parse),
+                fields.next().map(str::parse),
+                fields.next(),
+            ) else {
+                continue;
+            };
+            tracks.insert(PathBuf::from(path), (seconds, modified));
+        }
+        Ok(Self {
+            tracks,
+            changed: false,
+        })
+    }
+
+    /// Saves the file if any track was measured since it was read
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let path = durations_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for (track, (seconds, modified)) in &self.tracks {
+            text.push_str(&format!("{}\t{}\t{}\n", seconds, modified, track.display()));
+        }
+        fs::write(path, text)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    /// Length of a track if it is known, reading the metadata of a track
+    /// that is new or has changed
+    pub fn get(&mut self, track: &Path) -> Option<Duration> {
+        let modified = modified(track)?;
+        if let Some(&(seconds, at)) = self.tracks.get(track) {
+            if at == modified {
+                return Some(Duration::from_secs(seconds));
+            }
+        }
+        let length = codecs::open(track).ok()?.total_duration()?;
+        self.insert(track, length, modified);
+        Some(length)
+    }
+
+    fn insert(&mut self, track: &Path, length: Duration, modified: u64) {
+        self.tracks
+            .insert(track.to_path_buf(), (length.as_secs(), modified));
+        self.changed = true;
+    }
+}
+
+/// Decodes the tracks whose length is still unknown on another thread,
+/// then saves what it found
+pub fn measure_in_background(durations: Arc<Mutex<Durations>>, tracks: Vec<PathBuf>) {
+    thread::spawn(move || {
+        for track in tracks {
+            // Measuring would download the whole remote library
+            if remote::is_remote(&track) {
+                continue;
+            }
+            if durations.lock().unwrap().get(&track).is_some() {
+                continue;
+            }
+            // Decoding takes a while, so the lock isn't held meanwhile
+            if let (Some(length), Some(modified)) = (decode_length(&track), modified(&track)) {
+                durations.lock().unwrap().insert(&track, length, modified);
+            }
+        }
+        let _ = durations.lock().unwrap().save();
+    });
+}
+
+/// Decodes a whole track to count its samples
+fn decode_length(track: &Path) -> Option<Duration> {
+    let mut source = codecs::open(track).ok()?;
+    let mut seconds = 0.0;
+    loop {
+        let per_second = source.sample_rate() as f64 * source.channels() as f64;
+        // The rate and channels only change where a decoded block ends
+        let block = source.current_frame_len().unwrap_or(usize::MAX);
+        let counted = source.by_ref().take(block).count();
+        if counted == 0 || per_second == 0.0 {
+            break;
+        }
+        seconds += counted as f64 / per_second;
+    }
+    Some(Duration::from_secs_f64(seconds))
+}
+
+fn modified(track: &Path) -> Option<u64> {
+    let modified = fs::metadata(track).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+fn durations_path() -> Option<PathBuf> {
+    session::state_dir().map(|dir| dir.join(DURATIONS_FILE))
+}
@@ -0,0 +1,261 @@
+//! Parametric equalizer presets
+//! User presets are stored in the config directory in the Equalizer APO
+//! text format (`Preamp: -3 dB`, `Filter 1: ON PK Fc 100 Hz Gain 3 dB Q 1`),
+//! which is also what AutoEQ publishes as `ParametricEQ.txt`
+
+use crate::config;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Directory for user presets inside the config directory
+const PRESET_DIR: &str = "eq";
+
+/// Shape of an equalizer band
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Peak,      // Bell around the centre frequency
+    LowShelf,  // Everything below the corner frequency
+    HighShelf, // Everything above the corner frequency
+}
+
+/// A single equalizer band
+#[derive(Clone, Debug, PartialEq)]
+pub struct Band {
+    pub filter: Filter,
+    pub freq: f32,    // Centre or corner frequency in Hz
+    pub gain_db: f32, // Boost or cut
+    pub q: f32,       // Bandwidth; 0.707 gives a smooth shelf
+}
+
+/// A named set of bands plus the preamp that keeps boosts from clipping
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub preamp_db: f32,
+    pub bands: Vec<Band>,
+}
+
+/// Presets shipped with the player
+pub const BUILT_IN: &[&str] = &["flat", "rock", "classical", "bass-boost", "spoken-word"];
+
+impl Band {
+    fn new(filter: Filter, freq: f32, gain_db: f32, q: f32) -> Self {
+        Self {
+            filter,
+            freq,
+            gain_db,
+            q,
+        }
+    }
+}
+
+impl Preset {
+    /// A built-in preset by name
+    pub fn built_in(name: &str) -> Option<Self> {
+        use Filter::*;
+        let (preamp_db, bands) = match name {
+            "flat" => (0.0, vec![]),
+            "rock" => (
+                -4.0,
+                vec![
+                    Band::new(LowShelf, 100.0, 4.0, 0.707),
+                    Band::new(Peak, 400.0, -1.5, 1.0),
+                    Band::new(Peak, 2500.0, 1.5, 1.0),
+                    Band::new(HighShelf, 8000.0, 3.5, 0.707),
+                ],
+            ),
+            "classical" => (
+                -2.5,
+                vec![
+                    Band::new(Peak, 250.0, -1.0, 0.8),
+                    Band::new(HighShelf, 6000.0, 2.5, 0.707),
+                ],
+            ),
+            "bass-boost" => (-6.0, vec![Band::new(LowShelf, 90.0, 6.0, 0.707)]),
+            "spoken-word" => (
+                -3.0,
+                vec![
+                    Band::new(LowShelf, 120.0, -6.0, 0.707),
+                    Band::new(Peak, 3000.0, 3.0, 1.0),
+                    Band::new(HighShelf, 10000.0, -2.0, 0.707),
+                ],
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            name: name.to_string(),
+            preamp_db,
+            bands,
+        })
+    }
+
+    /// A built-in preset, or a user preset from the config directory
+    pub fn find(name: &str) -> io::Result<Self> {
+        if let Some(preset) = Self::built_in(name) {
+            return Ok(preset);
+        }
+        let path = preset_path(name)?;
+        let text = fs::read_to_string(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("No preset named `{}`", name)))?;
+        Self::parse(name, &text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Reads an AutoEQ `ParametricEQ.txt` (or any Equalizer APO filter
+    /// file), naming the preset after the file, e.g. `sennheiser-hd-600`
+    pub fn import(path: &Path) -> io::Result<Self> {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = stem
+            .trim_end_matches("ParametricEQ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("-")
+            .to_lowercase();
+        let text = fs::read_to_string(path)?;
+        let preset = Self::parse(&name, &text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })?;
+        if preset.bands.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: no parametric filters found", path.display()),
+            ));
+        }
+        Ok(preset)
+    }
+
+    /// Saves the preset under its name in the config directory
+    pub fn save(&self) -> io::Result<PathBuf> {
+        if BUILT_IN.contains(&self.name.as_str()) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("`{}` is a built-in preset", self.name),
+            ));
+        }
+        let path = preset_path(&self.name)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, self.to_text())?;
+        Ok(path)
+    }
+
+    /// Parses Equalizer APO filter lines, reporting the first bad line
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut preset = Self {
+            name: name.to_string(),
+            preamp_db: 0.0,
+            bands: Vec::new(),
+        };
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if let Some(value) = line.strip_prefix("Preamp:") {
+                preset.preamp_db =
+                    parse_db(value).ok_or_else(|| format!("line {}: bad preamp", number + 1))?;
+            } else if line.starts_with("Filter") {
+                if line.contains(": OFF") {
+                    continue;
+                }
+                let band =
+                    parse_filter(line).ok_or_else(|| format!("line {}: bad filter", number + 1))?;
+                preset.bands.push(band);
+            }
+        }
+        Ok(preset)
+    }
+
+    /// Formats the preset as Equalizer APO filter lines
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Preamp: {:.1} dB\n", self.preamp_db);
+        for (number, band) in self.bands.iter().enumerate() {
+            let kind = match band.filter {
+                Filter::Peak => "PK",
+                Filter::LowShelf => "LSC",
+                Filter::HighShelf => "HSC",
+            };
+            text.push_str(&format!(
+                "Filter {}: ON {} Fc {} Hz Gain {:.1} dB Q {:.2}\n",
+                number + 1,
+                kind,
+                band.freq,
+                band.gain_db,
+                band.q
+            ));
+        }
+        text
+    }
+}
+
+/// Names of the presets saved in the config directory
+pub fn user_presets() -> Vec<String> {
+    let Some(dir) = config::config_dir().map(|dir| dir.join(PRESET_DIR)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "txt" {
+                return None;
+            }
+            path.file_stem()?.to_str().map(str::to_string)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+fn preset_path(name: &str) -> io::Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid preset name `{}`", name),
+        ));
+    }
+    config::config_dir()
+        .map(|dir| dir.join(PRESET_DIR).join(format!("{}.txt", name)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory"))
+}
+
+/// Reads a `-3.5 dB` value
+fn parse_db(value: &str) -> Option<f32> {
+    value.trim().trim_end_matches("dB").trim().parse().ok()
+}
+
+/// Reads a `Filter 1: ON PK Fc 100 Hz Gain 3 dB Q 1` line
+fn parse_filter(line: &str) -> Option<Band> {
+    let (_, spec) = line.split_once(':')?;
+    let words: Vec<&str> = spec.split_whitespace().collect();
+    if words.first() != Some(&"ON") {
+        return None;
+    }
+    let filter = match *words.get(1)? {
+        "PK" | "PEQ" => Filter::Peak,
+        "LS" | "LSC" => Filter::LowShelf,
+        "HS" | "HSC" => Filter::HighShelf,
+        _ => return None,
+    };
+    let value = |key: &str| -> Option<f32> {
+        let position = words.iter().position(|word| *word == key)?;
+        words.get(position + 1)?.parse().ok()
+    };
+    Some(Band::new(
+        filter,
+        value("Fc")?,
+        value("Gain")?,
+        value("Q").unwrap_or(0.707),
+    ))
+}
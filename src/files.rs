@@ -0,0 +1,245 @@
+//! File operations on the music library: deleting, moving and
+//! organizing tracks
+
+use crate::{playlist, tags::Tags};
+use std::{
+    collections::HashSet,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Moves a file into a directory, creating the directory if needed
+/// Never replaces an existing file; falls back to copy and delete when
+/// the directory is on another filesystem
+pub fn move_into(file: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let name = file
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a file"))?;
+    let target = dir.join(name);
+    move_to(file, &target)?;
+    Ok(target)
+}
+
+/// Moves a file to a new path, creating its parent directories
+pub fn move_to(file: &Path, target: &Path) -> io::Result<()> {
+    if target.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display()),
+        ));
+    }
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::rename(file, target).is_ok() {
+        return Ok(());
+    }
+    fs::copy(file, target)?;
+    fs::remove_file(file).inspect_err(|_| {
+        // Don't leave two copies behind
+        let _ = fs::remove_file(target);
+    })
+}
+
+/// Template `organize move` uses unless `organize.template` is set
+pub const DEFAULT_TEMPLATE: &str = "{artist}/{album}/{track} {title}";
+
+/// Where a track belongs under `root` according to a template such as
+/// `{artist}/{album}/{track} {title}`; the extension is kept
+/// Also understands `{albumartist}`, `{disc}` and `{year}`; missing tags
+/// become "Unknown ..." folders and a missing title the old file name
+pub fn organized_path(root: &Path, template: &str, file: &Path, tags: &Tags) -> PathBuf {
+    let stem = file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let number = |n: Option<u32>| n.map(|n| format!("{:02}", n)).unwrap_or_default();
+    let fields = [
+        (
+            "{artist}",
+            tags.artist
+                .clone()
+                .unwrap_or_else(|| "Unknown Artist".to_string()),
+        ),
+        (
+            "{albumartist}",
+            tags.album_artist().unwrap_or("Unknown Artist").to_string(),
+        ),
+        (
+            "{album}",
+            tags.album
+                .clone()
+                .unwrap_or_else(|| "Unknown Album".to_string()),
+        ),
+        ("{title}", tags.title.clone().unwrap_or(stem)),
+        ("{track}", number(tags.track)),
+        (
+            "{disc}",
+            tags.disc.map(|n| n.to_string()).unwrap_or_default(),
+        ),
+        (
+            "{year}",
+            tags.year.map(|n| n.to_string()).unwrap_or_default(),
+        ),
+    ];
+
+    let mut path = root.to_path_buf();
+    let parts: Vec<&str> = template.split('/').collect();
+    for (i, part) in parts.iter().enumerate() {
+        let mut name = part.to_string();
+        for (field, value) in &fields {
+            // Tag values can't add folders of their own
+            name = name.replace(field, &sanitize(value));
+        }
+        // Drop the separators left around empty fields, e.g. " - Title"
+        let name = name.trim_matches([' ', '-', '_', '.']).to_string();
+        let name = if name.is_empty() {
+            "Unknown".to_string()
+        } else {
+            name
+        };
+        if i + 1 == parts.len() {
+            match file.extension() {
+                Some(ext) => path.push(format!("{}.{}", name, ext.to_string_lossy())),
+                None => path.push(name),
+            }
+        } else {
+            path.push(name);
+        }
+    }
+    path
+}
+
+/// `path`, or `path (2)`, `path (3)`... if that exists or is `taken`
+pub fn unique_path(path: &Path, taken: &HashSet<PathBuf>) -> PathBuf {
+    let free = |path: &Path| !path.exists() && !taken.contains(path);
+    if free(path) {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|candidate| free(candidate))
+        .expect("some numbered name is free")
+}
+
+/// Replaces characters that aren't allowed in file names on some system
+pub fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Moves a file to the desktop's trash so it can be restored
+/// Follows the freedesktop.org trash spec on Linux and the BSDs, uses
+/// `~/.Trash` on macOS, and the Recycle Bin on Windows
+pub fn trash(file: &Path) -> io::Result<()> {
+    let file = std::path::absolute(file)?;
+    if cfg!(windows) {
+        return recycle(&file);
+    }
+    let home = env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No home directory"))?;
+    if cfg!(target_os = "macos") {
+        let target = unique_path(
+            &home.join(".Trash").join(file_name(&file)?),
+            &HashSet::new(),
+        );
+        return move_to(&file, &target);
+    }
+
+    let trash = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".local/share"))
+        .join("Trash");
+    let target = unique_path(
+        &trash.join("files").join(file_name(&file)?),
+        &HashSet::new(),
+    );
+    let info_dir = trash.join("info");
+    fs::create_dir_all(&info_dir)?;
+    // The info file is written first, as the spec asks
+    let info = info_dir.join(format!(
+        "{}.trashinfo",
+        file_name(&target)?.to_string_lossy()
+    ));
+    fs::write(
+        &info,
+        format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            playlist::percent_encode(&file.to_string_lossy()),
+            deletion_date()
+        ),
+    )?;
+    move_to(&file, &target).inspect_err(|_| {
+        let _ = fs::remove_file(&info);
+    })
+}
+
+fn file_name(path: &Path) -> io::Result<&std::ffi::OsStr> {
+    path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a file"))
+}
+
+/// Sends a file to the Windows Recycle Bin through PowerShell
+fn recycle(file: &Path) -> io::Result<()> {
+    let path = file.to_string_lossy().replace('\'', "''");
+    let status = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command"])
+        .arg(format!(
+            "Add-Type -AssemblyName Microsoft.VisualBasic; \
+             [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteFile('{}', 'OnlyErrorDialogs', 'SendToRecycleBin')",
+            path
+        ))
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(
+            "Could not move the file to the Recycle Bin",
+        ))
+    }
+}
+
+/// Current UTC time as `YYYY-MM-DDThh:mm:ss`
+fn deletion_date() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
@@ -0,0 +1,215 @@
+//! Synchronized playback across instances for cheap multi-room audio
+//! A leader pushes its playback state over TCP as one line per update,
+//! `<playing|paused> <position_ms> <track>` or `stopped`; followers play
+//! the same local files and restart at the leader's position whenever
+//! their own audio clock drifts too far from it
+//! The track is its path relative to the music directory, so every
+//! machine needs the same layout under its own `--dir`
+
+use crate::PlayerEvent;
+use rodio::Source;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Sender,
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Address the leader listens on when `--lead` is given without one
+pub const DEFAULT_LEAD_ADDR: &str = "0.0.0.0:6610";
+
+/// How often the leader repeats its state for drift correction
+const HEARTBEAT: Duration = Duration::from_secs(2);
+
+/// How long writing to a follower may block
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long a follower waits before reconnecting to a lost leader
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+/// Drift a follower accepts before restarting the track, unless
+/// `follow.max_drift_ms` says otherwise
+pub const DEFAULT_MAX_DRIFT: Duration = Duration::from_millis(150);
+
+/// Playback state shared from leader to followers
+#[derive(Clone, Debug, PartialEq)]
+pub enum State {
+    Playing(String, Duration), // Track and position
+    Paused(String, Duration),  // Track and position
+    Stopped,                   // Nothing playing
+}
+
+impl State {
+    fn to_line(&self) -> String {
+        match self {
+            State::Playing(track, position) => {
+                format!("playing {} {}\n", position.as_millis(), track)
+            }
+            State::Paused(track, position) => {
+                format!("paused {} {}\n", position.as_millis(), track)
+            }
+            State::Stopped => "stopped\n".to_string(),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, ' ');
+        let mode = fields.next()?;
+        if mode == "stopped" {
+            return Some(State::Stopped);
+        }
+        let position = Duration::from_millis(fields.next()?.parse().ok()?);
+        let track = fields.next()?.to_string();
+        match mode {
+            "playing" => Some(State::Playing(track, position)),
+            "paused" => Some(State::Paused(track, position)),
+            _ => None,
+        }
+    }
+}
+
+/// Followers connected to this instance
+#[derive(Clone, Default)]
+pub struct Leader {
+    followers: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl Leader {
+    /// Sends the state to every follower, dropping those that have gone
+    pub fn broadcast(&self, state: &State) {
+        let line = state.to_line();
+        if let Ok(mut followers) = self.followers.lock() {
+            followers.retain_mut(|follower| follower.write_all(line.as_bytes()).is_ok());
+        }
+    }
+}
+
+/// Accepts followers on `addr`, and sends a `PlayerEvent::Heartbeat` every
+/// couple of seconds so the current state keeps reaching them
+pub fn spawn_leader(addr: &str, events: Sender<PlayerEvent>) -> io::Result<Leader> {
+    let listener = TcpListener::bind(addr)?;
+    let leader = Leader::default();
+
+    let followers = leader.followers.clone();
+    spawn_heartbeat(events.clone());
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = stream.set_nodelay(true);
+            // A stalled follower mustn't hold up the main loop
+            let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+            if let Ok(mut followers) = followers.lock() {
+                followers.push(stream);
+            }
+            // New followers shouldn't wait for the next heartbeat
+            if events.send(PlayerEvent::Heartbeat).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(leader)
+}
+
+/// Connects to a leader and forwards its state as `PlayerEvent::Leader`,
+/// reconnecting whenever the connection drops
+pub fn spawn_follower(addr: String, events: Sender<PlayerEvent>) {
+    thread::spawn(move || loop {
+        if let Ok(stream) = TcpStream::connect(&addr) {
+            let _ = stream.set_nodelay(true);
+            println!("\nFollowing {}", addr);
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if let Some(state) = State::parse(line.trim_end()) {
+                    if events.send(PlayerEvent::Leader(state)).is_err() {
+                        return;
+                    }
+                }
+            }
+            println!("\nLost connection to {}, retrying", addr);
+        }
+        thread::sleep(RECONNECT_DELAY);
+    });
+}
+
+/// Sends a `PlayerEvent::Heartbeat` at a fixed interval
+fn spawn_heartbeat(events: Sender<PlayerEvent>) {
+    thread::spawn(move || loop {
+        thread::sleep(HEARTBEAT);
+        if events.send(PlayerEvent::Heartbeat).is_err() {
+            break;
+        }
+    });
+}
+
+/// Position of a track measured by the samples handed to the output, so
+/// it follows the sound card's clock rather than the wall clock
+#[derive(Clone, Debug)]
+pub struct Clock {
+    samples: Arc<AtomicU64>, // Samples played since the offset
+    per_second: u64,         // Samples per second across all channels
+    offset: Duration,        // Where in the track counting started
+}
+
+impl Clock {
+    pub fn position(&self) -> Duration {
+        let samples = self.samples.load(Ordering::Relaxed);
+        self.offset + Duration::from_secs_f64(samples as f64 / self.per_second.max(1) as f64)
+    }
+}
+
+/// Wraps a source so a `Clock` can tell how far it has played
+pub fn counted<S: Source<Item = f32>>(source: S, offset: Duration) -> (Counted<S>, Clock) {
+    let clock = Clock {
+        samples: Arc::default(),
+        per_second: source.sample_rate() as u64 * source.channels() as u64,
+        offset,
+    };
+    let samples = clock.samples.clone();
+    (
+        Counted {
+            inner: source,
+            samples,
+        },
+        clock,
+    )
+}
+
+/// Source counting the samples pulled through it
+pub struct Counted<S> {
+    inner: S,
+    samples: Arc<AtomicU64>,
+}
+
+impl<S: Source<Item = f32>> Iterator for Counted<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        self.samples.fetch_add(1, Ordering::Relaxed);
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Counted<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
@@ -0,0 +1,222 @@
+//! Playback through a GStreamer `playbin` pipeline, which picks up any
+//! codec or source the installed GStreamer plugins provide
+//! Only built with the `gstreamer` feature, which links against the
+//! system's GStreamer libraries; like mpv, it skips our effects
+
+use std::{
+    cell::Cell,
+    ffi::{c_char, c_double, c_int, c_uint, c_void, CString},
+    path::Path,
+    ptr,
+    sync::Once,
+    time::Duration,
+};
+
+/// `GstState` values
+const STATE_NULL: c_int = 1;
+const STATE_PAUSED: c_int = 3;
+const STATE_PLAYING: c_int = 4;
+/// `GST_STATE_CHANGE_FAILURE`
+const CHANGE_FAILURE: c_int = 0;
+/// `GST_MESSAGE_EOS | GST_MESSAGE_ERROR`
+const MESSAGE_END: c_uint = 1 | 2;
+/// `GST_FORMAT_TIME`
+const FORMAT_TIME: c_int = 3;
+/// `GST_SEEK_FLAG_FLUSH | GST_SEEK_FLAG_ACCURATE`
+const SEEK_FLAGS: c_int = 1 | 2;
+/// `GST_CLOCK_TIME_NONE`, waiting as long as it takes
+const CLOCK_TIME_NONE: u64 = u64::MAX;
+
+#[link(name = "gstreamer-1.0")]
+extern "C" {
+    fn gst_init(argc: *mut c_int, argv: *mut *mut *mut c_char);
+    fn gst_element_factory_make(factory: *const c_char, name: *const c_char) -> *mut c_void;
+    fn gst_element_set_state(element: *mut c_void, state: c_int) -> c_int;
+    fn gst_element_get_state(
+        element: *mut c_void,
+        state: *mut c_int,
+        pending: *mut c_int,
+        timeout: u64,
+    ) -> c_int;
+    fn gst_element_seek_simple(
+        element: *mut c_void,
+        format: c_int,
+        flags: c_int,
+        position: i64,
+    ) -> c_int;
+    fn gst_element_get_bus(element: *mut c_void) -> *mut c_void;
+    fn gst_bus_pop_filtered(bus: *mut c_void, types: c_uint) -> *mut c_void;
+    fn gst_mini_object_unref(object: *mut c_void);
+    fn gst_object_ref_sink(object: *mut c_void) -> *mut c_void;
+    fn gst_object_unref(object: *mut c_void);
+    fn gst_filename_to_uri(filename: *const c_char, error: *mut *mut c_void) -> *mut c_char;
+}
+
+#[link(name = "gobject-2.0")]
+extern "C" {
+    fn g_object_set(object: *mut c_void, first_property: *const c_char, ...);
+}
+
+#[link(name = "glib-2.0")]
+extern "C" {
+    fn g_free(memory: *mut c_void);
+}
+
+/// A playbin pipeline playing one track at a time
+pub struct Playbin {
+    playbin: *mut c_void, // The GstElement
+    bus: *mut c_void,     // Its message bus, polled for the end of a track
+    volume: f32,          // Volume as a factor, as playbin takes it
+    finished: Cell<bool>, // The last track ended, failed or was stopped
+}
+
+// SAFETY: GStreamer elements and buses are thread-safe objects
+unsafe impl Send for Playbin {}
+
+impl Playbin {
+    /// Creates the pipeline, initialising GStreamer on first use
+    pub fn new() -> Result<Self, String> {
+        static INIT: Once = Once::new();
+        // SAFETY: gst_init accepts null arguments and runs only once
+        INIT.call_once(|| unsafe { gst_init(ptr::null_mut(), ptr::null_mut()) });
+
+        let factory = c_string("playbin")?;
+        // SAFETY: a NUL-terminated factory name; null means it is missing
+        let playbin = unsafe { gst_element_factory_make(factory.as_ptr(), ptr::null()) };
+        if playbin.is_null() {
+            return Err(
+                "GStreamer has no playbin element; is gst-plugins-base installed?".to_string(),
+            );
+        }
+        // SAFETY: playbin is a live element; sinking its floating reference
+        // makes it ours, as is the returned bus reference
+        let bus = unsafe {
+            gst_object_ref_sink(playbin);
+            gst_element_get_bus(playbin)
+        };
+        Ok(Self {
+            playbin,
+            bus,
+            volume: 1.0,
+            finished: Cell::new(true),
+        })
+    }
+
+    /// Replaces whatever is playing with a file, starting at `offset`
+    pub fn load(&self, path: &Path, offset: Duration) -> Result<(), String> {
+        let path = c_string(&path.to_string_lossy())?;
+        // SAFETY: a NUL-terminated path; the URI is freed with g_free below
+        let uri = unsafe { gst_filename_to_uri(path.as_ptr(), ptr::null_mut()) };
+        if uri.is_null() {
+            return Err("GStreamer could not turn the path into a URI".to_string());
+        }
+        let property = c_string("uri")?;
+        // SAFETY: the pipeline is live; "uri" takes a string, and g_object_set
+        // copies it before it is freed
+        unsafe {
+            gst_element_set_state(self.playbin, STATE_NULL);
+            g_object_set(self.playbin, property.as_ptr(), uri, ptr::null::<c_char>());
+            g_free(uri as *mut c_void);
+        }
+        self.drain_messages();
+
+        if !offset.is_zero() {
+            // Seeking needs the pipeline prerolled in the paused state
+            // SAFETY: the pipeline is live
+            unsafe {
+                self.set_state(STATE_PAUSED)?;
+                gst_element_get_state(
+                    self.playbin,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    CLOCK_TIME_NONE,
+                );
+                gst_element_seek_simple(
+                    self.playbin,
+                    FORMAT_TIME,
+                    SEEK_FLAGS,
+                    offset.as_nanos() as i64,
+                );
+            }
+        }
+        self.set_state(STATE_PLAYING)?;
+        self.finished.set(false);
+        Ok(())
+    }
+
+    /// Whether the last track is over
+    pub fn empty(&self) -> bool {
+        if self.drain_messages() {
+            self.finished.set(true);
+        }
+        self.finished.get()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        let _ = self.set_state(if paused { STATE_PAUSED } else { STATE_PLAYING });
+    }
+
+    pub fn stop(&self) {
+        let _ = self.set_state(STATE_NULL);
+        self.drain_messages();
+        self.finished.set(true);
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.max(0.0);
+        if let Ok(property) = c_string("volume") {
+            // SAFETY: the pipeline is live and "volume" takes a double
+            unsafe {
+                g_object_set(
+                    self.playbin,
+                    property.as_ptr(),
+                    self.volume as c_double,
+                    ptr::null::<c_char>(),
+                );
+            }
+        }
+    }
+
+    fn set_state(&self, state: c_int) -> Result<(), String> {
+        // SAFETY: the pipeline is live
+        if unsafe { gst_element_set_state(self.playbin, state) } == CHANGE_FAILURE {
+            return Err("GStreamer could not play the track".to_string());
+        }
+        Ok(())
+    }
+
+    /// Discards queued end-of-stream and error messages, returning whether
+    /// there were any
+    fn drain_messages(&self) -> bool {
+        let mut any = false;
+        loop {
+            // SAFETY: the bus is live; a popped message is ours to unref
+            let message = unsafe { gst_bus_pop_filtered(self.bus, MESSAGE_END) };
+            if message.is_null() {
+                return any;
+            }
+            // SAFETY: see above
+            unsafe { gst_mini_object_unref(message) };
+            any = true;
+        }
+    }
+}
+
+impl Drop for Playbin {
+    fn drop(&mut self) {
+        // SAFETY: both references are ours and not used after this
+        unsafe {
+            gst_element_set_state(self.playbin, STATE_NULL);
+            gst_object_unref(self.bus);
+            gst_object_unref(self.playbin);
+        }
+    }
+}
+
+fn c_string(value: &str) -> Result<CString, String> {
+    CString::new(value).map_err(|_| format!("`{}` contains a NUL byte", value))
+}
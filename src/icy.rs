@@ -0,0 +1,316 @@
+//! Internet radio: Shoutcast and Icecast stations over HTTP(S), fetched
+//! through `curl` with ICY metadata asked for
+//! A station that agrees puts the title of the song on air into the
+//! stream every `icy-metaint` bytes; those blocks are taken out before the
+//! decoder sees the audio and kept for `status` and the like
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    process::{Child, ChildStdout, Command, Stdio},
+    sync::{Arc, Condvar, Mutex, Weak},
+    thread,
+};
+
+/// Bytes read from curl at a time
+const CHUNK: usize = 16 * 1024;
+
+/// Bytes kept behind the decoder, for format probes that seek back to the
+/// start of the stream
+const KEEP: u64 = 256 * 1024;
+
+/// Most bytes held ahead of the decoder, e.g. while paused; the station is
+/// left to wait beyond that
+const AHEAD: usize = 8 * 1024 * 1024;
+
+/// Station of the stream opened last, for `on_air`
+static TUNED: Mutex<Option<Weak<Shared>>> = Mutex::new(None);
+
+/// Whether a track is a station rather than a file
+pub fn is_stream(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// What the station playing says about itself
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OnAir {
+    pub station: Option<String>, // From `icy-name`
+    pub title: Option<String>,   // `StreamTitle` of the song on air
+}
+
+/// The station being listened to, while one is
+pub fn on_air() -> Option<OnAir> {
+    let shared = TUNED.lock().unwrap().as_ref()?.upgrade()?;
+    let state = shared.state.lock().unwrap();
+    Some(state.on_air.clone())
+}
+
+#[derive(Default)]
+struct State {
+    data: Vec<u8>,         // Audio from `start` on
+    start: u64,            // Stream offset of the first byte in `data`
+    done: bool,            // The station hung up, or curl failed
+    error: Option<String>, // Why the stream ended, if not cleanly
+    closed: bool,          // The decoder is gone
+    on_air: OnAir,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar, // Signalled whenever the state changes
+}
+
+/// A station's audio, readable as it arrives
+pub struct Stream {
+    shared: Arc<Shared>,
+    position: u64, // Where the decoder is reading
+}
+
+impl Stream {
+    /// Tunes in, returning once the station has answered
+    pub fn connect(url: &Path) -> io::Result<Self> {
+        let mut child = Command::new("curl")
+            .args([
+                "--silent",
+                "--show-error",
+                "--fail",
+                "--location",
+                "--no-buffer",
+                "--include",
+                // Older Shoutcast servers answer `ICY 200 OK`, which curl
+                // only passes through as HTTP/0.9
+                "--http0.9",
+                "--connect-timeout",
+                "10",
+                "--header",
+                "Icy-MetaData: 1",
+                "--user-agent",
+                concat!("musicplayer/", env!("CARGO_PKG_VERSION")),
+                "--",
+            ])
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => {
+                    io::Error::new(e.kind(), "Radio streams need curl installed")
+                }
+                _ => e,
+            })?;
+        let mut reader = BufReader::new(child.stdout.take().unwrap());
+        let (metaint, station) = match read_headers(&mut reader) {
+            Ok(headers) => headers,
+            Err(e) => return Err(failure(&mut child).unwrap_or(e)),
+        };
+        let shared = Arc::new(Shared::default());
+        shared.state.lock().unwrap().on_air.station = station;
+        *TUNED.lock().unwrap() = Some(Arc::downgrade(&shared));
+        let feed = Arc::clone(&shared);
+        thread::spawn(move || {
+            let error = match receive(&mut reader, metaint, &feed) {
+                Ok(false) => None,
+                Ok(true) => failure(&mut child).map(|e| e.to_string()),
+                Err(e) => Some(e.to_string()),
+            };
+            let _ = child.kill();
+            let _ = child.wait();
+            let mut state = feed.state.lock().unwrap();
+            state.done = true;
+            state.error = error;
+            feed.changed.notify_all();
+        });
+        Ok(Self {
+            shared,
+            position: 0,
+        })
+    }
+}
+
+/// Reads the response headers up to the audio, passing over redirects,
+/// and returns the metadata interval and station name
+fn read_headers(
+    reader: &mut BufReader<ChildStdout>,
+) -> io::Result<(Option<usize>, Option<String>)> {
+    loop {
+        let mut status = String::new();
+        if reader.read_line(&mut status)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The station didn't answer",
+            ));
+        }
+        // `HTTP/1.1 200 OK`, or `ICY 200 OK` from older Shoutcast servers
+        let code: u16 = status
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .unwrap_or(0);
+        let mut metaint = None;
+        let mut station = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_lowercase().as_str() {
+                "icy-metaint" => metaint = value.parse().ok().filter(|&n: &usize| n > 0),
+                "icy-name" if !value.is_empty() => station = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        // Redirects and `100 Continue` are followed by the real response
+        if !(100..200).contains(&code) && !(300..400).contains(&code) {
+            return Ok((metaint, station));
+        }
+    }
+}
+
+/// Moves audio into `shared` until the station hangs up, taking out the
+/// metadata blocks; false if it stopped because the decoder went away
+fn receive(
+    reader: &mut BufReader<ChildStdout>,
+    metaint: Option<usize>,
+    shared: &Shared,
+) -> io::Result<bool> {
+    let mut chunk = vec![0; CHUNK];
+    let mut until_metadata = metaint.unwrap_or(usize::MAX);
+    loop {
+        if until_metadata == 0 {
+            let mut length = [0u8];
+            reader.read_exact(&mut length)?;
+            let mut block = vec![0; length[0] as usize * 16];
+            reader.read_exact(&mut block)?;
+            if let Some(title) = stream_title(&block) {
+                let mut state = shared.state.lock().unwrap();
+                state.on_air.title = Some(title).filter(|title| !title.is_empty());
+            }
+            until_metadata = metaint.unwrap_or(usize::MAX);
+            continue;
+        }
+        let n = match reader.read(&mut chunk[..CHUNK.min(until_metadata)]) {
+            Ok(0) => return Ok(true),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        until_metadata -= n;
+        let mut state = shared.state.lock().unwrap();
+        while state.data.len() > AHEAD && !state.closed {
+            state = shared.changed.wait(state).unwrap();
+        }
+        if state.closed {
+            return Ok(false);
+        }
+        state.data.extend_from_slice(&chunk[..n]);
+        shared.changed.notify_all();
+    }
+}
+
+/// `StreamTitle` out of a metadata block such as
+/// `StreamTitle='Artist - Song';StreamUrl='';`, padded with zeros
+fn stream_title(block: &[u8]) -> Option<String> {
+    let end = block.iter().position(|&b| b == 0).unwrap_or(block.len());
+    // Stations that aren't UTF-8 are nearly always Latin-1
+    let text = match std::str::from_utf8(&block[..end]) {
+        Ok(text) => text.to_string(),
+        Err(_) => block[..end].iter().map(|&b| b as char).collect(),
+    };
+    let rest = text.split_once("StreamTitle='")?.1;
+    let title = rest
+        .split_once("';")
+        .map_or(rest.trim_end_matches('\''), |(title, _)| title);
+    Some(title.trim().to_string())
+}
+
+/// What curl said went wrong, once it has exited with an error
+fn failure(child: &mut Child) -> Option<io::Error> {
+    let mut stderr = String::new();
+    child.stderr.take()?.read_to_string(&mut stderr).ok()?;
+    let status = child.wait().ok()?;
+    if status.success() {
+        return None;
+    }
+    let message = stderr.trim().trim_start_matches("curl: ");
+    Some(io::Error::other(match message {
+        "" => "The station didn't answer".to_string(),
+        message => message.to_string(),
+    }))
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            let end = state.start + state.data.len() as u64;
+            if self.position < state.start {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "That part of the stream has gone by",
+                ));
+            }
+            if self.position < end {
+                let from = (self.position - state.start) as usize;
+                let n = buf.len().min(state.data.len() - from);
+                buf[..n].copy_from_slice(&state.data[from..from + n]);
+                self.position += n as u64;
+                // What was played is dropped now and then, keeping a little
+                let behind = self.position - state.start;
+                if behind > 2 * KEEP {
+                    let drop = (behind - KEEP) as usize;
+                    state.data.drain(..drop);
+                    state.start += drop as u64;
+                    self.shared.changed.notify_all();
+                }
+                return Ok(n);
+            }
+            if state.done {
+                return match &state.error {
+                    Some(e) => Err(io::Error::other(e.clone())),
+                    None => Ok(0),
+                };
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+}
+
+impl Seek for Stream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "A live stream has no end",
+                ))
+            }
+        };
+        self.position = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of the stream",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
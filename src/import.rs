@@ -0,0 +1,342 @@
+//! Importing playlists and track history from other players
+//! Tracks are matched to local files by path first, then by file name,
+//! then by artist and title, since libraries often move between machines
+
+use crate::{
+    json::Json,
+    playlist::{self, Playlist, Track},
+    plist,
+    stats::Stats,
+};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// What an import brought in
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub playlists: usize,   // Playlists written
+    pub ratings: usize,     // Tracks that got a rating
+    pub play_counts: usize, // Tracks that got a play count
+    pub missing: usize,     // Tracks with no local file
+}
+
+/// Finds the local copy of a track from another library
+pub struct Matcher {
+    by_name: HashMap<String, PathBuf>, // Lowercase file name
+    by_stem: HashMap<String, PathBuf>, // Lowercase stem without track number
+}
+
+impl Matcher {
+    pub fn new(library: &[PathBuf]) -> Self {
+        let mut by_name = HashMap::new();
+        let mut by_stem = HashMap::new();
+        for path in library {
+            if let Some(name) = path.file_name() {
+                by_name.insert(name.to_string_lossy().to_lowercase(), path.clone());
+            }
+            if let Some(stem) = path.file_stem() {
+                by_stem.insert(normalize_stem(&stem.to_string_lossy()), path.clone());
+            }
+        }
+        Self { by_name, by_stem }
+    }
+
+    pub fn find(&self, path: &Path, artist: Option<&str>, title: Option<&str>) -> Option<PathBuf> {
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+        if let Some(found) = path
+            .file_name()
+            .and_then(|name| self.by_name.get(&name.to_string_lossy().to_lowercase()))
+        {
+            return Some(found.clone());
+        }
+        let title = title?;
+        let candidates = [
+            artist.map(|artist| format!("{} - {}", artist, title)),
+            Some(title.to_string()),
+        ];
+        candidates
+            .into_iter()
+            .flatten()
+            .find_map(|candidate| self.by_stem.get(&normalize_stem(&candidate)).cloned())
+    }
+}
+
+/// Imports playlists, ratings and play counts from an iTunes or
+/// Music.app `Library.xml` export
+/// Playlists land in an `itunes` folder, keeping iTunes' own folders
+pub fn itunes(file: &Path, matcher: &Matcher, stats: &mut Stats) -> io::Result<Summary> {
+    let text = fs::read_to_string(file)?;
+    let library = plist::parse(&text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", file.display(), e),
+        )
+    })?;
+    let mut summary = Summary::default();
+
+    // Track ID -> local file
+    let mut tracks: HashMap<i64, Track> = HashMap::new();
+    if let Some(Json::Object(entries)) = library.get("Tracks") {
+        for (_, entry) in entries {
+            let Some(id) = entry.get("Track ID").and_then(Json::as_f64) else {
+                continue;
+            };
+            let Some(location) = entry.get("Location").and_then(Json::as_str) else {
+                continue;
+            };
+            let path = location
+                .strip_prefix("file://localhost")
+                .or_else(|| location.strip_prefix("file://"))
+                .map(playlist::percent_decode)
+                .unwrap_or_else(|| location.to_string());
+            let artist = entry.get("Artist").and_then(Json::as_str);
+            let title = entry.get("Name").and_then(Json::as_str);
+            let Some(local) = matcher.find(Path::new(&path), artist, title) else {
+                summary.missing += 1;
+                continue;
+            };
+
+            // Album ratings iTunes derives for unrated tracks aren't real ratings
+            let computed = entry.get("Rating Computed") == Some(&Json::Bool(true));
+            let rating = entry
+                .get("Rating")
+                .and_then(Json::as_f64)
+                .filter(|rating| *rating > 0.0 && !computed);
+            if let Some(rating) = rating {
+                stats.entry(&local).rating = Some((rating / 20.0).round().clamp(1.0, 5.0) as u8);
+                summary.ratings += 1;
+            }
+            if let Some(plays) = entry.get("Play Count").and_then(Json::as_f64) {
+                let track = stats.entry(&local);
+                // Importing twice shouldn't double the counts
+                track.plays = track.plays.max(plays as u64);
+                summary.play_counts += 1;
+            }
+
+            tracks.insert(
+                id as i64,
+                Track {
+                    path: local,
+                    artist: artist.map(str::to_string),
+                    title: title.map(str::to_string),
+                    duration: entry
+                        .get("Total Time")
+                        .and_then(Json::as_f64)
+                        .map(|ms| (ms / 1000.0) as u64),
+                },
+            );
+        }
+    }
+
+    let Some(Json::Array(playlists)) = library.get("Playlists") else {
+        return Ok(summary);
+    };
+    // Persistent ID -> (name, parent ID) for playlist folders
+    let folders: HashMap<&str, (&str, Option<&str>)> = playlists
+        .iter()
+        .filter(|list| list.get("Folder") == Some(&Json::Bool(true)))
+        .filter_map(|list| {
+            Some((
+                list.get("Playlist Persistent ID")?.as_str()?,
+                (
+                    list.get("Name")?.as_str()?,
+                    list.get("Parent Persistent ID").and_then(Json::as_str),
+                ),
+            ))
+        })
+        .collect();
+
+    for list in playlists {
+        let flag = |key: &str| list.get(key) == Some(&Json::Bool(true));
+        // Skip the whole library, the built-in Music/Podcasts/... lists, and folders
+        if flag("Master") || flag("Folder") || list.get("Distinguished Kind").is_some() {
+            continue;
+        }
+        let Some(name) = list.get("Name").and_then(Json::as_str) else {
+            continue;
+        };
+
+        let mut parts = vec![sanitize(name)];
+        let mut parent = list.get("Parent Persistent ID").and_then(Json::as_str);
+        while let Some((folder, grandparent)) = parent.and_then(|id| folders.get(id)) {
+            parts.push(sanitize(folder));
+            parent = *grandparent;
+        }
+        parts.push("itunes".to_string());
+        parts.reverse();
+
+        let items = match list.get("Playlist Items") {
+            Some(Json::Array(items)) => items.as_slice(),
+            _ => &[],
+        };
+        let playlist = Playlist {
+            name: parts.join("/"),
+            tracks: items
+                .iter()
+                .filter_map(|item| item.get("Track ID")?.as_f64())
+                .filter_map(|id| tracks.get(&(id as i64)).cloned())
+                .collect(),
+            labels: Vec::new(),
+        };
+        playlist.save()?;
+        summary.playlists += 1;
+    }
+    Ok(summary)
+}
+
+/// Imports playlists and song stickers from an MPD data directory
+/// (usually `~/.config/mpd` or `~/.mpd`)
+/// Playlist entries are relative to MPD's `music_directory`, taken from
+/// `mpd.conf` when it is there and `music_dir` otherwise; ratings and play
+/// counts are read from `sticker.sql` through the `sqlite3` tool
+pub fn mpd(
+    dir: &Path,
+    music_dir: &Path,
+    matcher: &Matcher,
+    stats: &mut Stats,
+) -> io::Result<Summary> {
+    let music_dir = mpd_music_directory(dir).unwrap_or_else(|| music_dir.to_path_buf());
+    let mut summary = Summary::default();
+
+    let lists = fs::read_dir(dir.join("playlists")).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("{}: no MPD playlists directory", dir.display()),
+        )
+    })?;
+    for entry in lists.flatten() {
+        let file = entry.path();
+        if file.extension().is_none_or(|ext| ext != "m3u") {
+            continue;
+        }
+        let Some(name) = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+        else {
+            continue;
+        };
+        let text = fs::read_to_string(&file)?;
+        let mut tracks = Vec::new();
+        for mut track in playlist::parse(playlist::Format::M3u, &text, Some(&music_dir)) {
+            match matcher.find(&track.path, track.artist.as_deref(), track.title.as_deref()) {
+                Some(local) => {
+                    track.path = local;
+                    tracks.push(track);
+                }
+                None => summary.missing += 1,
+            }
+        }
+        Playlist {
+            name: format!("mpd/{}", sanitize(&name)),
+            tracks,
+            labels: Vec::new(),
+        }
+        .save()?;
+        summary.playlists += 1;
+    }
+
+    let stickers = dir.join("sticker.sql");
+    if stickers.is_file() {
+        for (uri, name, value) in read_stickers(&stickers)? {
+            let track = Track::from_path(music_dir.join(&uri));
+            let Some(local) =
+                matcher.find(&track.path, track.artist.as_deref(), track.title.as_deref())
+            else {
+                continue;
+            };
+            match name.as_str() {
+                // 0-10, as used by ncmpcpp and most clients
+                "rating" => {
+                    if let Some(rating) = value.parse::<f64>().ok().filter(|r| *r > 0.0) {
+                        stats.entry(&local).rating =
+                            Some((rating / 2.0).round().clamp(1.0, 5.0) as u8);
+                        summary.ratings += 1;
+                    }
+                }
+                "playCount" | "playcount" => {
+                    if let Ok(plays) = value.parse::<u64>() {
+                        let track = stats.entry(&local);
+                        track.plays = track.plays.max(plays);
+                        summary.play_counts += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// `music_directory` from the `mpd.conf` next to the data directory
+fn mpd_music_directory(dir: &Path) -> Option<PathBuf> {
+    let text = fs::read_to_string(dir.join("mpd.conf")).ok()?;
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("music_directory"))?;
+    let value = line["music_directory".len()..].trim().trim_matches('"');
+    match value.strip_prefix("~/") {
+        Some(rest) => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(rest)),
+        None => Some(PathBuf::from(value)),
+    }
+}
+
+/// `(uri, name, value)` of every song sticker
+fn read_stickers(database: &Path) -> io::Result<Vec<(String, String, String)>> {
+    let output = Command::new("sqlite3")
+        .arg("-separator")
+        .arg("\t")
+        .arg(database)
+        .arg("SELECT uri, name, value FROM sticker WHERE type = 'song'")
+        .output()
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                "Reading MPD stickers needs the sqlite3 command-line tool",
+            )
+        })?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some((
+                fields.next()?.to_string(),
+                fields.next()?.to_string(),
+                fields.next()?.to_string(),
+            ))
+        })
+        .collect())
+}
+
+/// Lowercase stem with a leading track number ("01 ", "01. ", "1-") cut off
+fn normalize_stem(stem: &str) -> String {
+    let stem = stem.trim().to_lowercase();
+    let rest = stem.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() < stem.len() {
+        let rest = rest.trim_start_matches(['.', '-', '_', ' ']);
+        if !rest.is_empty() {
+            return rest.to_string();
+        }
+    }
+    stem
+}
+
+/// Playlist names can't contain path separators
+fn sanitize(name: &str) -> String {
+    let name = name.replace(['/', '\\'], "-");
+    match name.trim() {
+        "" | "." | ".." => "untitled".to_string(),
+        name => name.to_string(),
+    }
+}
@@ -0,0 +1,192 @@
+//! Places other programs hand tracks to the player through, so they can be
+//! queued as they arrive: a playlist file they append to, e.g. a download
+//! script writing one path per finished file, and a drop folder they save
+//! audio files into
+//! Of a playlist file only what is appended after following starts counts,
+//! and only whole lines; a file that shrinks is taken to be rewritten and
+//! read on from its new end
+
+use crate::{
+    codecs,
+    playlist::{self, Format},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How often the file is checked for new lines
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A followed playlist file; following stops when this is dropped
+pub struct Inbox {
+    path: PathBuf,
+    appended: Arc<Mutex<Vec<PathBuf>>>, // Tracks appended since last asked
+    stop: Arc<AtomicBool>,
+}
+
+impl Inbox {
+    /// Starts following `path` from its current end
+    pub fn follow(path: &Path) -> io::Result<Self> {
+        let mut offset = File::open(path)?.metadata()?.len();
+        let appended = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let inbox = Self {
+            path: path.to_path_buf(),
+            appended: Arc::clone(&appended),
+            stop: Arc::clone(&stop),
+        };
+        let path = path.to_path_buf();
+        // Relative lines are relative to the playlist, as when importing one
+        let base = path.parent().map(Path::to_path_buf);
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+                let Ok(text) = read_from(&path, &mut offset) else {
+                    continue;
+                };
+                let tracks = playlist::parse(Format::M3u, &text, base.as_deref());
+                if !tracks.is_empty() {
+                    let mut appended = appended.lock().unwrap();
+                    appended.extend(tracks.into_iter().map(|track| track.path));
+                }
+            }
+        });
+        Ok(inbox)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Tracks appended to the file since the last call
+    pub fn take_appended(&self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.appended.lock().unwrap())
+    }
+}
+
+impl Drop for Inbox {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A folder watched for audio files saved into it; watching stops when
+/// this is dropped
+pub struct DropFolder {
+    dir: PathBuf,
+    arrived: Arc<Mutex<Vec<PathBuf>>>, // Files complete since last asked
+    stop: Arc<AtomicBool>,
+}
+
+impl DropFolder {
+    /// Starts watching `dir`; files already in it count as arrived only
+    /// with `existing`
+    pub fn watch(dir: &Path, existing: bool) -> io::Result<Self> {
+        if !fs::metadata(dir)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Not a directory",
+            ));
+        }
+        let arrived = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let folder = Self {
+            dir: dir.to_path_buf(),
+            arrived: Arc::clone(&arrived),
+            stop: Arc::clone(&stop),
+        };
+        let dir = dir.to_path_buf();
+        let mut seen: HashSet<PathBuf> = match existing {
+            true => HashSet::new(),
+            false => audio_files(&dir).into_keys().collect(),
+        };
+        // Size of each new file at the last check; a file counts once it
+        // stops growing, so one still being downloaded isn't queued early
+        let mut growing: HashMap<PathBuf, u64> = HashMap::new();
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                let files = audio_files(&dir);
+                seen.retain(|path| files.contains_key(path));
+                let mut complete = Vec::new();
+                for (path, size) in files {
+                    if seen.contains(&path) {
+                        continue;
+                    }
+                    if growing.get(&path) == Some(&size) {
+                        growing.remove(&path);
+                        seen.insert(path.clone());
+                        complete.push(path);
+                    } else {
+                        growing.insert(path, size);
+                    }
+                }
+                if !complete.is_empty() {
+                    complete.sort();
+                    arrived.lock().unwrap().extend(complete);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+        Ok(folder)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Files that arrived since the last call, by name
+    pub fn take_arrived(&self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.arrived.lock().unwrap())
+    }
+}
+
+impl Drop for DropFolder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Playable files directly in `dir` and their sizes; hidden ones, such as
+/// the partial files some downloaders write, are left out
+fn audio_files(dir: &Path) -> HashMap<PathBuf, u64> {
+    let entries = fs::read_dir(dir).into_iter().flatten().flatten();
+    entries
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let path = entry.path();
+            (metadata.is_file() && codecs::supported(&path)).then_some((path, metadata.len()))
+        })
+        .collect()
+}
+
+/// Whole lines written past `offset`, moving `offset` past them
+fn read_from(path: &Path, offset: &mut u64) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len < *offset {
+        *offset = len;
+    }
+    if len == *offset {
+        return Ok(String::new());
+    }
+    file.seek(SeekFrom::Start(*offset))?;
+    let mut bytes = Vec::new();
+    file.take(len - *offset).read_to_end(&mut bytes)?;
+    // A line still being written is left for the next check
+    let Some(end) = bytes.iter().rposition(|&b| b == b'\n') else {
+        return Ok(String::new());
+    };
+    bytes.truncate(end + 1);
+    *offset += bytes.len() as u64;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
@@ -0,0 +1,380 @@
+//! Minimal JSON value type with a parser and serializer
+//! Just enough for the JSON-RPC interface without pulling in serde
+
+use std::fmt;
+
+/// A parsed JSON document
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>), // Keeps member order for stable output
+}
+
+impl Json {
+    /// Builds an object from key/value pairs
+    pub fn object<K: Into<String>>(members: Vec<(K, Json)>) -> Json {
+        Json::Object(members.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Looks up a member of an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a number, if it is one
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a string slice, if it is one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl fmt::Display for Json {
+    /// Serializes the value as compact single-line JSON
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Writes a quoted, escaped JSON string
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Parses a complete JSON document
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.chars.len() {
+        return Err(format!("Unexpected trailing data at {}", parser.pos));
+    }
+    Ok(value)
+}
+
+/// Deepest nesting of arrays and objects accepted, so input from the
+/// network can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+/// Recursive-descent parser over the input characters
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize, // Arrays and objects open around the current value
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(format!("Expected '{}' at {}", expected, self.pos - 1)),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.literal("null", Json::Null),
+            Some('t') => self.literal("true", Json::Bool(true)),
+            Some('f') => self.literal("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[' | '{') if self.depth == MAX_DEPTH => {
+                Err(format!("Nested too deeply at {}", self.pos))
+            }
+            Some('[') => {
+                self.depth += 1;
+                let array = self.array();
+                self.depth -= 1;
+                array
+            }
+            Some('{') => {
+                self.depth += 1;
+                let object = self.object();
+                self.depth -= 1;
+                object
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(format!("Unexpected character at {}", self.pos)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse::<f64>()
+            .map(Json::Number)
+            .map_err(|_| format!("Invalid number at {}", start))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => match self.next() {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('/') => out.push('/'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('u') => out.push(self.unicode_escape()?),
+                    _ => return Err(format!("Invalid escape at {}", self.pos - 1)),
+                },
+                Some(c) => out.push(c),
+                None => return Err("Unterminated string".to_string()),
+            }
+        }
+    }
+
+    /// Decodes the digits of a `\u` escape, combining surrogate pairs
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            self.expect('\\')?;
+            self.expect('u')?;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(format!("Unpaired surrogate at {}", self.pos));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| format!("Invalid code point at {}", self.pos))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| format!("Invalid unicode escape at {}", self.pos - 1))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(items)),
+                _ => return Err(format!("Expected ',' or ']' at {}", self.pos - 1)),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(members)),
+                _ => return Err(format!("Expected ',' or '}}' at {}", self.pos - 1)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        let value = parse(r#"{"a": [1, -2.5e1, true, null], "b": {}}"#).unwrap();
+        assert_eq!(
+            value,
+            Json::object(vec![
+                (
+                    "a",
+                    Json::Array(vec![
+                        Json::from(1.0),
+                        Json::from(-25.0),
+                        Json::from(true),
+                        Json::Null,
+                    ])
+                ),
+                ("b", Json::Object(Vec::new())),
+            ])
+        );
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let within = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(parse(&within).is_ok());
+        let deeper = format!("{}{}", "[".repeat(MAX_DEPTH + 1), "]".repeat(MAX_DEPTH + 1));
+        assert!(parse(&deeper).is_err());
+        assert!(parse(&"[{\"a\":".repeat(200_000)).is_err());
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        let value = parse(r#""q\"b\\s\/n\nt\tu\u00e9""#).unwrap();
+        assert_eq!(value.as_str(), Some("q\"b\\s/n\nt\tu\u{e9}"));
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse(r#""\u12""#).is_err());
+        assert!(parse(r#""open"#).is_err());
+    }
+
+    #[test]
+    fn combines_surrogate_pairs() {
+        assert_eq!(
+            parse(r#""\ud83c\udfb5""#).unwrap().as_str(),
+            Some("\u{1f3b5}")
+        );
+        assert!(parse(r#""\ud83c""#).is_err());
+        assert!(parse(r#""\ud83c\u0041""#).is_err());
+        assert!(parse(r#""\udfb5""#).is_err());
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        assert!(parse("1 2").is_err());
+        assert!(parse("{} x").is_err());
+        assert!(parse(" [1] \n").is_ok());
+    }
+
+    #[test]
+    fn round_trips_strings() {
+        let text = "tab\tquote\" \u{1}";
+        let written = Json::from(text).to_string();
+        assert_eq!(parse(&written).unwrap().as_str(), Some(text));
+    }
+}
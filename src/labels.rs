@@ -0,0 +1,143 @@
+//! User labels such as moods (`chill`, `workout`) attached to tracks
+//! Kept in the state directory rather than in the files, so read-only and
+//! remote tracks can be labelled too
+
+use crate::session;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const LABELS_FILE: &str = "labels";
+
+/// Labels of every track that has any, keyed by path
+#[derive(Clone, Debug, Default)]
+pub struct Labels {
+    tracks: BTreeMap<PathBuf, BTreeSet<String>>,
+}
+
+impl Labels {
+    /// Reads the labels file, which may not exist yet
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = labels_path() else {
+            return Ok(Self::default());
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let mut tracks = BTreeMap::new();
+        for line in text.lines() {
+            let Some((labels, path)) = line.split_once('\t') else {
+                continue;
+            };
+            let labels: BTreeSet<String> = labels
+                .split(',')
+                .filter(|label| !label.is_empty())
+                .map(str::to_string)
+                .collect();
+            if !labels.is_empty() {
+                tracks.insert(PathBuf::from(path), labels);
+            }
+        }
+        Ok(Self { tracks })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = labels_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: String = self
+            .tracks
+            .iter()
+            .map(|(track, labels)| {
+                let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+                format!("{}\t{}\n", labels.join(","), track.display())
+            })
+            .collect();
+        fs::write(path, text)
+    }
+
+    /// Every track that has labels
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.tracks.keys().map(PathBuf::as_path)
+    }
+
+    /// Labels of a track, sorted
+    pub fn get(&self, track: &Path) -> Vec<&str> {
+        self.tracks
+            .get(track)
+            .map(|labels| labels.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether a track has every one of `labels`
+    pub fn has_all(&self, track: &Path, labels: &[String]) -> bool {
+        self.tracks
+            .get(track)
+            .is_some_and(|have| labels.iter().all(|label| have.contains(label)))
+    }
+
+    /// Adds a label, returning false if the track already had it
+    pub fn add(&mut self, track: &Path, label: &str) -> bool {
+        self.tracks
+            .entry(track.to_path_buf())
+            .or_default()
+            .insert(label.to_string())
+    }
+
+    /// Removes a label, returning false if the track didn't have it
+    pub fn remove(&mut self, track: &Path, label: &str) -> bool {
+        let Some(labels) = self.tracks.get_mut(track) else {
+            return false;
+        };
+        let removed = labels.remove(label);
+        if labels.is_empty() {
+            self.tracks.remove(track);
+        }
+        removed
+    }
+
+    /// Every label in use and how many tracks carry it
+    pub fn counts(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for label in self.tracks.values().flatten() {
+            *counts.entry(label.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Tracks with every one of `labels`, in path order
+    pub fn tracks_with(&self, labels: &[String]) -> Vec<PathBuf> {
+        self.tracks
+            .keys()
+            .filter(|track| self.has_all(track, labels))
+            .cloned()
+            .collect()
+    }
+
+    /// Moves a track's labels along with its file
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        if let Some(labels) = self.tracks.remove(from) {
+            self.tracks.insert(to.to_path_buf(), labels);
+        }
+    }
+}
+
+/// A label as stored: lowercase, with no separators in it
+pub fn normalize(label: &str) -> Option<String> {
+    let label = label.trim().to_lowercase();
+    let valid = !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '/'));
+    valid.then_some(label)
+}
+
+fn labels_path() -> Option<PathBuf> {
+    session::state_dir().map(|dir| dir.join(LABELS_FILE))
+}
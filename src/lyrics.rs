@@ -0,0 +1,87 @@
+//! Lyrics from a file next to the track: `Song.lrc`, or `Song.txt` for
+//! plain text
+//! LRC lines carry `[mm:ss.xx]` timestamps, which tell which line is being
+//! sung; `[offset:ms]` shifts them all
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Lyrics of one track
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Lyrics {
+    pub lines: Vec<(Option<Duration>, String)>, // Lines with their start, in order
+}
+
+impl Lyrics {
+    /// Reads the lyrics file of a track, None if it has none
+    pub fn load(track: &Path) -> io::Result<Option<Self>> {
+        let Some(path) = find(track) else {
+            return Ok(None);
+        };
+        let text = fs::read_to_string(&path)?;
+        let timed = path.extension().is_some_and(|ext| ext == "lrc");
+        Ok(Some(if timed {
+            parse_lrc(&text)
+        } else {
+            Self {
+                lines: text.lines().map(|line| (None, line.to_string())).collect(),
+            }
+        }))
+    }
+
+    /// Line being sung at `position`, when the lyrics are timed
+    pub fn current(&self, position: Duration) -> Option<usize> {
+        self.lines
+            .iter()
+            .rposition(|(start, _)| start.is_some_and(|start| start <= position))
+    }
+}
+
+/// `Song.lrc` or `Song.txt` beside `Song.mp3`
+fn find(track: &Path) -> Option<PathBuf> {
+    ["lrc", "txt"]
+        .iter()
+        .map(|ext| track.with_extension(ext))
+        .find(|path| path.is_file())
+}
+
+fn parse_lrc(text: &str) -> Lyrics {
+    let mut offset_ms = 0i64;
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut starts = Vec::new();
+        // A line may start with several timestamps when it repeats
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+            if let Some(value) = tag.strip_prefix("offset:") {
+                offset_ms = value.trim().parse().unwrap_or(0);
+            } else if let Some(start) = timestamp(tag) {
+                starts.push(start);
+            }
+            rest = after;
+        }
+        for start in starts {
+            lines.push((start, rest.trim().to_string()));
+        }
+    }
+    lines.sort_by_key(|(start, _)| *start);
+    // A positive offset makes the lyrics come sooner
+    let shift = |start: i64| Duration::from_millis((start - offset_ms).max(0) as u64);
+    Lyrics {
+        lines: lines
+            .into_iter()
+            .map(|(start, text)| (Some(shift(start)), text))
+            .collect(),
+    }
+}
+
+/// Milliseconds of an `mm:ss.xx` tag; None for metadata tags like `ar:`
+fn timestamp(tag: &str) -> Option<i64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = seconds.trim().parse().ok()?;
+    Some((minutes as f64 * 60_000.0 + seconds * 1000.0).round() as i64)
+}
@@ -2,15 +2,90 @@
 //! Supports basic playback controls, volume adjustment, and file management
 //! Author: ojalla
 
+#[cfg(feature = "aiff")]
+mod aiff;
+mod announce;
+mod auth;
+mod backup;
+mod bandwidth;
+mod bench;
+mod cache;
+#[macro_use]
+mod capture;
+mod cd;
+mod clipboard;
+mod codecs;
+mod columns;
+mod config;
+mod control;
+mod cork;
+mod devices;
+mod direct;
+mod doctor;
+#[cfg(feature = "dsd")]
+mod dsd;
+mod dsp;
+mod durations;
+mod eq;
+mod files;
+mod follow;
+#[cfg(feature = "gstreamer")]
+mod gstreamer;
+mod icy;
+mod import;
+mod inbox;
+mod json;
+mod labels;
+mod lyrics;
+#[cfg(feature = "mpv")]
+mod mpv;
+mod output;
+mod palette;
+mod picker;
+mod playhead;
+mod playlist;
+mod plist;
+mod podcast;
+mod power;
+mod queue;
+mod radio;
+mod readahead;
+mod remote;
+mod rip;
+mod rpc;
+mod safeguard;
+mod scan;
+mod screen_reader;
+mod selection;
+mod session;
+mod signals;
+mod similar;
+mod sink;
+mod speeds;
+mod stats;
+mod sync;
+mod systemd;
+mod tags;
+mod update;
+
 use clap::{ Arg, ArgMatches, Command};
 use colored::*;
-use rodio::{self, Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use config::Config;
+use json::Json;
+use rodio::{self, OutputStreamHandle, Source};
+use rpc::{RpcError, FORBIDDEN, INVALID_PARAMS, METHOD_NOT_FOUND, PLAYER_ERROR};
+use sink::Sink;
 use std::{
-    collections::HashMap,
-    fs::{self, read_dir, DirEntry, File},
-    io::{self, BufReader, ErrorKind, Write},
-    process::exit,
-    time::Instant,
+    collections::{HashMap, HashSet, VecDeque},
+    env, fs,
+    io::{self, ErrorKind, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
 };
 
 /// Configures and returns the command-line interface for the music player
@@ -25,8 +100,23 @@ fn cli_config() -> Command {
                 .short('d')
                 .long("dir")
                 .value_name("DIRECTORY")
-                .help("Sets the music directory")
-                .required_unless_present("how-to"),
+                .help("Sets the music directory (or set `dir` in the config)")
+                .env("MUSICPLAYER_DIR"),
+        )
+        .arg(
+            Arg::new("max-depth")
+                .long("max-depth")
+                .value_name("LEVELS")
+                .help("Searches only this many folder levels below the music directory")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("include-ext")
+                .long("include-ext")
+                .value_name("EXT,...")
+                .help("Also lists files with these extensions, e.g. m4a,opus for the mpv backend")
+                .value_delimiter(',')
+                .action(clap::ArgAction::Append),
         )
         .arg(
             Arg::new("how-to")
@@ -34,94 +124,705 @@ fn cli_config() -> Command {
                 .help("Shows operation commands and how to use the application.")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("screen-reader")
+                .long("screen-reader")
+                .help("Plain output for screen readers: no colors, symbols or redrawing")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("daemon")
+                .long("daemon")
+                .help("Runs without the interactive prompt, controlled only remotely")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("FILE")
+                .help("Reads settings from FILE instead of the default config.toml")
+                .env("MUSICPLAYER_CONFIG")
+                .global(true),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("NAME")
+                .help("Uses the settings of the named profile in the config")
+                .env("MUSICPLAYER_PROFILE")
+                .global(true),
+        )
+        .arg(
+            Arg::new("volume")
+                .long("volume")
+                .value_name("0.0-1.0")
+                .help("Sets the starting volume")
+                .env("MUSICPLAYER_VOLUME")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("now-playing-file")
+                .long("now-playing-file")
+                .value_name("FILE")
+                .help("Writes the current track to FILE for streaming overlays (e.g. OBS)"),
+        )
+        .arg(
+            Arg::new("now-playing-template")
+                .long("now-playing-template")
+                .value_name("TEMPLATE")
+                .help("Template for the now-playing file: {artist}, {title}, {file}")
+                .default_value("{artist} – {title}"),
+        )
+        .arg(
+            Arg::new("control-addr")
+                .long("control-addr")
+                .value_name("ADDR")
+                .help("Address of the control socket used by remote clients")
+                .default_value(control::DEFAULT_CONTROL_ADDR)
+                .env("MUSICPLAYER_SOCKET")
+                .global(true),
+        )
+        .arg(
+            Arg::new("rpc-addr")
+                .long("rpc-addr")
+                .value_name("ADDR")
+                .help("Serves JSON-RPC 2.0 on this TCP address and port (e.g. 127.0.0.1:6602)")
+                .env("MUSICPLAYER_RPC_ADDR"),
+        )
+        .arg(
+            Arg::new("buffer-size")
+                .long("buffer-size")
+                .value_name("FRAMES")
+                .help("Plays through a direct stream with this many frames per buffer")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("bit-depth")
+                .long("bit-depth")
+                .value_name("BITS")
+                .help("Sends 16-bit or 32-bit float samples to the device (direct stream)")
+                .value_parser(clap::value_parser!(u16)),
+        )
+        .arg(
+            Arg::new("backend")
+                .long("backend")
+                .value_name("BACKEND")
+                .help("Audio backend: alsa, pulse, pipewire, or a host such as jack or wasapi")
+                .env("MUSICPLAYER_BACKEND"),
+        )
+        .arg(
+            Arg::new("lead")
+                .long("lead")
+                .value_name("ADDR")
+                .num_args(0..=1)
+                .default_missing_value(follow::DEFAULT_LEAD_ADDR)
+                .help("Lets other instances follow this one's playback (default 0.0.0.0:6610)"),
+        )
+        .arg(
+            Arg::new("follow")
+                .long("follow")
+                .value_name("ADDR")
+                .help("Plays in sync with the instance leading on ADDR"),
+        )
+        .subcommand_negates_reqs(true)
+        // Any other subcommand is a prompt command for a running player
+        .allow_external_subcommands(true)
+        .subcommand(
+            Command::new("tmux-status")
+                .about("Prints a now-playing segment for tmux's status-right")
+                .arg(
+                    Arg::new("token")
+                        .long("token")
+                        .value_name("TOKEN")
+                        .help("Auth token (defaults to the first configured token, then the running player's)"),
+                )
+                .arg(
+                    Arg::new("max-width")
+                        .long("max-width")
+                        .value_name("CHARS")
+                        .help("Truncates the track title to this many characters")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("40"),
+                ),
+        )
+        .subcommand(
+            Command::new("self-update")
+                .about("Replaces this binary with the latest release")
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .help("Only says whether a newer release is out")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Checks the config, library, state files and audio output"),
+        )
 }
 
 /// Gets user input from the command line with a custom prompt
-/// Returns the trimmed input as a String
-fn input() -> String {
+/// Returns the trimmed input, or None once stdin is closed
+fn input() -> Option<String> {
     use std::io;
 
     let mut user_input = String::new();
 
-    print!("{}", "musicplayer> ".cyan().bold());
-    io::stdout().flush().expect("Failed To Flush Output");
+    print_prompt();
 
-    io::stdin()
+    let read = io::stdin()
         .read_line(&mut user_input)
         .expect("Error Getting User Input");
 
-    user_input.trim().to_string()
+    if read == 0 {
+        return None;
+    }
+    Some(user_input.trim().to_string())
+}
+
+/// Artist and title of a track from its tags, or for an untagged file from
+/// its name, which is often "Artist - Title"
+fn artist_and_title(song: &Path) -> (Option<String>, String) {
+    let tags = tags::read(song).unwrap_or_default();
+    match (tags.artist, tags.title) {
+        (artist, Some(title)) => (artist, title),
+        (_, None) => {
+            let stem = song.file_stem().map_or_else(
+                || file_name(song),
+                |stem| stem.to_string_lossy().to_string(),
+            );
+            split_artist(&stem)
+        }
+    }
+}
+
+/// Splits an "Artist - Title" line; without the dash it is all title
+fn split_artist(line: &str) -> (Option<String>, String) {
+    match line.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim().to_string()), title.trim().to_string()),
+        None => (None, line.to_string()),
+    }
+}
+
+/// Prints the tags of the current track for `status`, leaving out the
+/// ones it doesn't have
+fn print_tags(tags: &tags::Tags) {
+    let mut fields = vec![
+        ("Title", tags.title.clone()),
+        ("Artist", tags.artist.clone()),
+        ("Album", tags.album.clone()),
+    ];
+    if tags.album_artist != tags.artist {
+        fields.push(("Album artist", tags.album_artist.clone()));
+    }
+    let number = match (tags.track, tags.disc) {
+        (Some(track), Some(disc)) => Some(format!("{} (disc {})", track, disc)),
+        (track, _) => track.map(|track| track.to_string()),
+    };
+    fields.push(("Track", number));
+    fields.push(("Year", tags.year.map(|year| year.to_string())));
+    fields.push(("Genre", tags.genre.clone()));
+    fields.push(("BPM", tags.bpm.map(|bpm| bpm.to_string())));
+    for (name, value) in fields {
+        if let Some(value) = value {
+            outln!("  {}: {}", name.bold(), value);
+        }
+    }
+}
+
+/// Prints the command prompt without a newline
+fn print_prompt() {
+    // Not in the reply to a remote command
+    if capture::is_caught() {
+        return;
+    }
+    print!("{}", "musicplayer> ".cyan().bold());
+    io::stdout().flush().expect("Failed To Flush Output");
+}
+
+/// Name a track is listed under
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().to_string(),
+    )
+}
+
+/// Events delivered to the player's main loop
+pub enum PlayerEvent {
+    Input(String),                  // Line typed at the prompt
+    Remote(String, Sender<String>), // Control socket command and its reply channel
+    Rpc(rpc::RpcCall),              // JSON-RPC method call
+    Terminate,                      // SIGTERM: save state and exit cleanly
+    Reload,                         // SIGHUP: reload the config file
+    Interrupt,                      // Ctrl+C at the terminal
+    DeviceChanged(Option<String>),  // New default output device, None if gone
+    Woke,                           // System resumed from suspend
+    Call(bool),                     // A voice call started (true) or ended
+    Heartbeat,                      // Time to send followers our state again
+    Leader(follow::State),          // Playback state of the instance we follow
+    Tick,                           // Time to check whether the track has ended
+    Announced(u64, Option<String>), // A track announcement is over, with any error
+}
+
+/// How long playback fades out before a graceful shutdown
+const SHUTDOWN_FADE: Duration = Duration::from_millis(500);
+
+/// Tracks in a row that may fail to open before moving on by itself gives up
+const MAX_FAILED_STARTS: usize = 10;
+
+/// How often the main loop checks whether the track has ended
+const TICK: Duration = Duration::from_millis(500);
+
+/// How many of the closest tracks `radio` chooses among
+const RADIO_CHOICES: usize = 5;
+
+/// Sends a `PlayerEvent::Tick` at a fixed interval
+fn spawn_ticker(events: Sender<PlayerEvent>) {
+    thread::spawn(move || loop {
+        thread::sleep(TICK);
+        if events.send(PlayerEvent::Tick).is_err() {
+            break;
+        }
+    });
+}
+
+/// Reads prompt input on a background thread and forwards each line
+/// Returns a sender the main loop uses to signal the line was handled
+fn spawn_input_thread(events: Sender<PlayerEvent>) -> Sender<()> {
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::spawn(move || {
+        // Stops once stdin closes; the control socket keeps being served
+        while let Some(line) = input() {
+            if events.send(PlayerEvent::Input(line)).is_err() || done_rx.recv().is_err() {
+                break;
+            }
+        }
+    });
+    done_tx
 }
 
 /// Main struct representing the CLI music player
 /// Maintains state and handles all player operations
 struct CliPlayer {
-    sink: rodio::Sink,                           // Audio sink for playback
-    stream: rodio::OutputStream,                 // Audio output stream
+    sink: Sink,                                  // Audio sink for playback
+    _stream: rodio::OutputStream,                // Audio output stream (kept alive)
     stream_handle: OutputStreamHandle,           // Handle to the audio stream
+    output_channels: u16,                        // Channels of the output device
+    channel_map: Option<Vec<Option<usize>>>,     // Source channel for each output channel
+    effects: dsp::Effects,                       // Effect settings shared with the playing track
+    stats: stats::Stats,                         // Ratings and play counts
+    labels: labels::Labels,                      // User labels such as moods
+    speeds: speeds::Speeds,                      // Speeds remembered per feed and album
+    durations: Arc<Mutex<durations::Durations>>, // Track lengths shown by `list`
+    list_columns: Vec<columns::Column>,          // What `list` shows, in order
     is_playing: bool,                           // Current playback status
     is_paused: bool,                            // Current pause status
     main_dir: Option<String>,                   // Directory containing music files
-    current_file: Option<String>,               // Currently playing file name
+    extra_dirs: Vec<PathBuf>,                   // Library roots added with `dir add`
+    scan_filter: scan::Filter,                  // Files left out of or kept in the library
+    podcast_settings: podcast::Settings,        // Where episodes download to and how long they stay
+    downloads: podcast::Downloads,              // Episode downloads since startup
+    episodes: Vec<podcast::Episode>,            // Episodes numbered by the last `podcast episodes`
+    disc: Option<cd::Disc>,                     // CD loaded with `cd`
+    rips: rip::Rips,                            // CD rips since startup
+    current_index: Option<i32>,                 // Index of the current song
+    current_file: Option<String>,               // Currently playing file, below its library root
     last_input: Option<String>,                 // Last user input
-    available_songs: Option<HashMap<i32, DirEntry>>, // Map of available songs
-    start_time: Option<Instant>,                // Start time of current playback
+    available_songs: Option<HashMap<i32, PathBuf>>, // Map of available songs
+    now_playing_file: Option<NowPlayingFile>,   // Overlay text file, if enabled
+    rpc_notifier: Option<rpc::Notifier>,        // JSON-RPC clients to notify
+    last_status: Option<Json>,                  // Last status sent to JSON-RPC clients
+    config_path: Option<String>,                // Config file given with --config
+    profile: Option<String>,                    // Config profile given with --profile
+    tokens: auth::Tokens,                       // Tokens shared with the remote interfaces
+    auth_required: bool,                        // Keep auth on even if reload drops tokens
+    ctrl_c_exits: bool,                         // Quit on the first Ctrl+C
+    interrupted: bool,                          // Ctrl+C pressed since the last command
+    quitting: bool,                             // `exit` was given; the loop ends after this event
+    backend: Option<String>,                    // Audio backend chosen with --backend
+    output_device: Option<String>,              // Output device the stream was opened on
+    pause_on_device_change: bool,               // Pause when the default device changes
+    resume_on_device_return: bool,              // Resume once the original device is back
+    paused_by_device: bool,                     // Current pause came from a device change
+    reopen_on_wake: bool,                       // Rebuild the stream after suspend
+    on_call: cork::CallBehavior,                // Reaction to voice calls
+    call_restore: Option<CallRestore>,          // How to undo the reaction once the call ends
+    position_sync: Option<sync::PositionSync>,  // Positions shared with other machines
+    resume_min_position: u64,                   // Shortest synced position worth resuming
+    playhead: Option<playhead::Playhead>,       // Position in the current track, None when stopped
+    leader: Option<follow::Leader>,             // Instances following this one
+    max_drift: Duration,                        // Drift from the leader before resyncing
+    missing_track: Option<String>,              // Leader's track we've no copy of
+    party_mode: bool,                           // Remote clients can't take over playback
+    autoplay: bool,                             // Keeps playing picks from the library
+    radio: radio::Radio,                        // Recent plays autoplay holds back
+    radio_chain: bool,                          // Each track is followed by one like it
+    history: similar::History,                  // Play order, for tracks played together
+    offline: bool,                              // Only local files and cached remote tracks play
+    speed: f32,                                 // Playback speed of the current track
+    announcer: Option<announce::Announcer>,     // Speaks track changes, once running
+    announcements: u64,                         // Number of the latest announcement
+    announce_restore: Option<f32>,              // Volume from before ducking for one
+    at_prompt: bool,                            // Current command was typed at the prompt
+    organize_template: String,                  // Folder layout `organize move` uses
+    prev_restart_within: Duration,              // `prev` restarts the track until this far in
+    skip_intervals: SkipIntervals,              // How far `skipfwd` and `skipback` jump
+    skip_shorter_than: Duration,                // Tracks this short are passed over when advancing
+    advance: bool,                              // Goes on down the list when a track ends
+    duration: Option<Duration>,                 // Length of the current track, when known
+    show_remaining: bool,                       // Time displays count down to the end
+    stop_after_album: Option<tags::Tags>,       // Tags of the album to play out, then stop
+    queue: queue::Queue,                        // Tracks to play after the current one
+    inbox: Option<inbox::Inbox>,                // Playlist file whose new lines are queued
+    on_air: Option<icy::OnAir>,                 // What the station playing said last
+    drop_folder: Option<inbox::DropFolder>,     // Folder whose new audio files are queued
+    drop_into: Option<PathBuf>,                 // Where dropped files are moved, if anywhere
+}
+
+/// What the player changed when a call started
+enum CallRestore {
+    Resume,      // Playback was paused for the call
+    Volume(f32), // Volume was ducked from this level
+}
+
+/// How far `skipfwd` and `skipback` jump, set apart for podcast episodes
+/// and for music under `[skip]`
+struct SkipIntervals {
+    podcast_forward: Duration, // Over an ad or a tangent
+    podcast_back: Duration,    // To hear a sentence again
+    music_forward: Duration,   // Through a song in small steps
+    music_back: Duration,      // Back to a passage just heard
+}
+
+impl SkipIntervals {
+    fn from_config(config: &Config) -> Self {
+        let seconds = |key: &str, default: u64| {
+            config
+                .get_f64(&format!("skip.{}", key))
+                .map_or(Duration::from_secs(default), |seconds| {
+                    Duration::from_secs_f64(seconds.max(0.0))
+                })
+        };
+        Self {
+            podcast_forward: seconds("podcast_forward", 30),
+            podcast_back: seconds("podcast_back", 10),
+            music_forward: seconds("music_forward", 5),
+            music_back: seconds("music_back", 5),
+        }
+    }
+}
+
+/// Text file mirroring the current track, for OBS and other overlay tools
+/// The file is rewritten on every track change and emptied on stop
+struct NowPlayingFile {
+    path: PathBuf,    // File watched by the overlay tool
+    template: String, // Output template with {artist}, {title} and {file}
+}
+
+impl NowPlayingFile {
+    /// Renders the template for a track, from its tags when it has them
+    fn render(&self, song: &Path, file_name: &str) -> String {
+        let (artist, title) = artist_and_title(song);
+        self.fill(artist.as_deref(), &title, file_name)
+    }
+
+    /// Renders the template for an "Artist - Title" line
+    fn render_parts(&self, line: &str, file_name: &str) -> String {
+        let (artist, title) = split_artist(line);
+        self.fill(artist.as_deref(), &title, file_name)
+    }
+
+    fn fill(&self, artist: Option<&str>, title: &str, file_name: &str) -> String {
+        self.template
+            .replace("{artist}", artist.unwrap_or("Unknown Artist"))
+            .replace("{title}", title)
+            .replace("{file}", file_name)
+    }
+
+    /// Writes the rendered line for the given track
+    fn write(&self, song: &Path, file_name: &str) -> io::Result<()> {
+        self.replace_contents(&self.render(song, file_name))
+    }
+
+    /// Writes the rendered line for the song on air on a station, which
+    /// stands in for the file name
+    fn write_on_air(&self, title: &str, station: &str) -> io::Result<()> {
+        self.replace_contents(&self.render_parts(title, station))
+    }
+
+    /// Empties the file so overlays show nothing while stopped
+    fn clear(&self) -> io::Result<()> {
+        self.replace_contents("")
+    }
+
+    /// Writes through a temporary file and renames it into place, so
+    /// watchers never pick up a half-written line
+    fn replace_contents(&self, contents: &str) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)
+    }
 }
 
 /// Enum representing all possible commands the player can handle
 enum InputCommands {
-    Play,            // Plays a track
+    Play(String),    // Plays tracks by number, e.g. `3-7,12,15`
     Pause,           // Pauses current track
     Resume,          // Resumes paused track
     Exit,            // Exits application
     Stop,            // Stops playback
-    List,            // Lists available tracks
+    List(Vec<String>), // Lists available tracks, with options
     InvalidCommand,  // Invalid command handler
     Volume(f32),     // Sets volume (0.0-1.0)
     Status,          // Shows player status
     Help,            // Shows help information
+    Karaoke(bool),   // Turns vocal removal on or off
+    Crossfeed(bool), // Turns headphone crossfeed on or off
+    Eq(Vec<String>), // Equalizer subcommand and its arguments
+    NightMode(bool), // Turns dynamic range compression on or off
+    Playlist(Vec<String>), // Playlist subcommand and its arguments
+    Import(Vec<String>),   // Imports another player's library
+    Podcast(Vec<String>),  // Podcast subcommand and its arguments
+    Cd(Vec<String>),       // Loads or ejects an audio CD
+    Dir(Vec<String>),      // Adds or removes a library root
+    Rip(Vec<String>),      // Rips CD tracks into the library
+    Bench(Vec<String>),    // Measures decoding speed
+    Party(bool),           // Turns party mode on or off
+    Autoplay(bool),        // Turns autoplay radio on or off
+    Similar(Vec<String>),  // Suggests tracks like a given one
+    Tag(Vec<String>),      // Adds, removes or shows user labels
+    Radio(Vec<String>),    // Plays a chain of similar tracks
+    Db(Vec<String>),       // Exports or imports the library's user data
+    Follow(Vec<String>),   // Queues lines appended to a playlist file
+    Queue(Vec<String>),    // Adds to, removes from or shows the queue
+    Stream(String),        // Tunes in to an internet radio station
+    Offline(bool),         // Turns offline mode on or off
+    Speed(Option<f32>),    // Sets or shows the playback speed
+    Delete(Vec<String>),   // Deletes a track from disk
+    Move(Vec<String>),     // Moves a track to another folder
+    Organize(Vec<String>), // Sorts tracks into folders by their tags
+    Paste,                 // Plays the track on the clipboard
+    Pick(String),          // Chooses a track by typing part of its name
+    Replay(Duration),      // Jumps back in the current track
+    Seek(Duration),        // Jumps to a position in the current track
+    SkipForward(Option<Duration>), // Jumps ahead, by the mode's interval unless given
+    SkipBack(Option<Duration>), // Jumps back, by the mode's interval unless given
+    Restart,               // Plays the current track again from the start
+    Prev,                  // Plays the track before the current one
+    ShowRemaining(bool),   // Shows the time left instead of the time played
+    Lyrics,                // Shows the current track's lyrics
+    Palette,               // Chooses a command from a searchable list
+    ConfigReload,          // Re-reads the config file
+    StopAfterAlbum(bool),  // Stops once the current album has played out
+    Intro(Option<Duration>), // Sets or clears where the current track starts
 }
 
 impl CliPlayer {
     /// Creates a new instance of the CLI player
     /// Sets up audio streams and initializes default state
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let (stream, stream_handle) = OutputStream::try_default()?;
-        let sink = Sink::try_new(&stream_handle)?;
+    pub fn new(
+        backend: Option<String>,
+        direct: direct::Settings,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let output_device = devices::default_output_name();
+        let output = output::open(backend.as_deref())?;
+        let sink = Sink::new(backend.as_deref(), &output, direct)?;
 
         Ok(Self {
             sink,
-            stream,
-            stream_handle,
+            _stream: output.stream,
+            stream_handle: output.handle,
+            output_channels: output.channels,
+            channel_map: None,
+            effects: dsp::Effects::default(),
+            stats: stats::Stats::default(),
+            speeds: speeds::Speeds::default(),
+            durations: Arc::default(),
+            list_columns: columns::parse(&[columns::DEFAULT]).unwrap(),
             is_playing: false,
             is_paused: false,
             main_dir: None,
+            extra_dirs: Vec::new(),
+            scan_filter: scan::Filter::default(),
+            podcast_settings: podcast::Settings::from_config(&Config::default()),
+            downloads: podcast::Downloads::default(),
+            episodes: Vec::new(),
+            disc: None,
+            rips: rip::Rips::default(),
+            current_index: None,
             current_file: None,
             last_input: None,
             available_songs: Some(HashMap::new()),
-            start_time: None,
+            now_playing_file: None,
+            rpc_notifier: None,
+            last_status: None,
+            config_path: None,
+            profile: None,
+            tokens: auth::Tokens::default(),
+            auth_required: false,
+            ctrl_c_exits: false,
+            interrupted: false,
+            quitting: false,
+            backend,
+            output_device,
+            pause_on_device_change: true,
+            resume_on_device_return: false,
+            paused_by_device: false,
+            reopen_on_wake: true,
+            position_sync: None,
+            resume_min_position: 60,
+            playhead: None,
+            leader: None,
+            max_drift: follow::DEFAULT_MAX_DRIFT,
+            missing_track: None,
+            party_mode: false,
+            autoplay: false,
+            radio: radio::Radio::default(),
+            radio_chain: false,
+            history: similar::History::default(),
+            labels: labels::Labels::default(),
+            offline: false,
+            speed: 1.0,
+            announcer: None,
+            announcements: 0,
+            announce_restore: None,
+            at_prompt: false,
+            organize_template: files::DEFAULT_TEMPLATE.to_string(),
+            prev_restart_within: Duration::from_secs(3),
+            skip_intervals: SkipIntervals::from_config(&Config::default()),
+            skip_shorter_than: Duration::ZERO,
+            advance: true,
+            duration: None,
+            show_remaining: false,
+            stop_after_album: None,
+            queue: queue::Queue::default(),
+            inbox: None,
+            on_air: None,
+            drop_folder: None,
+            drop_into: None,
+            on_call: cork::CallBehavior::Pause,
+            call_restore: None,
         })
     }
 
     /// Main run loop for the player
     /// Handles initialization and command processing
-    pub fn run(&mut self, arguments: ArgMatches) -> io::Result<()> {
+    pub fn run(&mut self, arguments: ArgMatches, config: &Config) -> io::Result<()> {
         // Validate and set music directory
         let primary_dir = arguments
             .get_one::<String>("music-dir")
-            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "Missing music directory"))?;
+            .map(String::as_str)
+            .or_else(|| config.get_str("player.dir"))
+            .ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "Missing music directory: use --dir or set `dir` under [player]",
+                )
+            })?;
+        let primary_dir = &match (primary_dir.strip_prefix("~/"), env::var_os("HOME")) {
+            (Some(rest), Some(home)) => PathBuf::from(home).join(rest).display().to_string(),
+            _ => primary_dir.to_string(),
+        };
 
-        if !fs::metadata(primary_dir)?.is_dir() {
+        if !remote::is_remote(Path::new(primary_dir)) && !fs::metadata(primary_dir)?.is_dir() {
             return Err(io::Error::new(ErrorKind::NotFound, "Directory not found"));
         }
 
         self.main_dir = Some(primary_dir.to_string());
+        self.scan_filter = scan::Filter::from_config(config);
+        if let Some(&depth) = arguments.get_one::<usize>("max-depth") {
+            self.scan_filter.set_max_depth(depth);
+        }
+        remote::configure(config);
+        cache::configure(config);
+        bandwidth::configure(config);
+        announce::configure(config);
+        cd::configure(config);
+        self.profile = arguments.get_one::<String>("profile").cloned();
         self.load_songs()?;
+        self.stats = stats::Stats::load().unwrap_or_else(|e| {
+            outln!("{}: Could not read play counts: {}", "Warning".yellow(), e);
+            stats::Stats::default()
+        });
+        self.labels = labels::Labels::load().unwrap_or_else(|e| {
+            outln!("{}: Could not read labels: {}", "Warning".yellow(), e);
+            labels::Labels::default()
+        });
+        self.history = similar::History::load().unwrap_or_else(|e| {
+            outln!(
+                "{}: Could not read the play history: {}",
+                "Warning".yellow(),
+                e
+            );
+            similar::History::default()
+        });
+        self.speeds = speeds::Speeds::load().unwrap_or_else(|e| {
+            outln!(
+                "{}: Could not read playback speeds: {}",
+                "Warning".yellow(),
+                e
+            );
+            speeds::Speeds::default()
+        });
+        self.podcast_settings = podcast::Settings::from_config(config);
+        match podcast::clean_up(&self.podcast_settings, &self.stats) {
+            Ok(0) => {}
+            Ok(removed) => outln!(
+                "{}: Removed {} listened podcast episode(s)",
+                "Info".blue(),
+                removed
+            ),
+            Err(e) => outln!(
+                "{}: Could not clean up podcast episodes: {}",
+                "Warning".yellow(),
+                e
+            ),
+        }
+        let durations = durations::Durations::load().unwrap_or_else(|e| {
+            outln!(
+                "{}: Could not read track lengths: {}",
+                "Warning".yellow(),
+                e
+            );
+            durations::Durations::default()
+        });
+        self.durations = Arc::new(Mutex::new(durations));
+        let tracks = self
+            .available_songs
+            .iter()
+            .flat_map(|songs| songs.values().cloned())
+            .collect();
+        durations::measure_in_background(Arc::clone(&self.durations), tracks);
+        self.apply_settings(config);
+        let volume = arguments.get_one::<f64>("volume").copied();
+        match volume.or_else(|| config.get_f64("player.volume")) {
+            Some(volume) if (0.0..=1.0).contains(&volume) => self.sink.set_volume(volume as f32),
+            Some(_) => outln!("{}: volume must be 0.0 to 1.0", "Warning".yellow()),
+            None => {}
+        }
+
+        // Set up the now-playing overlay file, starting out empty
+        if let Some(path) = arguments.get_one::<String>("now-playing-file") {
+            let overlay = NowPlayingFile {
+                path: PathBuf::from(path),
+                template: arguments
+                    .get_one::<String>("now-playing-template")
+                    .cloned()
+                    .unwrap_or_default(),
+            };
+            overlay.clear()?;
+            self.now_playing_file = Some(overlay);
+        }
         
         // Display welcome message and initial song list
-        println!("\n{}", "Welcome to Music Player!".green().bold());
-        println!("Loaded directory: {}", primary_dir.blue());
-        println!(
+        outln!("\n{}", "Welcome to Music Player!".green().bold());
+        outln!("Loaded directory: {}", primary_dir.blue());
+        if let Some(profile) = &self.profile {
+            outln!("Profile: {}", profile.blue());
+        }
+        outln!(
             "Found {} songs.\n",
             self.available_songs
                 .as_ref()
@@ -130,240 +831,4353 @@ impl CliPlayer {
                 .to_string()
                 .yellow()
         );
-        self.list();
-
-        // Main program loop
-        loop {
-            self.get_commands();
+        let daemon = arguments.get_flag("daemon");
+        if !daemon {
+            self.list(&self.list_columns, None);
         }
-    }
 
-    /// Loads songs from the specified directory into the available_songs HashMap
-    fn load_songs(&mut self) -> io::Result<()> {
-        let mut index = 1;
-        if let Some(dir) = &self.main_dir {
-            if let Some(sound_map) = &mut self.available_songs {
-                for entry in read_dir(dir)? {
-                    let entry = entry?;
-                    if entry.path().is_file() {
-                        sound_map.insert(index, entry);
-                        index += 1;
-                    }
-                }
+        // Remote interfaces need tokens when configured, explicitly
+        // required, or reachable from other machines
+        let control_addr = arguments
+            .get_one::<String>("control-addr")
+            .map(String::as_str)
+            .unwrap_or(control::DEFAULT_CONTROL_ADDR);
+        let rpc_addr = arguments.get_one::<String>("rpc-addr");
+        self.config_path = arguments.get_one::<String>("config").cloned();
+        let mut tokens = auth::Tokens::from_config(config)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let exposed =
+            auth::is_exposed(control_addr) || rpc_addr.is_some_and(|a| auth::is_exposed(a));
+        let required = config.get_bool("auth.required");
+        self.auth_required = required.unwrap_or(true) || exposed;
+        let mut session_token = None;
+        if !tokens.is_enabled() && self.auth_required {
+            let (generated, token) = auth::Tokens::generated()?;
+            generated.set_read_only(tokens.is_read_only());
+            // Local clients read it from the token file
+            if exposed || required == Some(true) {
+                outln!("Remote control token for this session: {}", token.yellow());
             }
+            tokens = generated;
+            session_token = Some(token);
         }
-        Ok(())
-    }
-
-    /// Plays a song by its index number
-    /// Handles stopping current playback and starting new playback
-    pub fn play(&mut self, sound_index: i32) -> Result<(), Box<dyn std::error::Error>> {
-        if self.is_playing {
-            self.sink.stop();
-            self.sink = Sink::try_new(&self.stream_handle)?;
+        self.tokens = tokens.clone();
+        if exposed {
+            // The interfaces speak plain TCP; see "Remote Access" in the readme
+            outln!(
+                "{}: Remote control is reachable from the network without encryption; \
+                 tunnel it over SSH on untrusted networks",
+                "Warning".yellow()
+            );
         }
 
-        if let Some(sound_map) = &self.available_songs {
-            if let Some(song) = sound_map.get(&sound_index) {
-                let file = BufReader::new(File::open(song.path())?);
-                let source = Decoder::new(file)?;
-                self.sink.set_volume(1.0);
-                self.sink.append(source.convert_samples::<f32>());
-                self.is_playing = true;
-                self.is_paused = false;
-                self.current_file = Some(song.file_name().to_string_lossy().to_string());
-                self.start_time = Some(Instant::now());
-                println!(
-                    "{}: Playing {}",
-                    "Now playing".green().bold(),
-                    self.current_file.as_ref().unwrap().blue()
+        // Both the prompt and the control socket feed one event channel
+        let (events_tx, events_rx) = mpsc::channel();
+        let control_up = if let Some(listener) = systemd::activated_listener() {
+            control::serve(listener, events_tx.clone(), tokens.clone());
+            true
+        } else if let Err(e) =
+            control::spawn_server(control_addr, events_tx.clone(), tokens.clone())
+        {
+            outln!(
+                "{}: Control socket unavailable on {}: {}",
+                "Warning".yellow(),
+                control_addr,
+                e
+            );
+            false
+        } else {
+            true
+        };
+        // Not when another player holds the socket, whose token it would
+        // replace
+        if let Some(token) = session_token.filter(|_| control_up) {
+            if let Err(e) = auth::save_token(&token) {
+                outln!(
+                    "{}: Could not save the remote control token: {}",
+                    "Warning".yellow(),
+                    e
                 );
-                Ok(())
-            } else {
-                Err(format!("{}: Invalid song index", "Error".red()).into())
             }
-        } else {
-            Err("No songs available".into())
         }
-    }
-
-    /// Processes and executes commands based on the InputCommands enum
-    pub fn act_on_commands(&mut self, command: InputCommands) {
-        match command {
-            InputCommands::Play => {
-                if let Some(index) = &self.last_input {
-                    match index.parse::<i32>() {
-                        Ok(sound_index) => {
-                            if let Err(e) = self.play(sound_index) {
-                                println!("{}: {}", "Error".red(), e);
-                            }
-                        }
-                        Err(_) => println!("{}: Invalid song index", "Error".red()),
-                    }
-                } else {
-                    println!("{}: Please provide a song index", "Error".red());
-                }
-            }
-            InputCommands::Pause => {
-                if self.is_playing {
-                    self.sink.pause();
-                    self.is_paused = true;
-                    println!("{}: Playback paused", "Info".yellow());
+        if let Some(rpc_addr) = rpc_addr {
+            let notifier = rpc::Notifier::default();
+            match rpc::spawn_server(rpc_addr, events_tx.clone(), notifier.clone(), tokens) {
+                Ok(()) => {
+                    outln!("JSON-RPC listening on {}", rpc_addr.blue());
+                    self.rpc_notifier = Some(notifier);
                 }
+                Err(e) => outln!(
+                    "{}: JSON-RPC unavailable on {}: {}",
+                    "Warning".yellow(),
+                    rpc_addr,
+                    e
+                ),
             }
+        }
+        signals::install(events_tx.clone());
 
-            InputCommands::Resume => {
-                if self.is_paused {
-                    self.sink.play();
-                    self.is_paused = false;
-                    self.is_playing = true;
-                    println!("{}: Playback resumed", "Info".green());
-                }
+        // Ctrl+C goes through the main loop so quitting can save state
+        let interrupts = events_tx.clone();
+        if let Err(e) = ctrlc::set_handler(move || {
+            let _ = interrupts.send(PlayerEvent::Interrupt);
+        }) {
+            outln!(
+                "{}: Could not install Ctrl+C handler: {}",
+                "Warning".yellow(),
+                e
+            );
+        }
+        devices::spawn_watcher(self.output_device.clone(), events_tx.clone());
+        if let Some(name) = config.get_str("effects.eq") {
+            match eq::Preset::find(name) {
+                Ok(preset) => self.effects.update(|effects| effects.eq = Some(preset)),
+                Err(e) => outln!("{}: {}", "Warning".yellow(), e),
             }
-
-            InputCommands::Stop => {
-                if self.is_playing {
-                    self.sink.stop();
-                    self.is_playing = false;
-                    println!("{}: Playback stopped", "Info".red());
+        }
+        self.reopen_on_wake = config.get_bool("output.reopen_on_wake").unwrap_or(true);
+        if self.reopen_on_wake {
+            power::spawn_wake_watcher(events_tx.clone());
+        }
+        self.party_mode = config.get_bool("player.party_mode").unwrap_or(false);
+        self.offline = config.get_bool("network.offline").unwrap_or(false);
+        if cfg!(target_os = "linux") && self.on_call != cork::CallBehavior::Ignore {
+            cork::spawn_watcher(events_tx.clone());
+        }
+        let lead_addr = arguments
+            .get_one::<String>("lead")
+            .map(String::as_str)
+            .or_else(|| config.get_str("follow.listen"));
+        if let Some(addr) = lead_addr {
+            match follow::spawn_leader(addr, events_tx.clone()) {
+                Ok(leader) => {
+                    outln!("Leading followers on {}", addr.blue());
+                    self.leader = Some(leader);
                 }
+                Err(e) => outln!(
+                    "{}: Cannot lead followers on {}: {}",
+                    "Warning".yellow(),
+                    addr,
+                    e
+                ),
             }
-
-            InputCommands::List => {
-                self.list();
+        }
+        let leader_addr = arguments
+            .get_one::<String>("follow")
+            .map(String::as_str)
+            .or_else(|| config.get_str("follow.leader"));
+        if let Some(addr) = leader_addr {
+            follow::spawn_follower(addr.to_string(), events_tx.clone());
+        }
+        if config.get_bool("player.watch_config").unwrap_or(true) {
+            if let Some(path) = config::file_path(self.config_path.as_deref()) {
+                config::spawn_watcher(path, events_tx.clone());
             }
+        }
+        spawn_ticker(events_tx.clone());
+        self.announcer = Some(announce::Announcer::spawn(events_tx.clone()));
+        let input_done = (!daemon).then(|| spawn_input_thread(events_tx));
+        systemd::notify_ready();
 
-            InputCommands::Volume(vol) => {
-                if (0.0..=1.0).contains(&vol) {
-                    self.sink.set_volume(vol);
-                    println!("{}: Volume set to {:.1}", "Success".green(), vol);
-                } else {
-                    println!("{}: Volume must be 0.0 to 1.0", "Error".red());
+        // Main program loop
+        while let Ok(event) = events_rx.recv() {
+            match event {
+                PlayerEvent::Input(line) => {
+                    self.interrupted = false;
+                    self.at_prompt = true;
+                    self.get_commands(&line);
+                    self.at_prompt = false;
+                    // Let the prompt come back only once output is printed,
+                    // and not at all on the way out
+                    if let Some(input_done) = input_done.as_ref().filter(|_| !self.quitting) {
+                        let _ = input_done.send(());
+                    }
                 }
-            }
-
-            InputCommands::Status => {
-                println!("\n{}", "Player Status:".bold());
-                println!("{}", "--------------".bold());
-                if let Some(current) = &self.current_file {
-                    println!("  {}: {}", "Song".bold(), current.blue());
-                    let state = if self.is_paused {
-                        "Paused".yellow()
-                    } else if self.is_playing {
-                        "Playing".green()
-                    } else {
-                        "Stopped".red()
-                    };
-                    println!("  {}: {}", "State".bold(), state);
-                    if let Some(start) = &self.start_time {
-                        let elapsed = start.elapsed().as_secs();
-                        println!(
-                            "  {}: {} seconds",
-                            "Elapsed".bold(),
-                            elapsed.to_string().cyan()
-                        );
+                PlayerEvent::Remote(line, reply) => {
+                    let _ = reply.send(self.remote_reply(&line));
+                }
+                PlayerEvent::Rpc(call) => {
+                    let _ = call.reply.send(self.rpc_call(&call.method, &call.params));
+                }
+                PlayerEvent::Terminate => {
+                    self.shutdown();
+                    break;
+                }
+                PlayerEvent::Reload => self.reload_config(),
+                PlayerEvent::Interrupt => {
+                    if self.ctrl_c_exits || self.interrupted {
+                        self.shutdown();
+                        break;
                     }
-                } else {
-                    println!("  {}: No song playing", "Song".bold());
+                    self.interrupted = true;
+                    self.stop_playback();
+                    outln!(
+                        "\n{}: Press Ctrl+C again or type 'exit' to quit",
+                        "Info".blue()
+                    );
+                    print_prompt();
+                }
+                PlayerEvent::DeviceChanged(device) => self.device_changed(device),
+                PlayerEvent::Woke => self.woke_from_sleep(),
+                PlayerEvent::Call(active) => self.call_changed(active),
+                PlayerEvent::Heartbeat => {}
+                PlayerEvent::Leader(state) => self.follow_leader(state),
+                PlayerEvent::Tick => {
+                    self.check_track_end();
+                    self.report_downloads();
+                    self.report_rips();
+                    self.report_inbox();
+                    self.report_drops();
+                    self.report_on_air();
                 }
-                println!("  {}: {:.1}", "Volume".bold(), self.sink.volume());
+                PlayerEvent::Announced(number, error) => self.announced(number, error),
             }
-
-            InputCommands::Exit => exit(0),
-
-            InputCommands::Help => print_usage_instructions(),
-
-            InputCommands::InvalidCommand => {
-                println!(
-                    "{}: Invalid command - type 'help' for instructions",
-                    "Error".red().bold()
-                )
+            // After the reply to a remote `exit` has gone out
+            if self.quitting {
+                self.shutdown();
+                break;
             }
+            if let Some(leader) = &self.leader {
+                leader.broadcast(&self.follow_state());
+            }
+            self.notify_status_change();
+            self.record_session();
         }
+        Ok(())
     }
 
-    /// Processes user input and converts it to appropriate commands
-    pub fn get_commands(&mut self) {
-        let input_line = input();
-        let tokens: Vec<&str> = input_line.split_whitespace().collect();
-
-        // If no tokens, do nothing.
-        if tokens.is_empty() {
-            return;
+    /// Playback state as sent to followers
+    fn follow_state(&self) -> follow::State {
+        let track = self.current_index.and_then(|index| self.sync_key(index));
+        let position = self.position().unwrap_or(Duration::ZERO);
+        match track {
+            Some(track) if self.is_playing && self.is_paused => {
+                follow::State::Paused(track, position)
+            }
+            Some(track) if self.is_playing && !self.sink.empty() => {
+                follow::State::Playing(track, position)
+            }
+            _ => follow::State::Stopped,
         }
+    }
 
-        // Safely set last_input only if a second token exists.
-        self.last_input = tokens.get(1).map(|s| s.to_lowercase());
+    /// Mirrors the leader's playback, restarting the track at the leader's
+    /// position when we have drifted too far from it
+    fn follow_leader(&mut self, state: follow::State) {
+        let (track, position, paused) = match state {
+            follow::State::Playing(track, position) => (track, position, false),
+            follow::State::Paused(track, position) => (track, position, true),
+            follow::State::Stopped => {
+                self.stop_playback();
+                return;
+            }
+        };
+        let index = self.available_songs.as_ref().and_then(|songs| {
+            songs
+                .keys()
+                .copied()
+                .find(|&index| self.sync_key(index).as_ref() == Some(&track))
+        });
+        let Some(index) = index else {
+            if self.missing_track.as_ref() != Some(&track) {
+                outln!(
+                    "\n{}: Leader is playing {}, which isn't in the music directory",
+                    "Warning".yellow(),
+                    track
+                );
+                self.missing_track = Some(track);
+            }
+            self.stop_playback();
+            return;
+        };
+        self.missing_track = None;
 
-        // Dispatch based on the first token.
-        match tokens[0].to_lowercase().as_str() {
-            "play" => self.act_on_commands(InputCommands::Play),
-            "pause" => self.act_on_commands(InputCommands::Pause),
-            "list" => self.act_on_commands(InputCommands::List),
-            "resume" => self.act_on_commands(InputCommands::Resume),
-            "stop" => self.act_on_commands(InputCommands::Stop),
-            "volume" => {
-                if let Some(vol_str) = tokens.get(1) {
-                    if let Ok(vol) = vol_str.parse::<f32>() {
-                        self.act_on_commands(InputCommands::Volume(vol));
-                    } else {
-                        println!("{}: Invalid volume value", "Error".red());
-                    }
-                } else {
-                    println!("{}: Missing volume value", "Error".red());
-                }
+        let same_track = self.is_playing && self.current_index == Some(index);
+        let drift = self
+            .position()
+            .map_or(Duration::MAX, |ours| ours.abs_diff(position));
+        if !same_track || (!paused && drift > self.max_drift) {
+            if same_track {
+                outln!(
+                    "\n{}: {} ms off the leader, resyncing",
+                    "Info".blue(),
+                    drift.as_millis()
+                );
             }
-            "status" => self.act_on_commands(InputCommands::Status),
-            "help" => self.act_on_commands(InputCommands::Help),
-            "exit" => self.act_on_commands(InputCommands::Exit),
-            _ => self.act_on_commands(InputCommands::InvalidCommand),
+            if let Err(e) = self.play_from(index, position) {
+                outln!("{}: {}", "Error".red(), e);
+                return;
+            }
+        }
+        if paused != self.is_paused {
+            if paused {
+                self.sink.pause();
+            } else {
+                self.sink.play();
+            }
+            self.set_playhead_paused(paused);
+            self.is_paused = paused;
         }
     }
 
-    /// Lists all available songs with their index numbers
-    /// Highlights currently playing song if any
-    pub fn list(&self) {
-        if let Some(sound_map) = &self.available_songs {
-            println!("\n{}", "Available Songs:".green().bold());
-            println!("{}", "-------------------------------".green());
-            println!(
-                "{:<6} {:<}",
-                "Index".to_string().bold(),
-                "Filename".to_string().bold()
-            );
-            for (index, entry) in sound_map {
-                let filename = entry.file_name();
-                let filename = filename.to_string_lossy();
-                if let Some(current) = &self.current_file {
-                    if filename == *current {
-                        println!(
-                            "{:<6} {:<} {}",
-                            index.to_string().green(),
-                            filename.green(),
-                            "▶".green()
-                        );
-                    } else {
-                        println!("{:<6} {:<}", index, filename);
-                    }
-                } else {
-                    println!("{:<6} {:<}", index, filename);
-                }
-            }
-            println!();
+    /// Current session, as saved to the state file
+    fn session_state(&self) -> session::SessionState {
+        session::SessionState {
+            dir: self.main_dir.clone(),
+            file: self.current_file.clone(),
+            position: self.position().map_or(0, |position| position.as_secs()),
+            volume: self.sink.volume(),
+            paused: self.is_paused,
         }
     }
-}
 
-/// Main entry point for the application
-/// Sets up Ctrl+C handler and initializes the player
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Set up Ctrl+C handler for graceful exit
-    ctrlc::set_handler(|| {
-        println!("\n{}: Exiting...", "Info".blue());
-        exit(0);
-    })?;
+    /// Updates the session snapshot saved if the player crashes
+    fn record_session(&self) {
+        session::record(self.session_state());
+    }
+
+    /// Fades out, saves the session, and stops playback so the audio device
+    /// is released cleanly once the player is dropped
+    fn shutdown(&mut self) {
+        outln!("\n{}: Shutting down...", "Info".blue());
+        let state = self.session_state();
+        self.record_position();
+
+        if self.is_playing && !self.is_paused {
+            let volume = self.sink.volume();
+            for step in (0..10).rev() {
+                self.sink.set_volume(volume * step as f32 / 10.0);
+                thread::sleep(SHUTDOWN_FADE / 10);
+            }
+        }
+        self.sink.stop();
+
+        match state.save() {
+            Ok(path) => outln!("Session saved to {}", path.display()),
+            Err(e) => outln!("{}: Could not save session: {}", "Warning".yellow(), e),
+        }
+        if let Some(overlay) = &self.now_playing_file {
+            let _ = overlay.clear();
+        }
+    }
+
+    /// Pauses when the default output device changes away from the one we
+    /// play on, and optionally resumes once it is back
+    fn device_changed(&mut self, device: Option<String>) {
+        if device == self.output_device {
+            if self.paused_by_device && self.resume_on_device_return {
+                outln!("\n{}: Output device is back", "Info".green());
+                self.act_on_commands(InputCommands::Resume);
+            }
+            self.paused_by_device = false;
+            return;
+        }
+
+        if self.pause_on_device_change && self.is_playing && !self.is_paused {
+            outln!(
+                "\n{}: Output device changed to {}",
+                "Info".yellow(),
+                device.as_deref().unwrap_or("none")
+            );
+            self.act_on_commands(InputCommands::Pause);
+            self.paused_by_device = true;
+        }
+    }
+
+    /// Pauses or ducks playback while a voice call is running, and undoes
+    /// it when the call ends
+    fn call_changed(&mut self, active: bool) {
+        if !active {
+            match self.call_restore.take() {
+                Some(CallRestore::Resume) if self.is_paused => {
+                    outln!("\n{}: Call ended", "Info".green());
+                    self.act_on_commands(InputCommands::Resume);
+                }
+                Some(CallRestore::Volume(volume)) => self.sink.set_volume(volume),
+                _ => {}
+            }
+            return;
+        }
+
+        if !self.is_playing || self.is_paused {
+            return;
+        }
+        match self.on_call {
+            cork::CallBehavior::Pause => {
+                outln!("\n{}: Call started", "Info".yellow());
+                self.act_on_commands(InputCommands::Pause);
+                self.call_restore = Some(CallRestore::Resume);
+            }
+            cork::CallBehavior::Duck(factor) => {
+                let volume = self.sink.volume();
+                self.sink.set_volume(volume * factor);
+                self.call_restore = Some(CallRestore::Volume(volume));
+            }
+            cork::CallBehavior::Ignore => {}
+        }
+    }
+
+    /// Speaks the artist and title of a track that just started, ducking
+    /// the music until it is over
+    fn announce(&mut self, song: &Path) {
+        let (Some(announcer), Some(duck)) = (&self.announcer, announce::duck()) else {
+            return;
+        };
+        let (artist, title) = artist_and_title(song);
+        self.announcements += 1;
+        announcer.announce(self.announcements, &artist.unwrap_or_default(), &title);
+        // Already ducked when the previous announcement is still going
+        if self.announce_restore.is_none() {
+            let volume = self.sink.volume();
+            self.sink.set_volume(volume * duck);
+            self.announce_restore = Some(volume);
+        }
+    }
+
+    /// Brings the volume back once the latest announcement is over
+    fn announced(&mut self, number: u64, error: Option<String>) {
+        if let Some(e) = error {
+            outln!(
+                "\n{}: Could not speak announcement: {}",
+                "Warning".yellow(),
+                e
+            );
+            print_prompt();
+        }
+        if number == self.announcements {
+            if let Some(volume) = self.announce_restore.take() {
+                self.sink.set_volume(volume);
+            }
+        }
+    }
+
+    /// Re-opens the audio stream after a suspend, since the old one
+    /// usually goes silent, and leaves the track paused where it was
+    fn woke_from_sleep(&mut self) {
+        let position = self.position();
+        let resume_track = self.is_playing;
+
+        let reopened = output::open(self.backend.as_deref()).and_then(|output| {
+            self.sink.reopen(&output).map_err(|e| e.to_string())?;
+            Ok(output)
+        });
+        let output = match reopened {
+            Ok(output) => output,
+            Err(e) => {
+                outln!("\n{}: Could not re-open audio output: {}", "Error".red(), e);
+                return;
+            }
+        };
+        self._stream = output.stream;
+        self.stream_handle = output.handle;
+        self.output_channels = output.channels;
+        self.is_playing = false;
+        outln!(
+            "\n{}: Resumed from sleep, audio output re-opened",
+            "Info".blue()
+        );
+
+        if let (true, Some(index), Some(position)) = (resume_track, self.current_index, position) {
+            match self.play_from(index, position) {
+                Ok(()) => self.act_on_commands(InputCommands::Pause),
+                Err(e) => outln!("{}: {}", "Error".red(), e),
+            }
+        }
+    }
+
+    /// Re-reads the config file and applies the settings that can change
+    /// at runtime
+    fn reload_config(&mut self) {
+        let loaded = Config::load_profile(self.config_path.as_deref(), self.profile.as_deref())
+            .map_err(|e| e.to_string())
+            .and_then(|config| Ok((auth::Tokens::from_config(&config)?, config)));
+        match loaded {
+            Ok((tokens, config)) => {
+                self.apply_settings(&config);
+                self.tokens.set_read_only(tokens.is_read_only());
+                // Never drop to unauthenticated access on an exposed socket
+                if tokens.is_enabled() || !self.auth_required {
+                    self.tokens.replace(&tokens);
+                }
+                outln!("{}: Configuration reloaded", "Info".green());
+            }
+            Err(e) => outln!("{}: Could not reload config: {}", "Error".red(), e),
+        }
+    }
+
+    /// Applies the settings that can change while the player runs, at
+    /// startup and on every reload; missing ones go back to their defaults
+    /// Party mode and the equalizer are only read at startup, since they
+    /// are also switched at the prompt
+    fn apply_settings(&mut self, config: &Config) {
+        let list_columns = config.get_list("list.columns");
+        self.list_columns = match list_columns.as_slice() {
+            [] => columns::parse(&[columns::DEFAULT]).unwrap(),
+            items => columns::parse(items).unwrap_or_else(|e| {
+                outln!("{}: {}", "Warning".yellow(), e);
+                columns::parse(&[columns::DEFAULT]).unwrap()
+            }),
+        };
+        self.ctrl_c_exits = config.get_str("player.ctrl_c") == Some("exit");
+        remote::configure(config);
+        cache::configure(config);
+        bandwidth::configure(config);
+        screen_reader::configure(config);
+        announce::configure(config);
+        cd::configure(config);
+        self.pause_on_device_change = config
+            .get_bool("output.pause_on_device_change")
+            .unwrap_or(true);
+        self.resume_on_device_return = config
+            .get_bool("output.resume_on_device_return")
+            .unwrap_or(false);
+        // Used from the next track on
+        let channel_map = config.get_numbers("output.channel_map");
+        self.channel_map = (!channel_map.is_empty()).then(|| {
+            channel_map
+                .iter()
+                .map(|&channel| (channel >= 0.0).then_some(channel as usize))
+                .collect()
+        });
+        self.on_call = cork::CallBehavior::from_config(config);
+        self.organize_template = config
+            .get_str("organize.template")
+            .unwrap_or(files::DEFAULT_TEMPLATE)
+            .to_string();
+        self.position_sync = sync::PositionSync::from_config(config);
+        self.show_remaining = config.get_str("player.time_display") == Some("remaining");
+        self.prev_restart_within = config
+            .get_f64("player.prev_restart_within")
+            .map_or(Duration::from_secs(3), |seconds| {
+                Duration::from_secs_f64(seconds.max(0.0))
+            });
+        self.skip_intervals = SkipIntervals::from_config(config);
+        self.skip_shorter_than = config
+            .get_f64("player.skip_shorter_than")
+            .map_or(Duration::ZERO, |seconds| {
+                Duration::from_secs_f64(seconds.max(0.0))
+            });
+        self.advance = config.get_bool("player.advance").unwrap_or(true);
+        self.resume_min_position = config
+            .get_f64("sync.min_position")
+            .map_or(60, |seconds| seconds.max(0.0) as u64);
+        self.max_drift = config
+            .get_f64("follow.max_drift_ms")
+            .map_or(follow::DEFAULT_MAX_DRIFT, |ms| {
+                Duration::from_millis(ms.max(0.0) as u64)
+            });
+        self.configure_drop_folder(config);
+    }
+
+    /// Starts, changes or stops watching the `[drop]` folder
+    fn configure_drop_folder(&mut self, config: &Config) {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let expand = |dir: &str| match (dir.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => PathBuf::from(dir),
+        };
+        let dir = config.get_str("drop.dir").map(expand);
+        let music_dir = self
+            .main_dir
+            .as_deref()
+            .map(PathBuf::from)
+            .filter(|dir| !remote::is_remote(dir));
+        let into = match config.get_bool("drop.move").unwrap_or(false) {
+            true => music_dir.map(|music| match config.get_str("drop.into") {
+                Some(folder) => music.join(folder),
+                None => music,
+            }),
+            false => None,
+        };
+        let watched = self.drop_folder.as_ref().map(|folder| folder.dir());
+        if watched == dir.as_deref() && into == self.drop_into {
+            return;
+        }
+        self.drop_folder = None;
+        self.drop_into = into;
+        let Some(dir) = dir else {
+            return;
+        };
+        // Files left in the folder are only taken when they get moved out,
+        // or they would be queued again at every start
+        match inbox::DropFolder::watch(&dir, self.drop_into.is_some()) {
+            Ok(folder) => {
+                outln!("{}: Watching drop folder {}", "Info".blue(), dir.display());
+                self.drop_folder = Some(folder);
+            }
+            Err(e) => outln!(
+                "{}: Cannot watch drop folder {}: {}",
+                "Warning".yellow(),
+                dir.display(),
+                e
+            ),
+        }
+    }
+
+    /// Whether party mode lets a remote client run a command line
+    /// Guests may look at the player, add to the queue and start a song
+    /// when nothing is playing, but not skip, stop, take tracks out of the
+    /// queue or change the volume
+    fn party_allows(&self, line: &str) -> bool {
+        let mut words = line.split_whitespace().map(str::to_lowercase);
+        match words.next().as_deref() {
+            Some("status" | "list" | "help") => true,
+            Some("play") => !self.is_playing || self.sink.empty(),
+            Some("queue") => matches!(words.next().as_deref(), None | Some("add" | "show")),
+            _ => false,
+        }
+    }
+
+    /// Name of the current playback state as reported to remote clients
+    fn state_name(&self) -> &'static str {
+        if self.is_paused {
+            "paused"
+        } else if self.is_playing {
+            "playing"
+        } else {
+            "stopped"
+        }
+    }
+
+    /// Player status as a JSON object, without the ever-changing elapsed time
+    fn status_json(&self) -> Json {
+        let mut status = Json::object(vec![
+            ("state", Json::from(self.state_name())),
+            (
+                "file",
+                self.current_file
+                    .clone()
+                    .map(Json::from)
+                    .unwrap_or(Json::Null),
+            ),
+            ("volume", Json::from(self.sink.volume() as f64)),
+        ]);
+        if let (Json::Object(members), Some(on_air)) = (&mut status, self.listening()) {
+            let text = |text: Option<String>| text.map(Json::from).unwrap_or(Json::Null);
+            members.push(("station".to_string(), text(on_air.station)));
+            members.push(("streamTitle".to_string(), text(on_air.title)));
+        }
+        status
+    }
+
+    /// Runs a JSON-RPC method; each REPL command is exposed as a method
+    fn rpc_call(&mut self, method: &str, params: &Json) -> Result<Json, RpcError> {
+        if self.party_mode && !self.party_allows(method) {
+            return Err(RpcError::new(
+                FORBIDDEN,
+                "Party mode: guests can only see status, add to the queue and start a song when nothing is playing",
+            ));
+        }
+        match method {
+            "play" => {
+                let index = rpc::param(params, 0, "index")
+                    .and_then(Json::as_f64)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a song index"))?;
+                self.play(index as i32)
+                    .map_err(|e| RpcError::new(PLAYER_ERROR, e.to_string()))?;
+            }
+            "pause" => self.act_on_commands(InputCommands::Pause),
+            "resume" => self.act_on_commands(InputCommands::Resume),
+            "stop" => self.act_on_commands(InputCommands::Stop),
+            "volume" => {
+                let level = rpc::param(params, 0, "level")
+                    .and_then(Json::as_f64)
+                    .filter(|level| (0.0..=1.0).contains(level))
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Volume must be 0.0 to 1.0"))?;
+                self.act_on_commands(InputCommands::Volume(level as f32));
+            }
+            "status" => {
+                let mut status = self.status_json();
+                if let (Json::Object(members), Some(position)) = (&mut status, self.position()) {
+                    members.push(("elapsed".to_string(), Json::from(position.as_secs() as f64)));
+                    if let Some(remaining) = self.remaining() {
+                        members.push((
+                            "remaining".to_string(),
+                            Json::from(remaining.as_secs() as f64),
+                        ));
+                    }
+                }
+                return Ok(status);
+            }
+            "list" => {
+                let mut songs: Vec<(&i32, &PathBuf)> = self
+                    .available_songs
+                    .iter()
+                    .flat_map(|songs| songs.iter())
+                    .collect();
+                songs.sort_by_key(|(index, _)| **index);
+                return Ok(Json::Array(
+                    songs
+                        .into_iter()
+                        .map(|(index, entry)| {
+                            Json::object(vec![
+                                ("index", Json::from(*index as f64)),
+                                ("file", Json::from(file_name(entry))),
+                            ])
+                        })
+                        .collect(),
+                ));
+            }
+            // Any other command runs as typed, with the params as its words
+            _ => {
+                let words = match params {
+                    Json::Null => Vec::new(),
+                    Json::Array(items) => items
+                        .iter()
+                        .map(rpc::word)
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| {
+                            RpcError::new(INVALID_PARAMS, "Expected strings or numbers")
+                        })?,
+                    _ => return Err(RpcError::new(INVALID_PARAMS, "Expected params as an array")),
+                };
+                let line = std::iter::once(method.to_string())
+                    .chain(words)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if self.party_mode && !self.party_allows(&line) {
+                    return Err(RpcError::new(FORBIDDEN, "Party mode: guests can only see status, add to the queue and start a song when nothing is playing"));
+                }
+                let (output, error) = self.run_captured(&line);
+                return match error {
+                    Some(message) if message.starts_with("Invalid command") => {
+                        Err(RpcError::new(METHOD_NOT_FOUND, "Method not found"))
+                    }
+                    Some(message) => Err(RpcError::new(PLAYER_ERROR, message)),
+                    None => Ok(Json::from(output)),
+                };
+            }
+        }
+        Ok(self.status_json())
+    }
+
+    /// Sends a `statusChanged` notification to JSON-RPC clients whenever
+    /// the state, track, or volume changed since the last one
+    fn notify_status_change(&mut self) {
+        let Some(notifier) = &self.rpc_notifier else {
+            return;
+        };
+        let status = self.status_json();
+        if self.last_status.as_ref() != Some(&status) {
+            notifier.notify("statusChanged", status.clone());
+            self.last_status = Some(status);
+        }
+    }
+
+    /// Answers a control socket command
+    /// Queries return `key: value` lines; other commands run as if typed
+    fn remote_reply(&mut self, line: &str) -> String {
+        if self.party_mode && !self.party_allows(line) {
+            return "ERR party mode: guests can only see status, add to the queue and start a song when nothing is playing\n"
+                .to_string();
+        }
+        let mut reply = String::new();
+        match line.trim().to_lowercase().as_str() {
+            "status" => {
+                reply.push_str(&format!("state: {}\n", self.state_name()));
+                if let Some(current) = &self.current_file {
+                    reply.push_str(&format!("file: {}\n", current));
+                }
+                if let Some(position) = self.position() {
+                    reply.push_str(&format!("elapsed: {}\n", position.as_secs()));
+                }
+                // Only sent when chosen, so the tmux segment counts down
+                if let Some(remaining) = self.remaining().filter(|_| self.show_remaining) {
+                    reply.push_str(&format!("remaining: {}\n", remaining.as_secs()));
+                }
+                reply.push_str(&format!("volume: {:.1}\n", self.sink.volume()));
+                if let Some(on_air) = self.listening() {
+                    if let Some(station) = on_air.station {
+                        reply.push_str(&format!("station: {}\n", station));
+                    }
+                    if let Some(title) = on_air.title {
+                        reply.push_str(&format!("on_air: {}\n", title));
+                    }
+                }
+            }
+            _ => {
+                // What the command prints goes back to the client, with an
+                // error message as the ERR line
+                let (output, error) = self.run_captured(line);
+                reply.push_str(&output);
+                if let Some(message) = error {
+                    reply.push_str(&format!("ERR {}\n", message));
+                    return reply;
+                }
+            }
+        }
+        reply.push_str("OK\n");
+        reply
+    }
+
+    /// Runs a command line for a remote client, returning what it printed
+    /// without colors and the first error message apart
+    fn run_captured(&mut self, line: &str) -> (String, Option<String>) {
+        let mut output = Vec::new();
+        let _ = capture::to(&mut output, || self.get_commands(line));
+        let output = String::from_utf8_lossy(&output);
+        // Still shown here, so the terminal tells what was done
+        out!("{}", output);
+        let mut text = String::new();
+        let mut error = None;
+        for line in capture::plain(&output).lines() {
+            match line.strip_prefix("Error: ") {
+                Some(message) if error.is_none() => error = Some(message.to_string()),
+                _ => text.push_str(&format!("{}\n", line)),
+            }
+        }
+        (text, error)
+    }
+
+    /// Loads songs from the specified directory into the available_songs HashMap
+    fn load_songs(&mut self) -> io::Result<()> {
+        let mut index = 1;
+        if let Some(dir) = &self.main_dir {
+            if let Some(sound_map) = &mut self.available_songs {
+                for entry in self.scan_filter.files(Path::new(dir))? {
+                    sound_map.insert(index, entry);
+                    index += 1;
+                }
+            }
+        }
+        for dir in self.extra_dirs.clone() {
+            // A root that went away, e.g. an unplugged drive, is just left out
+            if let Some(sound_map) = &mut self.available_songs {
+                for entry in self.scan_filter.files(&dir).unwrap_or_default() {
+                    sound_map.insert(index, entry);
+                    index += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Plays a song by its index number
+    /// Handles stopping current playback and starting new playback
+    /// Picks up from the position synced from another machine, if any
+    pub fn play(&mut self, sound_index: i32) -> Result<(), Box<dyn std::error::Error>> {
+        let resume = self
+            .sync_key(sound_index)
+            .zip(self.position_sync.as_ref())
+            .and_then(|(key, sync)| sync.position(&key))
+            .filter(|&position| position > 0 && position >= self.resume_min_position);
+        let intro = self
+            .available_songs
+            .as_ref()
+            .and_then(|songs| songs.get(&sound_index))
+            .and_then(|entry| self.stats.get(entry))
+            .and_then(|stats| stats.intro);
+        // A station is joined wherever it is
+        let (resume, intro) = match self.is_station(sound_index) {
+            true => (None, None),
+            false => (resume, intro),
+        };
+        self.play_from(
+            sound_index,
+            Duration::from_secs(resume.or(intro).unwrap_or(0)),
+        )?;
+        if let Some(position) = resume {
+            outln!(
+                "{}: Resuming at {}:{:02}",
+                "Info".blue(),
+                position / 60,
+                position % 60
+            );
+        }
+        if let Some(path) = self.current_path() {
+            self.radio.played(&path);
+            if let Err(e) = self.history.record(&path) {
+                outln!(
+                    "{}: Could not save the play history: {}",
+                    "Warning".yellow(),
+                    e
+                );
+            }
+            self.stats.entry(&path).plays += 1;
+            if let Err(e) = self.stats.save() {
+                outln!("{}: Could not save play count: {}", "Warning".yellow(), e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Path of the current track
+    fn current_path(&self) -> Option<PathBuf> {
+        let index = self.current_index?;
+        Some(self.available_songs.as_ref()?.get(&index)?.clone())
+    }
+
+    /// Key of a track in the sync folder: its path relative to the music
+    /// directory, so it matches on machines with a different home
+    fn sync_key(&self, sound_index: i32) -> Option<String> {
+        let path = self.available_songs.as_ref()?.get(&sound_index)?.clone();
+        let dir = self.main_dir.as_ref()?;
+        let relative = path.strip_prefix(dir).unwrap_or(&path);
+        Some(relative.to_string_lossy().replace('\\', "/"))
+    }
+
+    /// Saves the current track's position to the sync folder
+    /// A track that played to the end is saved as position 0; a paused track
+    /// was already saved when it was paused
+    fn record_position(&mut self) {
+        if !self.is_playing || self.is_paused {
+            return;
+        }
+        let Some(index) = self.current_index.filter(|&index| !self.is_station(index)) else {
+            return;
+        };
+        let Some(key) = self.sync_key(index) else {
+            return;
+        };
+        let position = if self.sink.empty() {
+            0
+        } else {
+            self.position().map_or(0, |position| position.as_secs())
+        };
+        if let Some(sync) = &mut self.position_sync {
+            if let Err(e) = sync.record(&key, position) {
+                outln!("{}: Could not sync position: {}", "Warning".yellow(), e);
+            }
+        }
+    }
+
+    /// Plays a song starting `offset` into the track
+    /// The volume carries over from the previous track
+    fn play_from(
+        &mut self,
+        sound_index: i32,
+        offset: Duration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Refused before the current track stops
+        let song = self
+            .available_songs
+            .as_ref()
+            .and_then(|songs| songs.get(&sound_index));
+        if let Some(song) = song.filter(|song| self.unavailable(song)) {
+            return Err(format!(
+                "Offline: {} isn't cached; use `offline off` to download it",
+                file_name(song)
+            )
+            .into());
+        }
+        // A track restarted in place keeps the speed it had
+        let new_track = self.current_index != Some(sound_index);
+        if new_track {
+            self.speed = song
+                .and_then(|song| self.speed_key(song))
+                .and_then(|key| self.speeds.get(&key))
+                .unwrap_or(1.0);
+        }
+        if self.is_playing {
+            self.record_position();
+            self.sink.replace(&self.stream_handle)?;
+        }
+
+        if let Some(sound_map) = &self.available_songs {
+            if let Some(song) = sound_map.get(&sound_index) {
+                match &self.sink {
+                    #[cfg(feature = "mpv")]
+                    Sink::Mpv(_) if cd::is_cd(song) => {
+                        return Err("CD tracks only play with the default backend".into());
+                    }
+                    #[cfg(feature = "mpv")]
+                    Sink::Mpv(mpv) => {
+                        mpv.load(song, offset)?;
+                        mpv.set_speed(self.speed);
+                        let mut playhead = playhead::Playhead::start(offset, None);
+                        playhead.set_speed(self.speed as f64);
+                        self.playhead = Some(playhead);
+                        self.duration = codecs::length(song);
+                    }
+                    #[cfg(feature = "gstreamer")]
+                    Sink::Gstreamer(_) if cd::is_cd(song) => {
+                        return Err("CD tracks only play with the default backend".into());
+                    }
+                    #[cfg(feature = "gstreamer")]
+                    Sink::Gstreamer(playbin) => {
+                        playbin.load(song, offset)?;
+                        self.speed = 1.0;
+                        self.playhead = Some(playhead::Playhead::start(offset, None));
+                        self.duration = codecs::length(song);
+                    }
+                    _ => {
+                        let source = codecs::open_at(song, offset)?;
+                        self.duration = source.total_duration();
+                        let channels = source.channels();
+                        let matrix = match &self.channel_map {
+                            Some(map) => Some(dsp::channel_map_matrix(map, channels)),
+                            None if self.output_channels <= 2 => dsp::downmix_matrix(channels),
+                            None => None,
+                        };
+                        let (source, clock) = follow::counted(
+                            self.effects.apply(dsp::Remix::new(source, matrix)),
+                            offset,
+                        );
+                        // Sped up outside the clock, which counts track time
+                        self.sink.append(source.speed(self.speed));
+                        self.playhead = Some(playhead::Playhead::start(offset, Some(clock)));
+                    }
+                }
+                self.is_playing = true;
+                self.is_paused = false;
+                self.current_index = Some(sound_index);
+                self.current_file = Some(self.library_name(song));
+                outln!(
+                    "{}: Playing {}",
+                    "Now playing".green().bold(),
+                    self.current_file.as_ref().unwrap().blue()
+                );
+                let song = song.clone();
+                if new_track {
+                    self.announce(&song);
+                }
+                if let Some(overlay) = &self.now_playing_file {
+                    if let Err(e) = overlay.write(&song, self.current_file.as_ref().unwrap()) {
+                        outln!(
+                            "{}: Could not write now-playing file: {}",
+                            "Warning".yellow(),
+                            e
+                        );
+                    }
+                }
+                Ok(())
+            } else {
+                Err("Invalid song index".into())
+            }
+        } else {
+            Err("No songs available".into())
+        }
+    }
+
+    /// Stops playback and swaps in a fresh sink, since a stopped sink
+    /// stays silent for anything appended later
+    fn stop_playback(&mut self) {
+        if !self.is_playing {
+            return;
+        }
+        self.record_position();
+        // A stopped sink is still stopped, which is all that matters here
+        let _ = self.sink.replace(&self.stream_handle);
+        self.playhead = None;
+        self.is_playing = false;
+        self.is_paused = false;
+        outln!("{}: Playback stopped", "Info".red());
+        if let Some(overlay) = &self.now_playing_file {
+            if let Err(e) = overlay.clear() {
+                outln!(
+                    "{}: Could not clear now-playing file: {}",
+                    "Warning".yellow(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Processes and executes commands based on the InputCommands enum
+    pub fn act_on_commands(&mut self, command: InputCommands) {
+        match command {
+            InputCommands::Play(spec) => self.play_selection(&spec),
+            InputCommands::Pause => {
+                self.paused_by_device = false;
+                self.call_restore = None;
+                if self.is_playing {
+                    self.sink.pause();
+                    self.set_playhead_paused(true);
+                    self.record_position();
+                    self.is_paused = true;
+                    outln!("{}: Playback paused", "Info".yellow());
+                }
+            }
+
+            InputCommands::Resume => {
+                self.paused_by_device = false;
+                if self.is_paused {
+                    self.sink.play();
+                    self.set_playhead_paused(false);
+                    self.is_paused = false;
+                    self.is_playing = true;
+                    outln!("{}: Playback resumed", "Info".green());
+                }
+            }
+
+            InputCommands::Stop => self.stop_playback(),
+
+            InputCommands::List(args) => match args.as_slice() {
+                [] => self.list(&self.list_columns, None),
+                [flag] if flag == "--detail" => {
+                    self.list(&columns::parse(&[columns::DETAIL]).unwrap(), None)
+                }
+                [flag, spec @ ..] if flag == "--columns" && !spec.is_empty() => {
+                    match columns::parse(spec) {
+                        Ok(columns) => self.list(&columns, None),
+                        Err(e) => outln!("{}: {}", "Error".red(), e),
+                    }
+                }
+                [flag, label] if flag == "--tag" => match labels::normalize(label) {
+                    Some(label) => self.list(&self.list_columns, Some(&label)),
+                    None => outln!("{}: Invalid label {}", "Error".red(), label),
+                },
+                _ => outln!(
+                    "{}: Usage: list [--detail | --columns index,title:30,artist,... | --tag <label>]",
+                    "Error".red()
+                ),
+            },
+
+            InputCommands::Volume(vol) => {
+                if (0.0..=1.0).contains(&vol) {
+                    // An explicit volume wins over restoring a ducked one
+                    if let Some(CallRestore::Volume(_)) = self.call_restore {
+                        self.call_restore = None;
+                    }
+                    self.announce_restore = None;
+                    self.sink.set_volume(vol);
+                    outln!("{}: Volume set to {:.1}", "Success".green(), vol);
+                } else {
+                    outln!("{}: Volume must be 0.0 to 1.0", "Error".red());
+                }
+            }
+
+            InputCommands::Status if screen_reader::enabled() => {
+                outln!("{}", self.announce_status());
+            }
+
+            InputCommands::Status => {
+                outln!("\n{}", "Player Status:".bold());
+                screen_reader::rule("--------------".bold());
+                if let Some(current) = &self.current_file {
+                    outln!("  {}: {}", "Song".bold(), current.blue());
+                    if let Some(on_air) = self.listening() {
+                        if let Some(station) = on_air.station {
+                            outln!("  {}: {}", "Station".bold(), station);
+                        }
+                        if let Some(title) = on_air.title {
+                            outln!("  {}: {}", "On air".bold(), title.blue());
+                        }
+                    }
+                    let tags = self.current_path().and_then(|path| tags::read(&path).ok());
+                    if let Some(tags) = tags {
+                        print_tags(&tags);
+                    }
+                    let state = if self.is_paused {
+                        "Paused".yellow()
+                    } else if self.is_playing {
+                        "Playing".green()
+                    } else {
+                        "Stopped".red()
+                    };
+                    outln!("  {}: {}", "State".bold(), state);
+                    if let Some(position) = self.position() {
+                        let elapsed = position.as_secs();
+                        let remaining = self.remaining().map_or(String::new(), |left| {
+                            format!(
+                                ", -{}:{:02} remaining",
+                                left.as_secs() / 60,
+                                left.as_secs() % 60
+                            )
+                        });
+                        outln!(
+                            "  {}: {} seconds{}",
+                            "Elapsed".bold(),
+                            elapsed.to_string().cyan(),
+                            remaining
+                        );
+                    }
+                } else {
+                    outln!("  {}: No song playing", "Song".bold());
+                }
+                if let Some(stats) = self.current_path().and_then(|p| self.stats.get(&p)) {
+                    outln!("  {}: {}", "Plays".bold(), stats.plays);
+                    if let Some(rating) = stats.rating {
+                        outln!("  {}: {}", "Rating".bold(), screen_reader::stars(rating));
+                    }
+                    if let Some(intro) = stats.intro {
+                        outln!(
+                            "  {}: starts at {}:{:02}",
+                            "Intro".bold(),
+                            intro / 60,
+                            intro % 60
+                        );
+                    }
+                }
+                if let Some(album) = self
+                    .stop_after_album
+                    .as_ref()
+                    .and_then(|t| t.album.as_ref())
+                {
+                    outln!("  {}: end of {}", "Stopping".bold(), album.cyan());
+                }
+                outln!("  {}: {:.1}", "Volume".bold(), self.sink.volume());
+                if self.offline {
+                    outln!("  {}: on", "Offline".bold());
+                }
+                if self.autoplay {
+                    outln!("  {}: on", "Autoplay".bold());
+                }
+                if self.radio_chain {
+                    outln!("  {}: on", "Radio".bold());
+                }
+                if let Some(next) = self.queue.peek() {
+                    let name = self.song_path(&next.to_string());
+                    let name = name.as_deref().map_or("?".to_string(), file_name);
+                    let more = match self.queue.len() - 1 {
+                        0 => String::new(),
+                        n => format!(" (+{} more)", n),
+                    };
+                    outln!("  {}: {}. {}{}", "Up next".bold(), next, name, more);
+                }
+                if self.speed != 1.0 {
+                    outln!("  {}: {}x", "Speed".bold(), self.speed);
+                }
+                if let Sink::Direct(direct) = &self.sink {
+                    let buffer = match (direct.settings().frames, direct.latency_ms()) {
+                        (Some(frames), Some(ms)) => format!("{} frames ({:.1} ms)", frames, ms),
+                        _ => "device default".to_string(),
+                    };
+                    outln!("  {}: {}", "Buffer".bold(), buffer);
+                    outln!("  {}: {}", "Output".bold(), direct.format_name());
+                    let level = match direct.peak() {
+                        peak if peak > 0.0 => format!("{:.1} dBFS", 20.0 * peak.log10()),
+                        _ => "silent".to_string(),
+                    };
+                    outln!("  {}: {}", "Level".bold(), level);
+                }
+            }
+
+            InputCommands::Karaoke(enabled) => {
+                self.effects.update(|effects| effects.karaoke = enabled);
+                let state = if enabled { "on" } else { "off" };
+                outln!("{}: Karaoke {}", "Info".blue(), state);
+            }
+
+            InputCommands::Crossfeed(enabled) => {
+                self.effects.update(|effects| effects.crossfeed = enabled);
+                let state = if enabled { "on" } else { "off" };
+                outln!("{}: Crossfeed {}", "Info".blue(), state);
+            }
+
+            InputCommands::Eq(args) => self.equalizer(&args),
+
+            InputCommands::Playlist(args) => self.playlist(&args),
+
+            InputCommands::Import(args) => self.import(&args),
+
+            InputCommands::Podcast(args) => self.podcast(&args),
+
+            InputCommands::Cd(args) => self.cd(&args),
+
+            InputCommands::Dir(args) => self.dir(&args),
+
+            InputCommands::Rip(args) => self.rip(&args),
+
+            InputCommands::Bench(args) => self.bench(&args),
+
+            InputCommands::NightMode(enabled) => {
+                self.effects.update(|effects| effects.night_mode = enabled);
+                let state = if enabled { "on" } else { "off" };
+                outln!("{}: Night mode {}", "Info".blue(), state);
+            }
+
+            InputCommands::Party(enabled) => {
+                self.party_mode = enabled;
+                let state = if enabled { "on" } else { "off" };
+                outln!("{}: Party mode {}", "Info".blue(), state);
+            }
+
+            InputCommands::Similar(args) => self.similar(&args),
+
+            InputCommands::Tag(args) => self.tag(&args),
+            InputCommands::Db(args) => self.db(&args),
+            InputCommands::Follow(args) => self.follow_playlist(&args),
+            InputCommands::Queue(args) => self.queue_command(&args),
+            InputCommands::Stream(url) => self.tune_in(&url),
+
+            InputCommands::Radio(args) => self.radio(&args),
+
+            InputCommands::Autoplay(enabled) => {
+                self.autoplay = enabled;
+                let state = if enabled { "on" } else { "off" };
+                outln!("{}: Autoplay {}", "Info".blue(), state);
+            }
+
+            InputCommands::Offline(enabled) => {
+                self.offline = enabled;
+                if enabled {
+                    outln!(
+                        "{}: Offline mode on; remote tracks play only when cached",
+                        "Info".blue()
+                    );
+                } else {
+                    outln!("{}: Offline mode off", "Info".blue());
+                }
+            }
+
+            InputCommands::Speed(speed) => self.set_speed(speed),
+
+            InputCommands::Delete(args) => self.delete_track(&args),
+
+            InputCommands::Move(args) => self.move_track(&args),
+
+            InputCommands::Organize(args) => self.organize(&args),
+
+            InputCommands::Paste => self.paste(),
+
+            InputCommands::Pick(query) => self.pick(&query),
+
+            InputCommands::Replay(back) => {
+                let Some(position) = self.position() else {
+                    outln!("{}: Nothing is playing", "Error".red());
+                    return;
+                };
+                let target = position.saturating_sub(back);
+                match self.seek_to(target) {
+                    Ok(()) => outln!(
+                        "{}: Back to {}:{:02}",
+                        "Info".blue(),
+                        target.as_secs() / 60,
+                        target.as_secs() % 60
+                    ),
+                    Err(e) => outln!("{}: {}", "Error".red(), e),
+                }
+            }
+
+            InputCommands::Seek(target) => {
+                if self.current_index.is_some_and(|index| self.is_station(index)) {
+                    return outln!("{}: A live stream can't be sought in", "Error".red());
+                }
+                if self.position().is_none() {
+                    return outln!("{}: Nothing is playing", "Error".red());
+                }
+                if let Some(length) = self.duration.filter(|&length| target >= length) {
+                    return outln!(
+                        "{}: The track is only {}:{:02} long",
+                        "Error".red(),
+                        length.as_secs() / 60,
+                        length.as_secs() % 60
+                    );
+                }
+                match self.seek_to(target) {
+                    Ok(()) => outln!(
+                        "{}: Jumped to {}:{:02}",
+                        "Info".blue(),
+                        target.as_secs() / 60,
+                        target.as_secs() % 60
+                    ),
+                    Err(e) => outln!("{}: {}", "Error".red(), e),
+                }
+            }
+
+            InputCommands::SkipForward(by) => self.skip(true, by),
+
+            InputCommands::SkipBack(by) => self.skip(false, by),
+
+            InputCommands::ShowRemaining(enabled) => {
+                self.show_remaining = enabled;
+                let shown = if enabled {
+                    "time remaining"
+                } else {
+                    "time played"
+                };
+                outln!("{}: Showing {}", "Info".blue(), shown);
+            }
+
+            InputCommands::Lyrics => self.show_lyrics(),
+
+            InputCommands::ConfigReload => self.reload_config(),
+
+            InputCommands::StopAfterAlbum(true) => {
+                let tags = self
+                    .current_path()
+                    .filter(|_| self.is_playing)
+                    .map(|path| tags::read(&path).unwrap_or_default());
+                match tags {
+                    None => outln!("{}: Nothing is playing", "Error".red()),
+                    Some(tags::Tags { album: None, .. }) => {
+                        outln!("{}: The current track has no album tag", "Error".red())
+                    }
+                    Some(tags) => {
+                        outln!(
+                            "{}: Stopping after the last track of {}",
+                            "Info".blue(),
+                            tags.album.as_deref().unwrap_or_default().cyan()
+                        );
+                        self.stop_after_album = Some(tags);
+                        self.prefetch_next();
+                    }
+                }
+            }
+
+            InputCommands::Intro(intro) => {
+                let Some(path) = self.current_path() else {
+                    outln!("{}: Nothing is playing", "Error".red());
+                    return;
+                };
+                self.stats.entry(&path).intro = intro.map(|intro| intro.as_secs());
+                if let Err(e) = self.stats.save() {
+                    outln!("{}: Could not save intro: {}", "Warning".yellow(), e);
+                }
+                match intro {
+                    Some(intro) => outln!(
+                        "{}: This track will start at {}:{:02}",
+                        "Success".green(),
+                        intro.as_secs() / 60,
+                        intro.as_secs() % 60
+                    ),
+                    None => outln!("{}: This track will start from the top", "Success".green()),
+                }
+            }
+
+            InputCommands::StopAfterAlbum(false) => {
+                self.stop_after_album = None;
+                outln!("{}: Playing on as usual", "Info".blue());
+            }
+
+            InputCommands::Palette => {
+                if !self.at_prompt {
+                    outln!("{}: palette only works at the prompt", "Error".red());
+                } else if let Some(line) = palette::choose() {
+                    self.get_commands(&line);
+                }
+            }
+
+            InputCommands::Restart => {
+                if let Err(e) = self.seek_to(Duration::ZERO) {
+                    outln!("{}: {}", "Error".red(), e);
+                }
+            }
+
+            InputCommands::Prev => {
+                if let Err(e) = self.prev() {
+                    outln!("{}: {}", "Error".red(), e);
+                }
+            }
+
+            InputCommands::Exit => self.quitting = true,
+
+            InputCommands::Help => print_usage_instructions(),
+
+            InputCommands::InvalidCommand => {
+                outln!(
+                    "{}: Invalid command - type 'help' for instructions",
+                    "Error".red().bold()
+                )
+            }
+        }
+    }
+
+    /// Takes a track out of the library once its file is gone
+    /// Indices of the other tracks stay the same
+    fn remove_from_library(&mut self, index: i32) {
+        if self.current_index == Some(index) {
+            self.current_index = None;
+            self.current_file = None;
+        }
+        if let Some(songs) = &mut self.available_songs {
+            songs.remove(&index);
+        }
+        self.prune_queue();
+    }
+
+    /// Drops queued tracks that are no longer in the library
+    fn prune_queue(&mut self) {
+        let songs = self.available_songs.as_ref();
+        self.queue
+            .retain(|index| songs.is_some_and(|songs| songs.contains_key(index)));
+    }
+
+    /// A track's path below the library root holding it, which tells apart
+    /// files of the same name in different folders
+    fn library_name(&self, path: &Path) -> String {
+        let main = self.main_dir.as_deref().map(Path::new);
+        let relative = main
+            .into_iter()
+            .chain(self.extra_dirs.iter().map(PathBuf::as_path))
+            .find_map(|root| path.strip_prefix(root).ok());
+        match relative {
+            Some(relative) if !remote::is_remote(path) => relative.display().to_string(),
+            _ => file_name(path),
+        }
+    }
+
+    /// Looks up the track for an index typed by the user
+    fn song_path(&self, index: &str) -> Option<PathBuf> {
+        let index = index.parse::<i32>().ok()?;
+        Some(self.available_songs.as_ref()?.get(&index)?.clone())
+    }
+
+    /// Handles `delete <index> [--permanent]`
+    /// Tracks go to the trash; deleting for good asks first unless `--yes`
+    fn delete_track(&mut self, args: &[String]) {
+        let (guard, args) = safeguard::Safeguard::from_args(args, self.at_prompt);
+        let (index, permanent) = match &args[..] {
+            [index] => (index, false),
+            [index, flag] if flag == "--permanent" => (index, true),
+            _ => {
+                outln!(
+                    "{}: Usage: delete <index> [--permanent] [--dry-run] [--yes]",
+                    "Error".red()
+                );
+                return;
+            }
+        };
+        let Some(path) = self.song_path(index) else {
+            outln!("{}: Invalid song index", "Error".red());
+            return;
+        };
+        if permanent {
+            if !guard.allow(&format!("permanently delete {}", path.display())) {
+                return;
+            }
+        } else if guard.dry_run(&format!("move {} to the trash", path.display())) {
+            return;
+        }
+        if self.current_path().as_ref() == Some(&path) {
+            // Windows won't delete a file that is still open for playback
+            self.stop_playback();
+        }
+        let result = if permanent {
+            fs::remove_file(&path)
+        } else {
+            files::trash(&path)
+        };
+        match result {
+            Ok(()) => {
+                if let Ok(index) = index.parse() {
+                    self.remove_from_library(index);
+                }
+                let done = if permanent {
+                    "Deleted"
+                } else {
+                    "Moved to the trash:"
+                };
+                outln!("{}: {} {}", "Success".green(), done, path.display());
+            }
+            Err(e) => outln!(
+                "{}: Could not delete {}: {}",
+                "Error".red(),
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Handles `move <index> <dir>`; a relative `dir` is inside the music
+    /// directory, and an existing file of the same name is never replaced
+    fn move_track(&mut self, args: &[String]) {
+        let (guard, args) = safeguard::Safeguard::from_args(args, self.at_prompt);
+        let (Some(index), Some(_)) = (args.first(), args.get(1)) else {
+            outln!("{}: Usage: move <index> <dir> [--dry-run]", "Error".red());
+            return;
+        };
+        let Some(path) = self.song_path(index) else {
+            outln!("{}: Invalid song index", "Error".red());
+            return;
+        };
+        // The rest of the line is the directory, which may contain spaces
+        let dir = PathBuf::from(args[1..].join(" "));
+        let dir = match &self.main_dir {
+            Some(main_dir) if dir.is_relative() => Path::new(main_dir).join(dir),
+            _ => dir,
+        };
+        if guard.dry_run(&format!("move {} to {}", path.display(), dir.display())) {
+            return;
+        }
+        if self.current_path().as_ref() == Some(&path) {
+            // Windows won't move a file that is still open for playback
+            self.stop_playback();
+        }
+        match files::move_into(&path, &dir) {
+            Ok(target) => {
+                if let Ok(index) = index.parse() {
+                    self.remove_from_library(index);
+                }
+                self.stats.rename(&path, &target);
+                if let Err(e) = self.stats.save() {
+                    outln!("{}: Could not save play counts: {}", "Warning".yellow(), e);
+                }
+                self.labels.rename(&path, &target);
+                if let Err(e) = self.labels.save() {
+                    outln!("{}: Could not save labels: {}", "Warning".yellow(), e);
+                }
+                self.history.rename(&path, &target);
+                if let Err(e) = self.history.save() {
+                    outln!(
+                        "{}: Could not save the play history: {}",
+                        "Warning".yellow(),
+                        e
+                    );
+                }
+                outln!("{}: Moved to {}", "Success".green(), target.display());
+            }
+            Err(e) => outln!(
+                "{}: Could not move {}: {}",
+                "Error".red(),
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Plays the file whose path or `file://` URL is on the clipboard,
+    /// adding it to the library when it lives somewhere else
+    fn paste(&mut self) {
+        let text = match clipboard::read() {
+            Ok(text) => text,
+            Err(e) => {
+                outln!("{}: {}", "Error".red(), e);
+                return;
+            }
+        };
+        let line = text.lines().next().unwrap_or("").trim();
+        let line = line.trim_matches(['"', '\'']);
+        if icy::is_stream(Path::new(line)) {
+            return self.tune_in(line);
+        }
+        let path = match line.strip_prefix("file://") {
+            Some(uri) => PathBuf::from(playlist::percent_decode(
+                uri.strip_prefix("localhost").unwrap_or(uri),
+            )),
+            None => match (line.strip_prefix("~/"), env::var_os("HOME")) {
+                (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+                _ => PathBuf::from(line),
+            },
+        };
+        if line.is_empty() || !path.is_file() {
+            outln!(
+                "{}: The clipboard doesn't hold a path to a file",
+                "Error".red()
+            );
+            return;
+        }
+        match self.library_index(&path) {
+            Some(index) => {
+                if let Err(e) = self.play(index) {
+                    outln!("{}: {}", "Error".red(), e);
+                }
+            }
+            None => outln!("{}: Could not open {}", "Error".red(), path.display()),
+        }
+    }
+
+    /// How far into the current track playback is, None when stopped
+    fn position(&self) -> Option<Duration> {
+        self.playhead.as_ref().map(playhead::Playhead::position)
+    }
+
+    fn set_playhead_paused(&mut self, paused: bool) {
+        if let Some(playhead) = &mut self.playhead {
+            playhead.set_paused(paused);
+        }
+    }
+
+    /// Prints the current track's lyrics, marking the line being sung when
+    /// they are timed
+    fn show_lyrics(&self) {
+        let Some(path) = self.current_path() else {
+            outln!("{}: Nothing is playing", "Error".red());
+            return;
+        };
+        let lyrics = match lyrics::Lyrics::load(&path) {
+            Ok(Some(lyrics)) => lyrics,
+            Ok(None) => {
+                outln!(
+                    "{}: No lyrics found; put them in a .lrc or .txt file named after the track",
+                    "Info".blue()
+                );
+                return;
+            }
+            Err(e) => {
+                outln!("{}: Could not read lyrics: {}", "Error".red(), e);
+                return;
+            }
+        };
+        let current = self
+            .position()
+            .and_then(|position| lyrics.current(position));
+        outln!("\n{}", "Lyrics:".bold());
+        for (i, (_, line)) in lyrics.lines.iter().enumerate() {
+            match current {
+                Some(current) if i == current => {
+                    outln!(
+                        "{} {}",
+                        screen_reader::symbol("▶", "Now:").green(),
+                        line.green().bold()
+                    )
+                }
+                Some(current) if i < current => outln!("  {}", line.dimmed()),
+                _ => outln!("  {}", line),
+            }
+        }
+        outln!();
+    }
+
+    /// The player's state in one line, for screen-reader mode
+    fn announce_status(&self) -> String {
+        let Some(current) = &self.current_file else {
+            return "Nothing playing".to_string();
+        };
+        let state = if self.is_paused {
+            "Paused"
+        } else if self.is_playing {
+            "Playing"
+        } else {
+            "Stopped"
+        };
+        let mut parts = vec![format!("{} {}", state, current)];
+        if let Some(position) = self.position() {
+            let elapsed = position.as_secs();
+            parts.push(match self.duration {
+                Some(length) => format!(
+                    "{}:{:02} of {}:{:02}",
+                    elapsed / 60,
+                    elapsed % 60,
+                    length.as_secs() / 60,
+                    length.as_secs() % 60
+                ),
+                None => format!("{}:{:02}", elapsed / 60, elapsed % 60),
+            });
+        }
+        parts.push(format!("volume {:.1}", self.sink.volume()));
+        if self.speed != 1.0 {
+            parts.push(format!("speed {}x", self.speed));
+        }
+        if self.offline {
+            parts.push("offline".to_string());
+        }
+        parts.join(", ")
+    }
+
+    /// Time left in the current track, when its length is known
+    fn remaining(&self) -> Option<Duration> {
+        Some(self.duration?.saturating_sub(self.position()?))
+    }
+
+    /// Restarts the current track at `position`, staying paused if it was
+    fn seek_to(&mut self, position: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        let index = self.current_index.ok_or("Nothing is playing")?;
+        let paused = self.is_paused;
+        self.play_from(index, position)?;
+        if paused {
+            self.sink.pause();
+            self.set_playhead_paused(true);
+            self.is_paused = true;
+        }
+        Ok(())
+    }
+
+    /// Handles `skipfwd` and `skipback`, jumping by the podcast or music
+    /// interval unless told how far
+    fn skip(&mut self, forward: bool, by: Option<Duration>) {
+        let (Some(position), Some(path)) = (self.position(), self.current_path()) else {
+            outln!("{}: Nothing is playing", "Error".red());
+            return;
+        };
+        if icy::is_stream(&path) {
+            return outln!("{}: A live stream can't be sought in", "Error".red());
+        }
+        let intervals = &self.skip_intervals;
+        let by = by.unwrap_or(match (self.is_episode(&path), forward) {
+            (true, true) => intervals.podcast_forward,
+            (true, false) => intervals.podcast_back,
+            (false, true) => intervals.music_forward,
+            (false, false) => intervals.music_back,
+        });
+        let target = if forward {
+            position + by
+        } else {
+            position.saturating_sub(by)
+        };
+        if self.duration.is_some_and(|length| target >= length) {
+            outln!("{}: Less than {} seconds left", "Error".red(), by.as_secs());
+            return;
+        }
+        match self.seek_to(target) {
+            Ok(()) => outln!(
+                "{}: {} to {}:{:02}",
+                "Info".blue(),
+                if forward { "Ahead" } else { "Back" },
+                target.as_secs() / 60,
+                target.as_secs() % 60
+            ),
+            Err(e) => outln!("{}: {}", "Error".red(), e),
+        }
+    }
+
+    /// Whether a track is a downloaded podcast episode
+    fn is_episode(&self, path: &Path) -> bool {
+        path.starts_with(&self.podcast_settings.dir)
+    }
+
+    /// Plays the previous track in the list, or restarts the current one
+    /// early on or when it is the first
+    fn prev(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let index = self.current_index.ok_or("Nothing is playing")?;
+        let early = self
+            .position()
+            .is_some_and(|position| position < self.prev_restart_within);
+        let previous = self
+            .available_songs
+            .iter()
+            .flat_map(|songs| songs.keys())
+            .filter(|&&other| other < index)
+            .max()
+            .copied();
+        match previous {
+            Some(previous) if !early => self.play(previous),
+            _ => self.seek_to(Duration::ZERO),
+        }
+    }
+
+    /// Handles `play <numbers>`, e.g. `play 3-7,12,15`: plays the first
+    /// track, and the rest one after another once it has finished
+    fn play_selection(&mut self, spec: &str) {
+        let indices = match selection::parse(spec) {
+            Ok(indices) => indices,
+            Err(e) => return outln!("{}: {}", "Error".red(), e),
+        };
+        let known = |index: &i32| {
+            self.available_songs
+                .as_ref()
+                .is_some_and(|songs| songs.contains_key(index))
+        };
+        let (mut wanted, missing): (VecDeque<i32>, VecDeque<i32>) =
+            indices.into_iter().partition(known);
+        if !missing.is_empty() && !wanted.is_empty() {
+            let missing: Vec<String> = missing.iter().map(i32::to_string).collect();
+            outln!(
+                "{}: No tracks numbered {}; playing the rest",
+                "Warning".yellow(),
+                missing.join(", ")
+            );
+        }
+        let first = match wanted.pop_front() {
+            Some(first) => first,
+            // Lets `play` report a single bad number as before
+            None => missing[0],
+        };
+        if let Err(e) = self.play(first) {
+            return outln!("{}: {}", "Error".red(), e);
+        }
+        if !wanted.is_empty() {
+            let tracks = if wanted.len() == 1 { "track" } else { "tracks" };
+            outln!(
+                "{}: {} more {} to follow",
+                "Info".blue(),
+                wanted.len(),
+                tracks
+            );
+        }
+        // Ahead of what was queued before, which plays once they are done
+        self.queue.add_front(wanted);
+    }
+
+    /// Moves on once the current track has finished: to the next one
+    /// queued, of the album being played out, picked by autoplay or `radio`,
+    /// or else the next by list number
+    fn check_track_end(&mut self) {
+        if !self.is_playing || self.is_paused || !self.sink.empty() {
+            return;
+        }
+        let advance = self.advance && !self.current_index.is_some_and(|i| self.is_station(i));
+        if self.queue.is_empty()
+            && self.stop_after_album.is_none()
+            && !self.autoplay
+            && !self.radio_chain
+            && !advance
+        {
+            return;
+        }
+        outln!();
+        // A track that fails to open is passed over, so one bad file
+        // doesn't end the queue or the list
+        for _ in 0..MAX_FAILED_STARTS {
+            let Some(next) = self.next_track(advance) else {
+                break;
+            };
+            match self.play(next) {
+                Ok(()) => {
+                    self.prefetch_next();
+                    print_prompt();
+                    return;
+                }
+                Err(e) => {
+                    let name = self.song_path(&next.to_string());
+                    let name = name.as_deref().map_or(next.to_string(), file_name);
+                    outln!("{}: Skipping {}: {}", "Error".red(), name, e);
+                    // What comes next is counted from the track that failed
+                    self.current_index = Some(next);
+                }
+            }
+        }
+        self.stop_after_album = None;
+        self.stop_playback();
+        print_prompt();
+    }
+
+    /// Track to go on with: the next one queued, of the album being played
+    /// out, picked by autoplay or `radio`, or with `advance` the next by
+    /// list number
+    fn next_track(&mut self, advance: bool) -> Option<i32> {
+        if let Some(next) = self.queue.next() {
+            return Some(next);
+        }
+        let from_album = self
+            .stop_after_album
+            .clone()
+            .map(|album| self.next_on_album(&album));
+        let next = match from_album {
+            Some(Some(next)) => Some(next),
+            Some(None) => {
+                self.stop_after_album = None;
+                outln!("{}: End of the album", "Info".blue());
+                self.autoplay_pick()
+            }
+            None => self.autoplay_pick(),
+        };
+        match next {
+            None if advance && !self.autoplay && !self.radio_chain => {
+                let next = self.next_in_list();
+                if next.is_none() {
+                    outln!("{}: End of the list", "Info".blue());
+                }
+                next
+            }
+            next => next,
+        }
+    }
+
+    /// Track after the current one by list number, for going on down the
+    /// list; episodes, stations and tracks `skip_shorter_than` passes
+    /// over are left out
+    fn next_in_list(&self) -> Option<i32> {
+        let index = self.current_index?;
+        let songs = self.available_songs.as_ref()?;
+        songs
+            .iter()
+            .filter(|(&other, path)| {
+                other > index
+                    && !self.is_episode(path)
+                    && !icy::is_stream(path)
+                    && !self.unavailable(path)
+                    && !self.too_short(other)
+            })
+            .map(|(&other, _)| other)
+            .min()
+    }
+
+    /// Track autoplay or `radio` goes on with, if either is on
+    /// Podcast episodes, tracks offline mode keeps back and ones shorter
+    /// than `skip_shorter_than` are left out
+    fn autoplay_pick(&self) -> Option<i32> {
+        if !self.autoplay && !self.radio_chain {
+            return None;
+        }
+        let songs = self.available_songs.as_ref()?;
+        let tracks = || {
+            songs
+                .iter()
+                .filter(|(&index, path)| {
+                    !self.is_episode(path)
+                        && !icy::is_stream(path)
+                        && !self.unavailable(path)
+                        && !self.too_short(index)
+                })
+                .map(|(&index, path)| (index, path.as_path()))
+        };
+        if self.radio_chain {
+            // One of the closest few, leaving out what played lately
+            let closest: Vec<(i32, f64)> = self
+                .current_path()
+                .map(|current| similar::rank(&current, tracks(), &self.history))
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|suggestion| {
+                    let path = songs.get(&suggestion.index)?;
+                    let weight = suggestion.score * self.radio.recency(path);
+                    (weight > 0.0).then_some((suggestion.index, weight))
+                })
+                .take(RADIO_CHOICES)
+                .collect();
+            if !closest.is_empty() {
+                return radio::pick_weighted(closest);
+            }
+            if !self.autoplay {
+                outln!("{}: Radio found nothing like this track", "Info".blue());
+                return None;
+            }
+        }
+        self.radio.pick(tracks(), &self.stats)
+    }
+
+    /// Handles `db export <file>` and `db import <file>`
+    /// Overwriting an existing file and importing, which changes ratings,
+    /// both ask first
+    fn db(&mut self, args: &[String]) {
+        let (guard, args) = safeguard::Safeguard::from_args(args, self.at_prompt);
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        let (action, file) = match words[..] {
+            [action @ ("export" | "import"), file] => (action, PathBuf::from(file)),
+            _ => {
+                outln!(
+                    "{}: Usage: db export|import <file> [--dry-run] [--yes]",
+                    "Error".red()
+                );
+                return;
+            }
+        };
+        let music_dir = self.main_dir.clone().map(PathBuf::from);
+        let mut library = backup::Library {
+            music_dir: music_dir.as_deref(),
+            stats: &mut self.stats,
+            labels: &mut self.labels,
+            speeds: &mut self.speeds,
+            positions: self.position_sync.as_mut(),
+        };
+        let has_sync = library.positions.is_some();
+        let described = |summary: &backup::Summary| {
+            let count = |n: usize, what: &str| match n {
+                1 => format!("1 {}", what),
+                n => format!("{} {}s", n, what),
+            };
+            let mut parts = vec![
+                count(summary.tracks, "track"),
+                count(summary.speeds, "speed"),
+            ];
+            if has_sync {
+                parts.push(count(summary.positions, "position"));
+            }
+            parts.join(", ")
+        };
+        if action == "export" {
+            let overwrite = format!("overwrite {}", file.display());
+            let allowed = if file.exists() {
+                guard.allow(&overwrite)
+            } else {
+                !guard.dry_run(&format!("write a backup to {}", file.display()))
+            };
+            if !allowed {
+                return;
+            }
+            match backup::export(&file, &library) {
+                Ok(summary) => outln!(
+                    "{}: Exported {} to {}",
+                    "Success".green(),
+                    described(&summary),
+                    file.display()
+                ),
+                Err(e) => outln!(
+                    "{}: Could not write {}: {}",
+                    "Error".red(),
+                    file.display(),
+                    e
+                ),
+            }
+            return;
+        }
+        if !file.is_file() {
+            outln!("{}: No such file: {}", "Error".red(), file.display());
+            return;
+        }
+        if !guard.allow(&format!(
+            "merge the backup in {} into the library",
+            file.display()
+        )) {
+            return;
+        }
+        match backup::import(&file, &mut library) {
+            Ok(summary) => outln!(
+                "{}: Imported {} from {}",
+                "Success".green(),
+                described(&summary),
+                file.display()
+            ),
+            Err(e) => outln!("{}: {}", "Error".red(), e),
+        }
+    }
+
+    /// Handles `tag add|remove <label> [n]`, `tag show [n]` and `tag list`
+    /// Without a number the current track is meant
+    fn tag(&mut self, args: &[String]) {
+        let usage = || {
+            outln!(
+                "{}: Usage: tag add|remove <label> [number] | tag show [number] | tag list",
+                "Error".red()
+            )
+        };
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        let (action, label, number) = match words[..] {
+            [] | ["list"] => {
+                outln!("\n{}", "Labels:".green().bold());
+                screen_reader::rule("-------------------------------".green());
+                let counts = self.labels.counts();
+                for (label, count) in &counts {
+                    let tracks = if *count == 1 { "track" } else { "tracks" };
+                    outln!("  {:<20} {} {}", label, count, tracks);
+                }
+                if counts.is_empty() {
+                    outln!("  (none yet; `tag add <label>` labels the current track)");
+                }
+                return;
+            }
+            ["show"] => ("show", None, None),
+            ["show", number] => ("show", None, Some(number)),
+            [action @ ("add" | "remove"), label] => (action, Some(label), None),
+            [action @ ("add" | "remove"), label, number] => (action, Some(label), Some(number)),
+            _ => return usage(),
+        };
+        let track = match number {
+            Some(number) => number
+                .parse::<i32>()
+                .ok()
+                .and_then(|index| self.available_songs.as_ref()?.get(&index).cloned()),
+            None => self.current_path(),
+        };
+        let Some(track) = track else {
+            match number {
+                Some(_) => outln!("{}: Invalid song index", "Error".red()),
+                None => outln!("{}: Nothing is playing", "Error".red()),
+            }
+            return;
+        };
+        let Some(label) = label else {
+            let labels = self.labels.get(&track);
+            if labels.is_empty() {
+                outln!("{}: {} has no labels", "Info".blue(), file_name(&track));
+            } else {
+                outln!("{}: {}", file_name(&track).bold(), labels.join(", "));
+            }
+            return;
+        };
+        let Some(label) = labels::normalize(label) else {
+            outln!(
+                "{}: Labels are letters, digits, `-`, `_` and `/`",
+                "Error".red()
+            );
+            return;
+        };
+        let changed = match action {
+            "add" => self.labels.add(&track, &label),
+            _ => self.labels.remove(&track, &label),
+        };
+        if let Err(e) = self.labels.save() {
+            outln!("{}: Could not save labels: {}", "Error".red(), e);
+            return;
+        }
+        let name = file_name(&track);
+        match (action, changed) {
+            ("add", true) => outln!("{}: Tagged {} {}", "Success".green(), name, label.blue()),
+            ("add", false) => outln!("{}: {} is already tagged {}", "Info".blue(), name, label),
+            (_, true) => outln!("{}: Took {} off {}", "Success".green(), label.blue(), name),
+            (_, false) => outln!("{}: {} isn't tagged {}", "Info".blue(), name, label),
+        }
+    }
+
+    /// Handles `similar <n> [count]`, listing the tracks most like track n
+    fn similar(&self, args: &[String]) {
+        let (index, count) = match args {
+            [index] => (index.parse::<i32>().ok(), Some(10)),
+            [index, count] => (index.parse().ok(), count.parse().ok()),
+            _ => (None, None),
+        };
+        let (Some(index), Some(count)) = (index, count) else {
+            outln!("{}: Usage: similar <number> [count]", "Error".red());
+            return;
+        };
+        let Some(songs) = self.available_songs.as_ref() else {
+            return;
+        };
+        let Some(seed) = songs.get(&index) else {
+            outln!("{}: Invalid song index", "Error".red());
+            return;
+        };
+        let tracks = songs
+            .iter()
+            .filter(|(_, path)| !self.is_episode(path))
+            .map(|(&index, path)| (index, path.as_path()));
+        let suggestions = similar::rank(seed, tracks, &self.history);
+        outln!(
+            "\n{} {}:",
+            "Like".green().bold(),
+            file_name(seed).green().bold()
+        );
+        screen_reader::rule("-------------------------------".green());
+        for suggestion in suggestions.iter().take(count) {
+            outln!(
+                "{:<6} {}  {}",
+                suggestion.index,
+                file_name(&songs[&suggestion.index]),
+                format!("({})", suggestion.reasons.join(", ")).dimmed()
+            );
+        }
+        if suggestions.is_empty() {
+            outln!("  (nothing shares its artist, genre, decade or tempo yet)");
+        } else {
+            outln!(
+                "{}: `radio {}` plays it and keeps going with tracks like each one",
+                "Info".blue(),
+                index
+            );
+        }
+    }
+
+    /// Handles `radio <n>` and `radio off`
+    fn radio(&mut self, args: &[String]) {
+        match args {
+            [off] if off.eq_ignore_ascii_case("off") => {
+                self.radio_chain = false;
+                outln!("{}: Radio off", "Info".blue());
+            }
+            [index] => {
+                let Ok(index) = index.parse::<i32>() else {
+                    outln!("{}: Usage: radio <number>|off", "Error".red());
+                    return;
+                };
+                self.stop_after_album = None;
+                match self.play(index) {
+                    Ok(()) => {
+                        self.radio_chain = true;
+                        outln!(
+                            "{}: Radio on; `radio off` stops after this track",
+                            "Info".blue()
+                        );
+                    }
+                    Err(e) => outln!("{}: {}", "Error".red(), e),
+                }
+            }
+            _ => outln!("{}: Usage: radio <number>|off", "Error".red()),
+        }
+    }
+
+    /// Wakes the share the next track is on, if playback will move on to
+    /// one by itself
+    fn prefetch_next(&self) {
+        let next = self
+            .queue
+            .peek()
+            .or_else(|| match &self.stop_after_album {
+                Some(album) => self.next_on_album(album),
+                None if self.advance && !self.autoplay && !self.radio_chain => self.next_in_list(),
+                None => None,
+            })
+            .and_then(|next| Some(self.available_songs.as_ref()?.get(&next)?.clone()));
+        if let Some(path) = next {
+            readahead::prefetch(path);
+        }
+    }
+
+    /// Track after the current one on an album, by disc and track number,
+    /// then by list number, passing over interludes shorter than
+    /// `skip_shorter_than`
+    fn next_on_album(&self, album: &tags::Tags) -> Option<i32> {
+        let songs = self.available_songs.as_ref()?;
+        let order = |index: i32, tags: &tags::Tags| {
+            (tags.disc.unwrap_or(0), tags.track.unwrap_or(0), index)
+        };
+        let index = self.current_index?;
+        let current = order(
+            index,
+            &tags::read(&songs.get(&index)?.clone()).unwrap_or_default(),
+        );
+        songs
+            .iter()
+            .filter_map(|(&index, entry)| {
+                let tags = tags::read(entry).unwrap_or_default();
+                tags.same_album(album).then(|| order(index, &tags))
+            })
+            .filter(|&key| key > current && !self.too_short(key.2))
+            .filter(|&(_, _, index)| {
+                songs
+                    .get(&index)
+                    .is_some_and(|entry| !self.unavailable(entry))
+            })
+            .min()
+            .map(|(_, _, index)| index)
+    }
+
+    /// What a track's speed is remembered under: its podcast feed, or its
+    /// album so every chapter of an audiobook plays alike
+    fn speed_key(&self, path: &Path) -> Option<String> {
+        if let Ok(relative) = path.strip_prefix(&self.podcast_settings.dir) {
+            let feed = relative.components().next()?;
+            return Some(format!("feed:{}", feed.as_os_str().to_string_lossy()));
+        }
+        let tags = tags::read(path).ok()?;
+        let album = tags.album.as_deref()?;
+        Some(format!(
+            "album:{} – {}",
+            tags.album_artist().unwrap_or_default(),
+            album
+        ))
+    }
+
+    /// Handles `speed [0.5-3.0]`: plays the current track at a new speed
+    /// and keeps it for the rest of its feed or album
+    fn set_speed(&mut self, speed: Option<f32>) {
+        let Some(speed) = speed else {
+            outln!("{}: Playback speed {}x", "Info".blue(), self.speed);
+            return;
+        };
+        #[cfg(feature = "gstreamer")]
+        if let Sink::Gstreamer(_) = self.sink {
+            outln!(
+                "{}: Speed isn't supported with the GStreamer backend",
+                "Error".red()
+            );
+            return;
+        }
+        let Some(path) = self.current_path().filter(|_| self.is_playing) else {
+            outln!("{}: Nothing is playing", "Error".red());
+            return;
+        };
+        self.speed = speed;
+        match &mut self.sink {
+            #[cfg(feature = "mpv")]
+            Sink::Mpv(mpv) => {
+                mpv.set_speed(speed);
+                if let Some(playhead) = &mut self.playhead {
+                    playhead.set_speed(speed as f64);
+                }
+            }
+            // The speed is fixed once the track is in the sink, so it
+            // restarts where it is
+            _ => {
+                let position = self.position().unwrap_or_default();
+                if let Err(e) = self.seek_to(position) {
+                    outln!("{}: {}", "Error".red(), e);
+                    return;
+                }
+            }
+        }
+        match self.speed_key(&path) {
+            Some(key) => match self.speeds.set(&key, speed) {
+                Ok(()) => outln!(
+                    "{}: Speed {}x, kept for {}",
+                    "Success".green(),
+                    speed,
+                    key.split_once(':').map_or(key.as_str(), |(_, name)| name)
+                ),
+                Err(e) => outln!("{}: Could not save speed: {}", "Warning".yellow(), e),
+            },
+            None => outln!("{}: Speed {}x for this track", "Success".green(), speed),
+        }
+    }
+
+    /// Whether offline mode keeps a track from playing: it is remote and
+    /// hasn't been cached
+    fn unavailable(&self, path: &Path) -> bool {
+        self.offline && remote::is_remote(path) && !cache::contains(path)
+    }
+
+    /// Whether a track is short enough to be skipped when advancing on
+    /// its own; tracks of unknown length are kept
+    fn too_short(&self, index: i32) -> bool {
+        if self.skip_shorter_than.is_zero() {
+            return false;
+        }
+        let Some(entry) = self
+            .available_songs
+            .as_ref()
+            .and_then(|songs| songs.get(&index))
+        else {
+            return false;
+        };
+        let length = self.durations.lock().unwrap().get(entry);
+        length.is_some_and(|length| length < self.skip_shorter_than)
+    }
+
+    /// Lets the user narrow the library down by typing and plays the choice
+    fn pick(&mut self, query: &str) {
+        if !self.at_prompt {
+            outln!("{}: pick only works at the prompt", "Error".red());
+            return;
+        }
+        let Some(songs) = &self.available_songs else {
+            return;
+        };
+        let mut indices: Vec<i32> = songs.keys().copied().collect();
+        indices.sort();
+        let names: Vec<String> = indices
+            .iter()
+            .map(|index| file_name(&songs[index]))
+            .collect();
+        if names.is_empty() {
+            outln!("{}: No tracks to pick from", "Info".blue());
+            return;
+        }
+        if let Some(choice) = picker::pick(&names, query) {
+            if let Err(e) = self.play(indices[choice]) {
+                outln!("{}: {}", "Error".red(), e);
+            }
+        }
+    }
+
+    /// Index of a file in the library, adding it at the end if needed
+    fn library_index(&mut self, path: &Path) -> Option<i32> {
+        let wanted = match icy::is_stream(path) {
+            true => path.to_path_buf(),
+            false => fs::canonicalize(path).ok()?,
+        };
+        let songs = self.available_songs.get_or_insert_with(HashMap::new);
+        let existing = songs.iter().find(|(_, entry)| {
+            *entry == &wanted || fs::canonicalize(entry).ok().as_ref() == Some(&wanted)
+        });
+        if let Some((index, _)) = existing {
+            return Some(*index);
+        }
+        let index = songs.keys().max().map_or(1, |max| max + 1);
+        songs.insert(index, wanted);
+        Some(index)
+    }
+
+    /// Handles `organize move [--template <template>]`, moving every track
+    /// to the place its tags give under the music directory
+    /// Play counts and playlists follow the files; name clashes get a number
+    fn organize(&mut self, args: &[String]) {
+        let (guard, args) = safeguard::Safeguard::from_args(args, self.at_prompt);
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        let template = match words[..] {
+            ["move"] => self.organize_template.clone(),
+            ["move", "--template", ..] if words.len() > 2 => {
+                words[2..].join(" ").trim_matches(['"', '\'']).to_string()
+            }
+            _ => {
+                outln!(
+                    "{}: Usage: organize move [--template \"{}\"] [--dry-run] [--yes]",
+                    "Error".red(),
+                    files::DEFAULT_TEMPLATE
+                );
+                return;
+            }
+        };
+        let Some(root) = self.main_dir.clone().map(PathBuf::from) else {
+            return;
+        };
+
+        let mut songs: Vec<(i32, PathBuf)> = self
+            .available_songs
+            .iter()
+            .flat_map(|songs| songs.iter().map(|(index, entry)| (*index, entry.clone())))
+            .collect();
+        songs.sort();
+        let mut planned = HashSet::new();
+        let mut plan = Vec::new();
+        for (_, path) in songs {
+            let tags = tags::read(&path).unwrap_or_default();
+            let target = files::organized_path(&root, &template, &path, &tags);
+            if target == path {
+                continue;
+            }
+            let target = files::unique_path(&target, &planned);
+            planned.insert(target.clone());
+            plan.push((path, target));
+        }
+        if plan.is_empty() {
+            outln!("{}: Library is already organized", "Info".blue());
+            return;
+        }
+        for (from, to) in &plan {
+            let to = to.strip_prefix(&root).unwrap_or(to);
+            outln!("  {} → {}", from.display(), to.display());
+        }
+        let action = format!("move {} files into {}", plan.len(), root.display());
+        if !guard.allow(&action) {
+            return;
+        }
+
+        let playing = self.current_path();
+        if plan.iter().any(|(from, _)| Some(from) == playing.as_ref()) {
+            self.stop_playback();
+        }
+        let mut moves = HashMap::new();
+        for (from, to) in plan {
+            match files::move_to(&from, &to) {
+                Ok(()) => {
+                    self.stats.rename(&from, &to);
+                    self.labels.rename(&from, &to);
+                    self.history.rename(&from, &to);
+                    moves.insert(from, to);
+                }
+                Err(e) => outln!(
+                    "{}: Could not move {}: {}",
+                    "Error".red(),
+                    from.display(),
+                    e
+                ),
+            }
+        }
+        if let Err(e) = self.stats.save() {
+            outln!("{}: Could not save play counts: {}", "Warning".yellow(), e);
+        }
+        if let Err(e) = self.labels.save() {
+            outln!("{}: Could not save labels: {}", "Warning".yellow(), e);
+        }
+        if let Err(e) = self.history.save() {
+            outln!(
+                "{}: Could not save the play history: {}",
+                "Warning".yellow(),
+                e
+            );
+        }
+        if let Err(e) = playlist::rename_tracks(&moves) {
+            outln!("{}: Could not update playlists: {}", "Warning".yellow(), e);
+        }
+
+        // Indices change with the new layout, so the playing track and the
+        // queue are found again by path
+        let moved = |path: PathBuf| moves.get(&path).cloned().unwrap_or(path);
+        let current = self.current_path().map(moved);
+        let queued: Vec<PathBuf> = self
+            .queue
+            .iter()
+            .filter_map(|index| self.song_path(&index.to_string()))
+            .map(moved)
+            .collect();
+        if let Some(songs) = &mut self.available_songs {
+            songs.clear();
+        }
+        if let Err(e) = self.load_songs() {
+            outln!("{}: Could not reload the library: {}", "Error".red(), e);
+        }
+        let indices: HashMap<&PathBuf, i32> = self
+            .available_songs
+            .iter()
+            .flatten()
+            .map(|(&index, path)| (path, index))
+            .collect();
+        self.current_index = current.and_then(|path| indices.get(&path).copied());
+        let queued: Vec<i32> = queued
+            .iter()
+            .filter_map(|path| indices.get(path).copied())
+            .collect();
+        self.queue.clear();
+        self.queue.add(queued);
+        outln!("{}: Moved {} files", "Success".green(), moves.len());
+    }
+
+    /// Handles `playlist new|delete|rename|add|remove|import|export|show`
+    /// Deleting and overwriting go through the `--dry-run`/`--yes` safeguard
+    fn playlist(&mut self, args: &[String]) {
+        let (guard, args) = safeguard::Safeguard::from_args(args, self.at_prompt);
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        let result = match words[..] {
+            ["new", name] => playlist::Playlist::create(name)
+                .map(|_| format!("Created playlist {}", name.blue())),
+            ["delete", name] => {
+                if !guard.allow(&format!("delete playlist {}", name)) {
+                    return;
+                }
+                playlist::Playlist::delete(name)
+                    .map(|_| format!("Deleted playlist {}", name.blue()))
+            }
+            ["rename", old, new] => playlist::Playlist::rename(old, new)
+                .map(|_| format!("Renamed {} to {}", old.blue(), new.blue())),
+            ["smart", name, ref labels @ ..] if !labels.is_empty() => {
+                let normalized: Option<Vec<String>> = labels
+                    .iter()
+                    .map(|label| labels::normalize(label))
+                    .collect();
+                match normalized {
+                    Some(labels) => playlist::Playlist::create_smart(name, labels).map(|list| {
+                        format!(
+                            "Created smart playlist {} with {} tracks tagged {}",
+                            name.blue(),
+                            list.tracks.len(),
+                            list.labels.join(" and ")
+                        )
+                    }),
+                    None => Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Labels are letters, digits, `-`, `_` and `/`",
+                    )),
+                }
+            }
+            ["add", name, _] | ["remove", name, _]
+                if playlist::Playlist::load(name).is_ok_and(|list| list.is_smart()) =>
+            {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} is a smart playlist; `tag add` and `tag remove` change its tracks",
+                        name
+                    ),
+                ))
+            }
+            ["add", name, index] => {
+                let song = index
+                    .parse::<i32>()
+                    .ok()
+                    .and_then(|index| self.available_songs.as_ref()?.get(&index));
+                match song {
+                    Some(song) => playlist::Playlist::load(name).and_then(|mut list| {
+                        list.tracks.push(playlist::Track::from_path(song.clone()));
+                        list.save()?;
+                        Ok(format!(
+                            "Added {} to {}",
+                            file_name(song).blue(),
+                            name.blue()
+                        ))
+                    }),
+                    None => Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Invalid song index",
+                    )),
+                }
+            }
+            ["remove", name, position] => playlist::Playlist::load(name).and_then(|mut list| {
+                let position = position
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|p| (1..=list.tracks.len()).contains(p))
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidInput, "Invalid playlist position")
+                    })?;
+                let removed = list.tracks.remove(position - 1);
+                list.save()?;
+                Ok(format!(
+                    "Removed {} from {}",
+                    removed.path.display(),
+                    name.blue()
+                ))
+            }),
+            ["import", file] | ["import", file, _] => {
+                let file = Path::new(file);
+                let name = words
+                    .get(2)
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| {
+                        file.file_stem()
+                            .map(|stem| stem.to_string_lossy().to_string())
+                            .unwrap_or_default()
+                    });
+                playlist::Playlist::import(&name, file).and_then(|list| {
+                    if playlist::list().contains(&name) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("Playlist `{}` already exists", name),
+                        ));
+                    }
+                    list.save()?;
+                    Ok(format!(
+                        "Imported {} tracks into {}",
+                        list.tracks.len(),
+                        name.blue()
+                    ))
+                })
+            }
+            ["export", name, file] | ["export", name, file, "--relative"] => {
+                let relative = words.len() == 4;
+                if Path::new(file).exists() && !guard.allow(&format!("overwrite {}", file)) {
+                    return;
+                }
+                playlist::Playlist::load(name).and_then(|list| {
+                    list.export(Path::new(file), relative)?;
+                    Ok(format!("Exported {} to {}", name.blue(), file))
+                })
+            }
+            ["show", name] => match playlist::Playlist::load(name) {
+                Ok(list) => {
+                    let heading = if list.is_smart() {
+                        format!("Playlist {} (tagged {}):", name, list.labels.join(" and "))
+                    } else {
+                        format!("Playlist {}:", name)
+                    };
+                    outln!("\n{}", heading.green().bold());
+                    screen_reader::rule("-------------------------------".green());
+                    for (position, track) in list.tracks.iter().enumerate() {
+                        outln!("{:<6} {}", position + 1, track.path.display());
+                    }
+                    if list.tracks.is_empty() {
+                        outln!("  (empty)");
+                    }
+                    return;
+                }
+                Err(e) => Err(e),
+            },
+            ["show"] | [] => {
+                outln!("\n{}", "Playlists:".green().bold());
+                for name in playlist::list() {
+                    outln!("  {}", name);
+                }
+                return;
+            }
+            _ => {
+                outln!(
+                    "{}: Usage: playlist new|delete <name> | smart <name> <label> [label ...] | rename <old> <new> | add <name> <index> | remove <name> <n> | import <file> [name] | export <name> <file> [--relative] | show [name] (delete and export take --dry-run and --yes)",
+                    "Error".red()
+                );
+                return;
+            }
+        };
+        match result {
+            Ok(message) => outln!("{}: {}", "Success".green(), message),
+            Err(e) => outln!("{}: {}", "Error".red(), e),
+        }
+    }
+
+    /// Handles `podcast subscribe|unsubscribe|feeds|episodes|download|
+    /// downloads|play|import|export`
+    fn podcast(&mut self, args: &[String]) {
+        let (guard, args) = safeguard::Safeguard::from_args(args, self.at_prompt);
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        let number = |text: &str, len: usize| {
+            text.parse::<usize>()
+                .ok()
+                .filter(|n| (1..=len).contains(n))
+                .map(|n| n - 1)
+        };
+        let feeds = match podcast::load_feeds() {
+            Ok(feeds) => feeds,
+            Err(e) => {
+                outln!("{}: Could not read podcast feeds: {}", "Error".red(), e);
+                return;
+            }
+        };
+        let result = match words[..] {
+            ["subscribe", url] => {
+                if feeds.iter().any(|feed| feed.url == url) {
+                    outln!("{}: Already subscribed to {}", "Info".blue(), url);
+                    return;
+                }
+                podcast::fetch(url, &self.podcast_settings).and_then(|(title, episodes)| {
+                    let mut feeds = feeds;
+                    feeds.push(podcast::Feed {
+                        url: url.to_string(),
+                        title: title.clone(),
+                    });
+                    podcast::save_feeds(&feeds)?;
+                    // Only episodes published from now on count as new
+                    let urls: Vec<String> = episodes.iter().map(|e| e.url.clone()).collect();
+                    podcast::mark_seen(&urls)?;
+                    Ok(format!(
+                        "Subscribed to {} ({} episodes)",
+                        title.blue(),
+                        episodes.len()
+                    ))
+                })
+            }
+            ["unsubscribe", n] => match number(n, feeds.len()) {
+                Some(n) => {
+                    let mut feeds = feeds;
+                    let feed = feeds.remove(n);
+                    podcast::save_feeds(&feeds)
+                        .map(|_| format!("Unsubscribed from {}", feed.title.blue()))
+                }
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid feed number; see `podcast feeds`",
+                )),
+            },
+            ["feeds"] | [] => {
+                outln!("\n{}", "Podcasts:".green().bold());
+                for (n, feed) in feeds.iter().enumerate() {
+                    outln!("{:<6} {}  {}", n + 1, feed.title, feed.url.dimmed());
+                }
+                if feeds.is_empty() {
+                    outln!("  (none; add one with `podcast subscribe <url>`)");
+                }
+                return;
+            }
+            ["episodes"] | ["episodes", _] => {
+                let chosen: Vec<&podcast::Feed> = match words.get(1) {
+                    Some(n) => match number(n, feeds.len()) {
+                        Some(n) => vec![&feeds[n]],
+                        None => {
+                            outln!("{}: Invalid feed number", "Error".red());
+                            return;
+                        }
+                    },
+                    None => feeds.iter().collect(),
+                };
+                self.episodes.clear();
+                for feed in chosen {
+                    match podcast::fetch(&feed.url, &self.podcast_settings) {
+                        Ok((_, episodes)) => self.episodes.extend(episodes),
+                        Err(e) => outln!("{}: {}", "Warning".yellow(), e),
+                    }
+                }
+                self.show_episodes();
+                return;
+            }
+            ["download", "all-new"] => {
+                let seen = podcast::seen();
+                let mut new = Vec::new();
+                for feed in &feeds {
+                    match podcast::fetch(&feed.url, &self.podcast_settings) {
+                        Ok((_, episodes)) => new.extend(
+                            episodes
+                                .into_iter()
+                                .filter(|episode| !seen.contains(&episode.url)),
+                        ),
+                        Err(e) => outln!("{}: {}", "Warning".yellow(), e),
+                    }
+                }
+                let urls: Vec<String> = new.iter().map(|episode| episode.url.clone()).collect();
+                let queued = new
+                    .into_iter()
+                    .filter(|episode| {
+                        self.downloads
+                            .enqueue(episode.clone(), self.podcast_settings.parallel)
+                    })
+                    .count();
+                podcast::mark_seen(&urls).map(|_| match queued {
+                    0 => "No new episodes".to_string(),
+                    1 => {
+                        "Downloading 1 new episode; `podcast downloads` shows progress".to_string()
+                    }
+                    n => format!(
+                        "Downloading {} new episodes; `podcast downloads` shows progress",
+                        n
+                    ),
+                })
+            }
+            ["download", n] => match number(n, self.episodes.len()) {
+                Some(n) => {
+                    let episode = self.episodes[n].clone();
+                    if self
+                        .downloads
+                        .enqueue(episode.clone(), self.podcast_settings.parallel)
+                    {
+                        Ok(format!("Downloading {}", episode.title.blue()))
+                    } else {
+                        Ok(format!(
+                            "{} is already downloaded or queued",
+                            episode.title.blue()
+                        ))
+                    }
+                }
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid episode number; see `podcast episodes`",
+                )),
+            },
+            ["downloads"] => {
+                self.show_downloads();
+                return;
+            }
+            ["import", _, ..] => {
+                // The rest of the line is the path, which may contain spaces
+                let file = words[1..].join(" ");
+                fs::read_to_string(&file).and_then(|text| {
+                    let imported = podcast::parse_opml(&text);
+                    if imported.is_empty() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("No podcast feeds in {}", file),
+                        ));
+                    }
+                    let mut feeds = feeds;
+                    let before = feeds.len();
+                    for feed in imported {
+                        if !feeds.iter().any(|known| known.url == feed.url) {
+                            feeds.push(feed);
+                        }
+                    }
+                    podcast::save_feeds(&feeds)?;
+                    Ok(format!(
+                        "Subscribed to {} feeds from {}",
+                        feeds.len() - before,
+                        file
+                    ))
+                })
+            }
+            ["export", _, ..] => {
+                let file = words[1..].join(" ");
+                if Path::new(&file).exists() && !guard.allow(&format!("overwrite {}", file)) {
+                    return;
+                }
+                fs::write(&file, podcast::write_opml(&feeds))
+                    .map(|_| format!("Exported {} feeds to {}", feeds.len(), file))
+            }
+            ["play", n] => match number(n, self.episodes.len()) {
+                Some(n) if self.episodes[n].path.is_file() => {
+                    let path = self.episodes[n].path.clone();
+                    match self.library_index(&path) {
+                        Some(index) => {
+                            if let Err(e) = self.play(index) {
+                                outln!("{}: {}", "Error".red(), e);
+                            }
+                        }
+                        None => outln!("{}: Could not open {}", "Error".red(), path.display()),
+                    }
+                    return;
+                }
+                Some(_) => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "Not downloaded yet; use `podcast download <number>`",
+                )),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid episode number; see `podcast episodes`",
+                )),
+            },
+            _ => {
+                outln!(
+                    "{}: Usage: podcast subscribe <url> | unsubscribe <n> | feeds | episodes [feed] | download <episode|all-new> | downloads | play <episode> | import <file.opml> | export <file.opml> (export takes --dry-run and --yes)",
+                    "Error".red()
+                );
+                return;
+            }
+        };
+        match result {
+            Ok(message) => outln!("{}: {}", "Success".green(), message),
+            Err(e) => outln!("{}: {}", "Error".red(), e),
+        }
+    }
+
+    /// Handles `cd` and `cd eject`: puts the tracks of the disc in the drive
+    /// into the library, or takes them out and opens the tray
+    fn cd(&mut self, args: &[String]) {
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        match words[..] {
+            [] | ["load"] => {}
+            ["eject"] => {
+                if self.current_path().is_some_and(|path| cd::is_cd(&path)) {
+                    self.stop_playback();
+                }
+                self.remove_cd_tracks();
+                self.disc = None;
+                match cd::eject() {
+                    Ok(()) => outln!("{}: Disc ejected", "Info".blue()),
+                    Err(e) => outln!("{}: {}", "Error".red(), e),
+                }
+                return;
+            }
+            _ => {
+                outln!("{}: Usage: cd [load] | cd eject", "Error".red());
+                return;
+            }
+        }
+        let mut disc = match cd::read_disc() {
+            Ok(disc) => disc,
+            Err(e) => {
+                outln!("{}: {}", "Error".red(), e);
+                return;
+            }
+        };
+        outln!("{}: Looking up the disc on MusicBrainz...", "Info".blue());
+        if let Err(e) = disc.look_up() {
+            outln!("{}: No titles for this disc: {}", "Warning".yellow(), e);
+        }
+        self.remove_cd_tracks();
+        let songs = self.available_songs.get_or_insert_with(HashMap::new);
+        let first = songs.keys().max().map_or(1, |max| max + 1);
+        for (index, path) in (first..).zip(disc.track_paths()) {
+            songs.insert(index, path);
+        }
+        let heading = match (&disc.artist, &disc.album) {
+            (Some(artist), Some(album)) => format!("{} – {}:", artist, album),
+            (None, Some(album)) => format!("{}:", album),
+            _ => "Audio CD:".to_string(),
+        };
+        outln!("\n{}", heading.green().bold());
+        screen_reader::rule("-------------------------------".green());
+        for (index, track) in (first..).zip(&disc.tracks) {
+            let length = track.length().as_secs();
+            outln!(
+                "{:<6} {:>2}. {} ({}:{:02})",
+                index,
+                track.number,
+                track.title.as_deref().unwrap_or("Track"),
+                length / 60,
+                length % 60
+            );
+        }
+        outln!(
+            "{}: Added {} CD tracks; `play {}` starts the first",
+            "Success".green(),
+            disc.tracks.len(),
+            first
+        );
+        self.disc = Some(disc);
+    }
+
+    /// Handles `rip [flac|opus] [track ...]`, ripping the loaded CD, or the
+    /// tracks given, into the music directory in the background
+    fn rip(&mut self, args: &[String]) {
+        let mut words = args.iter().map(String::as_str).peekable();
+        let format = match words.peek().and_then(|word| rip::Format::parse(word)) {
+            Some(format) => {
+                words.next();
+                format
+            }
+            None => rip::Format::Flac,
+        };
+        let numbers: Option<Vec<u8>> = words.map(|word| word.parse().ok()).collect();
+        let Some(numbers) = numbers else {
+            outln!(
+                "{}: Usage: rip [flac|opus] [track number ...]",
+                "Error".red()
+            );
+            return;
+        };
+        let Some(dir) = self
+            .main_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .filter(|dir| !remote::is_remote(dir))
+        else {
+            outln!(
+                "{}: Tracks can only be ripped into a local library",
+                "Error".red()
+            );
+            return;
+        };
+        let disc = match self.disc.clone() {
+            Some(disc) => disc,
+            None => match cd::read_disc() {
+                Ok(mut disc) => {
+                    if let Err(e) = disc.look_up() {
+                        outln!("{}: No titles for this disc: {}", "Warning".yellow(), e);
+                    }
+                    disc
+                }
+                Err(e) => {
+                    outln!("{}: {}", "Error".red(), e);
+                    return;
+                }
+            },
+        };
+        let numbers = if numbers.is_empty() {
+            disc.tracks.iter().map(|track| track.number).collect()
+        } else if let Some(missing) = numbers
+            .iter()
+            .find(|&&n| !disc.tracks.iter().any(|track| track.number == n))
+        {
+            outln!("{}: The disc has no track {}", "Error".red(), missing);
+            return;
+        } else {
+            numbers
+        };
+        let count = numbers.len();
+        match self.rips.start(disc, numbers, format, dir) {
+            Ok(()) => outln!(
+                "{}: Ripping {} tracks to {} in the background",
+                "Info".blue(),
+                count,
+                format.extension()
+            ),
+            Err(e) => outln!("{}: {}", "Error".red(), e),
+        }
+    }
+
+    /// Handles `bench decode [n]`, timing tag reads and decoding over `n`
+    /// tracks of the library (10 by default), by format
+    fn bench(&mut self, args: &[String]) {
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        let count = match words[..] {
+            ["decode"] => Ok(10),
+            ["decode", n] => n.parse::<usize>().map_err(|_| ()),
+            _ => Err(()),
+        };
+        let Some(count) = count.ok().filter(|&n| n > 0) else {
+            outln!("{}: Usage: bench decode [number of tracks]", "Error".red());
+            return;
+        };
+        let library: Vec<PathBuf> = self
+            .available_songs
+            .iter()
+            .flat_map(|songs| songs.values().cloned())
+            .collect();
+        let picked = bench::pick(&library, count);
+        if picked.is_empty() {
+            outln!("{}: No local tracks to measure", "Error".red());
+            return;
+        }
+        outln!(
+            "{}: Decoding {} tracks, up to 30 seconds of each...",
+            "Info".blue(),
+            picked.len()
+        );
+        let mut samples = Vec::new();
+        for path in &picked {
+            match bench::measure(path) {
+                Ok(sample) => samples.push((path, sample)),
+                Err(e) => outln!("{}: {}: {}", "Warning".yellow(), file_name(path), e),
+            }
+        }
+
+        outln!("\n{}", "Decode Benchmark:".green().bold());
+        screen_reader::rule("-------------------------------".green());
+        outln!(
+            "{}",
+            format!(
+                "{:<8} {:>6} {:>10} {:>13} {:>10}",
+                "Format", "Tracks", "Tags", "First sample", "Speed"
+            )
+            .bold()
+        );
+        let ms = |time: Duration| format!("{:.1} ms", time.as_secs_f64() * 1000.0);
+        for format in codecs::compiled_in() {
+            let measured: Vec<&bench::Sample> = samples
+                .iter()
+                .map(|(_, sample)| sample)
+                .filter(|sample| sample.format == format)
+                .collect();
+            if measured.is_empty() {
+                outln!("{:<8} {}", format, "not in the sample".dimmed());
+                continue;
+            }
+            let n = measured.len() as u32;
+            let tags: Duration = measured.iter().map(|sample| sample.tags).sum();
+            let first: Duration = measured.iter().map(|sample| sample.first_sample).sum();
+            let audio: Duration = measured.iter().map(|sample| sample.audio).sum();
+            let decoding: Duration = measured.iter().map(|sample| sample.decoding).sum();
+            outln!(
+                "{:<8} {:>6} {:>10} {:>13} {:>9.0}x",
+                format,
+                n,
+                ms(tags / n),
+                ms(first / n),
+                audio.as_secs_f64() / decoding.as_secs_f64().max(1e-9)
+            );
+        }
+        let slowest = samples
+            .iter()
+            .min_by(|a, b| a.1.speed().total_cmp(&b.1.speed()));
+        if let Some((path, sample)) = slowest {
+            let line = format!(
+                "Slowest: {} at {:.0}x real time",
+                file_name(path),
+                sample.speed()
+            );
+            if sample.speed() < bench::TIGHT_SPEED {
+                outln!(
+                    "{}: {}; expect underruns on a busy machine, or try a larger `buffer_size`",
+                    "Warning".yellow(),
+                    line
+                );
+            } else {
+                outln!("{}", line);
+            }
+        }
+        if cfg!(debug_assertions) {
+            outln!(
+                "{}: This is a debug build, which decodes far slower than a release build",
+                "Info".blue()
+            );
+        }
+        if !matches!(self.sink, Sink::Rodio(_) | Sink::Direct(_)) {
+            outln!(
+                "{}: The current backend decodes on its own; these numbers are for the built-in decoders",
+                "Info".blue()
+            );
+        }
+    }
+
+    /// Adds freshly ripped tracks to the library and reports failures
+    fn report_rips(&mut self) {
+        let finished = self.rips.take_finished();
+        if finished.is_empty() {
+            return;
+        }
+        outln!();
+        for report in finished {
+            match report {
+                Ok(path) if codecs::supported(&path) => match self.library_index(&path) {
+                    Some(index) => outln!(
+                        "{}: Ripped {} as number {}",
+                        "Success".green(),
+                        file_name(&path),
+                        index
+                    ),
+                    None => outln!("{}: Ripped {}", "Success".green(), path.display()),
+                },
+                Ok(path) => outln!(
+                    "{}: Ripped {}, which this build can't play",
+                    "Success".green(),
+                    path.display()
+                ),
+                Err(message) => outln!("{}: {}", "Error".red(), message),
+            }
+        }
+        print_prompt();
+    }
+
+    /// Handles `stream <url>`: adds an internet radio station to the
+    /// library for the session and plays it
+    fn tune_in(&mut self, url: &str) {
+        let url = Path::new(url);
+        if !icy::is_stream(url) {
+            outln!(
+                "{}: Give the station's http:// or https:// address",
+                "Error".red()
+            );
+            return;
+        }
+        let Some(index) = self.library_index(url) else {
+            return;
+        };
+        outln!("{}: Tuning in to {}", "Info".blue(), url.display());
+        if let Err(e) = self.play(index) {
+            outln!("{}: {}", "Error".red(), e);
+        }
+    }
+
+    /// Whether a library entry is an internet radio station
+    fn is_station(&self, index: i32) -> bool {
+        self.available_songs
+            .as_ref()
+            .and_then(|songs| songs.get(&index))
+            .is_some_and(|song| icy::is_stream(song))
+    }
+
+    /// What the station playing says is on air, if a station is playing
+    fn listening(&self) -> Option<icy::OnAir> {
+        let station = self.current_index.filter(|&index| self.is_station(index));
+        station
+            .filter(|_| self.is_playing)
+            .and_then(|_| icy::on_air())
+    }
+
+    /// Shows a new song coming on air on the station playing, in the
+    /// terminal title and the now-playing file too
+    fn report_on_air(&mut self) {
+        let on_air = self.listening();
+        if on_air == self.on_air {
+            return;
+        }
+        let title = on_air.as_ref().and_then(|on_air| on_air.title.clone());
+        let changed = title != self.on_air.as_ref().and_then(|on_air| on_air.title.clone());
+        self.on_air = on_air;
+        if !changed {
+            return;
+        }
+        if io::stdout().is_terminal() {
+            out!("\x1b]2;{}\x07", title.as_deref().unwrap_or(""));
+        }
+        let Some(title) = title else {
+            return;
+        };
+        outln!("\n{}: {}", "On air".green().bold(), title.blue());
+        if let Some(overlay) = &self.now_playing_file {
+            let station = self
+                .on_air
+                .as_ref()
+                .and_then(|on_air| on_air.station.clone());
+            let station = station
+                .or_else(|| self.current_file.clone())
+                .unwrap_or_default();
+            if let Err(e) = overlay.write_on_air(&title, &station) {
+                outln!(
+                    "{}: Could not write now-playing file: {}",
+                    "Warning".yellow(),
+                    e
+                );
+            }
+        }
+        print_prompt();
+    }
+
+    /// Handles `follow <playlist>`, `follow off` and `follow`, which shows
+    /// the file being followed
+    fn follow_playlist(&mut self, args: &[String]) {
+        match args {
+            [] => match &self.inbox {
+                Some(inbox) => outln!("{}: Following {}", "Info".blue(), inbox.path().display()),
+                None => outln!("{}: Not following a playlist file", "Info".blue()),
+            },
+            [off] if off == "off" => {
+                if let Some(inbox) = self.inbox.take() {
+                    outln!(
+                        "{}: Stopped following {}",
+                        "Info".blue(),
+                        inbox.path().display()
+                    );
+                }
+            }
+            _ => {
+                let path = PathBuf::from(args.join(" "));
+                match inbox::Inbox::follow(&path) {
+                    Ok(inbox) => {
+                        outln!(
+                            "{}: Following {}; tracks appended to it will be queued",
+                            "Success".green(),
+                            path.display()
+                        );
+                        self.inbox = Some(inbox);
+                    }
+                    Err(e) => {
+                        outln!("{}: Cannot follow {}: {}", "Error".red(), path.display(), e)
+                    }
+                }
+            }
+        }
+    }
+
+    /// `queue add <tracks>`, `queue remove <position>`, `queue move <from>
+    /// <to>`, `queue jump <position>`, `queue clear` and `queue show`
+    fn queue_command(&mut self, args: &[String]) {
+        match args.first().map(String::as_str) {
+            None | Some("show") => self.show_queue(),
+            Some("add") => {
+                let indices = match selection::parse(&args[1..].join(" ")) {
+                    Ok(indices) => indices,
+                    Err(e) => return outln!("{}: {}", "Error".red(), e),
+                };
+                let (known, missing): (Vec<i32>, Vec<i32>) = indices
+                    .into_iter()
+                    .partition(|index| self.song_path(&index.to_string()).is_some());
+                if !missing.is_empty() {
+                    let missing: Vec<String> = missing.iter().map(i32::to_string).collect();
+                    outln!(
+                        "{}: No tracks numbered {}",
+                        "Warning".yellow(),
+                        missing.join(", ")
+                    );
+                }
+                if known.is_empty() {
+                    return;
+                }
+                let wanted = known.len();
+                let added = self.queue.add(known);
+                if added < wanted {
+                    outln!(
+                        "{}: The queue is full at {} tracks",
+                        "Warning".yellow(),
+                        queue::MAX_LEN
+                    );
+                }
+                let tracks = if added == 1 { "track" } else { "tracks" };
+                outln!("{}: Queued {} {}", "Success".green(), added, tracks);
+                // With nothing playing the queue starts right away
+                if !self.is_playing {
+                    if let Some(first) = self.queue.next() {
+                        if let Err(e) = self.play(first) {
+                            outln!("{}: {}", "Error".red(), e);
+                        }
+                    }
+                }
+                self.prefetch_next();
+            }
+            Some("remove") => {
+                let position = args.get(1).and_then(|n| n.parse::<usize>().ok());
+                let Some(position) = position else {
+                    return outln!(
+                        "{}: Please give the position in `queue show` to remove",
+                        "Error".red()
+                    );
+                };
+                match self.queue.remove(position) {
+                    Some(index) => {
+                        let name = self.song_path(&index.to_string());
+                        let name = name.as_deref().map_or(index.to_string(), file_name);
+                        outln!("{}: Removed {} from the queue", "Success".green(), name);
+                    }
+                    None => outln!("{}: The queue has no position {}", "Error".red(), position),
+                }
+            }
+            Some("move") => {
+                let positions = (
+                    args.get(1).and_then(|n| n.parse::<usize>().ok()),
+                    args.get(2).and_then(|n| n.parse::<usize>().ok()),
+                );
+                let (Some(from), Some(to)) = positions else {
+                    return outln!("{}: Usage: queue move <from> <to>", "Error".red());
+                };
+                match self.queue.move_entry(from, to) {
+                    Some(_) => {
+                        self.show_queue();
+                        self.prefetch_next();
+                    }
+                    None => outln!("{}: The queue has no position {}", "Error".red(), from),
+                }
+            }
+            Some("jump") => {
+                let position = args.get(1).and_then(|n| n.parse::<usize>().ok());
+                let Some(position) = position else {
+                    return outln!(
+                        "{}: Please give the position in `queue show` to play",
+                        "Error".red()
+                    );
+                };
+                match self.queue.jump(position) {
+                    Some(index) => {
+                        if let Err(e) = self.play(index) {
+                            outln!("{}: {}", "Error".red(), e);
+                        }
+                        self.prefetch_next();
+                    }
+                    None => outln!("{}: The queue has no position {}", "Error".red(), position),
+                }
+            }
+            Some("clear") => {
+                self.queue.clear();
+                outln!("{}: Queue cleared", "Success".green());
+            }
+            Some(other) => outln!(
+                "{}: Unknown queue command '{}'; try add, remove, move, jump, clear or show",
+                "Error".red(),
+                other
+            ),
+        }
+    }
+
+    fn show_queue(&self) {
+        if self.queue.is_empty() {
+            return outln!("{}: The queue is empty", "Info".blue());
+        }
+        outln!("{}", "Queue:".bold());
+        if let Some(current) = self.current_path().filter(|_| self.is_playing) {
+            outln!(
+                "  {} {}",
+                screen_reader::symbol("▶", "Now:").green(),
+                file_name(&current)
+            );
+        }
+        for (position, index) in self.queue.iter().enumerate() {
+            let name = self.song_path(&index.to_string());
+            let name = name.as_deref().map_or("(gone)".to_string(), file_name);
+            outln!(
+                "  {}. {} {}",
+                position + 1,
+                format!("#{}", index).dimmed(),
+                name
+            );
+        }
+    }
+
+    /// Queues the tracks appended to the followed playlist file since the
+    /// last tick, adding ones from outside the library to it
+    /// With nothing playing, the first of them starts
+    fn report_inbox(&mut self) {
+        let appended = match &self.inbox {
+            Some(inbox) => inbox.take_appended(),
+            None => return,
+        };
+        if appended.is_empty() {
+            return;
+        }
+        outln!();
+        for path in appended {
+            if !path.is_file() {
+                outln!("{}: No such file: {}", "Warning".yellow(), path.display());
+                continue;
+            }
+            if !codecs::supported(&path) {
+                outln!(
+                    "{}: {} is not a format this build can play",
+                    "Warning".yellow(),
+                    path.display()
+                );
+                continue;
+            }
+            self.enqueue(&path);
+        }
+        print_prompt();
+    }
+
+    /// Queues the audio files that arrived in the drop folder since the
+    /// last tick, first moving them into the library if `[drop]` says so
+    fn report_drops(&mut self) {
+        let arrived = match &self.drop_folder {
+            Some(folder) => folder.take_arrived(),
+            None => return,
+        };
+        if arrived.is_empty() {
+            return;
+        }
+        outln!();
+        for path in arrived {
+            let path = match (&self.drop_into, path.file_name()) {
+                (Some(dir), Some(name)) => {
+                    let target = files::unique_path(&dir.join(name), &HashSet::new());
+                    match files::move_to(&path, &target) {
+                        Ok(()) => {
+                            outln!(
+                                "{}: Moved {} into {}",
+                                "Info".blue(),
+                                file_name(&path),
+                                dir.display()
+                            );
+                            target
+                        }
+                        Err(e) => {
+                            outln!(
+                                "{}: Could not move {} into the library: {}",
+                                "Warning".yellow(),
+                                file_name(&path),
+                                e
+                            );
+                            path
+                        }
+                    }
+                }
+                _ => path,
+            };
+            self.enqueue(&path);
+        }
+        print_prompt();
+    }
+
+    /// Puts a track after those waiting, adding it to the library if
+    /// needed; with nothing playing it starts right away
+    fn enqueue(&mut self, path: &Path) {
+        let Some(index) = self.library_index(path) else {
+            return;
+        };
+        if self.is_playing {
+            self.queue.add([index]);
+            outln!(
+                "{}: Queued {} as number {}",
+                "Info".blue(),
+                file_name(path),
+                index
+            );
+        } else if let Err(e) = self.play(index) {
+            outln!("{}: {}", "Error".red(), e);
+        }
+    }
+
+    /// Handles `dir`, `dir add <path>` and `dir remove <path>`, changing the
+    /// library roots for this session
+    /// Only the root in question is scanned, and the other tracks keep
+    /// their numbers
+    fn dir(&mut self, args: &[String]) {
+        let (action, path) = match args.split_first() {
+            None => {
+                outln!("\n{}", "Library roots:".green().bold());
+                screen_reader::rule("-------------------------------".green());
+                if let Some(dir) = &self.main_dir {
+                    outln!("  {} (music directory)", dir);
+                }
+                for dir in &self.extra_dirs {
+                    outln!("  {}", dir.display());
+                }
+                return;
+            }
+            Some((action, rest)) if !rest.is_empty() => {
+                let path = rest.join(" ");
+                let path = path.trim_matches(['"', '\'']);
+                let path = match (path.strip_prefix("~/"), env::var_os("HOME")) {
+                    (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+                    _ => PathBuf::from(path),
+                };
+                (action.to_lowercase(), path)
+            }
+            _ => {
+                outln!("{}: Usage: dir [add|remove <path>]", "Error".red());
+                return;
+            }
+        };
+        match action.as_str() {
+            "add" => self.add_dir(path),
+            "remove" => self.remove_dir(&path),
+            _ => outln!("{}: Usage: dir [add|remove <path>]", "Error".red()),
+        }
+    }
+
+    fn add_dir(&mut self, path: PathBuf) {
+        let root = match fs::canonicalize(&path) {
+            Ok(root) if root.is_dir() => root,
+            Ok(_) => {
+                outln!("{}: {} isn't a folder", "Error".red(), path.display());
+                return;
+            }
+            Err(e) => {
+                outln!("{}: {}: {}", "Error".red(), path.display(), e);
+                return;
+            }
+        };
+        let main = self
+            .main_dir
+            .as_ref()
+            .and_then(|dir| fs::canonicalize(dir).ok());
+        if let Some(covering) = main
+            .iter()
+            .chain(&self.extra_dirs)
+            .find(|dir| root.starts_with(dir))
+        {
+            outln!(
+                "{}: {} is already in the library under {}",
+                "Info".blue(),
+                root.display(),
+                covering.display()
+            );
+            return;
+        }
+        let tracks = match self.scan_filter.files(&root) {
+            Ok(tracks) => tracks,
+            Err(e) => {
+                outln!(
+                    "{}: Could not scan {}: {}",
+                    "Error".red(),
+                    root.display(),
+                    e
+                );
+                return;
+            }
+        };
+        // Roots inside the new one are now covered by it
+        let absorbed: Vec<PathBuf> = self
+            .extra_dirs
+            .iter()
+            .filter(|dir| dir.starts_with(&root))
+            .cloned()
+            .collect();
+        self.extra_dirs.retain(|dir| !dir.starts_with(&root));
+        self.extra_dirs.push(root.clone());
+        let songs = self.available_songs.get_or_insert_with(HashMap::new);
+        let known: HashSet<PathBuf> = songs.values().cloned().collect();
+        let first = songs.keys().max().map_or(1, |max| max + 1);
+        let mut index = first;
+        for track in tracks {
+            if !known.contains(&track) {
+                songs.insert(index, track);
+                index += 1;
+            }
+        }
+        for dir in absorbed {
+            outln!(
+                "{}: {} is now part of {}",
+                "Info".blue(),
+                dir.display(),
+                root.display()
+            );
+        }
+        if index == first {
+            outln!(
+                "{}: Added {}, which holds no tracks this build plays",
+                "Warning".yellow(),
+                root.display()
+            );
+        } else {
+            outln!(
+                "{}: Added {} tracks from {} as numbers {} to {}",
+                "Success".green(),
+                index - first,
+                root.display(),
+                first,
+                index - 1
+            );
+        }
+    }
+
+    fn remove_dir(&mut self, path: &Path) {
+        // An unplugged drive can't be canonicalized any more
+        let wanted = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let Some(position) = self.extra_dirs.iter().position(|dir| *dir == wanted) else {
+            let is_main = self
+                .main_dir
+                .as_ref()
+                .and_then(|dir| fs::canonicalize(dir).ok())
+                .is_some_and(|dir| dir == wanted);
+            if is_main {
+                outln!(
+                    "{}: The music directory stays; start with another --dir to change it",
+                    "Error".red()
+                );
+            } else {
+                outln!(
+                    "{}: {} wasn't added with `dir add`",
+                    "Error".red(),
+                    path.display()
+                );
+            }
+            return;
+        };
+        let root = self.extra_dirs.remove(position);
+        if self
+            .current_path()
+            .is_some_and(|path| path.starts_with(&root))
+        {
+            self.stop_playback();
+        }
+        let mut removed = 0;
+        if let Some(songs) = &mut self.available_songs {
+            let before = songs.len();
+            songs.retain(|_, track| !track.starts_with(&root));
+            removed = before - songs.len();
+        }
+        self.prune_queue();
+        outln!(
+            "{}: Removed {} and its {} tracks",
+            "Success".green(),
+            root.display(),
+            removed
+        );
+    }
+
+    /// Takes the tracks of a previously loaded CD out of the library
+    fn remove_cd_tracks(&mut self) {
+        if let Some(songs) = &mut self.available_songs {
+            songs.retain(|_, path| !cd::is_cd(path));
+        }
+        self.prune_queue();
+    }
+
+    /// Lists the episodes from the last `podcast episodes`, marking the
+    /// downloaded ones
+    fn show_episodes(&self) {
+        outln!("\n{}", "Episodes:".green().bold());
+        screen_reader::rule("-------------------------------".green());
+        for (n, episode) in self.episodes.iter().enumerate() {
+            let line = format!("{:<6} {} – {}", n + 1, episode.feed, episode.title);
+            if episode.path.is_file() {
+                outln!("{} {}", line, "(downloaded)".green());
+            } else {
+                outln!("{}", line);
+            }
+        }
+        if self.episodes.is_empty() {
+            outln!("  (none)");
+        }
+    }
+
+    /// Shows the download queue with how far each download has got
+    fn show_downloads(&self) {
+        outln!("\n{}", "Downloads:".green().bold());
+        screen_reader::rule("-------------------------------".green());
+        let jobs = self.downloads.jobs();
+        for job in &jobs {
+            let done = job.downloaded();
+            let progress = match (&job.state, job.episode.length) {
+                (podcast::JobState::Queued, _) => "queued".dimmed().to_string(),
+                (podcast::JobState::Done, _) => "done".green().to_string(),
+                (podcast::JobState::Failed(e), _) => format!("{}: {}", "failed".red(), e),
+                (podcast::JobState::Running, Some(total)) => format!(
+                    "{:>3}%  {} of {}",
+                    (done * 100 / total.max(1)).min(100),
+                    columns::format_size(done),
+                    columns::format_size(total)
+                ),
+                (podcast::JobState::Running, None) => columns::format_size(done),
+            };
+            outln!("  {:<50} {}", job.episode.title, progress);
+        }
+        if jobs.is_empty() {
+            outln!("  (none)");
+        }
+    }
+
+    /// Announces episode downloads that finished since the last check
+    fn report_downloads(&mut self) {
+        let finished = self.downloads.take_finished();
+        if finished.is_empty() {
+            return;
+        }
+        outln!();
+        for report in finished {
+            match report {
+                Ok(message) => outln!("{}: {}", "Success".green(), message),
+                Err(message) => outln!("{}: {}", "Error".red(), message),
+            }
+        }
+        print_prompt();
+    }
+
+    /// Handles `import itunes <Library.xml>` and `import mpd <dir>`
+    fn import(&mut self, args: &[String]) {
+        let (Some(source), Some(_)) = (args.first().map(String::as_str), args.get(1)) else {
+            outln!(
+                "{}: Usage: import itunes <Library.xml> | mpd <dir>",
+                "Error".red()
+            );
+            return;
+        };
+        // The rest of the line is the path, which may contain spaces
+        let path = PathBuf::from(args[1..].join(" "));
+        let library: Vec<PathBuf> = self
+            .available_songs
+            .iter()
+            .flat_map(|songs| songs.values().cloned())
+            .collect();
+        let matcher = import::Matcher::new(&library);
+        let result = match source {
+            "itunes" => import::itunes(&path, &matcher, &mut self.stats),
+            "mpd" => {
+                let music_dir = PathBuf::from(self.main_dir.clone().unwrap_or_default());
+                import::mpd(&path, &music_dir, &matcher, &mut self.stats)
+            }
+            _ => {
+                outln!(
+                    "{}: Usage: import itunes <Library.xml> | mpd <dir>",
+                    "Error".red()
+                );
+                return;
+            }
+        }
+        .and_then(|summary| self.stats.save().map(|_| summary));
+        match result {
+            Ok(summary) => {
+                outln!(
+                    "{}: Imported {} playlists, {} ratings and {} play counts",
+                    "Success".green(),
+                    summary.playlists,
+                    summary.ratings,
+                    summary.play_counts
+                );
+                if summary.missing > 0 {
+                    outln!(
+                        "{}: {} tracks have no local file and were skipped",
+                        "Warning".yellow(),
+                        summary.missing
+                    );
+                }
+            }
+            Err(e) => outln!("{}: Could not import library: {}", "Error".red(), e),
+        }
+    }
+
+    /// Handles `eq preset|set|save|import|off|list`
+    fn equalizer(&mut self, args: &[String]) {
+        let (guard, args) = safeguard::Safeguard::from_args(args, self.at_prompt);
+        let words: Vec<&str> = args.iter().map(String::as_str).collect();
+        let overwrite_allowed = |name: &str| {
+            !eq::user_presets().iter().any(|preset| preset == name)
+                || guard.allow(&format!("overwrite preset {}", name))
+        };
+        match words[..] {
+            ["preset", name] => match eq::Preset::find(name) {
+                Ok(preset) => {
+                    self.effects.update(|effects| effects.eq = Some(preset));
+                    outln!("{}: Equalizer preset {}", "Info".blue(), name);
+                }
+                Err(e) => outln!("{}: {}", "Error".red(), e),
+            },
+            ["set", freq, gain] | ["set", freq, gain, _] => {
+                let q = words.get(3).map_or(Ok(1.0), |q| q.parse::<f32>());
+                let (Ok(freq), Ok(gain_db), Ok(q)) = (freq.parse::<f32>(), gain.parse(), q) else {
+                    outln!("{}: Usage: eq set <freq> <gain dB> [q]", "Error".red());
+                    return;
+                };
+                let mut preset = self.effects.settings().eq.unwrap_or_else(|| {
+                    eq::Preset::built_in("flat").expect("flat is a built-in preset")
+                });
+                preset.name = "custom".to_string();
+                preset.bands.retain(|band| band.freq != freq);
+                preset.bands.push(eq::Band {
+                    filter: eq::Filter::Peak,
+                    freq,
+                    gain_db,
+                    q,
+                });
+                self.effects.update(|effects| effects.eq = Some(preset));
+                outln!("{}: {} Hz set to {:+.1} dB", "Info".blue(), freq, gain_db);
+            }
+            ["save", name] => {
+                let Some(mut preset) = self.effects.settings().eq else {
+                    outln!("{}: The equalizer is off", "Error".red());
+                    return;
+                };
+                preset.name = name.to_string();
+                if !overwrite_allowed(name) {
+                    return;
+                }
+                match preset.save() {
+                    Ok(path) => {
+                        outln!("{}: Saved preset to {}", "Success".green(), path.display());
+                        self.effects.update(|effects| effects.eq = Some(preset));
+                    }
+                    Err(e) => outln!("{}: Could not save preset: {}", "Error".red(), e),
+                }
+            }
+            ["import", ..] if words.len() > 1 => {
+                // The rest of the line is the path, which may contain spaces
+                let path = words[1..].join(" ");
+                let preset = match eq::Preset::import(Path::new(&path)) {
+                    Ok(preset) => preset,
+                    Err(e) => {
+                        outln!("{}: Could not import preset: {}", "Error".red(), e);
+                        return;
+                    }
+                };
+                if !overwrite_allowed(&preset.name) {
+                    return;
+                }
+                let imported = preset.save().map(|saved| (preset, saved));
+                match imported {
+                    Ok((preset, saved)) => {
+                        outln!(
+                            "{}: Imported {} ({} bands) to {}",
+                            "Success".green(),
+                            preset.name.blue(),
+                            preset.bands.len(),
+                            saved.display()
+                        );
+                        self.effects.update(|effects| effects.eq = Some(preset));
+                    }
+                    Err(e) => outln!("{}: Could not import preset: {}", "Error".red(), e),
+                }
+            }
+            ["off"] => {
+                self.effects.update(|effects| effects.eq = None);
+                outln!("{}: Equalizer off", "Info".blue());
+            }
+            ["list"] | [] => {
+                let current = self.effects.settings().eq.map(|preset| preset.name);
+                outln!("\n{}", "Equalizer Presets:".green().bold());
+                let user = eq::user_presets();
+                for name in eq::BUILT_IN
+                    .iter()
+                    .copied()
+                    .chain(user.iter().map(String::as_str))
+                {
+                    if current.as_deref() == Some(name) {
+                        outln!("  {} {}", name.blue().bold(), "(active)".green());
+                    } else {
+                        outln!("  {}", name);
+                    }
+                }
+            }
+            _ => outln!(
+                "{}: Usage: eq preset <name> | set <freq> <gain> [q] | save <name> | import <file> | off | list",
+                "Error".red()
+            ),
+        }
+    }
+
+    /// Processes user input and converts it to appropriate commands
+    pub fn get_commands(&mut self, input_line: &str) {
+        let tokens: Vec<&str> = input_line.split_whitespace().collect();
+
+        // If no tokens, do nothing.
+        if tokens.is_empty() {
+            return;
+        }
+
+        // Safely set last_input only if a second token exists.
+        self.last_input = tokens.get(1).map(|s| s.to_lowercase());
+
+        // Dispatch based on the first token.
+        match tokens[0].to_lowercase().as_str() {
+            "play" => self.act_on_commands(InputCommands::Play(tokens[1..].join(" "))),
+            "pause" => self.act_on_commands(InputCommands::Pause),
+            "list" => self.act_on_commands(InputCommands::List(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "resume" => self.act_on_commands(InputCommands::Resume),
+            "stop" => self.act_on_commands(InputCommands::Stop),
+            "volume" => {
+                if let Some(vol_str) = tokens.get(1) {
+                    if let Ok(vol) = vol_str.parse::<f32>() {
+                        self.act_on_commands(InputCommands::Volume(vol));
+                    } else {
+                        outln!("{}: Invalid volume value", "Error".red());
+                    }
+                } else {
+                    outln!("{}: Missing volume value", "Error".red());
+                }
+            }
+            "status" => self.act_on_commands(InputCommands::Status),
+            "karaoke" => match self.last_input.as_deref() {
+                Some("on") => self.act_on_commands(InputCommands::Karaoke(true)),
+                Some("off") => self.act_on_commands(InputCommands::Karaoke(false)),
+                _ => outln!("{}: Usage: karaoke on|off", "Error".red()),
+            },
+            "crossfeed" => match self.last_input.as_deref() {
+                Some("on") => self.act_on_commands(InputCommands::Crossfeed(true)),
+                Some("off") => self.act_on_commands(InputCommands::Crossfeed(false)),
+                _ => outln!("{}: Usage: crossfeed on|off", "Error".red()),
+            },
+            "nightmode" => match self.last_input.as_deref() {
+                Some("on") => self.act_on_commands(InputCommands::NightMode(true)),
+                Some("off") => self.act_on_commands(InputCommands::NightMode(false)),
+                _ => outln!("{}: Usage: nightmode on|off", "Error".red()),
+            },
+            "eq" => self.act_on_commands(InputCommands::Eq(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "playlist" => self.act_on_commands(InputCommands::Playlist(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "import" => self.act_on_commands(InputCommands::Import(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "podcast" => self.act_on_commands(InputCommands::Podcast(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "delete" => self.act_on_commands(InputCommands::Delete(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "move" => self.act_on_commands(InputCommands::Move(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "paste" => self.act_on_commands(InputCommands::Paste),
+            "pick" => self.act_on_commands(InputCommands::Pick(tokens[1..].join(" "))),
+            // `/` opens the picker too, with anything after it already typed
+            first if first.starts_with('/') => self.act_on_commands(InputCommands::Pick(
+                input_line.trim_start().trim_start_matches('/').to_string(),
+            )),
+            "restart" => self.act_on_commands(InputCommands::Restart),
+            "lyrics" => self.act_on_commands(InputCommands::Lyrics),
+            "config" => match self.last_input.as_deref() {
+                Some("reload") => self.act_on_commands(InputCommands::ConfigReload),
+                _ => outln!("{}: Usage: config reload", "Error".red()),
+            },
+            "time" => match self.last_input.as_deref() {
+                Some("elapsed") => self.act_on_commands(InputCommands::ShowRemaining(false)),
+                Some("remaining") => self.act_on_commands(InputCommands::ShowRemaining(true)),
+                _ => outln!("{}: Usage: time elapsed|remaining", "Error".red()),
+            },
+            "prev" => self.act_on_commands(InputCommands::Prev),
+            "intro" => match &tokens[1..] {
+                ["set", time] => match playhead::parse_time(time) {
+                    Some(intro) => self.act_on_commands(InputCommands::Intro(Some(intro))),
+                    None => outln!("{}: Give the start as m:ss, e.g. 0:20", "Error".red()),
+                },
+                ["clear"] => self.act_on_commands(InputCommands::Intro(None)),
+                _ => outln!("{}: Usage: intro set <m:ss> | intro clear", "Error".red()),
+            },
+            "stopafter" => match self.last_input.as_deref() {
+                Some("album") => self.act_on_commands(InputCommands::StopAfterAlbum(true)),
+                Some("off") => self.act_on_commands(InputCommands::StopAfterAlbum(false)),
+                _ => outln!("{}: Usage: stopafter album|off", "Error".red()),
+            },
+            // Ctrl+F and Ctrl+B reach us as control characters once Enter
+            // is pressed
+            "skipfwd" | "\u{6}" | "skipback" | "\u{2}" => {
+                let forward = matches!(tokens[0].to_lowercase().as_str(), "skipfwd" | "\u{6}");
+                let by = match tokens.get(1).map(|secs| secs.parse::<f64>()) {
+                    None => None,
+                    Some(Ok(secs)) if secs.is_finite() && secs >= 0.0 => {
+                        Some(Duration::from_secs_f64(secs))
+                    }
+                    Some(_) => {
+                        outln!("{}: Usage: skipfwd|skipback [seconds]", "Error".red());
+                        return;
+                    }
+                };
+                self.act_on_commands(if forward {
+                    InputCommands::SkipForward(by)
+                } else {
+                    InputCommands::SkipBack(by)
+                });
+            }
+            // `seek 1:30` goes there, `seek +30` and `seek -10` jump from
+            // where playback is
+            "seek" => {
+                let Some(arg) = tokens.get(1) else {
+                    return outln!("{}: Usage: seek <m:ss>|+seconds|-seconds", "Error".red());
+                };
+                let (sign, time) = match arg.split_at(arg.starts_with(['+', '-']) as usize) {
+                    ("", time) => (None, time),
+                    (sign, time) => (Some(sign == "+"), time),
+                };
+                let Some(by) = playhead::parse_time(time) else {
+                    return outln!("{}: Usage: seek <m:ss>|+seconds|-seconds", "Error".red());
+                };
+                self.act_on_commands(match sign {
+                    None => InputCommands::Seek(by),
+                    Some(true) => InputCommands::SkipForward(Some(by)),
+                    Some(false) => InputCommands::SkipBack(Some(by)),
+                });
+            }
+            "replay" => match tokens.get(1).map(|secs| secs.parse::<f64>()) {
+                None => self.act_on_commands(InputCommands::Replay(Duration::from_secs(10))),
+                Some(Ok(secs)) if secs.is_finite() && secs >= 0.0 => {
+                    self.act_on_commands(InputCommands::Replay(Duration::from_secs_f64(secs)))
+                }
+                Some(_) => outln!("{}: Usage: replay [seconds]", "Error".red()),
+            },
+            "organize" => self.act_on_commands(InputCommands::Organize(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "party" => match self.last_input.as_deref() {
+                Some("on") => self.act_on_commands(InputCommands::Party(true)),
+                Some("off") => self.act_on_commands(InputCommands::Party(false)),
+                _ => outln!("{}: Usage: party on|off", "Error".red()),
+            },
+            "tag" => self.act_on_commands(InputCommands::Tag(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "stream" => match tokens.get(1) {
+                Some(url) => self.act_on_commands(InputCommands::Stream(url.to_string())),
+                None => outln!("{}: Usage: stream <url>", "Error".red()),
+            },
+            "follow" => self.act_on_commands(InputCommands::Follow(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "queue" => self.act_on_commands(InputCommands::Queue(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "db" => self.act_on_commands(InputCommands::Db(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "similar" => self.act_on_commands(InputCommands::Similar(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "radio" => self.act_on_commands(InputCommands::Radio(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "autoplay" => match self.last_input.as_deref() {
+                Some("on") => self.act_on_commands(InputCommands::Autoplay(true)),
+                Some("off") => self.act_on_commands(InputCommands::Autoplay(false)),
+                _ => outln!("{}: Usage: autoplay on|off", "Error".red()),
+            },
+            "bench" => self.act_on_commands(InputCommands::Bench(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "rip" => self.act_on_commands(InputCommands::Rip(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "cd" => self.act_on_commands(InputCommands::Cd(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "dir" => self.act_on_commands(InputCommands::Dir(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "offline" => match self.last_input.as_deref() {
+                Some("on") => self.act_on_commands(InputCommands::Offline(true)),
+                Some("off") => self.act_on_commands(InputCommands::Offline(false)),
+                _ => outln!("{}: Usage: offline on|off", "Error".red()),
+            },
+            "speed" => match tokens
+                .get(1)
+                .map(|speed| speed.trim_end_matches('x').parse())
+            {
+                None => self.act_on_commands(InputCommands::Speed(None)),
+                Some(Ok(speed)) if speeds::RANGE.contains(&speed) => {
+                    self.act_on_commands(InputCommands::Speed(Some(speed)))
+                }
+                Some(_) => outln!("{}: Usage: speed [0.5-3.0]", "Error".red()),
+            },
+            "help" | "?" => self.act_on_commands(InputCommands::Help),
+            // Ctrl+P reaches us as a control character once Enter is pressed
+            "palette" | "\u{10}" => self.act_on_commands(InputCommands::Palette),
+            "exit" => self.act_on_commands(InputCommands::Exit),
+            _ => self.act_on_commands(InputCommands::InvalidCommand),
+        }
+    }
+
+    /// Lists all available songs in the chosen columns
+    /// With `label`, only the tracks carrying it are listed
+    pub fn list(&self, columns: &[columns::Column], label: Option<&str>) {
+        if let Some(sound_map) = &self.available_songs {
+            outln!("\n{}", "Available Songs:".green().bold());
+            screen_reader::rule("-------------------------------".green());
+            let headers: Vec<String> = columns
+                .iter()
+                .map(|column| column.kind.header().to_string())
+                .collect();
+            outln!("{}", columns::row(columns, &headers).bold());
+            let needs_tags = columns.iter().any(|column| column.kind.needs_tags());
+            let needs_rate = columns
+                .iter()
+                .any(|column| column.kind == columns::Kind::Rate);
+            let mut durations = self.durations.lock().unwrap();
+            let mut tracks: Vec<(&i32, &PathBuf)> = sound_map.iter().collect();
+            tracks.sort();
+            for (index, path) in tracks {
+                if label.is_some_and(|label| !self.labels.get(path).contains(&label)) {
+                    continue;
+                }
+                let filename = file_name(path);
+                let tags = if needs_tags {
+                    tags::read(path).unwrap_or_default()
+                } else {
+                    tags::Tags::default()
+                };
+                let stats = self.stats.get(path);
+                let length = durations.get(path);
+                let size = fs::metadata(path).ok().map(|metadata| metadata.len());
+                // Finding a remote track's rate would mean downloading it
+                let rate = (needs_rate && !remote::is_remote(path))
+                    .then(|| codecs::open(path).ok().map(|track| track.sample_rate()))
+                    .flatten();
+                let values: Vec<String> = columns
+                    .iter()
+                    .map(|column| match column.kind {
+                        columns::Kind::Index => index.to_string(),
+                        columns::Kind::Track => {
+                            tags.describe().unwrap_or_else(|| self.library_name(path))
+                        }
+                        columns::Kind::Title => tags.title.clone().unwrap_or_else(|| {
+                            path.file_stem()
+                                .map_or(filename.clone(), |stem| stem.to_string_lossy().to_string())
+                        }),
+                        columns::Kind::Artist => tags.artist.clone().unwrap_or_default(),
+                        columns::Kind::Album => tags.album.clone().unwrap_or_default(),
+                        // Blank until the background pass has decoded the track
+                        columns::Kind::Duration => length.map_or(String::new(), |length| {
+                            format!("{}:{:02}", length.as_secs() / 60, length.as_secs() % 60)
+                        }),
+                        columns::Kind::Plays => stats.map_or(0, |stats| stats.plays).to_string(),
+                        columns::Kind::Rating => stats
+                            .and_then(|stats| stats.rating)
+                            .map_or(String::new(), screen_reader::stars),
+                        columns::Kind::File => self.library_name(path),
+                        columns::Kind::Codec => codecs::format_of(path).unwrap_or("").to_string(),
+                        columns::Kind::Rate => rate.map_or(String::new(), columns::format_rate),
+                        columns::Kind::Bitrate => length
+                            .filter(|length| !length.is_zero())
+                            .zip(size)
+                            .map_or(String::new(), |(length, size)| {
+                                format!(
+                                    "{:.0} kbps",
+                                    size as f64 * 8.0 / 1000.0 / length.as_secs_f64()
+                                )
+                            }),
+                        columns::Kind::Size => size.map_or(String::new(), columns::format_size),
+                    })
+                    .collect();
+                let line = columns::row(columns, &values);
+                if Some(*index) == self.current_index {
+                    outln!(
+                        "{} {}",
+                        line.green(),
+                        screen_reader::symbol("▶", "(playing)").green()
+                    );
+                } else if self.unavailable(path) {
+                    outln!("{} {}", line.dimmed(), "(not cached)".dimmed());
+                } else {
+                    outln!("{}", line);
+                }
+            }
+            if let Err(e) = durations.save() {
+                outln!(
+                    "{}: Could not save track lengths: {}",
+                    "Warning".yellow(),
+                    e
+                );
+            }
+            outln!();
+        }
+    }
+}
+
+/// Runs one prompt command on the running player and prints its output,
+/// returning the exit status
+fn run_remote(addr: &str, token: Option<&str>, command: &str) -> i32 {
+    let result = control::run_command(addr, token, command, |line| outln!("{}", line));
+    match result {
+        Ok(()) => 0,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionRefused | io::ErrorKind::TimedOut
+            ) =>
+        {
+            eprintln!(
+                "{}: No player is listening on {}; start one with --daemon, or pass --control-addr",
+                "Error".red(),
+                addr
+            );
+            1
+        }
+        Err(e) => {
+            eprintln!("{}: {}", "Error".red(), e);
+            1
+        }
+    }
+}
+
+/// Installs the latest release over this binary, or with `check` only
+/// reports it
+fn self_update(check: bool, config: &Config) -> Result<(), String> {
+    let release = update::latest()?;
+    if !release.is_newer() {
+        outln!(
+            "{}: {} is the latest release",
+            "Success".green(),
+            env!("CARGO_PKG_VERSION")
+        );
+        return Ok(());
+    }
+    outln!(
+        "{}: {} is out (this is {})",
+        "Info".blue(),
+        release.version,
+        env!("CARGO_PKG_VERSION")
+    );
+    if check {
+        outln!(
+            "{}: Run `musicplayer self-update` to install {}",
+            "Info".blue(),
+            release.archive_name()
+        );
+        return Ok(());
+    }
+    if config.get_str("update.public_key").is_none() {
+        outln!(
+            "{}: Only the checksum is checked; set `public_key` under [update] to require a signature",
+            "Warning".yellow()
+        );
+    }
+    let replaced = update::install(&release, config)?;
+    outln!(
+        "{}: Updated {} to {}",
+        "Success".green(),
+        replaced.display(),
+        release.version
+    );
+    Ok(())
+}
+
+/// Main entry point for the application
+/// Installs the panic hook and initializes the player
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Restore the terminal and save the session if anything panics
+    session::install_panic_hook();
 
     let arguments = cli_config().get_matches();
+    if let Some(extensions) = arguments.get_many::<String>("include-ext") {
+        codecs::include_extensions(&extensions.cloned().collect::<Vec<_>>());
+    }
+    // Before the config is loaded, so a broken one gets diagnosed
+    if arguments.subcommand_matches("doctor").is_some() {
+        if arguments.get_flag("screen-reader") {
+            screen_reader::force();
+            screen_reader::configure(&Config::default());
+        }
+        let healthy = doctor::run(&arguments);
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+    let config = Config::load_profile(
+        arguments.get_one::<String>("config").map(String::as_str),
+        arguments.get_one::<String>("profile").map(String::as_str),
+    )?;
+
+    if let Some(tmux) = arguments.subcommand_matches("tmux-status") {
+        let addr = tmux
+            .get_one::<String>("control-addr")
+            .map(String::as_str)
+            .unwrap_or(control::DEFAULT_CONTROL_ADDR);
+        let tokens = auth::Tokens::from_config(&config).unwrap_or_default();
+        let token = tmux
+            .get_one::<String>("token")
+            .cloned()
+            .or(tokens.first())
+            .or_else(auth::saved_token);
+        control::print_tmux_status(
+            addr,
+            token.as_deref(),
+            *tmux.get_one::<usize>("max-width").unwrap_or(&40),
+        );
+        return Ok(());
+    }
+
+    if let Some(update) = arguments.subcommand_matches("self-update") {
+        if let Err(e) = self_update(update.get_flag("check"), &config) {
+            eprintln!("{}: {}", "Error".red(), e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some((name, command)) = arguments.subcommand() {
+        let words: Vec<String> = std::iter::once(name.to_string())
+            .chain(
+                command
+                    .get_many::<std::ffi::OsString>("")
+                    .into_iter()
+                    .flatten()
+                    .map(|word| word.to_string_lossy().into_owned()),
+            )
+            .collect();
+        let addr = arguments
+            .get_one::<String>("control-addr")
+            .map(String::as_str)
+            .unwrap_or(control::DEFAULT_CONTROL_ADDR);
+        let token = auth::Tokens::from_config(&config)
+            .unwrap_or_default()
+            .first()
+            .or_else(auth::saved_token);
+        std::process::exit(run_remote(addr, token.as_deref(), &words.join(" ")));
+    }
+
+    if arguments.get_flag("screen-reader") {
+        screen_reader::force();
+    }
+    screen_reader::configure(&config);
 
     // Check if --how-to flag is present
     if arguments.get_flag("how-to") {
@@ -371,28 +5185,197 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
-    let mut application = CliPlayer::new()?;
-    application.run(arguments)?;
+    let backend = arguments
+        .get_one::<String>("backend")
+        .cloned()
+        .or_else(|| config.get_str("output.backend").map(str::to_string));
+    let direct = direct::Settings {
+        frames: arguments
+            .get_one::<u32>("buffer-size")
+            .copied()
+            .or_else(|| {
+                config
+                    .get_f64("output.buffer_size")
+                    .map(|frames| frames as u32)
+            }),
+        bit_depth: arguments
+            .get_one::<u16>("bit-depth")
+            .copied()
+            .or_else(|| config.get_f64("output.bit_depth").map(|bits| bits as u16)),
+        dither: config.get_bool("output.dither").unwrap_or(true),
+    };
+    let mut application = CliPlayer::new(backend, direct)?;
+    application.run(arguments, &config)?;
     Ok(())
 }
 
 /// Prints usage instructions and available commands
 fn print_usage_instructions() {
-    println!("\n{}", "Music Player Usage Instructions:".bold());
-    println!("{}", "--------------------------------".bold());
-    println!("{}:", "Commands".bold());
-    println!(
+    outln!("\n{}", "Music Player Usage Instructions:".bold());
+    screen_reader::rule("--------------------------------".bold());
+    outln!("{}:", "Commands".bold());
+    outln!(
         "  {} <number>   - Play the track with the given number",
         "play".green()
     );
-    println!("  {}           - Pause the current track", "pause".yellow());
-    println!("  {}          - Resume the paused track", "resume".green());
-    println!("  {}            - Stop the current playback", "stop".red());
-    println!("  {} <0.0-1.0> - Set playback volume", "volume".cyan());
-    println!("  {}           - Show player status", "status".blue());
-    println!("  {}           - Show available tracks", "list".cyan());
-    println!("  {}           - Show this help message", "help".yellow());
-    println!("  {}            - Exit the program", "exit".red());
-    println!("\n{}:", "Example".bold());
-    println!("  musicplayer --dir /path/to/music/directory\n");
-}
\ No newline at end of file
+    outln!(
+        "  {} 3-7,12     - Play several tracks one after another",
+        "play".green()
+    );
+    outln!("  {}           - Pause the current track", "pause".yellow());
+    outln!("  {}          - Resume the paused track", "resume".green());
+    outln!("  {}            - Stop the current playback", "stop".red());
+    outln!("  {} <0.0-1.0> - Set playback volume", "volume".cyan());
+    outln!("  {}           - Show player status", "status".blue());
+    outln!("  {}           - Show available tracks", "list".cyan());
+    outln!(
+        "  {} --columns <names> - List chosen columns, e.g. index,title:30,artist",
+        "list".cyan()
+    );
+    outln!(
+        "  {} --detail  - List codec, sample rate, bitrate and size",
+        "list".cyan()
+    );
+    outln!("  {} <on|off> - Remove centred vocals", "karaoke".magenta());
+    outln!("  {} <on|off> - Headphone crossfeed", "crossfeed".magenta());
+    outln!(
+        "  {} preset <name>  - Choose an equalizer preset",
+        "eq".magenta()
+    );
+    outln!(
+        "  {}           - Play the file path on the clipboard",
+        "paste".green()
+    );
+    outln!(
+        "  {} [text] or /[text] - Find a track by typing part of its name",
+        "pick".green()
+    );
+    outln!(
+        "  {} <elapsed|remaining> - Count time up or down",
+        "time".cyan()
+    );
+    outln!(
+        "  {}          - Show the current track's lyrics",
+        "lyrics".cyan()
+    );
+    outln!(
+        "  {}         - Play the current track from the start",
+        "restart".cyan()
+    );
+    outln!("  {}            - Play the previous track", "prev".cyan());
+    outln!(
+        "  {} <album|off> - Stop once the current album has played out",
+        "stopafter".cyan()
+    );
+    outln!(
+        "  {} set <m:ss> - Always start the current track there (intro clear undoes it)",
+        "intro".cyan()
+    );
+    outln!(
+        "  {} <m:ss>|+secs|-secs - Jump to a position, or ahead or back from here",
+        "seek".cyan()
+    );
+    outln!(
+        "  {} [seconds]  - Jump back in the track (10 seconds by default)",
+        "replay".cyan()
+    );
+    outln!(
+        "  {} [seconds] - Jump ahead (or Ctrl+F, Enter); 30s in podcasts, 5s in music",
+        "skipfwd".cyan()
+    );
+    outln!(
+        "  {} [seconds] - Jump back (or Ctrl+B, Enter); 10s in podcasts, 5s in music",
+        "skipback".cyan()
+    );
+    outln!(
+        "  {} <subcommand> - Subscribe to feeds and download episodes",
+        "podcast".yellow()
+    );
+    outln!("  {} <number> - Move a track to the trash", "delete".red());
+    outln!(
+        "  {} <number> <dir> - Move a track to another folder",
+        "move".yellow()
+    );
+    outln!(
+        "  {} <on|off>   - Limit remote clients to status and idle play",
+        "party".magenta()
+    );
+    outln!(
+        "  {} <on|off> - Play only local files and cached remote tracks",
+        "offline".yellow()
+    );
+    outln!(
+        "  {} <on|off> - Keep playing tracks picked by rating once playback would stop",
+        "autoplay".yellow()
+    );
+    outln!(
+        "  {} add|remove <label> [n] - Label track n or the current one, e.g. with a mood",
+        "tag".yellow()
+    );
+    outln!(
+        "  {} show [n] | list - Show a track's labels, or every label in use",
+        "tag".yellow()
+    );
+    outln!(
+        "  {} <url>     - Tune in to an internet radio station",
+        "stream".yellow()
+    );
+    outln!(
+        "  {} add <n[,n-m]>|remove <pos>|move <pos> <to>|jump <pos>|clear|show - Line up tracks to play next",
+        "queue".yellow()
+    );
+    outln!(
+        "  {} <file.m3u>|off - Queue tracks other programs append to a playlist file",
+        "follow".yellow()
+    );
+    outln!(
+        "  {} export|import <file> - Back up or restore ratings, plays, labels and positions",
+        "db".yellow()
+    );
+    outln!(
+        "  {} <n> [count] - List tracks like track n: artist, genre, decade, tempo, plays",
+        "similar".yellow()
+    );
+    outln!(
+        "  {} <n>|off  - Play track n, then keep going with tracks like the last",
+        "radio".yellow()
+    );
+    outln!(
+        "  {} [eject]       - Add the tracks of an audio CD to the list, or eject it",
+        "cd".cyan()
+    );
+    outln!(
+        "  {} [add|remove <path>] - List the library roots, or add or take one out",
+        "dir".cyan()
+    );
+    outln!(
+        "  {} decode [n]  - Time tag reads and decoding over n tracks, by format",
+        "bench".cyan()
+    );
+    outln!(
+        "  {} [flac|opus] [n ...] - Rip the CD, or some of its tracks, into the library",
+        "rip".cyan()
+    );
+    outln!(
+        "  {} [0.5-3.0]  - Play faster or slower, remembered per podcast or album",
+        "speed".cyan()
+    );
+    outln!("  {} reload   - Re-read the config file", "config".yellow());
+    outln!(
+        "  {}        - Search every command (or Ctrl+P, Enter)",
+        "palette".yellow()
+    );
+    outln!(
+        "  {} or {}      - Show this help message",
+        "help".yellow(),
+        "?".yellow()
+    );
+    outln!("  {}            - Exit the program", "exit".red());
+    outln!(
+        "\n{}: {}",
+        "Formats".bold(),
+        codecs::compiled_in().join(", ")
+    );
+    outln!("\n{}:", "Example".bold());
+    outln!("  musicplayer --dir /path/to/music/directory\n");
+}
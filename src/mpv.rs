@@ -0,0 +1,187 @@
+//! Playback through libmpv, which decodes nearly every codec and can
+//! open network streams
+//! Only built with the `mpv` feature, which links against the system's
+//! libmpv; effects, channel maps and sample-accurate following don't
+//! apply, since the audio never passes through this program
+
+use std::{
+    cell::Cell,
+    ffi::{c_char, c_double, c_int, c_void, CStr, CString},
+    path::Path,
+    ptr,
+    time::Duration,
+};
+
+/// Opaque `mpv_handle`
+#[repr(C)]
+struct Handle {
+    _private: [u8; 0],
+}
+
+/// Leading fields of `mpv_event`
+#[repr(C)]
+struct Event {
+    event_id: c_int,
+    error: c_int,
+    reply_userdata: u64,
+    data: *mut c_void,
+}
+
+/// `MPV_EVENT_NONE`, returned when no event is waiting
+const EVENT_NONE: c_int = 0;
+/// `MPV_EVENT_END_FILE`, whose data starts with the end reason
+const EVENT_END_FILE: c_int = 7;
+/// `MPV_END_FILE_REASON_STOP`, a file replaced or stopped on request
+const END_REASON_STOP: c_int = 2;
+/// `MPV_END_FILE_REASON_REDIRECT`, a playlist or stream forwarding
+const END_REASON_REDIRECT: c_int = 5;
+
+#[link(name = "mpv")]
+extern "C" {
+    fn mpv_create() -> *mut Handle;
+    fn mpv_initialize(ctx: *mut Handle) -> c_int;
+    fn mpv_terminate_destroy(ctx: *mut Handle);
+    fn mpv_set_option_string(ctx: *mut Handle, name: *const c_char, data: *const c_char) -> c_int;
+    fn mpv_set_property_string(ctx: *mut Handle, name: *const c_char, data: *const c_char)
+        -> c_int;
+    fn mpv_command(ctx: *mut Handle, args: *mut *const c_char) -> c_int;
+    fn mpv_wait_event(ctx: *mut Handle, timeout: c_double) -> *mut Event;
+    fn mpv_error_string(error: c_int) -> *const c_char;
+}
+
+/// An mpv player without video or terminal output
+pub struct Mpv {
+    handle: *mut Handle,
+    volume: f32,          // Volume as a factor, 1.0 being mpv's 100
+    finished: Cell<bool>, // The last file ended or was stopped
+}
+
+// SAFETY: the libmpv client API may be called from any thread
+unsafe impl Send for Mpv {}
+
+impl Mpv {
+    /// Starts an idle mpv instance
+    pub fn new() -> Result<Self, String> {
+        // SAFETY: mpv_create has no preconditions; null means failure
+        let handle = unsafe { mpv_create() };
+        if handle.is_null() {
+            return Err("Could not create an mpv instance".to_string());
+        }
+        let mpv = Self {
+            handle,
+            volume: 1.0,
+            finished: Cell::new(true),
+        };
+        for (name, value) in [
+            ("video", "no"),
+            ("audio-display", "no"),
+            ("idle", "yes"),
+            ("volume-max", "1000"),
+        ] {
+            let (name, value) = (c_string(name)?, c_string(value)?);
+            // SAFETY: the handle is live and both strings are NUL-terminated
+            check(unsafe { mpv_set_option_string(mpv.handle, name.as_ptr(), value.as_ptr()) })?;
+        }
+        // SAFETY: the handle is live and not yet initialised
+        check(unsafe { mpv_initialize(mpv.handle) })?;
+        Ok(mpv)
+    }
+
+    /// Replaces whatever is playing with a file, starting at `offset`
+    pub fn load(&self, path: &Path, offset: Duration) -> Result<(), String> {
+        self.set("start", &format!("{:.3}", offset.as_secs_f64()))?;
+        self.command(&["loadfile", &path.to_string_lossy()])?;
+        self.set("pause", "no")?;
+        self.drain_events();
+        self.finished.set(false);
+        Ok(())
+    }
+
+    /// Whether the last file is over
+    pub fn empty(&self) -> bool {
+        self.drain_events();
+        self.finished.get()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        let _ = self.set("pause", if paused { "yes" } else { "no" });
+    }
+
+    pub fn stop(&self) {
+        let _ = self.command(&["stop"]);
+        self.drain_events();
+        self.finished.set(true);
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.max(0.0);
+        let _ = self.set("volume", &format!("{:.1}", self.volume * 100.0));
+    }
+
+    /// Plays faster or slower, keeping the pitch
+    pub fn set_speed(&self, speed: f32) {
+        let _ = self.set("speed", &format!("{:.2}", speed));
+    }
+
+    /// Handles the events mpv queued, noting when a file ends by itself
+    fn drain_events(&self) {
+        loop {
+            // SAFETY: the handle is live; a zero timeout never blocks
+            let event = unsafe { &*mpv_wait_event(self.handle, 0.0) };
+            match event.event_id {
+                EVENT_NONE => return,
+                EVENT_END_FILE if !event.data.is_null() => {
+                    // SAFETY: END_FILE data is an mpv_event_end_file,
+                    // which starts with the reason
+                    let reason = unsafe { *(event.data as *const c_int) };
+                    if reason != END_REASON_STOP && reason != END_REASON_REDIRECT {
+                        self.finished.set(true);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        let (name, value) = (c_string(name)?, c_string(value)?);
+        // SAFETY: the handle is live and both strings are NUL-terminated
+        check(unsafe { mpv_set_property_string(self.handle, name.as_ptr(), value.as_ptr()) })
+    }
+
+    fn command(&self, args: &[&str]) -> Result<(), String> {
+        let args = args
+            .iter()
+            .map(|arg| c_string(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut pointers: Vec<*const c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+        pointers.push(ptr::null());
+        // SAFETY: a NULL-terminated array of strings that outlive the call
+        check(unsafe { mpv_command(self.handle, pointers.as_mut_ptr()) })
+    }
+}
+
+impl Drop for Mpv {
+    fn drop(&mut self) {
+        // SAFETY: the handle is live and not used after this
+        unsafe { mpv_terminate_destroy(self.handle) };
+    }
+}
+
+fn c_string(value: &str) -> Result<CString, String> {
+    CString::new(value).map_err(|_| format!("`{}` contains a NUL byte", value))
+}
+
+/// Turns an mpv status code into its message
+fn check(status: c_int) -> Result<(), String> {
+    if status >= 0 {
+        return Ok(());
+    }
+    // SAFETY: mpv_error_string returns a static string for any code
+    let message = unsafe { CStr::from_ptr(mpv_error_string(status)) };
+    Err(format!("mpv: {}", message.to_string_lossy()))
+}
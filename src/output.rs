@@ -0,0 +1,124 @@
+//! Audio output selection
+//! Maps a backend name to a cpal host and device: on Linux everything goes
+//! through ALSA, where PulseAudio and PipeWire appear as the `pulse` and
+//! `pipewire` devices and direct hardware access as `sysdefault`
+
+use rodio::{
+    cpal::{
+        self,
+        traits::{DeviceTrait, HostTrait},
+    },
+    OutputStream, OutputStreamHandle,
+};
+
+/// Backend names understood by `--backend` besides cpal's own host names
+const LINUX_BACKENDS: &[&str] = &["alsa", "pulse", "pipewire", "jack"];
+
+/// An open output stream
+pub struct Output {
+    pub stream: OutputStream,         // Must stay alive while playing
+    pub handle: OutputStreamHandle,   // Used to create sinks
+    pub channels: u16,                // Channel count the device was opened with
+    pub device: Option<cpal::Device>, // The device itself, for direct streams
+}
+
+/// Opens the output stream for a backend, or the system default when
+/// no backend is given
+pub fn open(backend: Option<&str>) -> Result<Output, String> {
+    // mpv and GStreamer play through their own outputs, but the rest of
+    // the player still expects a stream, so it gets the default one
+    let Some(backend) =
+        backend.filter(|b| *b != "default" && !crate::sink::SELF_PLAYING.contains(b))
+    else {
+        let (stream, handle) = OutputStream::try_default().map_err(|e| e.to_string())?;
+        let device = cpal::default_host().default_output_device();
+        return Ok(Output {
+            stream,
+            handle,
+            channels: device.as_ref().map_or(2, device_channels),
+            device,
+        });
+    };
+
+    let (host_name, device_prefix) = match backend.to_lowercase().as_str() {
+        "alsa" if cfg!(target_os = "linux") => ("alsa".to_string(), Some("sysdefault")),
+        "pulse" | "pulseaudio" if cfg!(target_os = "linux") => ("alsa".to_string(), Some("pulse")),
+        "pipewire" if cfg!(target_os = "linux") => ("alsa".to_string(), Some("pipewire")),
+        // Without cpal's own JACK host, go through the alsa-plugins JACK PCM
+        "jack" if cfg!(target_os = "linux") && !has_host("jack") => {
+            ("alsa".to_string(), Some("jack"))
+        }
+        other => (other.to_string(), None),
+    };
+
+    let host_id = find_host(&host_name).ok_or_else(|| {
+        format!(
+            "Unknown backend `{}` (available: {})",
+            backend,
+            available_backends().join(", ")
+        )
+    })?;
+    let host = cpal::host_from_id(host_id).map_err(|e| e.to_string())?;
+
+    let device = match device_prefix {
+        Some(prefix) => host
+            .output_devices()
+            .map_err(|e| e.to_string())?
+            .find(|d| d.name().is_ok_and(|name| name.starts_with(prefix)))
+            // Plain ALSA setups may lack `sysdefault`; `default` is still direct
+            .or_else(|| {
+                (prefix == "sysdefault")
+                    .then(|| host.default_output_device())
+                    .flatten()
+            }),
+        None => host.default_output_device(),
+    }
+    .ok_or_else(|| format!("No output device available for backend `{}`", backend))?;
+
+    let (stream, handle) = OutputStream::try_from_device(&device).map_err(|e| e.to_string())?;
+    Ok(Output {
+        stream,
+        handle,
+        channels: device_channels(&device),
+        device: Some(device),
+    })
+}
+
+/// Channels of the config rodio opens a device with, assuming stereo if
+/// the device can't say
+fn device_channels(device: &cpal::Device) -> u16 {
+    device
+        .default_output_config()
+        .map_or(2, |config| config.channels())
+}
+
+fn find_host(name: &str) -> Option<cpal::HostId> {
+    cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+}
+
+fn has_host(name: &str) -> bool {
+    find_host(name).is_some()
+}
+
+/// Backend names that can be passed to `--backend` on this system
+pub fn available_backends() -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    if cfg!(feature = "mpv") {
+        names.push(crate::sink::MPV.to_string());
+    }
+    if cfg!(feature = "gstreamer") {
+        names.push(crate::sink::GSTREAMER.to_string());
+    }
+    if cfg!(target_os = "linux") {
+        names.extend(LINUX_BACKENDS.iter().map(|name| name.to_string()));
+    }
+    for id in cpal::available_hosts() {
+        let name = id.name().to_lowercase();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
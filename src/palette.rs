@@ -0,0 +1,169 @@
+//! Command palette: every prompt command in the fuzzy picker, so commands
+//! can be found without knowing their names
+//! Opened with `palette`, or Ctrl+P then Enter at the prompt
+
+use crate::picker;
+use std::io::{self, Write};
+
+/// Commands with the arguments they take and what they do
+const COMMANDS: &[(&str, &str, &str)] = &[
+    ("play", "<number>", "Play the track with the given number"),
+    ("play", "<from-to,n,..>", "Play several tracks in turn"),
+    ("pause", "", "Pause the current track"),
+    ("resume", "", "Resume the paused track"),
+    ("stop", "", "Stop the current playback"),
+    ("volume", "<0.0-1.0>", "Set playback volume"),
+    ("status", "", "Show player status"),
+    ("list", "", "Show available tracks"),
+    (
+        "list --detail",
+        "",
+        "List codec, sample rate, bitrate and size",
+    ),
+    ("pick", "[text]", "Find a track by typing part of its name"),
+    ("paste", "", "Play the file path on the clipboard"),
+    ("restart", "", "Play the current track from the start"),
+    ("prev", "", "Play the previous track"),
+    (
+        "stopafter",
+        "<album|off>",
+        "Stop once the current album has played out",
+    ),
+    (
+        "seek",
+        "<m:ss>|+secs|-secs",
+        "Jump to a position in the track",
+    ),
+    ("replay", "[seconds]", "Jump back in the track"),
+    ("skipfwd", "[seconds]", "Jump ahead (Ctrl+F, Enter)"),
+    ("skipback", "[seconds]", "Jump back (Ctrl+B, Enter)"),
+    (
+        "intro",
+        "set <m:ss>",
+        "Always start the current track there",
+    ),
+    (
+        "intro",
+        "clear",
+        "Play the current track from the top again",
+    ),
+    ("time", "<elapsed|remaining>", "Count time up or down"),
+    ("lyrics", "", "Show the current track's lyrics"),
+    ("karaoke", "<on|off>", "Remove centred vocals"),
+    ("crossfeed", "<on|off>", "Headphone crossfeed"),
+    ("nightmode", "<on|off>", "Even out loud and quiet passages"),
+    ("eq", "preset <name>", "Choose an equalizer preset"),
+    ("eq", "off", "Turn the equalizer off"),
+    ("playlist", "<subcommand>", "Work with saved playlists"),
+    ("import", "itunes <Library.xml>", "Import an iTunes library"),
+    ("import", "mpd <dir>", "Import an MPD music directory"),
+    ("podcast", "subscribe <url>", "Follow a podcast feed"),
+    (
+        "podcast",
+        "episodes [feed]",
+        "List episodes of your podcasts",
+    ),
+    (
+        "podcast",
+        "download <episode|all-new>",
+        "Download episodes in the background",
+    ),
+    ("podcast", "downloads", "Show episode download progress"),
+    ("podcast", "play <episode>", "Play a downloaded episode"),
+    (
+        "podcast",
+        "import <file.opml>",
+        "Subscribe to feeds from another app",
+    ),
+    (
+        "podcast",
+        "export <file.opml>",
+        "Save your podcast feeds as OPML",
+    ),
+    ("delete", "<number>", "Move a track to the trash"),
+    ("move", "<number> <dir>", "Move a track to another folder"),
+    (
+        "organize",
+        "move [--dry-run]",
+        "Sort tracks into folders by their tags",
+    ),
+    (
+        "party",
+        "<on|off>",
+        "Limit remote clients to status and idle play",
+    ),
+    (
+        "tag",
+        "add|remove <label> [n]",
+        "Label a track, e.g. with a mood",
+    ),
+    ("stream", "<url>", "Tune in to an internet radio station"),
+    (
+        "queue",
+        "add <n>|remove <pos>|clear|show",
+        "Line up tracks to play next",
+    ),
+    (
+        "follow",
+        "<file.m3u>|off",
+        "Queue tracks appended to a playlist file",
+    ),
+    (
+        "db",
+        "export|import <file>",
+        "Back up or restore ratings, plays and labels",
+    ),
+    ("similar", "<n> [count]", "List tracks like track n"),
+    ("radio", "<n>|off", "Play a chain of similar tracks"),
+    (
+        "autoplay",
+        "<on|off>",
+        "Keep playing picks weighted by rating",
+    ),
+    (
+        "offline",
+        "<on|off>",
+        "Play only local files and cached remote tracks",
+    ),
+    (
+        "cd",
+        "[eject]",
+        "Add an audio CD's tracks to the list, or eject it",
+    ),
+    (
+        "dir",
+        "[add|remove <path>]",
+        "Add or take out a library root",
+    ),
+    ("bench", "decode [n]", "Time decoding by format"),
+    ("rip", "[flac|opus] [tracks]", "Rip the CD into the library"),
+    (
+        "speed",
+        "[0.5-3.0]",
+        "Play faster or slower, remembered per podcast or album",
+    ),
+    ("config", "reload", "Re-read the config file"),
+    ("help", "", "Show all commands"),
+    ("exit", "", "Exit the program"),
+];
+
+/// Lets the user pick a command, asking for its arguments if it takes
+/// any, and returns the line to run
+pub fn choose() -> Option<String> {
+    let items: Vec<String> = COMMANDS
+        .iter()
+        .map(|(name, args, about)| format!("{:<32} {}", format!("{} {}", name, args), about))
+        .collect();
+    let (name, args, _) = COMMANDS[picker::pick(&items, "")?];
+    // Words before the first placeholder, like `preset` in `eq preset
+    // <name>`, are part of the command
+    let Some(placeholder) = args.find(['<', '[']) else {
+        return Some(format!("{} {}", name, args));
+    };
+    let command = format!("{} {}", name, &args[..placeholder]);
+    print!("{}", command);
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).ok()?;
+    Some(format!("{}{}", command, line.trim()))
+}
@@ -0,0 +1,287 @@
+//! Inline fuzzy picker over the library
+//! On a Unix terminal the list narrows with every key typed; elsewhere the
+//! query is read as a line and the matches are numbered to choose from,
+//! as they also are in screen-reader mode
+
+use crate::screen_reader;
+use colored::*;
+use std::io::{self, Write};
+
+/// Matches shown at once
+const SHOWN: usize = 10;
+
+/// Lets the user choose one of `items`, starting from `query`, returning
+/// its position; None when cancelled or nothing matches
+pub fn pick(items: &[String], query: &str) -> Option<usize> {
+    // Redrawing as you type would have a screen reader repeat the list
+    #[cfg(unix)]
+    if !screen_reader::enabled() {
+        if let Some(terminal) = imp::Raw::enter() {
+            return interactive(items, query, terminal);
+        }
+    }
+    line_based(items, query)
+}
+
+/// How well `query` matches `candidate` as a subsequence, higher is better
+/// Consecutive letters and letters at word starts count extra
+fn score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + candidate[position..].iter().position(|&c| c == wanted)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    if score == 0 {
+        // Nothing typed yet, keep the library's order
+        return Some(0);
+    }
+    // Shorter names win ties
+    Some(score * 100 - candidate.len() as i32)
+}
+
+/// Positions of the matching items, best first
+fn matches(items: &[String], query: &str) -> Vec<usize> {
+    let mut scored: Vec<(i32, usize)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| Some((score(query, item)?, i)))
+        .collect();
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+#[cfg(unix)]
+fn interactive(items: &[String], query: &str, terminal: imp::Raw) -> Option<usize> {
+    let mut query = query.to_string();
+    let mut selected = 0;
+    let mut drawn = 0;
+    loop {
+        let found = matches(items, &query);
+        selected = selected.min(found.len().saturating_sub(1));
+        drawn = draw(items, &query, &found, selected, drawn);
+
+        match terminal.key()? {
+            imp::Key::Enter => {
+                clear(drawn);
+                return found.get(selected).copied();
+            }
+            // Esc clears what was typed first, then cancels
+            imp::Key::Escape if !query.is_empty() => {
+                query.clear();
+                selected = 0;
+            }
+            imp::Key::Escape | imp::Key::Cancel => {
+                clear(drawn);
+                return None;
+            }
+            imp::Key::Up => selected = selected.saturating_sub(1),
+            imp::Key::Down => selected = (selected + 1).min(SHOWN - 1),
+            imp::Key::Backspace => {
+                query.pop();
+            }
+            imp::Key::Char(c) => {
+                query.push(c);
+                selected = 0;
+            }
+        }
+    }
+}
+
+/// Draws the query and the best matches, returning how many lines below
+/// the query line were used
+#[cfg(unix)]
+fn draw(items: &[String], query: &str, found: &[usize], selected: usize, drawn: usize) -> usize {
+    let mut out = String::new();
+    if drawn > 0 {
+        out.push_str(&format!("\x1b[{}A", drawn));
+    }
+    out.push_str("\r\x1b[J");
+    let shown = &found[..found.len().min(SHOWN)];
+    for (row, &item) in shown.iter().enumerate() {
+        if row == selected {
+            out.push_str(&format!("{} {}\n", "▶".green(), items[item].green().bold()));
+        } else {
+            out.push_str(&format!("  {}\n", items[item]));
+        }
+    }
+    out.push_str(&format!(
+        "{} {}  {}",
+        "pick>".cyan().bold(),
+        query,
+        format!("{}/{}", found.len(), items.len()).dimmed()
+    ));
+    print!("{}", out);
+    let _ = io::stdout().flush();
+    shown.len()
+}
+
+#[cfg(unix)]
+fn clear(drawn: usize) {
+    if drawn > 0 {
+        print!("\x1b[{}A", drawn);
+    }
+    print!("\r\x1b[J");
+    let _ = io::stdout().flush();
+}
+
+/// Fallback for terminals without raw mode
+fn line_based(items: &[String], query: &str) -> Option<usize> {
+    let query = match query {
+        "" => read_line("Search: ")?,
+        query => query.to_string(),
+    };
+    let found = matches(items, &query);
+    if found.is_empty() {
+        println!("{}: Nothing matches `{}`", "Info".blue(), query);
+        return None;
+    }
+    for (row, &item) in found.iter().take(SHOWN).enumerate() {
+        println!("  {:<3} {}", row + 1, items[item]);
+    }
+    let choice = read_line("Number (Enter for 1): ")?;
+    let row = if choice.is_empty() {
+        1
+    } else {
+        choice.parse::<usize>().ok()?
+    };
+    found.get(row.checked_sub(1)?).copied()
+}
+
+fn read_line(prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).ok()?;
+    Some(line.trim().to_string())
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::mem::MaybeUninit;
+
+    /// A key the picker reacts to
+    pub enum Key {
+        Char(char),
+        Backspace,
+        Up,
+        Down,
+        Enter,
+        Escape,
+        Cancel, // Ctrl+C
+    }
+
+    /// Terminal in raw mode, restored when dropped
+    pub struct Raw {
+        original: libc::termios,
+    }
+
+    impl Raw {
+        /// Switches stdin to raw mode; None when it isn't a terminal
+        pub fn enter() -> Option<Self> {
+            // SAFETY: isatty only inspects the descriptor
+            if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+                return None;
+            }
+            let mut original = MaybeUninit::uninit();
+            // SAFETY: tcgetattr fills the termios it is given on success
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) } != 0 {
+                return None;
+            }
+            // SAFETY: initialised by the successful tcgetattr above
+            let original = unsafe { original.assume_init() };
+            let mut raw = original;
+            // Ctrl+C arrives as a key instead of a signal
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            // Wait at most 0.1 s for a byte, so a lone Esc isn't stuck
+            // waiting for the rest of an arrow key sequence
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 1;
+            // SAFETY: `raw` is a valid termios derived from the current one
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+                return None;
+            }
+            Some(Self { original })
+        }
+
+        /// Waits for the next key; None if stdin closed
+        pub fn key(&self) -> Option<Key> {
+            loop {
+                let Some(byte) = read_byte()? else {
+                    continue;
+                };
+                let key = match byte {
+                    b'\r' | b'\n' => Key::Enter,
+                    3 => Key::Cancel,
+                    8 | 127 => Key::Backspace,
+                    27 => match (read_byte()?, read_byte()?) {
+                        (Some(b'['), Some(b'A')) => Key::Up,
+                        (Some(b'['), Some(b'B')) => Key::Down,
+                        (None, _) => Key::Escape,
+                        _ => continue,
+                    },
+                    byte if byte >= 0x80 => match read_utf8(byte)? {
+                        Some(c) => Key::Char(c),
+                        None => continue,
+                    },
+                    byte if byte >= 0x20 => Key::Char(byte as char),
+                    _ => continue,
+                };
+                return Some(key);
+            }
+        }
+    }
+
+    impl Drop for Raw {
+        fn drop(&mut self) {
+            // SAFETY: restores the settings read when entering raw mode
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+            }
+        }
+    }
+
+    /// Next byte of input: Some(None) on timeout, None once stdin closed
+    fn read_byte() -> Option<Option<u8>> {
+        let mut byte = 0u8;
+        // SAFETY: reads at most one byte into `byte`
+        let read = unsafe { libc::read(libc::STDIN_FILENO, &mut byte as *mut u8 as *mut _, 1) };
+        match read {
+            1 => Some(Some(byte)),
+            0 => Some(None),
+            _ => None,
+        }
+    }
+
+    /// Rest of a multi-byte UTF-8 character
+    fn read_utf8(first: u8) -> Option<Option<char>> {
+        let len = match first {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Some(None),
+        };
+        let mut bytes = vec![first];
+        for _ in 1..len {
+            match read_byte()? {
+                Some(byte) => bytes.push(byte),
+                None => return Some(None),
+            }
+        }
+        Some(
+            std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next()),
+        )
+    }
+}
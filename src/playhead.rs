@@ -0,0 +1,107 @@
+//! Where playback is in the current track, shared by status, resuming,
+//! syncing and seeking
+//! The output's sample count is used when there is one; otherwise play
+//! time is added up between pauses, so a paused track stays put
+
+use crate::follow::Clock;
+use std::time::{Duration, Instant};
+
+/// Position of the track being played
+pub struct Playhead {
+    clock: Option<Clock>,     // Samples handed to the output, when counted
+    offset: Duration,         // Where in the track playback started
+    played: Duration,         // Play time up to the last pause
+    resumed: Option<Instant>, // When playback last started, None while paused
+    speed: f64,               // Track time per second of play time
+}
+
+impl Playhead {
+    /// Starts counting from `offset`, by `clock` when the output has one
+    pub fn start(offset: Duration, clock: Option<Clock>) -> Self {
+        Self {
+            clock,
+            offset,
+            played: Duration::ZERO,
+            resumed: Some(Instant::now()),
+            speed: 1.0,
+        }
+    }
+
+    pub fn position(&self) -> Duration {
+        if let Some(clock) = &self.clock {
+            return clock.position();
+        }
+        let running = self.resumed.map_or(Duration::ZERO, |since| since.elapsed());
+        self.offset + self.played + running.mul_f64(self.speed)
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        match (paused, self.resumed) {
+            (true, Some(since)) => {
+                self.played += since.elapsed().mul_f64(self.speed);
+                self.resumed = None;
+            }
+            (false, None) => self.resumed = Some(Instant::now()),
+            _ => {}
+        }
+    }
+
+    /// Counts play time at a new speed from now on; only mpv needs it, as
+    /// a clock counts track samples anyway
+    #[cfg(feature = "mpv")]
+    pub fn set_speed(&mut self, speed: f64) {
+        if let Some(since) = self.resumed {
+            self.played += since.elapsed().mul_f64(self.speed);
+            self.resumed = Some(Instant::now());
+        }
+        self.speed = speed;
+    }
+}
+
+/// Reads a position written as `m:ss`, `h:mm:ss` or plain seconds
+/// Only the first field may reach 60, so `1:99` is a typo, not 2:39
+pub fn parse_time(text: &str) -> Option<Duration> {
+    let parts: Vec<&str> = text.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0u64;
+    for (i, part) in parts.iter().enumerate() {
+        let value: u64 = part.parse().ok()?;
+        if i > 0 && value >= 60 {
+            return None;
+        }
+        seconds = seconds.checked_mul(60)?.checked_add(value)?;
+    }
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_layout() {
+        assert_eq!(parse_time("75"), Some(Duration::from_secs(75)));
+        assert_eq!(parse_time("1:05"), Some(Duration::from_secs(65)));
+        assert_eq!(parse_time("1:02:03"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_time(" 90:00 "), Some(Duration::from_secs(5400)));
+    }
+
+    #[test]
+    fn rejects_out_of_range_fields() {
+        assert_eq!(parse_time("1:99"), None);
+        assert_eq!(parse_time("1:60"), None);
+        assert_eq!(parse_time("1:60:00"), None);
+        assert_eq!(parse_time("1:00:60"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_text() {
+        assert_eq!(parse_time(""), None);
+        assert_eq!(parse_time("1:"), None);
+        assert_eq!(parse_time("-5"), None);
+        assert_eq!(parse_time("1:2:3:4"), None);
+        assert_eq!(parse_time("a:00"), None);
+    }
+}
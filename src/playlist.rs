@@ -0,0 +1,644 @@
+//! Named playlists stored as M3U files in the config directory
+//! A name like `road-trip/summer` puts the playlist in a `road-trip`
+//! folder, so related lists can be grouped
+//! Playlists can be imported from and exported to M3U, PLS and XSPF
+//! A smart playlist stores labels instead of tracks and holds whatever
+//! tracks carry all of them when it is loaded
+
+use crate::{config, labels};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Directory for playlists inside the config directory
+const PLAYLIST_DIR: &str = "playlists";
+
+/// Extension of stored playlist files
+const EXTENSION: &str = "m3u";
+
+/// Line holding a smart playlist's labels
+const SMART_PREFIX: &str = "#SMART:";
+
+/// A playlist and its tracks, in play order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Playlist {
+    pub name: String,
+    pub tracks: Vec<Track>,
+    pub labels: Vec<String>, // Labels a smart playlist's tracks carry
+}
+
+/// A playlist entry with whatever metadata the playlist file carries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Track {
+    pub path: PathBuf,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub duration: Option<u64>, // Seconds
+}
+
+/// Playlist file formats
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    M3u,  // Extended M3U, written as UTF-8
+    Pls,  // Winamp/Shoutcast INI style
+    Xspf, // XML Shareable Playlist Format
+}
+
+impl Track {
+    /// Entry for a file, guessing artist and title from an
+    /// "Artist - Title" style file name
+    pub fn from_path(path: PathBuf) -> Self {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (artist, title) = match stem.split_once(" - ") {
+            Some((artist, title)) => (Some(artist.trim().to_string()), title.trim().to_string()),
+            None => (None, stem),
+        };
+        Self {
+            path,
+            artist,
+            title: (!title.is_empty()).then_some(title),
+            duration: None,
+        }
+    }
+
+    /// "Artist - Title", or whichever of the two is known
+    fn display_name(&self) -> Option<String> {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+            (None, Some(title)) => Some(title.clone()),
+            (Some(artist), None) => Some(artist.clone()),
+            (None, None) => None,
+        }
+    }
+
+    /// Splits an "Artist - Title" display name back up
+    fn set_display_name(&mut self, name: &str) {
+        match name.split_once(" - ") {
+            Some((artist, title)) => {
+                self.artist = Some(artist.trim().to_string());
+                self.title = Some(title.trim().to_string());
+            }
+            None => self.title = Some(name.trim().to_string()),
+        }
+    }
+}
+
+impl Format {
+    /// Format for a file extension
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(Format::M3u),
+            "pls" => Some(Format::Pls),
+            "xspf" => Some(Format::Xspf),
+            _ => None,
+        }
+    }
+
+    /// Format of playlist text, going by the extension and then by content
+    pub fn detect(path: &Path, text: &str) -> Self {
+        if let Some(format) = Self::from_extension(path) {
+            return format;
+        }
+        let start = text.trim_start_matches('\u{feff}').trim_start();
+        if start.to_lowercase().starts_with("[playlist]") {
+            Format::Pls
+        } else if start.starts_with("<?xml") || start.contains("<playlist") {
+            Format::Xspf
+        } else {
+            Format::M3u
+        }
+    }
+}
+
+impl Playlist {
+    /// Creates an empty playlist, failing if the name is taken
+    pub fn create(name: &str) -> io::Result<Self> {
+        let path = playlist_path(name)?;
+        if path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Playlist `{}` already exists", name),
+            ));
+        }
+        let playlist = Self {
+            name: name.to_string(),
+            ..Self::default()
+        };
+        playlist.save()?;
+        Ok(playlist)
+    }
+
+    /// Creates a smart playlist of the tracks carrying all of `labels`
+    pub fn create_smart(name: &str, labels: Vec<String>) -> io::Result<Self> {
+        let path = playlist_path(name)?;
+        if path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Playlist `{}` already exists", name),
+            ));
+        }
+        let playlist = Self {
+            name: name.to_string(),
+            tracks: Vec::new(),
+            labels,
+        };
+        playlist.save()?;
+        Self::load(name)
+    }
+
+    pub fn is_smart(&self) -> bool {
+        !self.labels.is_empty()
+    }
+
+    /// Reads a stored playlist
+    pub fn load(name: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(playlist_path(name)?)
+            .map_err(|e| io::Error::new(e.kind(), format!("No playlist named `{}`", name)))?;
+        let labels: Vec<String> = text
+            .lines()
+            .find_map(|line| line.strip_prefix(SMART_PREFIX))
+            .map(|labels| {
+                labels
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|label| !label.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let tracks = if labels.is_empty() {
+            parse(Format::M3u, &text, None)
+        } else {
+            labels::Labels::load()?
+                .tracks_with(&labels)
+                .into_iter()
+                .map(Track::from_path)
+                .collect()
+        };
+        Ok(Self {
+            name: name.to_string(),
+            tracks,
+            labels,
+        })
+    }
+
+    /// Reads a playlist file in any supported format, resolving relative
+    /// paths against the file's folder
+    pub fn import(name: &str, file: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(file)?;
+        let format = Format::detect(file, &text);
+        Ok(Self {
+            name: name.to_string(),
+            tracks: parse(format, &text, file.parent()),
+            labels: Vec::new(),
+        })
+    }
+
+    /// Writes the playlist to a file, in the format its extension names
+    /// (M3U when it has none)
+    /// With `relative`, paths are written relative to the file's folder
+    /// with `/` separators, so the list works wherever the music is copied
+    pub fn export(&self, file: &Path, relative: bool) -> io::Result<()> {
+        let format = Format::from_extension(file).unwrap_or(Format::M3u);
+        let mut tracks = self.tracks.clone();
+        for track in &mut tracks {
+            if track.duration.is_none() {
+                track.duration = probe_duration(&track.path);
+            }
+        }
+        if relative {
+            let base = std::path::absolute(file)?
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            for track in &mut tracks {
+                let path = std::path::absolute(&track.path)?;
+                let relative = relative_path(&path, &base).unwrap_or(path);
+                track.path = PathBuf::from(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        fs::write(file, write(format, &tracks))
+    }
+
+    /// Writes the playlist, creating its folder if needed
+    pub fn save(&self) -> io::Result<()> {
+        let path = playlist_path(&self.name)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if self.is_smart() {
+            return fs::write(
+                path,
+                format!("#EXTM3U\n{}{}\n", SMART_PREFIX, self.labels.join(",")),
+            );
+        }
+        fs::write(path, write(Format::M3u, &self.tracks))
+    }
+
+    /// Deletes a stored playlist, and its folder once empty
+    pub fn delete(name: &str) -> io::Result<()> {
+        let path = playlist_path(name)?;
+        fs::remove_file(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("No playlist named `{}`", name)))?;
+        remove_empty_folders(&path);
+        Ok(())
+    }
+
+    /// Renames a playlist, which may also move it to another folder
+    pub fn rename(old: &str, new: &str) -> io::Result<()> {
+        let from = playlist_path(old)?;
+        let to = playlist_path(new)?;
+        if !from.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No playlist named `{}`", old),
+            ));
+        }
+        if to.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Playlist `{}` already exists", new),
+            ));
+        }
+        if let Some(dir) = to.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::rename(&from, &to)?;
+        remove_empty_folders(&from);
+        Ok(())
+    }
+}
+
+/// Points stored playlists at the new paths of moved tracks
+/// Returns how many playlists changed
+pub fn rename_tracks(moves: &HashMap<PathBuf, PathBuf>) -> io::Result<usize> {
+    let mut changed = 0;
+    for name in list() {
+        let mut playlist = Playlist::load(&name)?;
+        // Labels follow moved tracks by themselves
+        if playlist.is_smart() {
+            continue;
+        }
+        let mut touched = false;
+        for track in &mut playlist.tracks {
+            if let Some(to) = moves.get(&track.path) {
+                track.path = to.clone();
+                touched = true;
+            }
+        }
+        if touched {
+            playlist.save()?;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+/// Names of every stored playlist, sorted so folders group together
+pub fn list() -> Vec<String> {
+    let Some(root) = playlist_root() else {
+        return Vec::new();
+    };
+    let mut names = Vec::new();
+    collect(&root, &root, &mut names);
+    names.sort();
+    names
+}
+
+fn collect(root: &Path, dir: &Path, names: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect(root, &path, names);
+        } else if path.extension().is_some_and(|ext| ext == EXTENSION) {
+            if let Ok(relative) = path.with_extension("").strip_prefix(root) {
+                let parts: Vec<_> = relative
+                    .components()
+                    .map(|part| part.as_os_str().to_string_lossy())
+                    .collect();
+                names.push(parts.join("/"));
+            }
+        }
+    }
+}
+
+/// Tracks of playlist text; relative paths are joined to `base`
+pub fn parse(format: Format, text: &str, base: Option<&Path>) -> Vec<Track> {
+    let mut tracks = match format {
+        Format::M3u => parse_m3u(text),
+        Format::Pls => parse_pls(text),
+        Format::Xspf => parse_xspf(text),
+    };
+    if let Some(base) = base {
+        for track in &mut tracks {
+            if track.path.is_relative() {
+                track.path = base.join(&track.path);
+            }
+        }
+    }
+    tracks
+}
+
+/// Formats tracks as playlist text
+pub fn write(format: Format, tracks: &[Track]) -> String {
+    match format {
+        Format::M3u => write_m3u(tracks),
+        Format::Pls => write_pls(tracks),
+        Format::Xspf => write_xspf(tracks),
+    }
+}
+
+fn parse_m3u(text: &str) -> Vec<Track> {
+    let mut tracks = Vec::new();
+    let mut info: Option<(Option<u64>, String)> = None;
+    for line in text
+        .lines()
+        .map(|line| line.trim_start_matches('\u{feff}').trim())
+    {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            info = extinf.split_once(',').map(|(duration, name)| {
+                let seconds = duration.trim().parse::<i64>().ok();
+                (
+                    seconds.filter(|s| *s >= 0).map(|s| s as u64),
+                    name.to_string(),
+                )
+            });
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let mut track = Track::from_path(PathBuf::from(line));
+            if let Some((duration, name)) = info.take() {
+                track.duration = duration;
+                track.set_display_name(&name);
+            }
+            tracks.push(track);
+        }
+    }
+    tracks
+}
+
+fn write_m3u(tracks: &[Track]) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for track in tracks {
+        if let Some(name) = track.display_name() {
+            let duration = track.duration.map_or(-1, |d| d as i64);
+            text.push_str(&format!("#EXTINF:{},{}\n", duration, name));
+        }
+        text.push_str(&track.path.to_string_lossy());
+        text.push('\n');
+    }
+    text
+}
+
+fn parse_pls(text: &str) -> Vec<Track> {
+    let mut entries: BTreeMap<u32, Track> = BTreeMap::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        let field = ["file", "title", "length"]
+            .into_iter()
+            .find_map(|field| Some((field, key.strip_prefix(field)?.parse::<u32>().ok()?)));
+        match field {
+            Some(("file", number)) => {
+                let track = entries.entry(number).or_default();
+                let guessed = Track::from_path(PathBuf::from(value));
+                track.path = guessed.path;
+                if track.title.is_none() {
+                    track.artist = guessed.artist;
+                    track.title = guessed.title;
+                }
+            }
+            Some(("title", number)) => entries.entry(number).or_default().set_display_name(value),
+            Some(("length", number)) => {
+                entries.entry(number).or_default().duration = value.parse::<u64>().ok();
+            }
+            _ => {}
+        }
+    }
+    entries
+        .into_values()
+        .filter(|track| !track.path.as_os_str().is_empty())
+        .collect()
+}
+
+fn write_pls(tracks: &[Track]) -> String {
+    let mut text = String::from("[playlist]\n");
+    for (index, track) in tracks.iter().enumerate() {
+        let number = index + 1;
+        text.push_str(&format!(
+            "File{}={}\n",
+            number,
+            track.path.to_string_lossy()
+        ));
+        if let Some(name) = track.display_name() {
+            text.push_str(&format!("Title{}={}\n", number, name));
+        }
+        let length = track.duration.map_or(-1, |d| d as i64);
+        text.push_str(&format!("Length{}={}\n", number, length));
+    }
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", tracks.len()));
+    text
+}
+
+fn parse_xspf(text: &str) -> Vec<Track> {
+    let mut tracks = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("<track>") {
+        let after = &rest[start + "<track>".len()..];
+        let end = after.find("</track>").unwrap_or(after.len());
+        let body = &after[..end];
+        rest = &after[end..];
+
+        let Some(location) = xml_element(body, "location") else {
+            continue;
+        };
+        let path = location.strip_prefix("file://").map_or_else(
+            || PathBuf::from(&location),
+            |path| PathBuf::from(percent_decode(path)),
+        );
+        let mut track = Track::from_path(path);
+        if let Some(title) = xml_element(body, "title") {
+            track.title = Some(title);
+        }
+        if let Some(creator) = xml_element(body, "creator") {
+            track.artist = Some(creator);
+        }
+        track.duration = xml_element(body, "duration")
+            .and_then(|ms| ms.parse::<u64>().ok())
+            .map(|ms| ms / 1000);
+        tracks.push(track);
+    }
+    tracks
+}
+
+fn write_xspf(tracks: &[Track]) -> String {
+    let mut text = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for track in tracks {
+        text.push_str("    <track>\n");
+        let path = track.path.to_string_lossy();
+        let location = if track.path.is_absolute() {
+            format!("file://{}", percent_encode(&path))
+        } else {
+            percent_encode(&path)
+        };
+        text.push_str(&format!(
+            "      <location>{}</location>\n",
+            xml_escape(&location)
+        ));
+        if let Some(title) = &track.title {
+            text.push_str(&format!("      <title>{}</title>\n", xml_escape(title)));
+        }
+        if let Some(artist) = &track.artist {
+            text.push_str(&format!(
+                "      <creator>{}</creator>\n",
+                xml_escape(artist)
+            ));
+        }
+        if let Some(duration) = track.duration {
+            text.push_str(&format!("      <duration>{}</duration>\n", duration * 1000));
+        }
+        text.push_str("    </track>\n");
+    }
+    text.push_str("  </trackList>\n</playlist>\n");
+    text
+}
+
+/// Unescaped text of the first `<name>` element in an XML fragment
+pub fn xml_element(body: &str, name: &str) -> Option<String> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = body.find(&open)? + open.len();
+    let end = body[start..].find(&close)? + start;
+    Some(xml_unescape(body[start..end].trim()))
+}
+
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Escapes a path for a `file://` URI, keeping `/` as is
+pub fn percent_encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Decodes `%XX` escapes in a URI path
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Track length in whole seconds, when the decoder can tell without
+/// decoding the file
+fn probe_duration(path: &Path) -> Option<u64> {
+    crate::codecs::length(path).map(|duration| duration.as_secs())
+}
+
+/// `path` relative to the directory `base`, or None when they share no
+/// root (e.g. different Windows drives)
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    if path.first() != base.first() {
+        return None;
+    }
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for part in &path[common..] {
+        relative.push(part);
+    }
+    Some(relative)
+}
+
+fn playlist_root() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join(PLAYLIST_DIR))
+}
+
+/// File for a playlist name, refusing names that would leave the
+/// playlist directory
+fn playlist_path(name: &str) -> io::Result<PathBuf> {
+    let relative = Path::new(name);
+    let valid = !name.is_empty()
+        && relative
+            .components()
+            .all(|part| matches!(part, Component::Normal(_)));
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid playlist name `{}`", name),
+        ));
+    }
+    let mut file = relative.as_os_str().to_owned();
+    file.push(".");
+    file.push(EXTENSION);
+    playlist_root()
+        .map(|root| root.join(file))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory"))
+}
+
+/// Removes folders left empty by a delete or rename, up to the root
+fn remove_empty_folders(file: &Path) {
+    let Some(root) = playlist_root() else {
+        return;
+    };
+    let mut dir = file.parent();
+    while let Some(current) = dir {
+        if current == root || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
@@ -0,0 +1,111 @@
+//! Minimal XML property list reader
+//! Parses the plists iTunes and Music.app export into `Json` values:
+//! dicts become objects, and dates and data stay strings
+
+use crate::json::Json;
+
+/// Parses an XML plist document into its top-level value
+pub fn parse(text: &str) -> Result<Json, String> {
+    let start = text
+        .find("<plist")
+        .and_then(|start| text[start..].find('>').map(|end| start + end + 1))
+        .ok_or("not a property list")?;
+    let mut reader = Reader {
+        text,
+        position: start,
+    };
+    reader.value()
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Reader<'_> {
+    /// Next tag name, e.g. `dict`, `/dict` or `true/`, skipping text,
+    /// comments and processing instructions in between
+    fn tag(&mut self) -> Result<&str, String> {
+        loop {
+            let rest = &self.text[self.position..];
+            let start = rest.find('<').ok_or("unexpected end of document")?;
+            let end = rest[start..].find('>').ok_or("unterminated tag")? + start;
+            self.position += end + 1;
+            let tag = rest[start + 1..end].trim();
+            if !tag.starts_with('!') && !tag.starts_with('?') {
+                // Attributes aren't used by property lists
+                return Ok(tag.split_whitespace().next().unwrap_or(tag));
+            }
+        }
+    }
+
+    /// Text up to the closing tag of an element
+    fn text_until(&mut self, name: &str) -> Result<String, String> {
+        let close = format!("</{}>", name);
+        let rest = &self.text[self.position..];
+        let end = rest
+            .find(&close)
+            .ok_or_else(|| format!("missing {}", close))?;
+        self.position += end + close.len();
+        Ok(unescape(&rest[..end]))
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        let tag = self.tag()?.to_string();
+        self.value_for(&tag)
+    }
+
+    fn value_for(&mut self, tag: &str) -> Result<Json, String> {
+        match tag {
+            "dict" => {
+                let mut members = Vec::new();
+                loop {
+                    match self.tag()? {
+                        "/dict" => break,
+                        "key" => {
+                            let key = self.text_until("key")?;
+                            members.push((key, self.value()?));
+                        }
+                        other => return Err(format!("unexpected <{}> in dict", other)),
+                    }
+                }
+                Ok(Json::Object(members))
+            }
+            "array" => {
+                let mut items = Vec::new();
+                loop {
+                    let tag = self.tag()?.to_string();
+                    if tag == "/array" {
+                        break;
+                    }
+                    items.push(self.value_for(&tag)?);
+                }
+                Ok(Json::Array(items))
+            }
+            "dict/" => Ok(Json::Object(Vec::new())),
+            "array/" => Ok(Json::Array(Vec::new())),
+            "true/" => Ok(Json::Bool(true)),
+            "false/" => Ok(Json::Bool(false)),
+            "string/" => Ok(Json::String(String::new())),
+            "integer" | "real" => {
+                let number = self.text_until(tag)?;
+                number
+                    .trim()
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("bad number `{}`", number))
+            }
+            "string" | "date" | "data" => self.text_until(tag).map(Json::String),
+            other => Err(format!("unexpected <{}>", other)),
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#38;", "&")
+        .replace("&amp;", "&")
+}
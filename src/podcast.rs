@@ -0,0 +1,478 @@
+//! Podcast subscriptions and episode downloads
+//! Feeds are kept in `podcasts` beside the config file, one `url<TAB>title`
+//! line each. Episodes download into `dir` under `[podcast]`
+//! (`~/.local/share/musicplayer/podcasts` by default), one folder per feed,
+//! `parallel_downloads` at a time (2 by default). A download that stops
+//! halfway stays as a `.part` file and picks up from there next time
+//! Listened episodes older than `keep_days` (7 by default, 0 keeps them
+//! all) are removed at startup
+
+use crate::{config, config::Config, files, playlist, session, stats::Stats};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Name of the subscriptions file inside the config directory
+const FEEDS_FILE: &str = "podcasts";
+
+/// Name of the file listing episodes already offered, inside the state
+/// directory; `all-new` skips them
+const SEEN_FILE: &str = "podcast-seen";
+
+/// A subscribed feed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feed {
+    pub url: String,   // RSS address
+    pub title: String, // Channel title, also the folder name
+}
+
+/// An episode as the feed lists it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Episode {
+    pub feed: String,        // Title of the feed it belongs to
+    pub title: String,       // Episode title
+    pub url: String,         // Enclosure address
+    pub length: Option<u64>, // Size the feed gives, in bytes
+    pub path: PathBuf,       // Where the download goes
+}
+
+/// Where and how episodes are kept
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub dir: PathBuf,    // Folder holding one folder per feed
+    pub parallel: usize, // Downloads running at once
+    pub keep_days: u64,  // Age at which listened episodes go, 0 for never
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Self {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let dir = match config.get_str("podcast.dir") {
+            Some(dir) => match (dir.strip_prefix("~/"), &home) {
+                (Some(rest), Some(home)) => home.join(rest),
+                _ => PathBuf::from(dir),
+            },
+            None => env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| home.map(|home| home.join(".local/share")))
+                .unwrap_or_default()
+                .join("musicplayer")
+                .join("podcasts"),
+        };
+        Self {
+            dir,
+            parallel: config
+                .get_f64("podcast.parallel_downloads")
+                .filter(|&n| n >= 1.0)
+                .map_or(2, |n| n as usize),
+            keep_days: config
+                .get_f64("podcast.keep_days")
+                .filter(|&days| days >= 0.0)
+                .map_or(7, |days| days as u64),
+        }
+    }
+
+    /// Folder of a feed's episodes
+    pub fn feed_dir(&self, feed: &str) -> PathBuf {
+        self.dir.join(files::sanitize(feed))
+    }
+}
+
+/// Reads the subscriptions, which may not exist yet
+pub fn load_feeds() -> io::Result<Vec<Feed>> {
+    let Some(path) = feeds_path() else {
+        return Ok(Vec::new());
+    };
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (url, title) = line.split_once('\t').unwrap_or((line, line));
+            Feed {
+                url: url.to_string(),
+                title: title.to_string(),
+            }
+        })
+        .collect())
+}
+
+pub fn save_feeds(feeds: &[Feed]) -> io::Result<()> {
+    let path = feeds_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text: String = feeds
+        .iter()
+        .map(|feed| format!("{}\t{}\n", feed.url, feed.title))
+        .collect();
+    fs::write(path, text)
+}
+
+/// Feeds in an OPML file from another podcast app; outlines nested in
+/// folders count too
+pub fn parse_opml(text: &str) -> Vec<Feed> {
+    text.split("<outline")
+        .skip(1)
+        .filter_map(|outline| {
+            let tag = &outline[..outline.find('>')?];
+            let url = attribute(tag, "xmlUrl")?;
+            let title = attribute(tag, "title")
+                .or_else(|| attribute(tag, "text"))
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or_else(|| url.clone());
+            Some(Feed { url, title })
+        })
+        .collect()
+}
+
+/// The subscriptions as an OPML 2.0 file
+pub fn write_opml(feeds: &[Feed]) -> String {
+    let mut text = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n  <head>\n    <title>musicplayer podcasts</title>\n  </head>\n  <body>\n",
+    );
+    for feed in feeds {
+        let title = playlist::xml_escape(&feed.title);
+        text.push_str(&format!(
+            "    <outline type=\"rss\" text=\"{}\" title=\"{}\" xmlUrl=\"{}\"/>\n",
+            title,
+            title,
+            playlist::xml_escape(&feed.url)
+        ));
+    }
+    text.push_str("  </body>\n</opml>\n");
+    text
+}
+
+fn feeds_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join(FEEDS_FILE))
+}
+
+/// Downloads a feed, returning its title and episodes, newest first as
+/// feeds list them
+pub fn fetch(url: &str, settings: &Settings) -> io::Result<(String, Vec<Episode>)> {
+    let output = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--location",
+            "--",
+            url,
+        ])
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("{}: {}", url, message.trim())));
+    }
+    let xml = String::from_utf8_lossy(&output.stdout);
+    let (channel, _) = xml.split_once("<item").unwrap_or((&xml, ""));
+    let title = playlist::xml_element(channel, "title")
+        .map(|title| strip_cdata(&title))
+        .filter(|title| !title.is_empty())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} isn't a podcast feed", url),
+            )
+        })?;
+    let dir = settings.feed_dir(&title);
+    let mut used = HashMap::new();
+    let items: Vec<&str> = xml.split("<item").skip(1).collect();
+    let mut episodes: Vec<Episode> = items
+        .into_iter()
+        .rev()
+        .filter_map(|item| {
+            let item = item.split("</item>").next()?;
+            let enclosure = &item[item.find("<enclosure")?..];
+            let enclosure = &enclosure[..enclosure.find('>')?];
+            let url = attribute(enclosure, "url")?;
+            let episode_title = playlist::xml_element(item, "title")
+                .map(|title| strip_cdata(&title))
+                .unwrap_or_else(|| url.clone());
+            let path_part = url.split(['?', '#']).next().unwrap_or("");
+            let ext = Path::new(path_part)
+                .extension()
+                .map_or("mp3".to_string(), |ext| {
+                    ext.to_string_lossy().to_lowercase()
+                });
+            // Episodes sharing a title get a number, the oldest keeping the
+            // plain name, so names stay put as the feed grows
+            let name = files::sanitize(&episode_title);
+            let count = used.entry(name.clone()).or_insert(0);
+            *count += 1;
+            let path = dir.join(match *count {
+                1 => format!("{}.{}", name, ext),
+                n => format!("{} ({}).{}", name, n, ext),
+            });
+            Some(Episode {
+                feed: title.clone(),
+                title: episode_title,
+                length: attribute(enclosure, "length")
+                    .and_then(|length| length.parse().ok())
+                    .filter(|&length| length > 0),
+                url,
+                path,
+            })
+        })
+        .collect();
+    episodes.reverse();
+    Ok((title, episodes))
+}
+
+/// Value of an XML attribute in a start tag
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!("{}=", name);
+    let at = tag
+        .match_indices(&pattern)
+        .map(|(at, _)| at)
+        .find(|&at| tag[..at].ends_with(char::is_whitespace))?
+        + pattern.len();
+    let quote = tag[at..]
+        .chars()
+        .next()
+        .filter(|c| *c == '"' || *c == '\'')?;
+    let value = &tag[at + 1..];
+    Some(playlist::xml_unescape(&value[..value.find(quote)?]))
+}
+
+fn strip_cdata(text: &str) -> String {
+    text.trim()
+        .strip_prefix("<![CDATA[")
+        .and_then(|text| text.strip_suffix("]]>"))
+        .unwrap_or(text)
+        .trim()
+        .to_string()
+}
+
+/// Episode addresses `all-new` has already offered
+pub fn seen() -> HashSet<String> {
+    session::state_dir()
+        .and_then(|dir| fs::read_to_string(dir.join(SEEN_FILE)).ok())
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Remembers episodes so `all-new` doesn't offer them again
+pub fn mark_seen(urls: &[String]) -> io::Result<()> {
+    let dir = session::state_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+    fs::create_dir_all(&dir)?;
+    let mut all = seen();
+    all.extend(urls.iter().cloned());
+    let mut lines: Vec<String> = all.into_iter().collect();
+    lines.sort();
+    fs::write(dir.join(SEEN_FILE), lines.join("\n") + "\n")
+}
+
+/// Removes downloaded episodes that have been played and are older than
+/// `keep_days`, returning how many went
+pub fn clean_up(settings: &Settings, stats: &Stats) -> io::Result<usize> {
+    if settings.keep_days == 0 {
+        return Ok(0);
+    }
+    let Ok(feeds) = fs::read_dir(&settings.dir) else {
+        return Ok(0);
+    };
+    let cutoff = SystemTime::now() - Duration::from_secs(settings.keep_days * 24 * 60 * 60);
+    let mut removed = 0;
+    for feed in feeds {
+        let feed = feed?;
+        if !feed.file_type()?.is_dir() {
+            continue;
+        }
+        for episode in fs::read_dir(feed.path())? {
+            let path = episode?.path();
+            let listened = fs::canonicalize(&path)
+                .ok()
+                .and_then(|path| stats.get(&path))
+                .is_some_and(|stats| stats.plays > 0);
+            let old = fs::metadata(&path)?.modified()? < cutoff;
+            if listened && old && path.is_file() {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// How far a download has got
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed(String),
+}
+
+/// An episode in the download queue
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub episode: Episode,
+    pub state: JobState,
+}
+
+impl Job {
+    /// Bytes on disk so far
+    pub fn downloaded(&self) -> u64 {
+        let path = match self.state {
+            JobState::Done => self.episode.path.clone(),
+            _ => part_path(&self.episode.path),
+        };
+        fs::metadata(path).map_or(0, |metadata| metadata.len())
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: Vec<Job>,
+    workers: usize,                        // Threads taking jobs off the queue
+    finished: Vec<Result<String, String>>, // Reports not yet shown at the prompt
+}
+
+/// Episode downloads, run in the background a few at a time
+#[derive(Clone, Default)]
+pub struct Downloads {
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl Downloads {
+    /// Queues an episode unless it is downloaded or queued already; true
+    /// when it was added
+    pub fn enqueue(&self, episode: Episode, parallel: usize) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let waiting = queue.jobs.iter().any(|job| {
+            job.episode.url == episode.url
+                && matches!(job.state, JobState::Queued | JobState::Running)
+        });
+        if waiting || episode.path.is_file() {
+            return false;
+        }
+        queue.jobs.push(Job {
+            episode,
+            state: JobState::Queued,
+        });
+        if queue.workers < parallel {
+            queue.workers += 1;
+            let queue = Arc::clone(&self.queue);
+            thread::spawn(move || work(&queue));
+        }
+        true
+    }
+
+    /// Every download since startup, in the order they were queued
+    pub fn jobs(&self) -> Vec<Job> {
+        self.queue.lock().unwrap().jobs.clone()
+    }
+
+    /// Reports of downloads that finished or failed since the last call
+    pub fn take_finished(&self) -> Vec<Result<String, String>> {
+        std::mem::take(&mut self.queue.lock().unwrap().finished)
+    }
+}
+
+/// Takes jobs off the queue until none are left
+fn work(queue: &Mutex<Queue>) {
+    loop {
+        let episode = {
+            let mut queue = queue.lock().unwrap();
+            let Some(job) = queue
+                .jobs
+                .iter_mut()
+                .find(|job| job.state == JobState::Queued)
+            else {
+                queue.workers -= 1;
+                return;
+            };
+            job.state = JobState::Running;
+            job.episode.clone()
+        };
+        let result = download(&episode);
+        let mut queue = queue.lock().unwrap();
+        if let Some(job) = queue
+            .jobs
+            .iter_mut()
+            .find(|job| job.episode.url == episode.url && job.state == JobState::Running)
+        {
+            job.state = match &result {
+                Ok(()) => JobState::Done,
+                Err(e) => JobState::Failed(e.to_string()),
+            };
+        }
+        let report = match result {
+            Ok(()) => Ok(format!("Downloaded {}", episode.title)),
+            Err(e) => Err(format!("Could not download {}: {}", episode.title, e)),
+        };
+        queue.finished.push(report);
+    }
+}
+
+/// Partial download of an episode: `Episode.mp3.part`
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Downloads an episode, carrying on from its `.part` file if there is one
+fn download(episode: &Episode) -> io::Result<()> {
+    if let Some(dir) = episode.path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let part = part_path(&episode.path);
+    let have = fs::metadata(&part).map_or(0, |metadata| metadata.len());
+    if have == 0 || episode.length != Some(have) {
+        match curl(&episode.url, &part, have > 0)? {
+            Some(0) => {}
+            // The server can't resume, so start over
+            Some(33) if have > 0 => {
+                fs::remove_file(&part)?;
+                if let code @ (None | Some(1..)) = curl(&episode.url, &part, false)? {
+                    return Err(curl_failed(code));
+                }
+            }
+            code => return Err(curl_failed(code)),
+        }
+    }
+    fs::rename(&part, &episode.path)
+}
+
+/// Runs curl into `part`, returning its exit code
+fn curl(url: &str, part: &Path, resume: bool) -> io::Result<Option<i32>> {
+    let mut curl = Command::new("curl");
+    curl.args(["--silent", "--show-error", "--fail", "--location"]);
+    if resume {
+        curl.args(["--continue-at", "-"]);
+    }
+    let status = curl
+        .arg("--output")
+        .arg(part)
+        .arg("--")
+        .arg(url)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    Ok(status.code())
+}
+
+fn curl_failed(code: Option<i32>) -> io::Error {
+    io::Error::other(match code {
+        Some(22) => "the server refused the download".to_string(),
+        Some(6 | 7) => "couldn't reach the server".to_string(),
+        Some(code) => format!("curl exited with {}", code),
+        None => "curl was stopped".to_string(),
+    })
+}
@@ -0,0 +1,33 @@
+//! Suspend/resume detection
+//! The monotonic clock stands still while the machine sleeps but the wall
+//! clock keeps going, so a large gap between the two means we just woke up
+
+use crate::PlayerEvent;
+use std::{
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often the clocks are compared
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Wall-clock time beyond the poll interval that counts as a suspend;
+/// large enough to ignore ordinary NTP adjustments
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// Sends a `PlayerEvent::Woke` each time the system resumes from sleep
+pub fn spawn_wake_watcher(events: Sender<PlayerEvent>) {
+    thread::spawn(move || loop {
+        let wall = SystemTime::now();
+        let monotonic = Instant::now();
+        thread::sleep(POLL_INTERVAL);
+
+        let wall_elapsed = wall.elapsed().unwrap_or_default();
+        if wall_elapsed > monotonic.elapsed() + SLEEP_THRESHOLD
+            && events.send(PlayerEvent::Woke).is_err()
+        {
+            break;
+        }
+    });
+}
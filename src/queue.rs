@@ -0,0 +1,155 @@
+//! Tracks waiting to play after the current one, in order
+//! Filled by `queue add`, by `play` given several tracks and by the inbox
+//! and drop folder; the player takes the next one when a track ends
+
+use std::collections::VecDeque;
+
+/// Most tracks the queue holds, so a runaway script can't fill memory
+pub const MAX_LEN: usize = 10_000;
+
+#[derive(Default)]
+pub struct Queue {
+    tracks: VecDeque<i32>, // Track numbers, next first
+}
+
+impl Queue {
+    /// Adds tracks at the end, returning how many fit
+    pub fn add(&mut self, tracks: impl IntoIterator<Item = i32>) -> usize {
+        let before = self.tracks.len();
+        let room = MAX_LEN - before;
+        self.tracks.extend(tracks.into_iter().take(room));
+        self.tracks.len() - before
+    }
+
+    /// Puts tracks ahead of those already waiting, keeping their order
+    pub fn add_front(&mut self, tracks: impl IntoIterator<Item = i32>) {
+        let mut tracks: VecDeque<i32> = tracks.into_iter().collect();
+        tracks.append(&mut self.tracks);
+        tracks.truncate(MAX_LEN);
+        self.tracks = tracks;
+    }
+
+    /// Takes out the track at `position`, counting from 1
+    pub fn remove(&mut self, position: usize) -> Option<i32> {
+        self.tracks.remove(position.checked_sub(1)?)
+    }
+
+    /// Moves the track at `from` to `to`, both counting from 1
+    pub fn move_entry(&mut self, from: usize, to: usize) -> Option<i32> {
+        let track = self.remove(from)?;
+        let at = to.clamp(1, self.tracks.len() + 1) - 1;
+        self.tracks.insert(at, track);
+        Some(track)
+    }
+
+    /// Takes the track at `position` to play now, skipping those ahead of it
+    pub fn jump(&mut self, position: usize) -> Option<i32> {
+        let at = position
+            .checked_sub(1)
+            .filter(|at| *at < self.tracks.len())?;
+        self.tracks.drain(..at);
+        self.next()
+    }
+
+    /// Drops every waiting track that `keep` says no to, e.g. deleted ones
+    pub fn retain(&mut self, keep: impl FnMut(&i32) -> bool) {
+        self.tracks.retain(keep);
+    }
+
+    /// Takes the track to play next
+    pub fn next(&mut self) -> Option<i32> {
+        self.tracks.pop_front()
+    }
+
+    pub fn peek(&self) -> Option<i32> {
+        self.tracks.front().copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i32> + '_ {
+        self.tracks.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks(queue: &Queue) -> Vec<i32> {
+        queue.iter().collect()
+    }
+
+    #[test]
+    fn add_front_goes_ahead_in_order() {
+        let mut queue = Queue::default();
+        queue.add([1, 2]);
+        queue.add_front([7, 8]);
+        assert_eq!(tracks(&queue), vec![7, 8, 1, 2]);
+        assert_eq!(queue.next(), Some(7));
+        assert_eq!(queue.peek(), Some(8));
+    }
+
+    #[test]
+    fn remove_counts_from_one() {
+        let mut queue = Queue::default();
+        queue.add([4, 5, 6]);
+        assert_eq!(queue.remove(0), None);
+        assert_eq!(queue.remove(2), Some(5));
+        assert_eq!(queue.remove(3), None);
+        assert_eq!(tracks(&queue), vec![4, 6]);
+    }
+
+    #[test]
+    fn move_entry_reorders() {
+        let mut queue = Queue::default();
+        queue.add([1, 2, 3, 4]);
+        assert_eq!(queue.move_entry(4, 1), Some(4));
+        assert_eq!(tracks(&queue), vec![4, 1, 2, 3]);
+        assert_eq!(queue.move_entry(1, 3), Some(4));
+        assert_eq!(tracks(&queue), vec![1, 2, 4, 3]);
+        // Past the end goes last
+        assert_eq!(queue.move_entry(1, 99), Some(1));
+        assert_eq!(tracks(&queue), vec![2, 4, 3, 1]);
+        assert_eq!(queue.move_entry(5, 1), None);
+        assert_eq!(queue.move_entry(0, 1), None);
+        assert_eq!(tracks(&queue), vec![2, 4, 3, 1]);
+    }
+
+    #[test]
+    fn jump_skips_the_tracks_ahead() {
+        let mut queue = Queue::default();
+        queue.add([1, 2, 3, 4]);
+        assert_eq!(queue.jump(5), None);
+        assert_eq!(queue.jump(0), None);
+        assert_eq!(queue.jump(3), Some(3));
+        assert_eq!(tracks(&queue), vec![4]);
+        assert_eq!(queue.jump(1), Some(4));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn holds_at_most_max_len() {
+        let mut queue = Queue::default();
+        assert_eq!(queue.add(0..MAX_LEN as i32 - 1), MAX_LEN - 1);
+        assert_eq!(queue.add([-1, -2]), 1);
+        assert_eq!(queue.len(), MAX_LEN);
+        assert_eq!(queue.add([-3]), 0);
+
+        // Tracks put in front push the last ones out
+        queue.add_front([-4]);
+        assert_eq!(queue.len(), MAX_LEN);
+        assert_eq!(queue.peek(), Some(-4));
+        assert_eq!(queue.iter().last(), Some(MAX_LEN as i32 - 2));
+    }
+}
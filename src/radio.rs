@@ -0,0 +1,80 @@
+//! Autoplay radio: once playback would stop, picks the next track at random,
+//! favoring well-rated tracks and holding back the ones played lately
+
+use crate::stats::Stats;
+use std::{
+    collections::{hash_map::RandomState, VecDeque},
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+};
+
+/// How many recent plays are held back
+const RECENT: usize = 50;
+
+/// Stars assumed for a track nobody has rated
+const UNRATED: u8 = 3;
+
+/// Tracks played this session, newest first
+#[derive(Default)]
+pub struct Radio {
+    recent: VecDeque<PathBuf>,
+}
+
+impl Radio {
+    /// Notes a play, however it was started
+    pub fn played(&mut self, path: &Path) {
+        self.recent.retain(|recent| recent != path);
+        self.recent.push_front(path.to_path_buf());
+        self.recent.truncate(RECENT);
+    }
+
+    /// Picks one of `tracks`
+    /// A track's chance grows with the square of its stars and shrinks the
+    /// more recently it played
+    pub fn pick<'a>(
+        &self,
+        tracks: impl Iterator<Item = (i32, &'a Path)>,
+        stats: &Stats,
+    ) -> Option<i32> {
+        let weighted = tracks.map(|(index, path)| {
+            let stars = stats
+                .get(path)
+                .and_then(|stats| stats.rating)
+                .unwrap_or(UNRATED) as f64;
+            (index, stars * stars * self.recency(path))
+        });
+        pick_weighted(weighted.collect())
+    }
+
+    /// 0 for the track played last, rising to 1 for ones not played lately
+    pub fn recency(&self, path: &Path) -> f64 {
+        match self.recent.iter().position(|recent| recent == path) {
+            Some(age) => age as f64 / RECENT as f64,
+            None => 1.0,
+        }
+    }
+}
+
+/// Picks an index with a chance in proportion to its weight; if every
+/// weight is 0, e.g. the only track just played, the first is taken
+pub fn pick_weighted(weighted: Vec<(i32, f64)>) -> Option<i32> {
+    let total: f64 = weighted.iter().map(|(_, weight)| weight).sum();
+    if total <= 0.0 {
+        return weighted.first().map(|(index, _)| *index);
+    }
+    let mut point = random() * total;
+    for &(index, weight) in &weighted {
+        if point < weight {
+            return Some(index);
+        }
+        point -= weight;
+    }
+    weighted.last().map(|(index, _)| *index)
+}
+
+/// A number in 0..1; `RandomState` is reseeded on every call, which is
+/// plenty for picking tracks
+fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
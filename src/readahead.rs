@@ -0,0 +1,252 @@
+//! Read-ahead for tracks on network shares (SMB, NFS, SSHFS and the like)
+//! A background thread keeps reading well past the decoder, so a share
+//! that stalls or a NAS spinning up doesn't starve playback. Used for files
+//! on a network filesystem, and for any file whose first read is slow
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How far the background thread reads ahead of the decoder
+const AHEAD: usize = 8 * 1024 * 1024;
+
+/// How much the background thread reads at a time
+const CHUNK: usize = 256 * 1024;
+
+/// A first read slower than this marks the file as being on a slow mount
+const SLOW_READ: Duration = Duration::from_millis(150);
+
+/// Filesystem types, as in /proc/mounts, that live across the network
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "cifs",
+    "smb3",
+    "smbfs",
+    "nfs",
+    "nfs4",
+    "fuse.sshfs",
+    "fuse.rclone",
+    "fuse.s3fs",
+    "davfs",
+    "fuse.davfs2",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+];
+
+/// Whether a track should be read through a `ReadAhead`; probes `file`
+/// and rewinds it
+pub fn needed(path: &Path, file: &mut File) -> bool {
+    if on_network_share(path) {
+        return true;
+    }
+    // Elsewhere, a slow first read gives a sluggish mount away
+    let start = Instant::now();
+    let mut probe = [0; 4096];
+    let _ = file.read(&mut probe);
+    let slow = start.elapsed() > SLOW_READ;
+    let _ = file.seek(SeekFrom::Start(0));
+    slow
+}
+
+/// Reads a track on a network share in the background, so the share is
+/// awake and the file cached by the time it plays
+pub fn prefetch(path: PathBuf) {
+    if !on_network_share(&path) {
+        return;
+    }
+    thread::spawn(move || {
+        if let Ok(mut file) = File::open(&path) {
+            let _ = io::copy(&mut file, &mut io::sink());
+        }
+    });
+}
+
+/// Whether a path is on a network filesystem, going by the longest mount
+/// point containing it; only known on Linux
+fn on_network_share(path: &Path) -> bool {
+    if !cfg!(target_os = "linux") {
+        return false;
+    }
+    let (Ok(path), Ok(mounts)) = (
+        fs::canonicalize(path),
+        fs::read_to_string("/proc/self/mounts"),
+    ) else {
+        return false;
+    };
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let point = unescape(fields.nth(1)?);
+            let kind = fields.next()?;
+            path.starts_with(&point).then_some((point.len(), kind))
+        })
+        .max_by_key(|(len, _)| *len)
+        .is_some_and(|(_, kind)| NETWORK_FILESYSTEMS.contains(&kind))
+}
+
+/// Mount points in /proc/mounts write spaces and the like as `\040`
+fn unescape(field: &str) -> String {
+    let mut text = String::new();
+    let mut rest = field;
+    while let Some(at) = rest.find('\\') {
+        text.push_str(&rest[..at]);
+        match u8::from_str_radix(rest.get(at + 1..at + 4).unwrap_or(""), 8) {
+            Ok(byte) => {
+                text.push(byte as char);
+                rest = &rest[at + 4..];
+            }
+            Err(_) => {
+                text.push('\\');
+                rest = &rest[at + 1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// State shared with the background thread
+#[derive(Default)]
+struct State {
+    buffer: VecDeque<u8>,     // Bytes read ahead, starting at `start`
+    start: u64,               // File position of the buffer's first byte
+    seek: Option<u64>,        // Position the thread should continue from
+    eof: bool,                // The thread reached the end of the file
+    error: Option<io::Error>, // Why the thread stopped reading
+    closed: bool,             // The reader was dropped
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar, // Signalled whenever the state changes
+}
+
+/// File reader fed by a background thread
+pub struct ReadAhead {
+    shared: Arc<Shared>,
+    position: u64, // Where the decoder is reading
+    len: u64,      // File size, for seeks from the end
+}
+
+impl ReadAhead {
+    pub fn new(file: File) -> io::Result<Self> {
+        let len = file.metadata()?.len();
+        let shared = Arc::new(Shared::default());
+        let background = Arc::clone(&shared);
+        thread::spawn(move || fill(file, &background));
+        Ok(Self {
+            shared,
+            position: 0,
+            len,
+        })
+    }
+}
+
+/// Keeps the buffer topped up until the reader is dropped
+fn fill(mut file: File, shared: &Shared) {
+    let mut chunk = vec![0; CHUNK];
+    loop {
+        let mut state = shared.state.lock().unwrap();
+        while !state.closed && state.seek.is_none() && (state.eof || state.buffer.len() >= AHEAD) {
+            state = shared.changed.wait(state).unwrap();
+        }
+        if state.closed {
+            return;
+        }
+        if let Some(to) = state.seek.take() {
+            state.buffer.clear();
+            state.start = to;
+            state.eof = false;
+            if let Err(e) = file.seek(SeekFrom::Start(to)) {
+                state.error = Some(e);
+                state.eof = true;
+            }
+            shared.changed.notify_all();
+            continue;
+        }
+        drop(state);
+
+        // The slow part happens without holding the lock
+        let read = file.read(&mut chunk);
+        let mut state = shared.state.lock().unwrap();
+        if state.seek.is_some() {
+            continue; // The bytes belong to the old position
+        }
+        match read {
+            Ok(0) => state.eof = true,
+            Ok(n) => state.buffer.extend(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                state.error = Some(e);
+                state.eof = true;
+            }
+        }
+        shared.changed.notify_all();
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            let end = state.start + state.buffer.len() as u64;
+            if state.seek.is_none() && (state.start..end).contains(&self.position) {
+                // Bytes behind the decoder aren't needed again
+                let behind = (self.position - state.start) as usize;
+                state.buffer.drain(..behind);
+                let n = buf.len().min(state.buffer.len());
+                for (to, from) in buf.iter_mut().zip(state.buffer.drain(..n)) {
+                    *to = from;
+                }
+                state.start = self.position + n as u64;
+                self.position += n as u64;
+                self.shared.changed.notify_all();
+                return Ok(n);
+            }
+            if state.seek.is_none() && self.position == end && state.eof {
+                return match state.error.take() {
+                    Some(e) => Err(e),
+                    None => Ok(0),
+                };
+            }
+            if state.seek.is_none() && (self.position < state.start || self.position > end) {
+                state.seek = Some(self.position);
+                self.shared.changed.notify_all();
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+}
+
+impl Seek for ReadAhead {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+        };
+        self.position = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of the file",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
@@ -0,0 +1,726 @@
+//! Libraries on other machines, listed and streamed without a local mount
+//! `--dir sftp://user@host/path` goes through the `ssh` command, so keys,
+//! agents and `~/.ssh/config` work as usual; a path starting with `/~/`
+//! is taken from the remote home directory
+//! `--dir s3://bucket/prefix` goes through `curl`, signing requests with
+//! the endpoint and credentials under `[s3]`
+//! `--dir davs://host/path` (or `dav://` without TLS) reads a WebDAV
+//! share such as a Nextcloud folder, logging in with `[webdav]`
+//! `--dir rclone:remote:path` reaches anything set up in `rclone config`
+//! Tracks are known by their URL and stream into memory, playing while the
+//! rest downloads; a seek past what has arrived restarts the download there
+
+use crate::{bandwidth, cache, config::Config, playlist};
+use std::{
+    env,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// URL scheme of SFTP libraries
+const SFTP: &str = "sftp://";
+
+/// URL scheme of S3 buckets
+const S3: &str = "s3://";
+
+/// URL schemes of WebDAV shares, over HTTPS and plain HTTP
+const DAVS: &str = "davs://";
+const DAV: &str = "dav://";
+
+/// Prefix of rclone remotes, followed by `remote:path`
+const RCLONE: &str = "rclone:";
+
+/// How much a download reads at a time
+const CHUNK: usize = 64 * 1024;
+
+/// A seek at most this far past the downloaded bytes waits for them
+/// rather than restarting the download
+const SEEK_AHEAD: u64 = 1024 * 1024;
+
+/// How often a download that broke off is picked up again, resuming
+/// where it stopped
+const RETRIES: u32 = 3;
+
+/// Pause before picking a broken-off download up again, to let the
+/// network come back
+const RETRY_PAUSE: Duration = Duration::from_secs(2);
+
+/// Endpoint and credentials for S3 buckets, from `[s3]` or the usual AWS
+/// environment variables
+static S3_SETTINGS: Mutex<Option<S3Settings>> = Mutex::new(None);
+
+/// User name and password for WebDAV shares, from `[webdav]`
+static WEBDAV_LOGIN: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+#[derive(Clone, Debug)]
+struct S3Settings {
+    endpoint: String,   // Server URL, e.g. https://s3.eu-central-1.amazonaws.com
+    region: String,     // Region requests are signed for
+    access_key: String, // Access key ID
+    secret_key: String, // Secret access key
+}
+
+/// Reads the `[s3]` settings, `endpoint`, `region`, `access_key` and
+/// `secret_key`, and the `[webdav]` ones, `user` and `password`
+pub fn configure(config: &Config) {
+    let setting = |key: &str, var: &str| {
+        config
+            .get_str(&format!("s3.{}", key))
+            .map(str::to_string)
+            .or_else(|| env::var(var).ok())
+    };
+    let region = setting("region", "AWS_REGION").unwrap_or_else(|| "us-east-1".to_string());
+    let settings = match (
+        setting("access_key", "AWS_ACCESS_KEY_ID"),
+        setting("secret_key", "AWS_SECRET_ACCESS_KEY"),
+    ) {
+        (Some(access_key), Some(secret_key)) => Some(S3Settings {
+            endpoint: setting("endpoint", "AWS_ENDPOINT_URL")
+                .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+                .trim_end_matches('/')
+                .to_string(),
+            region,
+            access_key,
+            secret_key,
+        }),
+        _ => None,
+    };
+    *S3_SETTINGS.lock().unwrap() = settings;
+    *WEBDAV_LOGIN.lock().unwrap() = config.get_str("webdav.user").map(|user| {
+        let password = config.get_str("webdav.password").unwrap_or("");
+        (user.to_string(), password.to_string())
+    });
+}
+
+/// How a remote library is reached
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    Sftp {
+        host: String,         // `user@host` or a host alias from ~/.ssh/config
+        port: Option<String>, // Port given in the URL
+    },
+    S3 {
+        bucket: String, // Bucket name
+    },
+    WebDav {
+        base: String, // Scheme and server, e.g. https://cloud.example.com
+    },
+    Rclone, // `path` is `remote:path`
+}
+
+/// A library directory or track on another machine
+#[derive(Clone, Debug, PartialEq)]
+pub struct Source {
+    url: String,  // Location as given, without a trailing slash
+    kind: Kind,   // How it is reached
+    path: String, // Path on the remote machine or server, or key in the bucket
+}
+
+/// Whether a library path or track lives on another machine
+pub fn is_remote(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    [SFTP, S3, DAVS, DAV, RCLONE]
+        .iter()
+        .any(|scheme| path.starts_with(scheme))
+}
+
+impl Source {
+    /// Reads an `sftp://`, `s3://`, `dav(s)://` or `rclone:` location;
+    /// None for local paths
+    pub fn parse(location: &Path) -> Option<Self> {
+        let url = location.to_string_lossy().trim_end_matches('/').to_string();
+        if let Some(remote) = url.strip_prefix(RCLONE) {
+            // `gdrive:` alone is the remote's top folder
+            if !remote.contains(':') || remote.starts_with(':') {
+                return None;
+            }
+            return Some(Self {
+                kind: Kind::Rclone,
+                path: remote.to_string(),
+                url,
+            });
+        }
+        let dav = [(DAVS, "https://"), (DAV, "http://")]
+            .into_iter()
+            .find_map(|(scheme, http)| Some((url.strip_prefix(scheme)?, http)));
+        if let Some((rest, http)) = dav {
+            let (server, path) = match rest.find('/') {
+                Some(at) => rest.split_at(at),
+                None => (rest, ""),
+            };
+            if server.is_empty() {
+                return None;
+            }
+            return Some(Self {
+                kind: Kind::WebDav {
+                    base: format!("{}{}", http, server),
+                },
+                path: path.to_string(),
+                url,
+            });
+        }
+        if let Some(rest) = url.strip_prefix(S3) {
+            let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+            if bucket.is_empty() {
+                return None;
+            }
+            return Some(Self {
+                kind: Kind::S3 {
+                    bucket: bucket.to_string(),
+                },
+                path: key.to_string(),
+                url,
+            });
+        }
+        let rest = url.strip_prefix(SFTP)?;
+        let (authority, path) = match rest.find('/') {
+            Some(at) => rest.split_at(at),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => {
+                (host, Some(port.to_string()))
+            }
+            _ => (authority, None),
+        };
+        // ssh would read a host like `-oProxyCommand=…` as an option
+        if host.is_empty() || host.starts_with('-') {
+            return None;
+        }
+        // The remote shell starts in the home directory
+        let path = match path {
+            "/~" => ".",
+            path => path.strip_prefix("/~/").unwrap_or(path),
+        };
+        Some(Self {
+            kind: Kind::Sftp {
+                host: host.to_string(),
+                port,
+            },
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+            url,
+        })
+    }
+
+    /// Names of the files in the remote directory
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        match &self.kind {
+            Kind::Sftp { .. } => {
+                let output = self
+                    .ssh(&format!("ls -1Ap -- {}", quote(&self.path)))
+                    .stdin(Stdio::null())
+                    .output()?;
+                if !output.status.success() {
+                    return Err(self.failure(&output.stderr));
+                }
+                // `-p` marks directories with a trailing slash
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .filter(|name| !name.is_empty() && !name.ends_with('/'))
+                    .map(str::to_string)
+                    .collect())
+            }
+            Kind::S3 { .. } => self.list_bucket(),
+            Kind::WebDav { .. } => self.list_share(),
+            Kind::Rclone => {
+                let output = rclone()
+                    .args(["lsf", "--files-only", "--", &self.path])
+                    .stdin(Stdio::null())
+                    .output()
+                    .map_err(rclone_missing)?;
+                if !output.status.success() {
+                    return Err(self.failure(&output.stderr));
+                }
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect())
+            }
+        }
+    }
+
+    /// Lists the objects right under the prefix, a page at a time
+    fn list_bucket(&self) -> io::Result<Vec<String>> {
+        let prefix = match self.path.as_str() {
+            "" => String::new(),
+            path => format!("{}/", path),
+        };
+        let mut names = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = format!(
+                "?list-type=2&delimiter=%2F&prefix={}",
+                query_encode(&prefix)
+            );
+            if let Some(token) = &token {
+                query.push_str(&format!("&continuation-token={}", query_encode(token)));
+            }
+            let page = self.curl_output(&query, &[])?;
+            for object in page.split("<Contents>").skip(1) {
+                if let Some(key) = playlist::xml_element(object, "Key") {
+                    names.extend(key.strip_prefix(&prefix).map(str::to_string));
+                }
+            }
+            token = playlist::xml_element(&page, "NextContinuationToken");
+            if token.is_none()
+                || playlist::xml_element(&page, "IsTruncated").as_deref() != Some("true")
+            {
+                return Ok(names);
+            }
+        }
+    }
+
+    /// Lists the files in the share's folder with a PROPFIND one level deep
+    fn list_share(&self) -> io::Result<Vec<String>> {
+        let body = self.curl_output("/", &["--request", "PROPFIND", "--header", "Depth: 1"])?;
+        Ok(dav_elements(&body, "response")
+            .into_iter()
+            // Folders, the listed one among them, are collections
+            .filter(|response| {
+                dav_elements(response, "resourcetype")
+                    .iter()
+                    .all(|kind| !kind.contains("collection"))
+            })
+            .filter_map(|response| {
+                let href = dav_elements(response, "href").into_iter().next()?;
+                let name = href.trim().trim_end_matches('/').rsplit('/').next()?;
+                Some(playlist::percent_decode(&name.replace("&amp;", "&")))
+            })
+            .filter(|name| !name.is_empty())
+            .collect())
+    }
+
+    /// URL of a file in the remote directory
+    pub fn track(&self, name: &str) -> PathBuf {
+        match self.kind {
+            // `gdrive:/song` would be from the drive's root
+            Kind::Rclone if self.url.ends_with(':') => {
+                PathBuf::from(format!("{}{}", self.url, name))
+            }
+            _ => PathBuf::from(format!("{}/{}", self.url, name)),
+        }
+    }
+
+    /// Starts sending the file's bytes from `offset` on to stdout
+    fn fetch(&self, offset: u64) -> io::Result<Child> {
+        match &self.kind {
+            Kind::Sftp { .. } => {
+                let command = match offset {
+                    0 => format!("cat -- {}", quote(&self.path)),
+                    offset => format!("tail -c +{} -- {}", offset + 1, quote(&self.path)),
+                };
+                self.ssh(&command)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()
+            }
+            Kind::S3 { .. } | Kind::WebDav { .. } => match offset {
+                0 => self.curl("", &[]),
+                offset => self.curl("", &["--range", &format!("{}-", offset)]),
+            },
+            Kind::Rclone => rclone()
+                .args(["cat", "--offset", &offset.to_string(), "--", &self.path])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(rclone_missing),
+        }
+    }
+
+    fn ssh(&self, command: &str) -> Command {
+        let mut ssh = Command::new("ssh");
+        // Never stop to ask for a password behind the prompt
+        ssh.args(["-o", "BatchMode=yes"]);
+        if let Kind::Sftp { host, port } = &self.kind {
+            if let Some(port) = port {
+                ssh.args(["-p", port]);
+            }
+            ssh.args(["--", host]);
+        }
+        ssh.arg(command);
+        ssh
+    }
+
+    /// Starts a request for the file at `path`, or with `query` added to
+    /// it; credentials go in through stdin so other users can't see them
+    /// in the process list
+    fn curl(&self, query: &str, args: &[&str]) -> io::Result<Child> {
+        let mut curl = Command::new("curl");
+        curl.args(["--silent", "--show-error", "--fail", "--config", "-"])
+            .args(args);
+        let user = match &self.kind {
+            Kind::S3 { bucket } => {
+                let settings = S3_SETTINGS.lock().unwrap().clone().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "No S3 credentials: set access_key and secret_key under [s3]",
+                    )
+                })?;
+                curl.arg("--aws-sigv4")
+                    .arg(format!("aws:amz:{}:s3", settings.region));
+                curl.arg(match query {
+                    "" => format!(
+                        "{}/{}/{}",
+                        settings.endpoint,
+                        bucket,
+                        playlist::percent_encode(&self.path)
+                    ),
+                    query => format!("{}/{}{}", settings.endpoint, bucket, query),
+                });
+                Some((settings.access_key, settings.secret_key))
+            }
+            Kind::WebDav { base } => {
+                curl.arg(format!(
+                    "{}{}{}",
+                    base,
+                    playlist::percent_encode(&self.path),
+                    query
+                ));
+                WEBDAV_LOGIN.lock().unwrap().clone()
+            }
+            Kind::Sftp { .. } | Kind::Rclone => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Not reached over HTTP",
+                ))
+            }
+        };
+        let mut child = curl
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        if let Some((name, secret)) = user {
+            let user = format!("{}:{}", name, secret);
+            writeln!(
+                stdin,
+                "user = \"{}\"",
+                user.replace('\\', "\\\\").replace('"', "\\\"")
+            )?;
+        }
+        Ok(child)
+    }
+
+    fn curl_output(&self, query: &str, args: &[&str]) -> io::Result<String> {
+        let output = self.curl(query, args)?.wait_with_output()?;
+        if !output.status.success() {
+            return Err(self.failure(&output.stderr));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn failure(&self, stderr: &[u8]) -> io::Error {
+        let message = String::from_utf8_lossy(stderr);
+        let tool = match &self.kind {
+            Kind::Sftp { host, .. } => format!("ssh {}", host),
+            Kind::S3 { bucket } => format!("S3 bucket {}", bucket),
+            Kind::WebDav { base } => format!("WebDAV {}", base),
+            Kind::Rclone => format!("rclone {}", self.path),
+        };
+        io::Error::other(format!("{}: {}", tool, message.trim()))
+    }
+}
+
+/// Quotes a path for the remote shell
+fn quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', "'\\''"))
+}
+
+fn rclone() -> Command {
+    let mut rclone = Command::new("rclone");
+    // Never stop to ask for a config password behind the prompt
+    rclone.arg("--ask-password=false");
+    rclone
+}
+
+/// Turns a failed start of rclone into a hint to install it
+fn rclone_missing(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::NotFound => io::Error::new(
+            io::ErrorKind::NotFound,
+            "rclone isn't installed; see https://rclone.org/install/",
+        ),
+        _ => e,
+    }
+}
+
+/// Escapes a query value, slashes included
+fn query_encode(value: &str) -> String {
+    playlist::percent_encode(value).replace('/', "%2F")
+}
+
+/// Bodies of the elements called `name` in a WebDAV reply, whatever
+/// namespace prefix the server gives them
+fn dav_elements<'a>(body: &'a str, name: &str) -> Vec<&'a str> {
+    let mut found = Vec::new();
+    let mut rest = body;
+    while let Some(at) = rest.find('<') {
+        rest = &rest[at + 1..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..tag_end];
+        rest = &rest[tag_end + 1..];
+        let tag_name = tag.split_whitespace().next().unwrap_or("");
+        if tag.ends_with('/') || tag_name.rsplit(':').next() != Some(name) {
+            continue;
+        }
+        let close = format!("</{}>", tag_name);
+        if let Some(end) = rest.find(&close) {
+            found.push(&rest[..end]);
+            rest = &rest[end + close.len()..];
+        }
+    }
+    found
+}
+
+/// State shared with the download thread
+#[derive(Default)]
+struct State {
+    data: Vec<u8>,         // Bytes downloaded so far, starting at `start`
+    start: u64,            // File position the download started at
+    done: bool,            // The download finished or failed
+    error: Option<String>, // Why the download failed
+    generation: u64,       // Bumped when the download restarts or is dropped
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar, // Signalled whenever the state changes
+}
+
+/// A remote track being downloaded, readable while it arrives
+pub struct Download {
+    source: Source,
+    shared: Arc<Shared>,
+    position: u64, // Where the decoder is reading
+    retries: u32,  // Times the download was picked up again after breaking off
+}
+
+impl Download {
+    /// Starts downloading a track given by its URL
+    pub fn start(track: &Path) -> io::Result<Self> {
+        let source = Source::parse(track)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a remote track"))?;
+        let download = Self {
+            source,
+            shared: Arc::default(),
+            position: 0,
+            retries: 0,
+        };
+        download.fetch_from(0)?;
+        Ok(download)
+    }
+
+    /// Drops what has arrived and downloads again from `offset`
+    fn fetch_from(&self, offset: u64) -> io::Result<()> {
+        let generation = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.generation += 1;
+            state.data.clear();
+            state.start = offset;
+            state.done = false;
+            state.error = None;
+            state.generation
+        };
+        let mut child = self.source.fetch(offset)?;
+        let shared = Arc::clone(&self.shared);
+        let source = self.source.clone();
+        thread::spawn(move || {
+            let mut stdout = child.stdout.take().unwrap();
+            let mut chunk = vec![0; CHUNK];
+            let mut error = None;
+            loop {
+                match stdout.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        bandwidth::take(n);
+                        let mut state = shared.state.lock().unwrap();
+                        // A newer download took over, or the reader is gone
+                        if state.generation != generation {
+                            let _ = child.kill();
+                            let _ = child.wait();
+                            return;
+                        }
+                        state.data.extend_from_slice(&chunk[..n]);
+                        shared.changed.notify_all();
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        error = Some(e.to_string());
+                        break;
+                    }
+                }
+            }
+            let mut stderr = Vec::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_end(&mut stderr);
+            }
+            if matches!(child.wait(), Ok(status) if !status.success()) && error.is_none() {
+                error = Some(source.failure(&stderr).to_string());
+            }
+            let mut state = shared.state.lock().unwrap();
+            if state.generation != generation {
+                return;
+            }
+            let complete = (state.start == 0 && error.is_none()).then(|| state.data.clone());
+            state.done = true;
+            state.error = error;
+            shared.changed.notify_all();
+            drop(state);
+            if let Some(data) = complete {
+                let _ = cache::store(Path::new(&source.url), &data);
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Read for Download {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            let end = state.start + state.data.len() as u64;
+            if (state.start..end).contains(&self.position) {
+                let from = (self.position - state.start) as usize;
+                let n = buf.len().min(state.data.len() - from);
+                buf[..n].copy_from_slice(&state.data[from..from + n]);
+                self.position += n as u64;
+                return Ok(n);
+            }
+            if self.position < state.start || (self.position > end + SEEK_AHEAD && !state.done) {
+                drop(state);
+                self.fetch_from(self.position)?;
+                state = self.shared.state.lock().unwrap();
+                continue;
+            }
+            // A dropped connection after playback started is retried
+            if state.done && state.error.is_some() && self.position > 0 && self.retries < RETRIES {
+                drop(state);
+                self.retries += 1;
+                thread::sleep(RETRY_PAUSE);
+                self.fetch_from(self.position)?;
+                state = self.shared.state.lock().unwrap();
+                continue;
+            }
+            if state.done {
+                return match &state.error {
+                    Some(e) => Err(io::Error::other(e.clone())),
+                    None => Ok(0),
+                };
+            }
+            state = self.shared.changed.wait(state).unwrap();
+        }
+    }
+}
+
+impl Seek for Download {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => {
+                // The length is only known once everything has arrived
+                let mut state = self.shared.state.lock().unwrap();
+                while !state.done {
+                    state = self.shared.changed.wait(state).unwrap();
+                }
+                (state.start + state.data.len() as u64).checked_add_signed(delta)
+            }
+        };
+        self.position = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of the file",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().generation += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(location: &str) -> Option<Source> {
+        Source::parse(Path::new(location))
+    }
+
+    #[test]
+    fn reads_sftp_locations() {
+        let music = source("sftp://me@box:2222/~/Music/").unwrap();
+        assert_eq!(
+            music.kind,
+            Kind::Sftp {
+                host: "me@box".to_string(),
+                port: Some("2222".to_string()),
+            }
+        );
+        assert_eq!(music.path, "Music");
+        assert_eq!(music.url, "sftp://me@box:2222/~/Music");
+        assert_eq!(source("sftp://box/~").unwrap().path, ".");
+        assert_eq!(source("sftp://box").unwrap().path, "/");
+        assert!(source("sftp:///music").is_none());
+        assert!(source("/home/me/Music").is_none());
+    }
+
+    #[test]
+    fn rejects_hosts_ssh_would_take_as_options() {
+        assert!(source("sftp://-oProxyCommand=touch%20x/music").is_none());
+        assert!(source("sftp://-p:22/music").is_none());
+    }
+
+    #[test]
+    fn ends_ssh_options_before_the_host() {
+        let music = source("sftp://box:22/music").unwrap();
+        let ssh = music.ssh("ls");
+        let args: Vec<_> = ssh.get_args().map(|arg| arg.to_string_lossy()).collect();
+        assert_eq!(args, ["-o", "BatchMode=yes", "-p", "22", "--", "box", "ls"]);
+    }
+
+    #[test]
+    fn reads_other_schemes() {
+        let bucket = source("s3://tunes/albums").unwrap();
+        assert_eq!(
+            bucket.kind,
+            Kind::S3 {
+                bucket: "tunes".to_string()
+            }
+        );
+        assert_eq!(bucket.path, "albums");
+        let share = source("davs://cloud.example.com/remote.php/Music").unwrap();
+        assert_eq!(
+            share.kind,
+            Kind::WebDav {
+                base: "https://cloud.example.com".to_string()
+            }
+        );
+        assert_eq!(share.path, "/remote.php/Music");
+        assert_eq!(source("rclone:gdrive:Music").unwrap().path, "gdrive:Music");
+        assert!(source("rclone:gdrive").is_none());
+        assert!(source("s3://").is_none());
+        assert!(source("dav://").is_none());
+    }
+
+    #[test]
+    fn quotes_for_the_remote_shell() {
+        assert_eq!(quote("Music"), "'Music'");
+        assert_eq!(quote("it's $HOME; rm -rf ~"), "'it'\\''s $HOME; rm -rf ~'");
+        assert_eq!(quote(""), "''");
+    }
+}
@@ -0,0 +1,207 @@
+//! Ripping audio CDs into the library with the `flac` or `opusenc` encoder
+//! Tracks are read one after another on a background thread and go to
+//! `<music dir>/<artist>/<album>/<nn> <title>.<ext>`, tagged from the disc
+//! lookup
+
+use crate::{cd, files};
+use std::{
+    fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// What tracks are encoded to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Flac, // Lossless
+    Opus, // Small, for portable players
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "flac" => Some(Format::Flac),
+            "opus" => Some(Format::Opus),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Flac => "flac",
+            Format::Opus => "opus",
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Format::Flac => "flac",
+            Format::Opus => "opusenc",
+        }
+    }
+
+    /// Encoder reading raw CD audio on stdin and writing `out`
+    fn encoder(self, tags: &[(&str, String)], out: &Path) -> Command {
+        let mut command = Command::new(self.program());
+        match self {
+            Format::Flac => {
+                command.args([
+                    "--silent",
+                    "--force",
+                    "--force-raw-format",
+                    "--endian=little",
+                    "--sign=signed",
+                    "--channels=2",
+                    "--bps=16",
+                    "--sample-rate=44100",
+                ]);
+                for (name, value) in tags {
+                    command.arg(format!("--tag={}={}", name, value));
+                }
+                command.arg("-o").arg(out).arg("-");
+            }
+            Format::Opus => {
+                command.args([
+                    "--quiet",
+                    "--raw",
+                    "--raw-rate",
+                    "44100",
+                    "--raw-chan",
+                    "2",
+                    "--raw-bits",
+                    "16",
+                ]);
+                for (name, value) in tags {
+                    command.arg("--comment").arg(format!("{}={}", name, value));
+                }
+                command.arg("-").arg(out);
+            }
+        }
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        command
+    }
+}
+
+/// Rips run since startup; one disc at a time
+#[derive(Clone, Default)]
+pub struct Rips {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    running: bool,                          // A rip is under way
+    finished: Vec<Result<PathBuf, String>>, // Tracks done since last asked
+}
+
+impl Rips {
+    /// Starts ripping `numbers` of a disc into `dir` in the background
+    pub fn start(
+        &self,
+        disc: cd::Disc,
+        numbers: Vec<u8>,
+        format: Format,
+        dir: PathBuf,
+    ) -> Result<(), String> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.running {
+                return Err("A rip is already under way".to_string());
+            }
+            state.running = true;
+        }
+        let state = Arc::clone(&self.state);
+        thread::spawn(move || {
+            let paths = disc.track_paths();
+            for (track, source) in disc.tracks.iter().zip(paths) {
+                if !numbers.contains(&track.number) {
+                    continue;
+                }
+                let result = rip_track(&disc, track, &source, format, &dir)
+                    .map_err(|e| format!("Track {}: {}", track.number, e));
+                state.lock().unwrap().finished.push(result);
+            }
+            state.lock().unwrap().running = false;
+        });
+        Ok(())
+    }
+
+    /// Tracks that were ripped, or failed, since the last call
+    pub fn take_finished(&self) -> Vec<Result<PathBuf, String>> {
+        std::mem::take(&mut self.state.lock().unwrap().finished)
+    }
+}
+
+/// Reads one track off the disc through the encoder, returning where it
+/// went
+fn rip_track(
+    disc: &cd::Disc,
+    track: &cd::DiscTrack,
+    source: &Path,
+    format: Format,
+    dir: &Path,
+) -> io::Result<PathBuf> {
+    let artist = disc
+        .artist
+        .clone()
+        .unwrap_or_else(|| "Unknown Artist".to_string());
+    let album = disc
+        .album
+        .clone()
+        .unwrap_or_else(|| "Unknown Album".to_string());
+    let title = track
+        .title
+        .clone()
+        .unwrap_or_else(|| format!("Track {}", track.number));
+    let folder = dir
+        .join(files::sanitize(&artist))
+        .join(files::sanitize(&album));
+    fs::create_dir_all(&folder)?;
+    let name = format!("{:02} {}", track.number, files::sanitize(&title));
+    let path = folder.join(format!("{}.{}", name, format.extension()));
+    // Encoded aside first, so a scan never picks up half a track
+    let partial = folder.join(format!(".{}.partial", name));
+    let tags = [
+        ("ARTIST", artist.clone()),
+        ("ALBUMARTIST", artist),
+        ("ALBUM", album),
+        ("TITLE", title),
+        ("TRACKNUMBER", track.number.to_string()),
+    ];
+    let mut encoder = format.encoder(&tags, &partial).spawn().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Could not run {}: {}", format.program(), e),
+        )
+    })?;
+    let mut reader = cd::CdTrack::open(source, std::time::Duration::ZERO)?;
+    let mut stdin = encoder.stdin.take().unwrap();
+    let copied = (|| {
+        while let Some(block) = reader.next_block()? {
+            stdin.write_all(&block)?;
+        }
+        Ok::<(), io::Error>(())
+    })();
+    drop(stdin);
+    let output = encoder.wait_with_output()?;
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial);
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "Encoder failed: {}",
+            message.trim()
+        )));
+    }
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
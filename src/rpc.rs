@@ -0,0 +1,370 @@
+//! JSON-RPC 2.0 control interface over TCP
+//! Messages are newline-delimited JSON; every player command is a method,
+//! with array params as its words, and player changes are pushed to all
+//! clients as notifications
+//! When authentication is enabled, clients first call `auth` with a token
+
+use crate::{
+    auth::{self, Scope, Tokens},
+    json::{self, Json},
+    PlayerEvent,
+};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Longest request line read, so a client can't fill memory
+const MAX_LINE: u64 = 1 << 20;
+
+/// Standard JSON-RPC error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// Application error, e.g. a track that fails to decode
+pub const PLAYER_ERROR: i64 = -32000;
+/// Missing or invalid token
+pub const UNAUTHORIZED: i64 = -32001;
+/// Token scope doesn't allow the method
+pub const FORBIDDEN: i64 = -32002;
+
+/// Error returned by a method call
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// A method call forwarded to the main loop
+pub struct RpcCall {
+    pub method: String,                        // Method name, e.g. "play"
+    pub params: Json,                          // Positional or named params
+    pub reply: Sender<Result<Json, RpcError>>, // Where the result goes
+}
+
+/// Fetches a parameter by position (array params) or name (object params)
+pub fn param<'a>(params: &'a Json, position: usize, name: &str) -> Option<&'a Json> {
+    match params {
+        Json::Array(items) => items.get(position),
+        Json::Object(_) => params.get(name),
+        _ => None,
+    }
+}
+
+/// A param as a word of a command line, e.g. `5` for `queue add 5`
+pub fn word(param: &Json) -> Option<String> {
+    match param {
+        Json::String(word) => Some(word.clone()),
+        Json::Number(n) if n.fract() == 0.0 => Some(format!("{}", *n as i64)),
+        Json::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Pushes notifications to every connected client
+#[derive(Clone, Default)]
+pub struct Notifier {
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+}
+
+impl Notifier {
+    /// Sends a notification, dropping clients that have disconnected
+    pub fn notify(&self, method: &str, params: Json) {
+        let message = Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from(method)),
+            ("params", params),
+        ])
+        .to_string();
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|client| client.send(message.clone()).is_ok());
+        }
+    }
+
+    fn subscribe(&self, client: Sender<String>) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.push(client);
+        }
+    }
+}
+
+/// Binds the JSON-RPC port and serves clients on background threads
+pub fn spawn_server(
+    addr: &str,
+    events: Sender<PlayerEvent>,
+    notifier: Notifier,
+    tokens: Tokens,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let session = Session {
+                events: events.clone(),
+                scope: tokens.initial_scope(),
+                tokens: tokens.clone(),
+            };
+            let notifier = notifier.clone();
+            thread::spawn(move || {
+                // A failed client connection only affects that client
+                let _ = serve_client(stream, session, notifier);
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Per-connection state
+struct Session {
+    events: Sender<PlayerEvent>, // Main loop the calls are forwarded to
+    tokens: Tokens,              // Accepted tokens
+    scope: Option<Scope>,        // Granted scope, None until authenticated
+}
+
+/// Handles a single client: replies and notifications share one writer
+/// thread so they never interleave mid-line
+/// Notifications are only sent once the client may read player state
+fn serve_client(stream: TcpStream, mut session: Session, notifier: Notifier) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let (out_tx, out_rx) = mpsc::channel::<String>();
+    let mut subscribed = false;
+    thread::spawn(move || {
+        for message in out_rx {
+            if writeln!(writer, "{}", message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.by_ref().take(MAX_LINE + 1).read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        if read as u64 > MAX_LINE {
+            let too_long = RpcError::new(INVALID_REQUEST, "Request too long");
+            let _ = out_tx.send(error_response(Json::Null, too_long).to_string());
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_message(&line, &mut session) {
+            if out_tx.send(response.to_string()).is_err() {
+                break;
+            }
+        }
+        if !subscribed && session.scope.is_some() {
+            notifier.subscribe(out_tx.clone());
+            subscribed = true;
+        }
+    }
+    // Unblocks the writer thread so the notifier forgets this client
+    stream.shutdown(Shutdown::Both)
+}
+
+/// Handles a single request or a batch; returns None when nothing needs
+/// a response (notifications only)
+fn handle_message(line: &str, session: &mut Session) -> Option<Json> {
+    let message = match json::parse(line) {
+        Ok(message) => message,
+        Err(e) => return Some(error_response(Json::Null, RpcError::new(PARSE_ERROR, e))),
+    };
+
+    match message {
+        Json::Array(requests) if requests.is_empty() => Some(error_response(
+            Json::Null,
+            RpcError::new(INVALID_REQUEST, "Empty batch"),
+        )),
+        Json::Array(requests) => {
+            let responses: Vec<Json> = requests
+                .iter()
+                .filter_map(|request| handle_request(request, session))
+                .collect();
+            (!responses.is_empty()).then_some(Json::Array(responses))
+        }
+        request => handle_request(&request, session),
+    }
+}
+
+/// Validates one request and runs it on the main loop
+fn handle_request(request: &Json, session: &mut Session) -> Option<Json> {
+    let id = request.get("id").cloned();
+    let method = request.get("method").and_then(Json::as_str);
+    let method = match (request.get("jsonrpc").and_then(Json::as_str), method) {
+        (Some("2.0"), Some(method)) => method.to_string(),
+        _ => {
+            return Some(error_response(
+                id.unwrap_or(Json::Null),
+                RpcError::new(INVALID_REQUEST, "Invalid Request"),
+            ))
+        }
+    };
+
+    let params = request.get("params").cloned().unwrap_or(Json::Null);
+    let result = match (method.as_str(), session.scope) {
+        ("auth", _) => authenticate(&params, session),
+        (_, None) => Err(RpcError::new(UNAUTHORIZED, "Authentication required")),
+        (method, Some(Scope::Read)) if !auth::is_read_only(method) => {
+            Err(RpcError::new(FORBIDDEN, "Permission denied"))
+        }
+        _ => call_player(method.clone(), params, &session.events),
+    };
+
+    // Requests without an id are notifications and get no response
+    let id = id?;
+    Some(match result {
+        Ok(value) => Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("result", value),
+            ("id", id),
+        ]),
+        Err(e) => error_response(id, e),
+    })
+}
+
+/// Handles the `auth` method: `[token]` or `{"token": "..."}`
+fn authenticate(params: &Json, session: &mut Session) -> Result<Json, RpcError> {
+    let token = param(params, 0, "token")
+        .and_then(Json::as_str)
+        .unwrap_or("");
+    let scope = session
+        .tokens
+        .check(token)
+        .ok_or_else(|| RpcError::new(UNAUTHORIZED, "Invalid token"))?;
+    session.scope = Some(scope);
+    let name = match scope {
+        Scope::Read => "read",
+        Scope::Control => "control",
+    };
+    Ok(Json::object(vec![("scope", Json::from(name))]))
+}
+
+/// Forwards a call to the main loop and waits for its result
+fn call_player(
+    method: String,
+    params: Json,
+    events: &Sender<PlayerEvent>,
+) -> Result<Json, RpcError> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let call = RpcCall {
+        method,
+        params,
+        reply: reply_tx,
+    };
+    if events.send(PlayerEvent::Rpc(call)).is_err() {
+        return Err(RpcError::new(PLAYER_ERROR, "Player unavailable"));
+    }
+    reply_rx
+        .recv()
+        .unwrap_or_else(|_| Err(RpcError::new(PLAYER_ERROR, "Player unavailable")))
+}
+
+fn error_response(id: Json, error: RpcError) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        (
+            "error",
+            Json::object(vec![
+                ("code", Json::from(error.code as f64)),
+                ("message", Json::from(error.message)),
+            ]),
+        ),
+        ("id", id),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// A session whose calls reach no player, so allowed ones come back
+    /// as "Player unavailable"
+    fn session() -> Session {
+        let config = Config::parse("[auth]\ntokens = [\"full\", \"view:read\"]").unwrap();
+        let tokens = Tokens::from_config(&config).unwrap();
+        Session {
+            events: mpsc::channel().0,
+            scope: tokens.initial_scope(),
+            tokens,
+        }
+    }
+
+    fn error_code(response: Option<Json>) -> Option<f64> {
+        response?.get("error")?.get("code")?.as_f64()
+    }
+
+    fn call(session: &mut Session, method: &str, params: &str) -> Option<Json> {
+        let line = format!(
+            r#"{{"jsonrpc":"2.0","method":"{}","params":{},"id":1}}"#,
+            method, params
+        );
+        handle_message(&line, session)
+    }
+
+    #[test]
+    fn calls_need_a_token_first() {
+        let mut session = session();
+        let response = call(&mut session, "status", "[]");
+        assert_eq!(error_code(response), Some(UNAUTHORIZED as f64));
+        let response = call(&mut session, "auth", r#"["wrong"]"#);
+        assert_eq!(error_code(response), Some(UNAUTHORIZED as f64));
+        assert_eq!(session.scope, None);
+        call(&mut session, "auth", r#"{"token":"full"}"#);
+        assert_eq!(session.scope, Some(Scope::Control));
+        let response = call(&mut session, "play", "[1]");
+        assert_eq!(error_code(response), Some(PLAYER_ERROR as f64));
+    }
+
+    #[test]
+    fn read_tokens_only_query() {
+        let mut session = session();
+        call(&mut session, "auth", r#"["view"]"#);
+        assert_eq!(session.scope, Some(Scope::Read));
+        let response = call(&mut session, "queue", r#"["clear"]"#);
+        assert_eq!(error_code(response), Some(FORBIDDEN as f64));
+        let response = call(&mut session, "status", "[]");
+        assert_eq!(error_code(response), Some(PLAYER_ERROR as f64));
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let mut session = session();
+        let response = handle_message("{", &mut session);
+        assert_eq!(error_code(response), Some(PARSE_ERROR as f64));
+        let response = handle_message("[]", &mut session);
+        assert_eq!(error_code(response), Some(INVALID_REQUEST as f64));
+        let response = handle_message(r#"{"method":"status","id":1}"#, &mut session);
+        assert_eq!(error_code(response), Some(INVALID_REQUEST as f64));
+        // Notifications get no response, even when refused
+        let notification = r#"{"jsonrpc":"2.0","method":"stop"}"#;
+        assert_eq!(handle_message(notification, &mut session), None);
+    }
+
+    #[test]
+    fn params_become_command_words() {
+        assert_eq!(word(&Json::from("add")), Some("add".to_string()));
+        assert_eq!(word(&Json::from(5.0)), Some("5".to_string()));
+        assert_eq!(word(&Json::from(0.25)), Some("0.25".to_string()));
+        assert_eq!(word(&Json::Null), None);
+        assert_eq!(word(&Json::Array(Vec::new())), None);
+    }
+}
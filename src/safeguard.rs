@@ -0,0 +1,87 @@
+//! Shared checks for commands that delete or overwrite files
+//! `--dry-run` only reports what a command would do; otherwise the
+//! command asks for confirmation at the prompt unless `--yes` was given
+//! Remote clients can't answer a prompt, so they must pass `--yes`
+
+use colored::*;
+use std::io::{self, Write};
+
+/// How a destructive command was asked to run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Safeguard {
+    dry_run: bool,     // Report instead of acting
+    assume_yes: bool,  // Skip the confirmation prompt
+    interactive: bool, // The command was typed at the prompt
+}
+
+impl Safeguard {
+    /// Takes `--dry-run` and `--yes`/`-y` out of a command's arguments
+    pub fn from_args(args: &[String], interactive: bool) -> (Self, Vec<String>) {
+        let mut guard = Self {
+            interactive,
+            ..Self::default()
+        };
+        let rest = args
+            .iter()
+            .filter(|arg| match arg.as_str() {
+                "--dry-run" => {
+                    guard.dry_run = true;
+                    false
+                }
+                "--yes" | "-y" => {
+                    guard.assume_yes = true;
+                    false
+                }
+                _ => true,
+            })
+            .cloned()
+            .collect();
+        (guard, rest)
+    }
+
+    /// Whether this is a dry run, printing what would have happened
+    /// For commands that lose nothing and so don't ask first
+    pub fn dry_run(&self, action: &str) -> bool {
+        if self.dry_run {
+            outln!("{}: Would {}", "Dry run".yellow(), action);
+        }
+        self.dry_run
+    }
+
+    /// Whether to go ahead with an action, e.g. "delete playlist trips"
+    /// Prints why when it doesn't
+    pub fn allow(&self, action: &str) -> bool {
+        if self.dry_run {
+            outln!("{}: Would {}", "Dry run".yellow(), action);
+            return false;
+        }
+        if self.assume_yes {
+            return true;
+        }
+        if !self.interactive {
+            outln!("{}: Pass --yes to {}", "Error".red(), action);
+            return false;
+        }
+        if ask(action) {
+            return true;
+        }
+        outln!("{}: Cancelled", "Info".blue());
+        false
+    }
+}
+
+/// Asks a yes/no question on the terminal, defaulting to no
+fn ask(action: &str) -> bool {
+    let mut first = action.chars();
+    let question: String = first
+        .next()
+        .map(|c| c.to_uppercase().chain(first).collect())
+        .unwrap_or_default();
+    print!("{}? [y/N] ", question);
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
@@ -0,0 +1,214 @@
+//! Which files the library scan picks up
+//! `exclude` and `include` under `[library]` hold glob patterns matched
+//! against paths relative to the music directory: `*` and `?` stay within
+//! one folder, `**` spans any number of them, and a pattern without a `/`
+//! is matched against the file name alone. `include` wins over `exclude`
+//! Dotfiles are skipped unless `hidden = true`, and symlinks are followed
+//! unless `follow_symlinks = false`
+//! Subfolders are searched as deep as they go, or `max_depth` levels below
+//! the music directory; remote libraries are listed one level only
+
+use crate::{codecs, config::Config, remote};
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Rules files are kept or left out by
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    include: Vec<String>,     // Files kept even when excluded
+    exclude: Vec<String>,     // Files left out of the library
+    skip_symlinks: bool,      // Leave out symlinked files
+    show_hidden: bool,        // Keep dotfiles, like macOS `._` resource files
+    max_depth: Option<usize>, // Folder levels searched below the root, 0 for none
+}
+
+impl Filter {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            include: config.get_list("library.include"),
+            exclude: config.get_list("library.exclude"),
+            skip_symlinks: !config.get_bool("library.follow_symlinks").unwrap_or(true),
+            show_hidden: config.get_bool("library.hidden").unwrap_or(false),
+            max_depth: config
+                .get_f64("library.max_depth")
+                .map(|depth| depth.max(0.0) as usize),
+        }
+    }
+
+    /// Overrides `max_depth` from the config, as `--max-depth` does
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = Some(depth);
+    }
+
+    /// Playable files inside `dir`, which may be remote, and its subfolders
+    /// that the rules let through, sorted by path
+    pub fn files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        if let Some(source) = remote::Source::parse(dir) {
+            let names = source.list()?.into_iter();
+            return Ok(names
+                .filter(|name| self.show_hidden || !is_hidden(OsStr::new(name)))
+                .filter(|name| codecs::supported(Path::new(name)) && self.allows(Path::new(name)))
+                .map(|name| source.track(&name))
+                .collect());
+        }
+        let mut files = Vec::new();
+        let mut visited = HashSet::new();
+        visited.extend(fs::canonicalize(dir).ok());
+        self.collect(dir, dir, 0, &mut visited, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    /// Adds the files in `folder`, `depth` levels below `root`, and goes on
+    /// into its subfolders; `visited` keeps symlinked folders from looping
+    fn collect(
+        &self,
+        root: &Path,
+        folder: &Path,
+        depth: usize,
+        visited: &mut HashSet<PathBuf>,
+        files: &mut Vec<PathBuf>,
+    ) -> io::Result<()> {
+        for entry in fs::read_dir(folder)? {
+            let entry = entry?;
+            if !self.show_hidden && is_hidden(&entry.file_name()) {
+                continue;
+            }
+            if self.skip_symlinks && entry.file_type()?.is_symlink() {
+                continue;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                let deeper = self.max_depth.is_none_or(|max| depth < max);
+                let canonical = fs::canonicalize(&path).ok();
+                if deeper && canonical.is_some_and(|canonical| visited.insert(canonical)) {
+                    // A folder that can't be read is left out, not the library
+                    let _ = self.collect(root, &path, depth + 1, visited, files);
+                }
+                continue;
+            }
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            // Skip covers, playlists and formats this build can't play
+            if path.is_file() && codecs::supported(&path) && self.allows(relative) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a file, given relative to the music directory, belongs in
+    /// the library
+    fn allows(&self, relative: &Path) -> bool {
+        let path = relative
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let hits = |patterns: &[String]| patterns.iter().any(|pattern| matches(pattern, &path));
+        !hits(&self.exclude) || hits(&self.include)
+    }
+}
+
+fn is_hidden(name: &OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// Matches a glob against a `/`-separated path
+fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    if pattern.contains(&'/') {
+        glob(&pattern, &path)
+    } else {
+        let name = path.rsplit(|&c| c == '/').next().unwrap_or(&[]);
+        glob(&pattern, name)
+    }
+}
+
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` may also stand for no folder at all
+            if let ['/', after @ ..] = rest {
+                if glob(after, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|i| glob(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => {
+            let folder_end = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=folder_end).any(|i| glob(rest, &text[i..]))
+        }
+        ['?', rest @ ..] => matches!(text, [c, ..] if *c != '/') && glob(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && glob(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A library four folders deep, with one track on each level
+    fn library(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("scan-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let deepest = root.join("one/two/three");
+        fs::create_dir_all(&deepest).unwrap();
+        for track in ["a.wav", "one/b.wav", "one/two/c.wav", "one/two/three/d.wav"] {
+            fs::write(root.join(track), b"").unwrap();
+        }
+        fs::write(root.join("cover.jpg"), b"").unwrap();
+        fs::write(root.join("one/.e.wav"), b"").unwrap();
+        root
+    }
+
+    fn names(filter: &Filter, root: &Path) -> Vec<String> {
+        filter
+            .files(root)
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn searches_every_level_without_a_limit() {
+        let root = library("unlimited");
+        assert_eq!(
+            names(&Filter::default(), &root),
+            ["a.wav", "b.wav", "c.wav", "d.wav"]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn max_depth_counts_levels_below_the_root() {
+        let root = library("depth");
+        let mut filter = Filter::default();
+        filter.set_max_depth(0);
+        assert_eq!(names(&filter, &root), ["a.wav"]);
+        filter.set_max_depth(1);
+        assert_eq!(names(&filter, &root), ["a.wav", "b.wav"]);
+        filter.set_max_depth(3);
+        assert_eq!(names(&filter, &root), ["a.wav", "b.wav", "c.wav", "d.wav"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn patterns_are_matched_relative_to_the_root() {
+        let filter = Filter {
+            exclude: vec!["one/**".to_string()],
+            include: vec!["*/two/c.wav".to_string()],
+            ..Filter::default()
+        };
+        assert!(filter.allows(Path::new("a.wav")));
+        assert!(!filter.allows(Path::new("one/b.wav")));
+        assert!(filter.allows(Path::new("one/two/c.wav")));
+    }
+}
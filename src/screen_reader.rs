@@ -0,0 +1,63 @@
+//! Screen-reader mode: output a screen reader reads well
+//! Colors are off, separator rules and symbols give way to words, the
+//! picker asks for a line instead of redrawing the screen, and `status` is
+//! a single line. Turned on with `--screen-reader` or `screen_reader = true`
+//! under `[accessibility]`
+
+use crate::config::Config;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether screen-reader mode is on
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--screen-reader`, which holds whatever the config says
+static FORCED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps screen-reader mode on regardless of the config
+pub fn force() {
+    FORCED.store(true, Ordering::Relaxed);
+}
+
+/// Reads `screen_reader` under `[accessibility]`
+pub fn configure(config: &Config) {
+    let enabled = FORCED.load(Ordering::Relaxed)
+        || config
+            .get_bool("accessibility.screen_reader")
+            .unwrap_or(false);
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if enabled {
+        colored::control::set_override(false);
+    } else {
+        colored::control::unset_override();
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Prints a separator rule under a heading, which a screen reader would
+/// read out dash by dash
+pub fn rule(line: impl std::fmt::Display) {
+    if !enabled() {
+        outln!("{}", line);
+    }
+}
+
+/// A symbol, or the words standing in for it
+pub fn symbol<'a>(symbol: &'a str, words: &'a str) -> &'a str {
+    if enabled() {
+        words
+    } else {
+        symbol
+    }
+}
+
+/// A star rating, spelled out in screen-reader mode
+pub fn stars(rating: u8) -> String {
+    match (enabled(), rating) {
+        (true, 1) => "1 star".to_string(),
+        (true, rating) => format!("{} stars", rating),
+        (false, rating) => "★".repeat(rating as usize),
+    }
+}
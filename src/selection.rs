@@ -0,0 +1,72 @@
+//! Track numbers as typed after `play`: single numbers and ranges,
+//! separated by commas or spaces, e.g. `3-7,12,15`
+
+/// Most tracks one range may cover, so a typo can't build a huge list
+const MAX_RANGE: i32 = 10_000;
+
+/// Track numbers in the order given; a range may run downwards
+pub fn parse(spec: &str) -> Result<Vec<i32>, String> {
+    let mut indices = Vec::new();
+    let parts = spec
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty());
+    for part in parts {
+        let invalid = || format!("Not a track number or range: {}", part);
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let from: i32 = from.parse().map_err(|_| invalid())?;
+                let to: i32 = to.parse().map_err(|_| invalid())?;
+                if (i64::from(to) - i64::from(from)).abs() >= i64::from(MAX_RANGE) {
+                    return Err(format!("Range {} covers too many tracks", part));
+                }
+                if from <= to {
+                    indices.extend(from..=to);
+                } else {
+                    indices.extend((to..=from).rev());
+                }
+            }
+            None => indices.push(part.parse().map_err(|_| invalid())?),
+        }
+    }
+    if indices.is_empty() {
+        return Err("Please provide a song index".to_string());
+    }
+    Ok(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_expand_in_order() {
+        assert_eq!(parse("3-6"), Ok(vec![3, 4, 5, 6]));
+        assert_eq!(parse("6-3"), Ok(vec![6, 5, 4, 3]));
+        assert_eq!(parse("4-4"), Ok(vec![4]));
+    }
+
+    #[test]
+    fn lists_mix_numbers_and_ranges() {
+        assert_eq!(parse("3-5,12 15"), Ok(vec![3, 4, 5, 12, 15]));
+        assert_eq!(parse(" 2, ,9-8 "), Ok(vec![2, 9, 8]));
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_track_number() {
+        assert!(parse("").is_err());
+        assert!(parse("a").is_err());
+        assert!(parse("1-").is_err());
+        assert!(parse("1-x").is_err());
+    }
+
+    #[test]
+    fn limits_the_range_length() {
+        assert_eq!(
+            parse(&format!("1-{}", MAX_RANGE)).map(|v| v.len()),
+            Ok(MAX_RANGE as usize)
+        );
+        assert!(parse(&format!("1-{}", MAX_RANGE + 1)).is_err());
+        assert!(parse(&format!("{}-1", MAX_RANGE + 1)).is_err());
+        assert!(parse(&format!("{}-{}", i32::MIN, i32::MAX)).is_err());
+    }
+}
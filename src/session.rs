@@ -0,0 +1,120 @@
+//! Session state snapshots and crash handling
+//! The main loop keeps a snapshot of what is playing so a panic can still
+//! write it to the state file before the process dies
+
+use colored::*;
+use std::{
+    env, fs,
+    io::{self, Write},
+    panic,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// Name of the session file inside the state directory
+const SESSION_FILE: &str = "session";
+
+/// What the player was doing, as saved to the state file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionState {
+    pub dir: Option<String>,  // Music directory
+    pub file: Option<String>, // Current track
+    pub position: u64,        // Seconds into the current track
+    pub volume: f32,          // Playback volume
+    pub paused: bool,         // Whether playback was paused
+}
+
+/// Latest snapshot, readable from the panic hook
+static SNAPSHOT: Mutex<Option<SessionState>> = Mutex::new(None);
+
+/// Directory for state that should survive restarts
+/// `$XDG_STATE_HOME/musicplayer` or `~/.local/state/musicplayer`, and
+/// `%LOCALAPPDATA%\musicplayer` on Windows
+pub fn state_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("musicplayer"));
+    }
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .map(|dir| dir.join("musicplayer"))
+}
+
+/// Replaces the snapshot the panic hook would save
+pub fn record(state: SessionState) {
+    if let Ok(mut snapshot) = SNAPSHOT.lock() {
+        *snapshot = Some(state);
+    }
+}
+
+impl SessionState {
+    /// Writes the state as `key = value` lines, returning the file path
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let dir = state_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(SESSION_FILE);
+
+        let mut contents = String::new();
+        if let Some(dir) = &self.dir {
+            contents.push_str(&format!("dir = {}\n", dir));
+        }
+        if let Some(file) = &self.file {
+            contents.push_str(&format!("file = {}\n", file));
+        }
+        contents.push_str(&format!("position = {}\n", self.position));
+        contents.push_str(&format!("volume = {:.2}\n", self.volume));
+        contents.push_str(&format!("paused = {}\n", self.paused));
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+}
+
+/// Installs a panic hook that puts the terminal back into a usable state,
+/// saves the session, and prints a short report instead of the default
+/// message (which is still shown when `RUST_BACKTRACE` is set)
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+
+        // The lock may be held by the panicking thread; never block here
+        let saved = match SNAPSHOT.try_lock() {
+            Ok(snapshot) => snapshot.as_ref().map(SessionState::save),
+            Err(_) => None,
+        };
+
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown error".to_string());
+        let location = info
+            .location()
+            .map(|l| format!(" at {}:{}", l.file(), l.line()))
+            .unwrap_or_default();
+        eprintln!(
+            "\n{}: musicplayer crashed: {}{}",
+            "Error".red().bold(),
+            message,
+            location
+        );
+        match saved {
+            Some(Ok(path)) => eprintln!("Session saved to {}", path.display()),
+            Some(Err(e)) => eprintln!("Could not save session: {}", e),
+            None => {}
+        }
+
+        if env::var_os("RUST_BACKTRACE").is_some() {
+            default_hook(info);
+        }
+    }));
+}
+
+/// Resets colors and shows the cursor again
+fn restore_terminal() {
+    let mut stdout = io::stdout();
+    let _ = write!(stdout, "\x1b[0m\x1b[?25h");
+    let _ = stdout.flush();
+}
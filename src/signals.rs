@@ -0,0 +1,77 @@
+//! Unix signal handling for SIGTERM (graceful shutdown) and SIGHUP
+//! (config reload)
+//! Handlers only write the signal number to a pipe; a watcher thread turns
+//! it into a `PlayerEvent` for the main loop
+
+use crate::PlayerEvent;
+use std::sync::mpsc::Sender;
+
+#[cfg(unix)]
+mod imp {
+    use crate::PlayerEvent;
+    use std::{
+        fs::File,
+        io::Read,
+        os::unix::io::FromRawFd,
+        sync::{
+            atomic::{AtomicI32, Ordering},
+            mpsc::Sender,
+        },
+        thread,
+    };
+
+    /// Write end of the self-pipe, used from the signal handler
+    static PIPE_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn handle_signal(signal: libc::c_int) {
+        let byte = signal as u8;
+        // SAFETY: write(2) is async-signal-safe and the buffer outlives it
+        unsafe {
+            libc::write(
+                PIPE_WRITE.load(Ordering::Relaxed),
+                &byte as *const u8 as *const libc::c_void,
+                1,
+            );
+        }
+    }
+
+    pub fn install(events: Sender<PlayerEvent>) {
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two descriptors pipe(2) returns
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return;
+        }
+        PIPE_WRITE.store(fds[1], Ordering::Relaxed);
+
+        let handler = handle_signal as extern "C" fn(libc::c_int) as *const ();
+        // SAFETY: the handler only performs async-signal-safe work
+        unsafe {
+            libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+            libc::signal(libc::SIGHUP, handler as libc::sighandler_t);
+        }
+
+        // SAFETY: the read end is owned exclusively by this file from here on
+        let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+        thread::spawn(move || {
+            let mut byte = [0u8; 1];
+            while reader.read_exact(&mut byte).is_ok() {
+                let event = match byte[0] as libc::c_int {
+                    libc::SIGHUP => PlayerEvent::Reload,
+                    _ => PlayerEvent::Terminate,
+                };
+                if events.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Routes SIGTERM and SIGHUP to the main loop
+/// Does nothing on platforms without Unix signals
+pub fn install(events: Sender<PlayerEvent>) {
+    #[cfg(unix)]
+    imp::install(events);
+    #[cfg(not(unix))]
+    drop(events);
+}
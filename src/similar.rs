@@ -0,0 +1,183 @@
+//! Finds tracks like a given one from what is known locally: shared
+//! artist, genre and decade, close tempo, and how often the two were
+//! played back to back
+//! The play history behind the last is kept in the state directory
+
+use crate::{session, tags};
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+const HISTORY_FILE: &str = "history";
+
+/// Most plays the history keeps
+const HISTORY: usize = 5000;
+
+/// Tempos further apart than this don't count as close
+const BPM_RANGE: f64 = 20.0;
+
+/// Tracks in the order they were played, oldest first
+#[derive(Default)]
+pub struct History {
+    plays: Vec<PathBuf>,
+}
+
+impl History {
+    /// Reads the history, dropping the oldest plays beyond `HISTORY`
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = history_path() else {
+            return Ok(Self::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let mut plays: Vec<PathBuf> = text.lines().map(PathBuf::from).collect();
+        if plays.len() > HISTORY {
+            plays.drain(..plays.len() - HISTORY);
+            let history = Self { plays };
+            history.save()?;
+            return Ok(history);
+        }
+        Ok(Self { plays })
+    }
+
+    /// Rewrites the whole file
+    pub fn save(&self) -> io::Result<()> {
+        let path = history_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: String = self
+            .plays
+            .iter()
+            .map(|play| format!("{}\n", play.display()))
+            .collect();
+        fs::write(path, text)
+    }
+
+    /// Carries the plays of a moved or renamed track over to its new path
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        for play in self.plays.iter_mut().filter(|play| *play == from) {
+            *play = to.to_path_buf();
+        }
+    }
+
+    /// Adds a play and appends it to the file
+    pub fn record(&mut self, track: &Path) -> io::Result<()> {
+        self.plays.push(track.to_path_buf());
+        let path = history_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", track.display())
+    }
+
+    /// How often each track was played right before or after `track`
+    fn neighbors(&self, track: &Path) -> HashMap<&Path, usize> {
+        let mut counts = HashMap::new();
+        for pair in self.plays.windows(2) {
+            let other = match (&pair[0], &pair[1]) {
+                (a, b) if a == track && b != track => b,
+                (a, b) if b == track && a != track => a,
+                _ => continue,
+            };
+            *counts.entry(other.as_path()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// A track that resembles the one asked about, and why
+pub struct Suggestion {
+    pub index: i32,           // Library number
+    pub score: f64,           // Higher is closer
+    pub reasons: Vec<String>, // What the tracks share
+}
+
+/// Tracks sharing anything with `seed`, closest first
+pub fn rank<'a>(
+    seed: &Path,
+    tracks: impl Iterator<Item = (i32, &'a Path)>,
+    history: &History,
+) -> Vec<Suggestion> {
+    let wanted = tags::read(seed).unwrap_or_default();
+    let artists = |tags: &tags::Tags| -> Vec<String> {
+        [tags.artist.as_deref(), tags.album_artist()]
+            .into_iter()
+            .flatten()
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let wanted_artists = artists(&wanted);
+    let genre = wanted.genre.as_deref().map(str::to_lowercase);
+    let neighbors = history.neighbors(seed);
+    let mut suggestions: Vec<Suggestion> = tracks
+        .filter(|(_, path)| *path != seed)
+        .filter_map(|(index, path)| {
+            let tags = tags::read(path).unwrap_or_default();
+            let mut score = 0.0;
+            let mut reasons = Vec::new();
+            if artists(&tags).iter().any(|a| wanted_artists.contains(a)) {
+                score += 3.0;
+                reasons.push("same artist".to_string());
+            }
+            if genre.is_some() && tags.genre.as_deref().map(str::to_lowercase) == genre {
+                score += 2.0;
+                reasons.push(tags.genre.clone().unwrap_or_default());
+            }
+            if let (Some(a), Some(b)) = (wanted.year, tags.year) {
+                if a / 10 == b / 10 {
+                    score += 1.0;
+                    reasons.push(format!("{}s", b / 10 * 10));
+                }
+            }
+            if let (Some(a), Some(b)) = (wanted.bpm, tags.bpm) {
+                let apart = (a as f64 - b as f64).abs();
+                if apart < BPM_RANGE {
+                    score += 2.0 * (1.0 - apart / BPM_RANGE);
+                    reasons.push(format!("{} BPM", b));
+                }
+            }
+            if let Some(&count) = neighbors.get(path) {
+                score += 1.5 * count.min(3) as f64;
+                reasons.push(format!("played together {}×", count));
+            }
+            (score > 0.0).then_some(Suggestion {
+                index,
+                score,
+                reasons,
+            })
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.index.cmp(&b.index)));
+    suggestions
+}
+
+fn history_path() -> Option<PathBuf> {
+    session::state_dir().map(|dir| dir.join(HISTORY_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renamed_tracks_keep_their_neighbors() {
+        let mut history = History {
+            plays: ["a", "b", "a", "c", "b"].map(PathBuf::from).to_vec(),
+        };
+        history.rename(Path::new("a"), Path::new("moved/a"));
+        assert!(history.neighbors(Path::new("a")).is_empty());
+        let neighbors = history.neighbors(Path::new("moved/a"));
+        assert_eq!(neighbors.get(Path::new("b")), Some(&2));
+        assert_eq!(neighbors.get(Path::new("c")), Some(&1));
+    }
+}
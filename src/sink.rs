@@ -0,0 +1,177 @@
+//! Where playback goes: a rodio sink or our own cpal stream fed by our
+//! decoder and effects, or an mpv instance or GStreamer pipeline that
+//! decodes and plays files by itself
+
+use crate::{
+    direct::{self, Direct},
+    output::Output,
+};
+use rodio::{OutputStreamHandle, Source};
+use std::error::Error;
+
+/// `--backend` value that plays through libmpv
+pub const MPV: &str = "mpv";
+/// `--backend` value that plays through a GStreamer pipeline
+pub const GSTREAMER: &str = "gstreamer";
+
+/// Backends that play files themselves instead of through a cpal stream
+pub const SELF_PLAYING: &[&str] = &[MPV, GSTREAMER];
+
+/// A playback sink
+pub enum Sink {
+    Rodio(rodio::Sink), // Plays sources decoded by this program
+    Direct(Direct),     // The same, on a stream with a fixed buffer size
+    #[cfg(feature = "mpv")]
+    Mpv(crate::mpv::Mpv), // Plays files through libmpv
+    #[cfg(feature = "gstreamer")]
+    Gstreamer(crate::gstreamer::Playbin), // Plays files through GStreamer
+}
+
+impl Sink {
+    /// Creates the sink for a backend; cpal host names get rodio, or a
+    /// direct stream when its buffer size or format is chosen
+    pub fn new(
+        backend: Option<&str>,
+        output: &Output,
+        direct: direct::Settings,
+    ) -> Result<Self, Box<dyn Error>> {
+        if backend == Some(MPV) {
+            #[cfg(feature = "mpv")]
+            return Ok(Sink::Mpv(crate::mpv::Mpv::new()?));
+            #[cfg(not(feature = "mpv"))]
+            return Err(
+                "mpv support is not built into this binary (build with --features mpv)".into(),
+            );
+        }
+        if backend == Some(GSTREAMER) {
+            #[cfg(feature = "gstreamer")]
+            return Ok(Sink::Gstreamer(crate::gstreamer::Playbin::new()?));
+            #[cfg(not(feature = "gstreamer"))]
+            return Err(
+                "GStreamer support is not built into this binary (build with --features gstreamer)"
+                    .into(),
+            );
+        }
+        if direct.wanted() {
+            let device = output.device.as_ref().ok_or("No output device to open")?;
+            return Ok(Sink::Direct(Direct::new(device, direct)?));
+        }
+        Ok(Sink::Rodio(rodio::Sink::try_new(&output.handle)?))
+    }
+
+    /// Moves playback to a newly opened output, keeping the volume
+    pub fn reopen(&mut self, output: &Output) -> Result<(), Box<dyn Error>> {
+        if let Sink::Direct(direct) = self {
+            let device = output.device.as_ref().ok_or("No output device to open")?;
+            let reopened = Direct::new(device, direct.settings())?;
+            reopened.set_volume(direct.volume());
+            *direct = reopened;
+            return Ok(());
+        }
+        self.replace(&output.handle)
+    }
+
+    /// Stops playback so the sink is ready for another track
+    /// A stopped rodio sink stays silent for anything appended later, so
+    /// it is swapped for a fresh one on `handle` with the same volume
+    pub fn replace(&mut self, handle: &OutputStreamHandle) -> Result<(), Box<dyn Error>> {
+        match self {
+            Sink::Rodio(sink) => {
+                let volume = sink.volume();
+                sink.stop();
+                *sink = rodio::Sink::try_new(handle)?;
+                sink.set_volume(volume);
+            }
+            Sink::Direct(direct) => direct.clear(),
+            #[cfg(feature = "mpv")]
+            Sink::Mpv(mpv) => mpv.stop(),
+            #[cfg(feature = "gstreamer")]
+            Sink::Gstreamer(playbin) => playbin.stop(),
+        }
+        Ok(())
+    }
+
+    /// Whether nothing is left to play
+    pub fn empty(&self) -> bool {
+        match self {
+            Sink::Rodio(sink) => sink.empty(),
+            Sink::Direct(direct) => direct.empty(),
+            #[cfg(feature = "mpv")]
+            Sink::Mpv(mpv) => mpv.empty(),
+            #[cfg(feature = "gstreamer")]
+            Sink::Gstreamer(playbin) => playbin.empty(),
+        }
+    }
+
+    pub fn pause(&self) {
+        match self {
+            Sink::Rodio(sink) => sink.pause(),
+            Sink::Direct(direct) => direct.set_paused(true),
+            #[cfg(feature = "mpv")]
+            Sink::Mpv(mpv) => mpv.set_paused(true),
+            #[cfg(feature = "gstreamer")]
+            Sink::Gstreamer(playbin) => playbin.set_paused(true),
+        }
+    }
+
+    pub fn play(&self) {
+        match self {
+            Sink::Rodio(sink) => sink.play(),
+            Sink::Direct(direct) => direct.set_paused(false),
+            #[cfg(feature = "mpv")]
+            Sink::Mpv(mpv) => mpv.set_paused(false),
+            #[cfg(feature = "gstreamer")]
+            Sink::Gstreamer(playbin) => playbin.set_paused(false),
+        }
+    }
+
+    pub fn stop(&mut self) {
+        match self {
+            Sink::Rodio(sink) => sink.stop(),
+            Sink::Direct(direct) => direct.clear(),
+            #[cfg(feature = "mpv")]
+            Sink::Mpv(mpv) => mpv.stop(),
+            #[cfg(feature = "gstreamer")]
+            Sink::Gstreamer(playbin) => playbin.stop(),
+        }
+    }
+
+    /// Volume as a factor, 1.0 being unchanged
+    pub fn volume(&self) -> f32 {
+        match self {
+            Sink::Rodio(sink) => sink.volume(),
+            Sink::Direct(direct) => direct.volume(),
+            #[cfg(feature = "mpv")]
+            Sink::Mpv(mpv) => mpv.volume(),
+            #[cfg(feature = "gstreamer")]
+            Sink::Gstreamer(playbin) => playbin.volume(),
+        }
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        match self {
+            Sink::Rodio(sink) => sink.set_volume(volume),
+            Sink::Direct(direct) => direct.set_volume(volume),
+            #[cfg(feature = "mpv")]
+            Sink::Mpv(mpv) => mpv.set_volume(volume),
+            #[cfg(feature = "gstreamer")]
+            Sink::Gstreamer(playbin) => playbin.set_volume(volume),
+        }
+    }
+
+    /// Queues a decoded source; the self-playing backends load files
+    /// themselves instead
+    pub fn append<S>(&self, source: S)
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        match self {
+            Sink::Rodio(sink) => sink.append(source),
+            Sink::Direct(direct) => direct.append(source),
+            #[cfg(feature = "mpv")]
+            Sink::Mpv(_) => {}
+            #[cfg(feature = "gstreamer")]
+            Sink::Gstreamer(_) => {}
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Playback speed remembered per podcast feed and per album, so a show or
+//! an audiobook always plays at the speed last chosen for it
+//! Kept in the state directory as one `speed<TAB>key` line each; the key
+//! is `feed:<feed>` for podcast episodes and `album:<artist> – <album>`
+//! for other tracks
+
+use crate::session;
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+/// Name of the speeds file inside the state directory
+const SPEEDS_FILE: &str = "speeds";
+
+/// Slowest and fastest speeds on offer
+pub const RANGE: std::ops::RangeInclusive<f32> = 0.5..=3.0;
+
+/// Remembered speeds by feed or album
+#[derive(Clone, Debug, Default)]
+pub struct Speeds {
+    speeds: BTreeMap<String, f32>,
+}
+
+impl Speeds {
+    /// Reads the speeds file, which may not exist yet
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = speeds_path() else {
+            return Ok(Self::default());
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let speeds = text
+            .lines()
+            .filter_map(|line| {
+                let (speed, key) = line.split_once('\t')?;
+                let speed: f32 = speed.parse().ok()?;
+                RANGE.contains(&speed).then(|| (key.to_string(), speed))
+            })
+            .collect();
+        Ok(Self { speeds })
+    }
+
+    pub fn get(&self, key: &str) -> Option<f32> {
+        self.speeds.get(key).copied()
+    }
+
+    /// Every remembered speed by key
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.speeds
+            .iter()
+            .map(|(key, &speed)| (key.as_str(), speed))
+    }
+
+    /// Remembers a speed and saves the file; normal speed is forgotten
+    pub fn set(&mut self, key: &str, speed: f32) -> io::Result<()> {
+        self.remember(key, speed);
+        self.save()
+    }
+
+    /// Remembers several speeds and saves the file once
+    pub fn set_all(&mut self, speeds: &[(String, f32)]) -> io::Result<()> {
+        for (key, speed) in speeds {
+            self.remember(key, *speed);
+        }
+        self.save()
+    }
+
+    fn remember(&mut self, key: &str, speed: f32) {
+        if speed == 1.0 {
+            self.speeds.remove(key);
+        } else {
+            self.speeds.insert(key.to_string(), speed);
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = speeds_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: String = self
+            .speeds
+            .iter()
+            .map(|(key, speed)| format!("{}\t{}\n", speed, key))
+            .collect();
+        fs::write(path, text)
+    }
+}
+
+fn speeds_path() -> Option<PathBuf> {
+    session::state_dir().map(|dir| dir.join(SPEEDS_FILE))
+}
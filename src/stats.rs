@@ -0,0 +1,106 @@
+//! Per-track ratings, play counts and intro offsets
+//! Kept in the state directory as one `plays<TAB>rating<TAB>intro<TAB>path`
+//! line per track, with `-` for no rating or intro; older files without the
+//! intro field are still read
+
+use crate::session;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Name of the stats file inside the state directory
+const STATS_FILE: &str = "stats";
+
+/// What the player knows about a track beyond the file itself
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackStats {
+    pub plays: u64,         // Times the track was started
+    pub rating: Option<u8>, // Stars, 1 to 5
+    pub intro: Option<u64>, // Seconds skipped at the start of every play
+}
+
+/// Stats for every track that has any, keyed by path
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    tracks: BTreeMap<PathBuf, TrackStats>,
+}
+
+impl Stats {
+    /// Reads the stats file, which may not exist yet
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = stats_path() else {
+            return Ok(Self::default());
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let mut tracks = BTreeMap::new();
+        for line in text.lines() {
+            let fields: Vec<&str> = line.splitn(4, '\t').collect();
+            let (plays, rating, intro, path) = match fields[..] {
+                [plays, rating, intro, path] => (plays, rating, intro, path),
+                [plays, rating, path] => (plays, rating, "-", path),
+                _ => continue,
+            };
+            tracks.insert(
+                PathBuf::from(path),
+                TrackStats {
+                    plays: plays.parse().unwrap_or(0),
+                    rating: rating.parse().ok(),
+                    intro: intro.parse().ok(),
+                },
+            );
+        }
+        Ok(Self { tracks })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = stats_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for (track, stats) in &self.tracks {
+            let rating = stats.rating.map_or("-".to_string(), |r| r.to_string());
+            let intro = stats.intro.map_or("-".to_string(), |i| i.to_string());
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                stats.plays,
+                rating,
+                intro,
+                track.display()
+            ));
+        }
+        fs::write(path, text)
+    }
+
+    /// Every track that has stats
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.tracks.keys().map(PathBuf::as_path)
+    }
+
+    pub fn get(&self, track: &Path) -> Option<&TrackStats> {
+        self.tracks.get(track)
+    }
+
+    /// Moves a track's stats along with its file
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        if let Some(stats) = self.tracks.remove(from) {
+            self.tracks.insert(to.to_path_buf(), stats);
+        }
+    }
+
+    /// Stats for a track, created empty if the track has none yet
+    pub fn entry(&mut self, track: &Path) -> &mut TrackStats {
+        self.tracks.entry(track.to_path_buf()).or_default()
+    }
+}
+
+fn stats_path() -> Option<PathBuf> {
+    session::state_dir().map(|dir| dir.join(STATS_FILE))
+}
@@ -0,0 +1,190 @@
+//! Playback positions shared between machines through a synced folder
+//! Each machine writes only its own `positions-<host>` file, so Syncthing
+//! or Dropbox never has to merge concurrent edits; readers merge every
+//! file in the folder and the most recent update for a track wins
+
+use crate::config::Config;
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::PathBuf,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Prefix of the per-machine position files
+const FILE_PREFIX: &str = "positions-";
+
+/// A saved position and when it was saved
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Entry {
+    updated: u64,  // Unix time of the update
+    position: u64, // Seconds into the track, 0 once finished
+}
+
+/// Per-track positions in the synced folder, keyed by the track's path
+/// relative to the music directory so keys match across machines
+pub struct PositionSync {
+    dir: PathBuf,
+    host: String,
+    own: HashMap<String, Entry>, // Entries this machine wrote
+}
+
+impl PositionSync {
+    /// Reads `[sync] dir`; None when syncing isn't configured
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let dir = config.get_str("sync.dir")?;
+        let dir = match dir.strip_prefix("~/") {
+            Some(rest) => PathBuf::from(env::var_os("HOME")?).join(rest),
+            None => PathBuf::from(dir),
+        };
+        let host = hostname();
+        let own = fs::read_to_string(dir.join(format!("{}{}", FILE_PREFIX, host)))
+            .map(|text| parse(&text))
+            .unwrap_or_default();
+        Some(Self { dir, host, own })
+    }
+
+    /// Latest position for a track across all machines
+    pub fn position(&self, key: &str) -> Option<u64> {
+        let mut latest: Option<Entry> = self.own.get(key).copied();
+        for entries in self.files() {
+            if let Some(entry) = entries.get(key) {
+                if latest.is_none_or(|latest| entry.updated > latest.updated) {
+                    latest = Some(*entry);
+                }
+            }
+        }
+        latest.map(|entry| entry.position)
+    }
+
+    /// Latest position of every track across all machines, as
+    /// `(key, updated, position)`
+    pub fn all(&self) -> Vec<(String, u64, u64)> {
+        let mut latest = self.own.clone();
+        for entries in self.files() {
+            for (key, entry) in entries {
+                if latest
+                    .get(&key)
+                    .is_none_or(|known| entry.updated > known.updated)
+                {
+                    latest.insert(key, entry);
+                }
+            }
+        }
+        let mut all: Vec<(String, u64, u64)> = latest
+            .into_iter()
+            .map(|(key, entry)| (key, entry.updated, entry.position))
+            .collect();
+        all.sort();
+        all
+    }
+
+    /// Takes in positions from elsewhere, e.g. a backup, as this
+    /// machine's; ones older than what is known are passed over
+    /// Returns how many were taken
+    pub fn import(&mut self, entries: &[(String, u64, u64)]) -> io::Result<usize> {
+        let mut taken = 0;
+        for (key, updated, position) in entries {
+            if self
+                .position_updated(key)
+                .is_some_and(|known| known >= *updated)
+            {
+                continue;
+            }
+            let entry = Entry {
+                updated: *updated,
+                position: *position,
+            };
+            self.own.insert(key.clone(), entry);
+            taken += 1;
+        }
+        if taken > 0 {
+            self.write()?;
+        }
+        Ok(taken)
+    }
+
+    /// When a track's latest position was saved
+    fn position_updated(&self, key: &str) -> Option<u64> {
+        let own = self.own.get(key).map(|entry| entry.updated);
+        self.files()
+            .filter_map(|entries| entries.get(key).map(|entry| entry.updated))
+            .chain(own)
+            .max()
+    }
+
+    /// Entries of every machine's file in the folder
+    fn files(&self) -> impl Iterator<Item = HashMap<String, Entry>> {
+        let files = fs::read_dir(&self.dir).into_iter().flatten().flatten();
+        files.filter_map(|file| {
+            let name = file.file_name().to_string_lossy().to_string();
+            // Also picks up conflict copies such as `positions-a.sync-conflict-...`
+            if !name.starts_with(FILE_PREFIX) || name.ends_with(".tmp") {
+                return None;
+            }
+            fs::read_to_string(file.path())
+                .ok()
+                .map(|text| parse(&text))
+        })
+    }
+
+    /// Saves a position for this machine
+    pub fn record(&mut self, key: &str, position: u64) -> io::Result<()> {
+        let updated = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.own
+            .insert(key.to_string(), Entry { updated, position });
+        self.write()
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let mut keys: Vec<&String> = self.own.keys().collect();
+        keys.sort();
+        let mut text = String::new();
+        for key in keys {
+            let entry = self.own[key];
+            text.push_str(&format!("{}\t{}\t{}\n", entry.updated, entry.position, key));
+        }
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}{}", FILE_PREFIX, self.host));
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(tmp, path)
+    }
+}
+
+/// `updated<TAB>position<TAB>key` lines
+fn parse(text: &str) -> HashMap<String, Entry> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let updated = fields.next()?.parse().ok()?;
+            let position = fields.next()?.parse().ok()?;
+            Some((fields.next()?.to_string(), Entry { updated, position }))
+        })
+        .collect()
+}
+
+/// Name of this machine, used to keep each machine's file separate
+fn hostname() -> String {
+    let name = env::var("COMPUTERNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| {
+            let output = Command::new("hostname").output().ok()?;
+            Some(String::from_utf8_lossy(&output.stdout).to_string())
+        })
+        .unwrap_or_default();
+    let name: String = name
+        .trim()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    if name.is_empty() {
+        "local".to_string()
+    } else {
+        name
+    }
+}
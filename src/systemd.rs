@@ -0,0 +1,68 @@
+//! systemd integration for running as a user service
+//! Readiness is reported through `sd_notify`, and a control socket passed
+//! in by socket activation is used instead of binding our own
+
+use std::{env, net::TcpListener};
+
+/// First file descriptor passed by systemd (`SD_LISTEN_FDS_START`)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Tells systemd the player is ready (for `Type=notify` units)
+/// Does nothing when not started by systemd
+pub fn notify_ready() {
+    notify("READY=1");
+}
+
+#[cfg(unix)]
+fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let Ok(socket) = UnixDatagram::unbound() else {
+        return;
+    };
+    let path = path.to_string_lossy().to_string();
+
+    // A leading '@' names a socket in the Linux abstract namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        if let Ok(addr) = std::os::unix::net::SocketAddr::from_abstract_name(name) {
+            let _ = socket.send_to_addr(state.as_bytes(), &addr);
+        }
+        return;
+    }
+    let _ = socket.send_to(state.as_bytes(), path);
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) {}
+
+/// Takes over the listening socket passed by systemd socket activation,
+/// if this process was started that way
+#[cfg(unix)]
+pub fn activated_listener() -> Option<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let pid = env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let fds = env::var("LISTEN_FDS").ok()?.parse::<i32>().ok()?;
+    if pid != std::process::id() || fds < 1 {
+        return None;
+    }
+    // Child processes must not think the socket was meant for them
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    // SAFETY: systemd hands us ownership of this descriptor, and the
+    // LISTEN_PID check above ensures it was meant for this process
+    Some(unsafe { TcpListener::from_raw_fd(LISTEN_FDS_START) })
+}
+
+#[cfg(not(unix))]
+pub fn activated_listener() -> Option<TcpListener> {
+    None
+}
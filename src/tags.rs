@@ -0,0 +1,532 @@
+//! Minimal tag reader for the formats rodio plays
+//! Reads ID3v2 and ID3v1 (MP3, and ID3 chunks in WAV), Vorbis comments
+//! (FLAC, Ogg Vorbis, Opus), RIFF INFO (WAV) and iTunes atoms (M4A)
+//! Only the fields the player uses are kept
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Largest metadata block read into memory, to cope with corrupt sizes
+const MAX_BLOCK: u64 = 16 * 1024 * 1024;
+
+/// How much of an Ogg file is searched for the comment header
+const OGG_SCAN: u64 = 256 * 1024;
+
+/// Tags of a track; fields missing from the file are None
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tags {
+    pub artist: Option<String>,       // Track artist
+    pub album_artist: Option<String>, // Album artist, e.g. for compilations
+    pub album: Option<String>,        // Album title
+    pub title: Option<String>,        // Track title
+    pub track: Option<u32>,           // Track number on the album
+    pub disc: Option<u32>,            // Disc number
+    pub year: Option<u32>,            // Release year
+    pub genre: Option<String>,        // Genre name
+    pub bpm: Option<u32>,             // Tempo in beats per minute
+}
+
+impl Tags {
+    /// Album artist, falling back to the track artist
+    pub fn album_artist(&self) -> Option<&str> {
+        self.album_artist.as_deref().or(self.artist.as_deref())
+    }
+
+    /// "Artist – Title – Album", leaving out what is missing; None without
+    /// a title, which the file name then stands in for
+    pub fn describe(&self) -> Option<String> {
+        let title = self.title.as_deref()?;
+        let parts = [self.artist.as_deref(), Some(title), self.album.as_deref()];
+        Some(parts.into_iter().flatten().collect::<Vec<_>>().join(" – "))
+    }
+
+    /// Whether both tracks come from the same album, going by the album
+    /// title and album artist; tracks without an album tag never match
+    pub fn same_album(&self, other: &Tags) -> bool {
+        self.album.is_some()
+            && self.album == other.album
+            && self.album_artist() == other.album_artist()
+    }
+
+    /// Fills a field from a tag name used by Vorbis comments and RIFF INFO
+    fn set(&mut self, key: &str, value: &str) {
+        let value = value.trim_matches(char::from(0)).trim();
+        if value.is_empty() {
+            return;
+        }
+        let text = Some(value.to_string());
+        match key.to_ascii_uppercase().as_str() {
+            "ARTIST" | "IART" | "TPE1" | "TP1" | "\u{a9}ART" => self.artist = text,
+            "ALBUMARTIST" | "ALBUM ARTIST" | "TPE2" | "TP2" | "AART" => self.album_artist = text,
+            "ALBUM" | "IPRD" | "TALB" | "TAL" | "\u{a9}ALB" => self.album = text,
+            "TITLE" | "INAM" | "TIT2" | "TT2" | "\u{a9}NAM" => self.title = text,
+            "TRACKNUMBER" | "ITRK" | "IPRT" | "TRCK" | "TRK" => self.track = leading_number(value),
+            "DISCNUMBER" | "TPOS" | "TPA" => self.disc = leading_number(value),
+            "DATE" | "YEAR" | "ICRD" | "TYER" | "TDRC" | "TYE" | "\u{a9}DAY" => {
+                self.year = leading_number(value)
+            }
+            "GENRE" | "IGNR" | "TCON" | "TCO" | "\u{a9}GEN" => {
+                // ID3 may refer to its numbered genres as `(17)`, often
+                // followed by the name
+                let name = match value.strip_prefix('(').and_then(|v| v.split_once(')')) {
+                    Some((_, name)) if !name.trim().is_empty() => name.trim(),
+                    _ => value,
+                };
+                self.genre = Some(name.to_string());
+            }
+            "BPM" | "TBPM" | "TBP" => self.bpm = leading_number(value).filter(|&bpm| bpm > 0),
+            _ => {}
+        }
+    }
+
+    /// Keeps fields already set, taking the rest from `other`
+    fn merge(&mut self, other: Tags) {
+        self.artist = self.artist.take().or(other.artist);
+        self.album_artist = self.album_artist.take().or(other.album_artist);
+        self.album = self.album.take().or(other.album);
+        self.title = self.title.take().or(other.title);
+        self.track = self.track.or(other.track);
+        self.disc = self.disc.or(other.disc);
+        self.year = self.year.or(other.year);
+        self.genre = self.genre.take().or(other.genre);
+        self.bpm = self.bpm.or(other.bpm);
+    }
+}
+
+/// Reads the tags of a file, which are empty if it has none
+pub fn read(path: &Path) -> io::Result<Tags> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 12];
+    let read = read_up_to(&mut file, &mut magic)?;
+    let magic = &magic[..read];
+    file.seek(SeekFrom::Start(0))?;
+
+    let tags = if magic.starts_with(b"fLaC") {
+        flac(&mut file)?
+    } else if magic.starts_with(b"OggS") {
+        ogg(&mut file)?
+    } else if magic.starts_with(b"RIFF") && magic.get(8..12) == Some(b"WAVE") {
+        wav(&mut file)?
+    } else if magic.get(4..8) == Some(b"ftyp") {
+        mp4(&mut file)?
+    } else {
+        let mut tags = if magic.starts_with(b"ID3") {
+            id3v2(&mut file)?
+        } else {
+            Tags::default()
+        };
+        tags.merge(id3v1(&mut file)?);
+        tags
+    };
+    Ok(tags)
+}
+
+/// ID3v2.2, 2.3 or 2.4 tag at the current position
+fn id3v2(file: &mut File) -> io::Result<Tags> {
+    let mut header = [0; 10];
+    file.read_exact(&mut header)?;
+    let version = header[3];
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]);
+    let mut data = read_block(file, size as u64)?;
+    if flags & 0x80 != 0 {
+        // Unsynchronisation inserts a zero after every 0xFF
+        data = resynchronise(&data);
+    }
+    let mut position = 0;
+    if flags & 0x40 != 0 && version >= 3 {
+        // Extended header
+        let size = match version {
+            3 => be_u32(&data, 0).map(|size| size as usize + 4),
+            _ => (data.len() >= 4).then(|| syncsafe(&data[0..4]) as usize),
+        };
+        position = size.unwrap_or(data.len());
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut tags = Tags::default();
+    while position + header_len <= data.len() {
+        let id = &data[position..position + id_len];
+        if id[0] == 0 {
+            break; // Padding
+        }
+        let size = match version {
+            2 => u32::from_be_bytes([
+                0,
+                data[position + 3],
+                data[position + 4],
+                data[position + 5],
+            ]),
+            3 => be_u32(&data, position + 4).unwrap_or(0),
+            _ => syncsafe(&data[position + 4..position + 8]),
+        } as usize;
+        let start = position + header_len;
+        let Some(body) = data.get(start..start + size) else {
+            break;
+        };
+        if id[0] == b'T' {
+            let id = String::from_utf8_lossy(id);
+            tags.set(&id, &id3_text(body));
+        }
+        position = start + size;
+    }
+    Ok(tags)
+}
+
+/// ID3v1 tag in the last 128 bytes
+fn id3v1(file: &mut File) -> io::Result<Tags> {
+    let mut tags = Tags::default();
+    if file.seek(SeekFrom::End(0))? < 128 {
+        return Ok(tags);
+    }
+    file.seek(SeekFrom::End(-128))?;
+    let mut tag = [0; 128];
+    file.read_exact(&mut tag)?;
+    if &tag[0..3] != b"TAG" {
+        return Ok(tags);
+    }
+    tags.set("TITLE", &latin1(&tag[3..33]));
+    tags.set("ARTIST", &latin1(&tag[33..63]));
+    tags.set("ALBUM", &latin1(&tag[63..93]));
+    tags.set("YEAR", &latin1(&tag[93..97]));
+    // ID3v1.1 keeps the track number in the last byte of the comment
+    if tag[125] == 0 && tag[126] != 0 {
+        tags.track = Some(tag[126] as u32);
+    }
+    Ok(tags)
+}
+
+/// FLAC metadata blocks, looking for the Vorbis comment block
+fn flac(file: &mut File) -> io::Result<Tags> {
+    file.seek(SeekFrom::Start(4))?;
+    loop {
+        let mut header = [0; 4];
+        file.read_exact(&mut header)?;
+        let last = header[0] & 0x80 != 0;
+        let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        if header[0] & 0x7f == 4 {
+            return Ok(vorbis_comments(&read_block(file, size)?));
+        }
+        if last {
+            return Ok(Tags::default());
+        }
+        file.seek(SeekFrom::Current(size as i64))?;
+    }
+}
+
+/// Comment header of an Ogg Vorbis or Opus stream
+fn ogg(file: &mut File) -> io::Result<Tags> {
+    let mut data = Vec::new();
+    file.take(OGG_SCAN).read_to_end(&mut data)?;
+
+    // Join page bodies so a comment packet split across pages reads whole
+    let mut packets = Vec::new();
+    let mut position = 0;
+    while data.get(position..position + 4) == Some(b"OggS") {
+        let Some(&segments) = data.get(position + 26) else {
+            break;
+        };
+        let table = position + 27;
+        let Some(lengths) = data.get(table..table + segments as usize) else {
+            break;
+        };
+        let body: usize = lengths.iter().map(|&l| l as usize).sum();
+        let start = table + segments as usize;
+        let end = (start + body).min(data.len());
+        packets.extend_from_slice(&data[start..end]);
+        position = start + body;
+    }
+
+    for marker in [&b"\x03vorbis"[..], b"OpusTags"] {
+        if let Some(found) = find(&packets, marker) {
+            return Ok(vorbis_comments(&packets[found + marker.len()..]));
+        }
+    }
+    Ok(Tags::default())
+}
+
+/// RIFF INFO list, or an embedded ID3 chunk, in a WAV file
+fn wav(file: &mut File) -> io::Result<Tags> {
+    let end = file.seek(SeekFrom::End(0))?;
+    let mut position = 12;
+    let mut tags = Tags::default();
+    while position + 8 <= end {
+        file.seek(SeekFrom::Start(position))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        match &header[0..4] {
+            b"LIST" => {
+                let list = read_block(file, size)?;
+                if list.starts_with(b"INFO") {
+                    let mut offset = 4;
+                    while offset + 8 <= list.len() {
+                        let id = String::from_utf8_lossy(&list[offset..offset + 4]).to_string();
+                        let len = le_u32(&list, offset + 4).unwrap_or(0) as usize;
+                        let start = offset + 8;
+                        let Some(value) = list.get(start..start + len) else {
+                            break;
+                        };
+                        tags.set(&id, &String::from_utf8_lossy(value));
+                        // Chunks are padded to an even length
+                        offset = start + len + len % 2;
+                    }
+                }
+            }
+            b"id3 " | b"ID3 " => tags.merge(id3v2(file)?),
+            _ => {}
+        }
+        position += 8 + size + size % 2;
+    }
+    Ok(tags)
+}
+
+/// iTunes metadata atoms in an MP4/M4A file
+fn mp4(file: &mut File) -> io::Result<Tags> {
+    let end = file.seek(SeekFrom::End(0))?;
+    let Some(moov) = find_atom(file, 0, end, b"moov")? else {
+        return Ok(Tags::default());
+    };
+    let moov = read_block(file, moov.1)?;
+    let mut tags = Tags::default();
+    let ilst = atom(&moov, b"udta")
+        .and_then(|udta| atom(udta, b"meta"))
+        // `meta` starts with a version and flags
+        .and_then(|meta| atom(meta.get(4..)?, b"ilst"));
+    let Some(ilst) = ilst else {
+        return Ok(tags);
+    };
+    for (name, item) in atoms(ilst) {
+        let Some(data) = atom(item, b"data").and_then(|data| data.get(8..)) else {
+            continue;
+        };
+        match name {
+            b"tmpo" => {
+                tags.bpm = data
+                    .get(0..2)
+                    .map(|n| u16::from_be_bytes([n[0], n[1]]) as u32)
+                    .filter(|&n| n > 0);
+            }
+            b"trkn" | b"disk" => {
+                let number = data
+                    .get(2..4)
+                    .map(|n| u16::from_be_bytes([n[0], n[1]]) as u32);
+                if name == b"trkn" {
+                    tags.track = number.filter(|&n| n > 0);
+                } else {
+                    tags.disc = number.filter(|&n| n > 0);
+                }
+            }
+            _ => {
+                // `©` is stored as the single byte 0xA9
+                let name: String = name.iter().map(|&b| b as char).collect();
+                tags.set(&name, &String::from_utf8_lossy(data));
+            }
+        }
+    }
+    Ok(tags)
+}
+
+/// Finds a top-level atom, returning its body's offset and size
+fn find_atom(
+    file: &mut File,
+    mut position: u64,
+    end: u64,
+    name: &[u8],
+) -> io::Result<Option<(u64, u64)>> {
+    while position + 8 <= end {
+        file.seek(SeekFrom::Start(position))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            let mut large = [0; 8];
+            file.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = end - position;
+        }
+        if size < header_len {
+            break;
+        }
+        if &header[4..8] == name {
+            return Ok(Some((position + header_len, size - header_len)));
+        }
+        position += size;
+    }
+    Ok(None)
+}
+
+/// Child atoms of an atom body, as (name, body) pairs
+fn atoms(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut children = Vec::new();
+    let mut position = 0;
+    while let Some(size) = be_u32(data, position) {
+        let size = size as usize;
+        let (Some(name), Some(body)) = (
+            data.get(position + 4..position + 8),
+            data.get(position + 8..position + size.max(8)),
+        ) else {
+            break;
+        };
+        if size < 8 {
+            break;
+        }
+        children.push((name, body));
+        position += size;
+    }
+    children
+}
+
+fn atom<'a>(data: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    atoms(data)
+        .into_iter()
+        .find(|(child, _)| *child == name)
+        .map(|(_, body)| body)
+}
+
+/// Vorbis comment block: vendor string, then `KEY=value` entries
+fn vorbis_comments(data: &[u8]) -> Tags {
+    let mut tags = Tags::default();
+    let Some(vendor) = le_u32(data, 0) else {
+        return tags;
+    };
+    let mut position = 4 + vendor as usize;
+    let Some(count) = le_u32(data, position) else {
+        return tags;
+    };
+    position += 4;
+    for _ in 0..count {
+        let Some(len) = le_u32(data, position) else {
+            break;
+        };
+        let start = position + 4;
+        let Some(entry) = data.get(start..start + len as usize) else {
+            break;
+        };
+        let entry = String::from_utf8_lossy(entry);
+        if let Some((key, value)) = entry.split_once('=') {
+            tags.set(key, value);
+        }
+        position = start + len as usize;
+    }
+    tags
+}
+
+/// Text of an ID3v2 text frame, the first value if there are several
+fn id3_text(body: &[u8]) -> String {
+    let Some((&encoding, text)) = body.split_first() else {
+        return String::new();
+    };
+    let text = match encoding {
+        1 | 2 => {
+            let big_endian = match text.get(0..2) {
+                Some([0xfe, 0xff]) => true,
+                Some([0xff, 0xfe]) => false,
+                _ => encoding == 2,
+            };
+            let text = if matches!(text.get(0..2), Some([0xfe, 0xff] | [0xff, 0xfe])) {
+                &text[2..]
+            } else {
+                text
+            };
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|pair| {
+                    if big_endian {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(text).to_string(),
+        _ => latin1(text),
+    };
+    text.split('\0').next().unwrap_or("").to_string()
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| b as char)
+        .collect()
+}
+
+/// First run of digits, e.g. 3 from "03/12" or 1999 from "1999-05-01"
+fn leading_number(text: &str) -> Option<u32> {
+    let digits: String = text
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+/// 28-bit integer stored in 7 bits per byte
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| (value << 7) | (byte & 0x7f) as u32)
+}
+
+fn resynchronise(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == 0 && i > 0 && data[i - 1] == 0xff {
+            continue;
+        }
+        out.push(byte);
+    }
+    out
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    let bytes = data.get(at..at + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn le_u32(data: &[u8], at: usize) -> Option<u32> {
+    let bytes = data.get(at..at + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Reads a metadata block of a size taken from the file itself
+fn read_block(file: &mut File, size: u64) -> io::Result<Vec<u8>> {
+    if size > MAX_BLOCK {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Metadata block too large",
+        ));
+    }
+    let mut data = vec![0; size as usize];
+    let read = read_up_to(file, &mut data)?;
+    data.truncate(read);
+    Ok(data)
+}
+
+/// Fills as much of the buffer as the file has left
+fn read_up_to(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,412 @@
+//! `self-update`: replaces a binary installed from a release tarball with
+//! the latest GitHub release
+//! The download has to match the release's `SHA256SUMS`; with `public_key`
+//! under `[update]` set, `SHA256SUMS` must also carry a valid minisign
+//! signature from that key
+
+use crate::{config::Config, json};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Where releases are published
+const REPOSITORY: &str = "parado-xy/rust-cli-music-player";
+
+/// Release asset listing the checksum of every other asset
+const CHECKSUMS: &str = "SHA256SUMS";
+
+/// A published release
+pub struct Release {
+    pub version: String,      // Tag without the leading `v`
+    archive: Asset,           // Tarball built for this platform
+    checksums: Asset,         // SHA256SUMS
+    signature: Option<Asset>, // SHA256SUMS.minisig
+}
+
+struct Asset {
+    name: String,
+    url: String,
+}
+
+impl Release {
+    /// Whether the release is newer than the running binary
+    pub fn is_newer(&self) -> bool {
+        version_parts(&self.version) > version_parts(env!("CARGO_PKG_VERSION"))
+    }
+
+    pub fn archive_name(&self) -> &str {
+        &self.archive.name
+    }
+}
+
+/// Looks up the latest release and its assets for this platform
+pub fn latest() -> Result<Release, String> {
+    let url = format!(
+        "https://api.github.com/repos/{}/releases/latest",
+        REPOSITORY
+    );
+    let body = fetch(&url)?;
+    let response = json::parse(&String::from_utf8_lossy(&body))?;
+    let version = response
+        .get("tag_name")
+        .and_then(json::Json::as_str)
+        .ok_or("The release has no tag")?
+        .trim_start_matches('v')
+        .to_string();
+    let Some(json::Json::Array(assets)) = response.get("assets") else {
+        return Err("The release has no downloads".to_string());
+    };
+    let assets: Vec<Asset> = assets
+        .iter()
+        .filter_map(|asset| {
+            Some(Asset {
+                name: asset.get("name")?.as_str()?.to_string(),
+                url: asset.get("browser_download_url")?.as_str()?.to_string(),
+            })
+        })
+        .collect();
+    let mut archive = None;
+    let mut checksums = None;
+    let mut signature = None;
+    for asset in assets {
+        if asset.name == CHECKSUMS {
+            checksums = Some(asset);
+        } else if asset.name == format!("{}.minisig", CHECKSUMS) {
+            signature = Some(asset);
+        } else if is_for_this_platform(&asset.name) {
+            archive = Some(asset);
+        }
+    }
+    Ok(Release {
+        archive: archive.ok_or_else(|| {
+            format!(
+                "Release {} has no download for {}-{}",
+                version,
+                env::consts::ARCH,
+                env::consts::OS
+            )
+        })?,
+        checksums: checksums.ok_or_else(|| format!("Release {} has no {}", version, CHECKSUMS))?,
+        signature,
+        version,
+    })
+}
+
+/// Downloads and verifies a release, then puts its binary in place of
+/// the running one, returning the path that was replaced
+pub fn install(release: &Release, config: &Config) -> Result<PathBuf, String> {
+    let key = config.get_str("update.public_key");
+    // Refused before anything is downloaded
+    if key.is_some() && release.signature.is_none() {
+        return Err(format!(
+            "Release {} isn't signed, but `public_key` under [update] asks for a signature",
+            release.version
+        ));
+    }
+    let checksums = fetch(&release.checksums.url)?;
+    if let (Some(key), Some(signature)) = (key, &release.signature) {
+        verify_signature(&checksums, &fetch(&signature.url)?, key)?;
+    }
+    let expected = listed_checksum(&checksums, &release.archive.name)
+        .ok_or_else(|| format!("{} doesn't list {}", CHECKSUMS, release.archive.name))?;
+    let archive = fetch(&release.archive.url)?;
+    let actual: String = sha256(&archive)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if actual != expected {
+        return Err(format!(
+            "{} doesn't match its checksum; nothing was replaced",
+            release.archive.name
+        ));
+    }
+
+    let current = env::current_exe()
+        .and_then(fs::canonicalize)
+        .map_err(|e| format!("Could not find the running binary: {}", e))?;
+    let dir = current.parent().ok_or("The binary has no folder")?;
+    let work = dir.join(format!(".musicplayer-update-{}", std::process::id()));
+    let result = unpack_and_swap(&archive, &release.archive.name, &work, &current);
+    let _ = fs::remove_dir_all(&work);
+    result.map(|()| current)
+}
+
+/// Extracts the archive next to the binary and renames its binary over
+/// the running one, so a failure leaves the old binary untouched
+fn unpack_and_swap(archive: &[u8], name: &str, work: &Path, current: &Path) -> Result<(), String> {
+    let failed = |e: io::Error| format!("Could not write to {}: {}", work.display(), e);
+    fs::create_dir_all(work).map_err(failed)?;
+    let saved = work.join(name);
+    fs::write(&saved, archive).map_err(failed)?;
+    let status = Command::new("tar")
+        .arg("-xf")
+        .arg(&saved)
+        .arg("-C")
+        .arg(work)
+        .stdin(Stdio::null())
+        .status()
+        .map_err(|e| format!("Could not run tar: {}", e))?;
+    if !status.success() {
+        return Err(format!("Could not unpack {}", name));
+    }
+    let binary_name = current.file_name().ok_or("The binary has no name")?;
+    let binary = find_binary(work, binary_name)
+        .ok_or_else(|| format!("{} holds no {}", name, binary_name.to_string_lossy()))?;
+    let replaced = |e: io::Error| format!("Could not replace {}: {}", current.display(), e);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).map_err(replaced)?;
+    }
+    // Windows won't overwrite a running binary, but lets it be renamed
+    if cfg!(windows) {
+        let old = current.with_extension("old.exe");
+        let _ = fs::remove_file(&old);
+        fs::rename(current, &old).map_err(replaced)?;
+    }
+    fs::rename(&binary, current).map_err(replaced)
+}
+
+/// Looks through the unpacked archive for a file named like the binary
+fn find_binary(dir: &Path, name: &std::ffi::OsStr) -> Option<PathBuf> {
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if let Some(found) = find_binary(&path, name) {
+                return Some(found);
+            }
+        } else if path.file_name() == Some(name) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Checksum `SHA256SUMS` gives for an asset, lowercase; binary-mode
+/// lines mark the name with `*`
+fn listed_checksum(checksums: &[u8], asset: &str) -> Option<String> {
+    String::from_utf8_lossy(checksums).lines().find_map(|line| {
+        let (sum, name) = line.split_once(char::is_whitespace)?;
+        (name.trim().trim_start_matches('*') == asset).then(|| sum.to_lowercase())
+    })
+}
+
+/// Checks the checksums file against its signature with `minisign`
+fn verify_signature(checksums: &[u8], signature: &[u8], key: &str) -> Result<(), String> {
+    let dir = env::temp_dir().join(format!("musicplayer-verify-{}", std::process::id()));
+    let failed = |e: io::Error| format!("Could not write to {}: {}", dir.display(), e);
+    fs::create_dir_all(&dir).map_err(failed)?;
+    let file = dir.join(CHECKSUMS);
+    let result = fs::write(&file, checksums)
+        .and_then(|()| fs::write(file.with_extension("minisig"), signature))
+        .map_err(failed)
+        .and_then(|()| {
+            Command::new("minisign")
+                .arg("-Vqm")
+                .arg(&file)
+                .arg("-x")
+                .arg(file.with_extension("minisig"))
+                .arg("-P")
+                .arg(key)
+                .stdin(Stdio::null())
+                .status()
+                .map_err(|e| format!("Could not run minisign: {}", e))
+        });
+    let _ = fs::remove_dir_all(&dir);
+    if result?.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}'s signature doesn't match `public_key`; nothing was replaced",
+            CHECKSUMS
+        ))
+    }
+}
+
+/// Whether a release asset is the tarball for this OS and CPU
+fn is_for_this_platform(name: &str) -> bool {
+    let name = name.to_lowercase();
+    let os_names: &[&str] = match env::consts::OS {
+        "macos" => &["macos", "darwin", "apple"],
+        os => &[os],
+    };
+    let archive = [".tar.gz", ".tgz", ".tar.xz", ".zip"]
+        .iter()
+        .any(|extension| name.ends_with(extension));
+    archive && name.contains(env::consts::ARCH) && os_names.iter().any(|os| name.contains(os))
+}
+
+/// Numeric parts of a version, so 0.10.0 sorts after 0.9.1
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .split(['.', '-', '+'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    let output = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--location",
+            "--user-agent",
+            concat!("musicplayer/", env!("CARGO_PKG_VERSION")),
+            "--",
+            url,
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Could not run curl: {}", e))?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{}: {}", url, message.trim()));
+    }
+    Ok(output.stdout)
+}
+
+/// SHA-256 of a download, small enough not to need a crate
+fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    let mut digest = [0u8; 32];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(digest: [u8; 32]) -> String {
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn sha256_matches_the_standard_vectors() {
+        assert_eq!(
+            hex(sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Padding spills into a second block
+        assert_eq!(
+            hex(sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex(sha256(&[b'a'; 1_000_000])),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn finds_the_listed_checksum() {
+        let sums =
+            b"AB12  musicplayer-x86_64-linux.tar.gz\ncd34 *musicplayer-aarch64-macos.tar.gz\n";
+        assert_eq!(
+            listed_checksum(sums, "musicplayer-x86_64-linux.tar.gz").as_deref(),
+            Some("ab12")
+        );
+        assert_eq!(
+            listed_checksum(sums, "musicplayer-aarch64-macos.tar.gz").as_deref(),
+            Some("cd34")
+        );
+        assert_eq!(listed_checksum(sums, "musicplayer-x86_64-linux.tar"), None);
+        assert_eq!(listed_checksum(b"garbage", "garbage"), None);
+    }
+
+    #[test]
+    fn refuses_unsigned_releases_when_a_key_is_set() {
+        let asset = |name: &str| Asset {
+            name: name.to_string(),
+            // Never fetched: the check comes first
+            url: "http://127.0.0.1:9/unreachable".to_string(),
+        };
+        let release = Release {
+            version: "9.9.9".to_string(),
+            archive: asset("musicplayer.tar.gz"),
+            checksums: asset(CHECKSUMS),
+            signature: None,
+        };
+        let config = Config::parse("[update]\npublic_key = \"RWQ...\"").unwrap();
+        let error = install(&release, &config).unwrap_err();
+        assert!(error.contains("isn't signed"), "{}", error);
+    }
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert!(version_parts("0.10.0") > version_parts("0.9.1"));
+        assert!(version_parts("2") > version_parts("1.9.9"));
+        assert_eq!(version_parts("1.2.3-beta"), [1, 2, 3]);
+        assert_eq!(version_parts("v1"), Vec::<u64>::new());
+    }
+}